@@ -0,0 +1,101 @@
+# Messages printed while apps are downloaded.  Arguments are named after what they hold: $app is
+# an app ID, with its version if one was asked for, and $error the reason something failed.
+
+## Downloading
+
+downloading = Downloading {$app}...
+downloading-version = Downloading {$app} version {$version}...
+downloading-arch = Downloading {$app} arch {$arch}...
+downloading-version-arch = Downloading {$app} version {$version} arch {$arch}...
+downloaded = {$app} downloaded successfully!
+downloaded-as = Successfully downloaded {$app} as {$file}
+download-retry = An error has occurred attempting to download {$app}.  Retry #{$attempt}...
+download-failed = An error has occurred attempting to download {$app}. Skipping...
+download-not-retried = An error which --retry-on does not retry has occurred attempting to download {$app}: {$error}. Skipping...
+source-tripped = {$source} has failed {$failures} apps in a row, so it is skipped for the rest of the run.
+source-skipped = {$app} was not tried on {$source}, which is being skipped after failing too many apps in a row. Skipping...
+app-timed-out = {$app} took longer than {$seconds} seconds, so it was cancelled and its partial files deleted. Skipping...
+download-failed-reason = Could not download {$app} from {$source}: {$error}. Skipping...
+check-failed = Could not check {$app} on {$source}: {$error}. Skipping...
+info-failed = Could not look up {$app} on {$source}: {$error}. Skipping...
+download-error = Error downloading {$app}: {$error}
+file-exists = File already exists for {$app}. Skipping...
+file-up-to-date = File already exists for {$app} and is up to date. Skipping...
+file-changed = {$app} has changed since it was last downloaded. Downloading it again...
+file-changed-not-replaced = {$app} has changed since it was last downloaded, but could not be replaced: {$error}. Skipping...
+split-directory-exists = Split APK directory already exists for {$app}. Skipping...
+permission-denied = Permission denied when attempting to write file for {$app}. Skipping...
+invalid-response = Invalid response for {$app}. Skipping...
+invalid-app-response = Invalid app response for {$app}. Skipping...
+not-offered-to-device = Invalid app response for {$app}. It may not be offered to the {$device} device profile; another can be chosen with -o device=. Skipping...
+no-download-url = Could not get download URL for {$app}. Skipping...
+no-versions-retrieved = Could not retrieve versions for {$app}. {$error} Skipping...
+no-app-response = Could not read app response for {$app}. {$error} Skipping...
+no-app-information = Could not retrieve app information for {$app}. {$error} Skipping...
+no-specific-versions = Specific versions can not be downloaded from {$source} ({$app}). Skipping...
+not-newer = {$app} is not newer than the version already installed or downloaded. Skipping...
+too-large = {$app} is {$size}, larger than --skip-larger-than allows. Skipping...
+already-downloading = {$app} is already being downloaded to {$file}. Skipping...
+file-name-collision = {$app} would be saved as {$file}, the same file as {$other}; saving it as {$renamed} instead.
+latest-version = latest
+version-not-found = Could not find version {$version} of {$app}. Skipping...
+not-in-package-list = Could not find {$app} in package list. Skipping...
+searching = Searching for {$app} on {$source}
+found-app-page = Found app page: {$url}
+found-download-url = Found download URL: {$url}
+downloading-from = Downloading APK from: {$url}
+plugin-download-failed = Could not download {$app} with the plugin {$plugin}: {$error}. Skipping...
+
+## F-Droid
+
+fdroid-unverified = The F-Droid package index will not be verified, so the apps downloaded may have been tampered with. Proceed with caution.
+fdroid-mirroring = Mirroring {$count} apps from the repository...
+fdroid-mirroring-category = Mirroring {$count} apps in category {$category}...
+fdroid-mirroring-developer = Mirroring {$count} apps by {$developer}...
+fdroid-fastlane-failed = Could not mirror the listing of {$app}: {$error}. Skipping...
+fdroid-index-unparsable = Could not parse JSON of F-Droid package index. Exiting.
+fdroid-index-downloaded = Package index downloaded successfully!
+fdroid-verifying = Verifying...
+fdroid-hash-mismatch-quarantined = {$app} downloaded, but the sha256sum does not match the one signed by F-Droid. Quarantined as {$path}. Skipping...
+fdroid-hash-mismatch-deleted = {$app} downloaded, but the sha256sum does not match the one signed by F-Droid, so it has been deleted. Skipping...
+fdroid-downloading-repository = Downloading F-Droid package repository...
+fdroid-repository-downloaded =
+    Package repository downloaded successfully!
+    Extracting...
+
+## Google Play
+
+google-play-tos-accepted = Google Play Terms of Service accepted.
+google-play-aas-token = AAS Token: {$token}
+google-play-no-aas-token = Error: was not able to retrieve AAS token with the provided OAuth token. Please provide new OAuth token and try again.
+
+## Listing versions
+
+versions-available = Versions available for {$app} on {$source}:
+versions-not-available = | {$source} does not make old versions of apps available.
+versions-not-listed = The rules for {$source} don't list versions.
+versions-not-supported =
+    {$source} does not support listing versions at this time.
+    Only the latest version of each app is available for download.
+versions-error = | {$error} Skipping...
+versions-invalid-app-response = | Invalid app response for {$app}. Skipping...
+versions-not-in-package-list = | Could not find {$app} in package list. Skipping...
+versions-not-found = | Could not find {$app}: {$error}. Skipping...
+
+## Racing sources
+
+race-directory-failed = Could not create a directory to download {$app} from {$source} into: {$error}. Skipping...
+race-all-failed = Could not download {$app} from any of the sources raced. Skipping...
+race-move-failed = Could not move the download of {$app} into place: {$error}. Skipping...
+race-won = {$app} was fetched first from {$source}.
+
+## Pruning
+
+prune-no-version-code = {$path} does not specify a versionCode. Skipping...
+prune-manifest-unreadable = Could not read the manifest of {$path}: {$error}. Skipping...
+prune-would-remove = Would remove {$app} version {$version_code}
+prune-would-archive = Would archive {$app} version {$version_code}
+prune-removing = Removing {$app} version {$version_code}
+prune-archiving = Archiving {$app} version {$version_code}
+retention-removed = Removed {$versions} old versions ({$files} files, {$size} MiB).
+retention-failed = Could not remove old versions: {$error}
@@ -0,0 +1,109 @@
+# Сообщения, которые выводятся при загрузке приложений.  См. en.ftl.
+
+## Downloading
+
+downloading = Загрузка {$app}...
+downloading-version = Загрузка {$app} версии {$version}...
+downloading-arch = Загрузка {$app} для архитектуры {$arch}...
+downloading-version-arch = Загрузка {$app} версии {$version} для архитектуры {$arch}...
+downloaded = {$app} успешно загружено!
+downloaded-as = {$app} успешно загружено как {$file}
+download-retry = При загрузке {$app} произошла ошибка.  Попытка №{$attempt}...
+download-failed = При загрузке {$app} произошла ошибка. Пропускаем...
+download-not-retried = При загрузке {$app} произошла ошибка, которую --retry-on не повторяет: {$error}. Пропускаем...
+source-tripped = {$source} не смог загрузить {$failures} приложений подряд, поэтому до конца запуска он пропускается.
+source-skipped = {$app} не загружалось с {$source}, который пропускается после слишком многих неудач подряд. Пропускаем...
+app-timed-out = {$app} загружалось дольше {$seconds} секунд, поэтому загрузка отменена, а незавершённые файлы удалены. Пропускаем...
+download-failed-reason = Не удалось загрузить {$app} с {$source}: {$error}. Пропускаем...
+check-failed = Не удалось проверить {$app} на {$source}: {$error}. Пропускаем...
+info-failed = Не удалось получить сведения о {$app} на {$source}: {$error}. Пропускаем...
+download-error = Ошибка при загрузке {$app}: {$error}
+file-exists = Файл для {$app} уже существует. Пропускаем...
+file-up-to-date = Файл для {$app} уже существует и не устарел. Пропускаем...
+file-changed = {$app} изменилось после прошлой загрузки. Загружаем заново...
+file-changed-not-replaced = {$app} изменилось после прошлой загрузки, но файл не удалось заменить: {$error}. Пропускаем...
+split-directory-exists = Каталог с разделёнными APK для {$app} уже существует. Пропускаем...
+permission-denied = Нет прав на запись файла для {$app}. Пропускаем...
+invalid-response = Некорректный ответ для {$app}. Пропускаем...
+invalid-app-response = Некорректный ответ о приложении {$app}. Пропускаем...
+not-offered-to-device = Некорректный ответ о приложении {$app}. Возможно, оно недоступно для профиля устройства {$device}; другой профиль можно выбрать с помощью -o device=. Пропускаем...
+no-download-url = Не удалось получить ссылку для загрузки {$app}. Пропускаем...
+no-versions-retrieved = Не удалось получить список версий {$app}. {$error} Пропускаем...
+no-app-response = Не удалось прочитать ответ о приложении {$app}. {$error} Пропускаем...
+no-app-information = Не удалось получить сведения о приложении {$app}. {$error} Пропускаем...
+no-specific-versions = Из {$source} нельзя загрузить конкретную версию ({$app}). Пропускаем...
+not-newer = {$app} не новее версии, которая уже установлена или загружена. Пропускаем...
+too-large = {$app} занимает {$size}, больше, чем позволяет --skip-larger-than. Пропускаем...
+already-downloading = {$app} уже загружается в {$file}. Пропускаем...
+file-name-collision = {$app} был бы сохранён как {$file}, в тот же файл, что и {$other}; сохраняем его как {$renamed}.
+latest-version = последнюю
+version-not-found = Не удалось найти {$version} версию {$app}. Пропускаем...
+not-in-package-list = {$app} не найдено в списке пакетов. Пропускаем...
+searching = Поиск {$app} в {$source}
+found-app-page = Найдена страница приложения: {$url}
+found-download-url = Найдена ссылка для загрузки: {$url}
+downloading-from = Загрузка APK с {$url}
+plugin-download-failed = Не удалось загрузить {$app} с помощью плагина {$plugin}: {$error}. Пропускаем...
+
+## F-Droid
+
+fdroid-unverified = Индекс пакетов F-Droid не будет проверен, поэтому загруженные приложения могут оказаться подменены. Будьте осторожны.
+fdroid-mirroring = Зеркалирование { $count ->
+        [one] {$count} приложения
+       *[other] {$count} приложений
+    } из репозитория...
+fdroid-mirroring-category = Зеркалирование { $count ->
+        [one] {$count} приложения
+       *[other] {$count} приложений
+    } из категории {$category}...
+fdroid-mirroring-developer = Зеркалирование { $count ->
+        [one] {$count} приложения
+       *[other] {$count} приложений
+    } разработчика {$developer}...
+fdroid-fastlane-failed = Не удалось зеркалировать описание {$app}: {$error}. Пропускаем...
+fdroid-index-unparsable = Не удалось разобрать JSON индекса пакетов F-Droid. Выход.
+fdroid-index-downloaded = Индекс пакетов успешно загружен!
+fdroid-verifying = Проверка...
+fdroid-hash-mismatch-quarantined = {$app} загружено, но его sha256sum не совпадает с подписанной F-Droid. Помещено в карантин как {$path}. Пропускаем...
+fdroid-hash-mismatch-deleted = {$app} загружено, но его sha256sum не совпадает с подписанной F-Droid, поэтому оно удалено. Пропускаем...
+fdroid-downloading-repository = Загрузка репозитория пакетов F-Droid...
+fdroid-repository-downloaded =
+    Репозиторий пакетов успешно загружен!
+    Распаковка...
+
+## Google Play
+
+google-play-tos-accepted = Условия использования Google Play приняты.
+google-play-aas-token = Токен AAS: {$token}
+google-play-no-aas-token = Ошибка: не удалось получить токен AAS по указанному токену OAuth. Укажите новый токен OAuth и попробуйте снова.
+
+## Listing versions
+
+versions-available = Версии {$app}, доступные в {$source}:
+versions-not-available = | {$source} не предоставляет старые версии приложений.
+versions-not-listed = Правила для {$source} не содержат списка версий.
+versions-not-supported =
+    {$source} пока не поддерживает просмотр списка версий.
+    Для загрузки доступна только последняя версия каждого приложения.
+versions-error = | {$error} Пропускаем...
+versions-invalid-app-response = | Некорректный ответ о приложении {$app}. Пропускаем...
+versions-not-in-package-list = | {$app} не найдено в списке пакетов. Пропускаем...
+versions-not-found = | Не удалось найти {$app}: {$error}. Пропускаем...
+
+## Racing sources
+
+race-directory-failed = Не удалось создать каталог для загрузки {$app} из {$source}: {$error}. Пропускаем...
+race-all-failed = Не удалось загрузить {$app} ни из одного из источников. Пропускаем...
+race-move-failed = Не удалось переместить загруженное {$app} на место: {$error}. Пропускаем...
+race-won = {$app} быстрее всего загрузилось из {$source}.
+
+## Pruning
+
+prune-no-version-code = В {$path} не указан versionCode. Пропускаем...
+prune-manifest-unreadable = Не удалось прочитать манифест {$path}: {$error}. Пропускаем...
+prune-would-remove = Будет удалена версия {$version_code} {$app}
+prune-would-archive = Будет архивирована версия {$version_code} {$app}
+prune-removing = Удаление версии {$version_code} {$app}
+prune-archiving = Архивирование версии {$version_code} {$app}
+retention-removed = Удалено старых версий: {$versions} (файлов: {$files}, {$size} МиБ).
+retention-failed = Не удалось удалить старые версии: {$error}
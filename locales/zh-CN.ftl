@@ -0,0 +1,100 @@
+# 下载应用时输出的消息。参见 en.ftl。
+
+## Downloading
+
+downloading = 正在下载 {$app}...
+downloading-version = 正在下载 {$app} 版本 {$version}...
+downloading-arch = 正在下载 {$app}（架构 {$arch}）...
+downloading-version-arch = 正在下载 {$app} 版本 {$version}（架构 {$arch}）...
+downloaded = {$app} 下载成功！
+downloaded-as = 已成功下载 {$app}，保存为 {$file}
+download-retry = 下载 {$app} 时出错。第 {$attempt} 次重试...
+download-failed = 下载 {$app} 时出错。跳过...
+download-not-retried = 下载 {$app} 时出错，--retry-on 不重试此类错误：{$error}。跳过...
+source-tripped = {$source} 已连续 {$failures} 个应用下载失败，本次运行的其余部分将跳过它。
+source-skipped = 未从 {$source} 尝试下载 {$app}，该来源因连续失败过多而被跳过。跳过...
+app-timed-out = {$app} 耗时超过 {$seconds} 秒，已取消并删除未完成的文件。跳过...
+download-failed-reason = 无法从 {$source} 下载 {$app}：{$error}。跳过...
+check-failed = 无法在 {$source} 上检查 {$app}：{$error}。跳过...
+info-failed = 无法在 {$source} 上查询 {$app}：{$error}。跳过...
+download-error = 下载 {$app} 时出错：{$error}
+file-exists = {$app} 的文件已存在。跳过...
+file-up-to-date = {$app} 的文件已存在且为最新。跳过...
+file-changed = {$app} 自上次下载后已更改。正在重新下载...
+file-changed-not-replaced = {$app} 自上次下载后已更改，但无法替换：{$error}。跳过...
+split-directory-exists = {$app} 的拆分 APK 目录已存在。跳过...
+permission-denied = 没有权限写入 {$app} 的文件。跳过...
+invalid-response = {$app} 的响应无效。跳过...
+invalid-app-response = {$app} 的应用响应无效。跳过...
+not-offered-to-device = {$app} 的应用响应无效。该应用可能不向 {$device} 设备配置提供；可以用 -o device= 选择其他配置。跳过...
+no-download-url = 无法获取 {$app} 的下载链接。跳过...
+no-versions-retrieved = 无法获取 {$app} 的版本列表。{$error} 跳过...
+no-app-response = 无法读取 {$app} 的应用响应。{$error} 跳过...
+no-app-information = 无法获取 {$app} 的应用信息。{$error} 跳过...
+no-specific-versions = 无法从 {$source} 下载指定版本（{$app}）。跳过...
+not-newer = {$app} 并不比已安装或已下载的版本更新。跳过...
+too-large = {$app} 大小为 {$size}，超过了 --skip-larger-than 的限制。跳过...
+already-downloading = {$app} 已在下载到 {$file}。跳过...
+file-name-collision = {$app} 将被保存为 {$file}，与 {$other} 是同一个文件；改为保存为 {$renamed}。
+latest-version = 最新
+version-not-found = 找不到 {$app} 的{$version}版本。跳过...
+not-in-package-list = 在软件包列表中找不到 {$app}。跳过...
+searching = 正在 {$source} 上搜索 {$app}
+found-app-page = 找到应用页面：{$url}
+found-download-url = 找到下载链接：{$url}
+downloading-from = 正在从以下地址下载 APK：{$url}
+plugin-download-failed = 无法使用插件 {$plugin} 下载 {$app}：{$error}。跳过...
+
+## F-Droid
+
+fdroid-unverified = 不会验证 F-Droid 软件包索引，因此下载的应用可能已被篡改。请谨慎操作。
+fdroid-mirroring = 正在镜像仓库中的 {$count} 个应用...
+fdroid-mirroring-category = 正在镜像分类 {$category} 中的 {$count} 个应用...
+fdroid-mirroring-developer = 正在镜像开发者 {$developer} 的 {$count} 个应用...
+fdroid-fastlane-failed = 无法镜像 {$app} 的应用介绍：{$error}。跳过...
+fdroid-index-unparsable = 无法解析 F-Droid 软件包索引的 JSON。正在退出。
+fdroid-index-downloaded = 软件包索引下载成功！
+fdroid-verifying = 正在验证...
+fdroid-hash-mismatch-quarantined = {$app} 已下载，但其 sha256sum 与 F-Droid 签名的不一致。已隔离为 {$path}。跳过...
+fdroid-hash-mismatch-deleted = {$app} 已下载，但其 sha256sum 与 F-Droid 签名的不一致，因此已被删除。跳过...
+fdroid-downloading-repository = 正在下载 F-Droid 软件包仓库...
+fdroid-repository-downloaded =
+    软件包仓库下载成功！
+    正在解压...
+
+## Google Play
+
+google-play-tos-accepted = 已接受 Google Play 服务条款。
+google-play-aas-token = AAS 令牌：{$token}
+google-play-no-aas-token = 错误：无法使用所提供的 OAuth 令牌获取 AAS 令牌。请提供新的 OAuth 令牌后重试。
+
+## Listing versions
+
+versions-available = {$app} 在 {$source} 上的可用版本：
+versions-not-available = | {$source} 不提供应用的旧版本。
+versions-not-listed = {$source} 的规则未列出版本。
+versions-not-supported =
+    {$source} 目前不支持列出版本。
+    每个应用只能下载最新版本。
+versions-error = | {$error} 跳过...
+versions-invalid-app-response = | {$app} 的应用响应无效。跳过...
+versions-not-in-package-list = | 在软件包列表中找不到 {$app}。跳过...
+versions-not-found = | 找不到 {$app}：{$error}。跳过...
+
+## Racing sources
+
+race-directory-failed = 无法创建从 {$source} 下载 {$app} 的目录：{$error}。跳过...
+race-all-failed = 无法从任何参与竞速的来源下载 {$app}。跳过...
+race-move-failed = 无法将下载的 {$app} 移动到位：{$error}。跳过...
+race-won = {$app} 最先从 {$source} 获取完成。
+
+## Pruning
+
+prune-no-version-code = {$path} 未指定 versionCode。跳过...
+prune-manifest-unreadable = 无法读取 {$path} 的清单：{$error}。跳过...
+prune-would-remove = 将删除 {$app} 版本 {$version_code}
+prune-would-archive = 将归档 {$app} 版本 {$version_code}
+prune-removing = 正在删除 {$app} 版本 {$version_code}
+prune-archiving = 正在归档 {$app} 版本 {$version_code}
+retention-removed = 已删除 {$versions} 个旧版本（{$files} 个文件，{$size} MiB）。
+retention-failed = 无法删除旧版本：{$error}
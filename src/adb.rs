@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use simple_error::SimpleError;
+use tempfile::{tempdir, TempDir};
+use tokio::process::Command;
+
+use crate::device_config::DeviceConfig;
+use crate::download_sources::DownloadedApp;
+
+/// Build an `adb` command, targeting a specific device if a serial is given.
+fn adb_command(serial: Option<&str>) -> Command {
+    let mut command = Command::new("adb");
+    if let Some(serial) = serial {
+        command.arg("-s").arg(serial);
+    }
+    command
+}
+
+fn adb_error(err: io::Error) -> Box<dyn Error> {
+    if err.kind() == io::ErrorKind::NotFound {
+        Box::new(SimpleError::new("adb could not be found. Make sure the Android platform tools are installed and in your PATH."))
+    } else {
+        Box::new(err)
+    }
+}
+
+/// List the serials of all devices adb reports as connected and authorized.
+pub async fn devices() -> Result<Vec<String>, Box<dyn Error>> {
+    let output = adb_command(None).arg("devices").output().await.map_err(adb_error)?;
+    if !output.status.success() {
+        return Err(Box::new(SimpleError::new("adb could not list connected devices.")));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .skip(1)
+        .filter_map(|line| match line.split_once('\t') {
+            Some((serial, "device")) => Some(serial.to_string()),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Resolve the devices given with `--device` to the serials to act on.  No devices means adb's
+/// default device, and `all` means every connected device.
+pub async fn select_devices(requested: Vec<String>) -> Result<Vec<Option<String>>, Box<dyn Error>> {
+    if requested.is_empty() {
+        Ok(vec![None])
+    } else if requested.iter().any(|serial| serial == "all") {
+        let devices = devices().await?;
+        if devices.is_empty() {
+            return Err(Box::new(SimpleError::new("No connected devices were found.")));
+        }
+        Ok(devices.into_iter().map(Some).collect())
+    } else {
+        Ok(requested.into_iter().map(Some).collect())
+    }
+}
+
+/// Query the versionCode of every package installed on the given device.
+pub async fn installed_version_codes(serial: Option<&str>) -> Result<HashMap<String, u64>, Box<dyn Error>> {
+    let output = adb_command(serial)
+        .args(["shell", "pm", "list", "packages", "--show-versioncode"])
+        .output().await.map_err(adb_error)?;
+    if !output.status.success() {
+        return Err(Box::new(SimpleError::new("adb could not list the packages installed on the device.")));
+    }
+    // Each line looks like `package:org.example.app versionCode:1234`
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (package, version_code) = line.trim().strip_prefix("package:")?.split_once(" versionCode:")?;
+            Some((package.to_string(), version_code.trim().parse().ok()?))
+        })
+        .collect())
+}
+
+/// List the third-party (user-installed) packages on the given device.
+pub async fn third_party_packages(serial: Option<&str>) -> Result<Vec<String>, Box<dyn Error>> {
+    let output = adb_command(serial)
+        .args(["shell", "pm", "list", "packages", "-3"])
+        .output().await.map_err(adb_error)?;
+    if !output.status.success() {
+        return Err(Box::new(SimpleError::new("adb could not list the packages installed on the device.")));
+    }
+    let mut packages = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("package:").map(String::from))
+        .collect::<Vec<String>>();
+    packages.sort();
+    Ok(packages)
+}
+
+/// Run a shell command on the given device and return its trimmed output.
+async fn shell(serial: Option<&str>, args: &[&str]) -> Result<String, Box<dyn Error>> {
+    let output = adb_command(serial).arg("shell").args(args).output().await.map_err(adb_error)?;
+    if !output.status.success() {
+        return Err(Box::new(SimpleError::new(format!("adb could not run `{}` on the device.", args.join(" ")))));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Read the ABIs, screen density, locales and SDK level of the given device.
+pub async fn device_config(serial: Option<&str>) -> Result<DeviceConfig, Box<dyn Error>> {
+    let abis = shell(serial, &["getprop", "ro.product.cpu.abilist"]).await?
+        .split(',').filter(|abi| !abi.is_empty()).map(String::from).collect();
+    // `wm density` prints the physical density, followed by any override in effect
+    let density = shell(serial, &["wm", "density"]).await?
+        .lines()
+        .filter_map(|line| line.rsplit(':').next()?.trim().parse().ok())
+        .last()
+        .ok_or_else(|| SimpleError::new("adb did not report the screen density of the device."))?;
+    let mut locales = shell(serial, &["getprop", "persist.sys.locale"]).await?;
+    if locales.is_empty() {
+        locales = shell(serial, &["getprop", "ro.product.locale"]).await?;
+    }
+    let sdk = shell(serial, &["getprop", "ro.build.version.sdk"]).await?
+        .parse()
+        .map_err(|_| SimpleError::new("adb did not report the SDK level of the device."))?;
+    Ok(DeviceConfig {
+        abis,
+        density,
+        locales: locales.split(',').filter(|locale| !locale.is_empty()).map(String::from).collect(),
+        sdk,
+    })
+}
+
+/// Install a downloaded app on the given device.  Apps made up of several split APKs are
+/// installed with `adb install-multiple`, and XAPK bundles are unpacked first.
+pub async fn install(app: &DownloadedApp, serial: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let temp_dir = tempdir()?;
+    let apks = installable_apks(&app.files, &temp_dir)?;
+    if apks.is_empty() {
+        return Err(Box::new(SimpleError::new("No APK files to install.")));
+    }
+
+    let mut command = adb_command(serial);
+    if apks.len() > 1 {
+        command.arg("install-multiple");
+    } else {
+        command.arg("install");
+    }
+    command.arg("-r").args(&apks);
+    let output = command.output().await.map_err(adb_error)?;
+
+    // Older versions of adb exit successfully even when the install fails, so check the output
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if output.status.success() && !stdout.contains("Failure") {
+        Ok(())
+    } else {
+        let reason = stdout.lines().chain(stderr.lines())
+            .find(|line| line.contains("Failure") || line.starts_with("adb: "))
+            .unwrap_or("adb exited unsuccessfully")
+            .trim()
+            .to_string();
+        Err(Box::new(SimpleError::new(reason)))
+    }
+}
+
+fn installable_apks(files: &[PathBuf], temp_dir: &TempDir) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut apks = vec![];
+    for file in files {
+        match file.extension().and_then(|ext| ext.to_str()) {
+            Some("apk") => apks.push(file.clone()),
+            Some("xapk") => apks.append(&mut unpack_xapk(file, temp_dir)?),
+            _ => {},
+        }
+    }
+    Ok(apks)
+}
+
+/// An XAPK is a zip containing the base and split APKs (and possibly `obb` files) at its root.
+fn unpack_xapk(xapk: &Path, temp_dir: &TempDir) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut archive = zip::ZipArchive::new(File::open(xapk)?)?;
+    let mut apks = vec![];
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        let name = match file.enclosed_name() {
+            Some(name) if name.extension().is_some_and(|ext| ext == "apk") && name.components().count() == 1 => name,
+            _ => continue,
+        };
+        let outpath = temp_dir.path().join(name);
+        io::copy(&mut file, &mut File::create(&outpath)?)?;
+        apks.push(outpath);
+    }
+    Ok(apks)
+}
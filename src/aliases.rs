@@ -0,0 +1,72 @@
+//! Friendly names for apps, such as `signal` for `org.thoughtcrime.securesms`, so that lists of
+//! apps kept by hand needn't spell out package names, and an app which changes its package name
+//! only has to be updated in one place.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use simple_error::SimpleError;
+
+use crate::config;
+use crate::util;
+
+const ALIASES_FILE: &str = "aliases.txt";
+
+pub struct Aliases {
+    aliases: HashMap<String, String>,
+}
+
+impl Aliases {
+    /// The aliases in `aliases.txt` in the config directory, and those in the file given with
+    /// `--aliases`, which take precedence.
+    pub fn load(extra: Option<&Path>) -> Result<Aliases, Box<dyn Error>> {
+        let mut aliases = Aliases { aliases: HashMap::new() };
+        if let Ok(config_dir) = config::config_dir() {
+            let user_file = config_dir.join(ALIASES_FILE);
+            if user_file.is_file() {
+                aliases.add(&fs::read_to_string(&user_file)?)
+                    .map_err(|err| SimpleError::new(format!("{}: {}", user_file.display(), err)))?;
+            }
+        }
+        if let Some(extra) = extra {
+            aliases.add(&fs::read_to_string(extra)?)
+                .map_err(|err| SimpleError::new(format!("{}: {}", extra.display(), err)))?;
+        }
+        Ok(aliases)
+    }
+
+    /// Read `name = package` lines, ignoring blank lines and those starting with `#`.  Names are
+    /// matched regardless of case.
+    fn add(&mut self, text: &str) -> Result<(), SimpleError> {
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, package) = line.split_once('=')
+                .ok_or_else(|| SimpleError::new(format!("line {} is not of the form name = package", i + 1)))?;
+            let (name, package) = (name.trim(), package.trim());
+            if name.is_empty() {
+                return Err(SimpleError::new(format!("line {} does not give a name", i + 1)));
+            }
+            if !util::is_app_id(package) {
+                return Err(SimpleError::new(format!("line {} does not give an app ID for {}", i + 1, name)));
+            }
+            self.aliases.insert(name.to_lowercase(), package.to_string());
+        }
+        Ok(())
+    }
+
+    /// Replace the names in a list of apps with the app IDs they are aliases for, keeping the
+    /// versions asked for.
+    pub fn resolve(&self, list: Vec<(String, Option<String>)>) -> Vec<(String, Option<String>)> {
+        list.into_iter().map(|(app, version)| {
+            match self.aliases.get(&app.to_lowercase()) {
+                Some(app_id) => (app_id.to_string(), version),
+                None => (app, version),
+            }
+        }).collect()
+    }
+}
@@ -0,0 +1,123 @@
+//! Comparing two versions of an app for `apkeep diff`: the entries added, removed and changed,
+//! the change in size, the permissions requested, and the trackers whose classes appear, judged
+//! by the package names of those classes, as Exodus Privacy does.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use crate::apk;
+
+/// Refuse to decompress a `.dex` larger than this from an untrusted archive.
+const MAX_DEX_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Trackers, and the prefix of the class names by which each is recognised, in the form type
+/// descriptors take in `.dex` files.
+const TRACKERS: [(&str, &str); 16] = [
+    ("Adjust", "Lcom/adjust/sdk/"),
+    ("Amplitude", "Lcom/amplitude/"),
+    ("AppsFlyer", "Lcom/appsflyer/"),
+    ("Branch", "Lio/branch/"),
+    ("Braze", "Lcom/braze/"),
+    ("Facebook Analytics", "Lcom/facebook/appevents/"),
+    ("Facebook Login", "Lcom/facebook/login/"),
+    ("Firebase Analytics", "Lcom/google/firebase/analytics/"),
+    ("Flurry", "Lcom/flurry/"),
+    ("Google AdMob", "Lcom/google/android/gms/ads/"),
+    ("Google Crashlytics", "Lcom/google/firebase/crashlytics/"),
+    ("Microsoft Visual Studio App Center", "Lcom/microsoft/appcenter/"),
+    ("Mixpanel", "Lcom/mixpanel/"),
+    ("OneSignal", "Lcom/onesignal/"),
+    ("Segment", "Lcom/segment/analytics/"),
+    ("Sentry", "Lio/sentry/"),
+];
+
+/// What an APK holds, as far as a comparison is concerned
+struct Contents {
+    /// The CRC and uncompressed size of each entry
+    entries: BTreeMap<String, (u32, u64)>,
+    size: u64,
+    /// The permissions requested, if the manifest could be read
+    permissions: Option<BTreeSet<String>>,
+    trackers: BTreeSet<&'static str>,
+}
+
+pub struct Diff {
+    pub added: Vec<(String, u64)>,
+    pub removed: Vec<(String, u64)>,
+    /// Changed entries, with their sizes before and after
+    pub changed: Vec<(String, u64, u64)>,
+    /// The sizes of the two files
+    pub sizes: (u64, u64),
+    /// Whether both manifests could be read, so that permissions could be compared
+    pub permissions_compared: bool,
+    pub permissions_added: Vec<String>,
+    pub permissions_removed: Vec<String>,
+    pub trackers_added: Vec<&'static str>,
+    pub trackers_removed: Vec<&'static str>,
+}
+
+fn read(path: &Path) -> Result<Contents, Box<dyn Error>> {
+    let permissions = apk::read_manifest(path).ok().map(|manifest| manifest.permissions.into_iter().collect());
+    let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+    let mut entries = BTreeMap::new();
+    let mut trackers = BTreeSet::new();
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+        if entry.is_dir() {
+            continue;
+        }
+        let name = entry.name().to_string();
+        entries.insert(name.clone(), (entry.crc32(), entry.size()));
+        if name.starts_with("classes") && name.ends_with(".dex") && !name.contains('/') {
+            let mut dex = vec![];
+            (&mut entry).take(MAX_DEX_SIZE).read_to_end(&mut dex)?;
+            for (tracker, prefix) in TRACKERS {
+                if dex.windows(prefix.len()).any(|window| window == prefix.as_bytes()) {
+                    trackers.insert(tracker);
+                }
+            }
+        }
+    }
+    Ok(Contents {
+        entries,
+        size: std::fs::metadata(path)?.len(),
+        permissions,
+        trackers,
+    })
+}
+
+/// Compare the APKs at `old` and `new`.
+pub fn diff(old: &Path, new: &Path) -> Result<Diff, Box<dyn Error>> {
+    let (old, new) = (read(old)?, read(new)?);
+    let (old_permissions, new_permissions) = match (&old.permissions, &new.permissions) {
+        (Some(old_permissions), Some(new_permissions)) => (old_permissions.clone(), new_permissions.clone()),
+        _ => Default::default(),
+    };
+    let mut diff = Diff {
+        added: vec![],
+        removed: vec![],
+        changed: vec![],
+        sizes: (old.size, new.size),
+        permissions_compared: old.permissions.is_some() && new.permissions.is_some(),
+        permissions_added: new_permissions.difference(&old_permissions).cloned().collect(),
+        permissions_removed: old_permissions.difference(&new_permissions).cloned().collect(),
+        trackers_added: new.trackers.difference(&old.trackers).copied().collect(),
+        trackers_removed: old.trackers.difference(&new.trackers).copied().collect(),
+    };
+    for (name, (crc, size)) in &new.entries {
+        match old.entries.get(name) {
+            None => diff.added.push((name.clone(), *size)),
+            Some((old_crc, old_size)) if old_crc != crc || old_size != size => diff.changed.push((name.clone(), *old_size, *size)),
+            Some(_) => {},
+        }
+    }
+    for (name, (_, size)) in &old.entries {
+        if !new.entries.contains_key(name) {
+            diff.removed.push((name.clone(), *size));
+        }
+    }
+    Ok(diff)
+}
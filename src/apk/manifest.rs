@@ -0,0 +1,272 @@
+//! A minimal parser for the binary XML format `AndroidManifest.xml` is compiled to inside an APK.
+//! Only the handful of elements and attributes apkeep cares about are extracted.
+
+use std::error::Error;
+
+use simple_error::SimpleError;
+
+const RES_STRING_POOL_TYPE: u16 = 0x0001;
+const RES_XML_TYPE: u16 = 0x0003;
+const RES_XML_START_ELEMENT_TYPE: u16 = 0x0102;
+const RES_XML_RESOURCE_MAP_TYPE: u16 = 0x0180;
+
+const UTF8_FLAG: u32 = 1 << 8;
+const NO_VALUE: u32 = 0xffffffff;
+
+const TYPE_REFERENCE: u8 = 0x01;
+const TYPE_STRING: u8 = 0x03;
+const TYPE_INT_DEC: u8 = 0x10;
+const TYPE_INT_HEX: u8 = 0x11;
+const TYPE_INT_BOOLEAN: u8 = 0x12;
+
+// Resource ids of the `android:` attributes we read, used when attribute names are obfuscated
+const ATTR_LABEL: u32 = 0x01010001;
+const ATTR_NAME: u32 = 0x01010003;
+const ATTR_MIN_SDK_VERSION: u32 = 0x0101020c;
+const ATTR_VERSION_CODE: u32 = 0x0101021b;
+const ATTR_VERSION_NAME: u32 = 0x0101021c;
+const ATTR_TARGET_SDK_VERSION: u32 = 0x01010270;
+const ATTR_REQUIRED: u32 = 0x0101028e;
+const ATTR_VERSION_CODE_MAJOR: u32 = 0x01010576;
+
+#[derive(Debug, Default, Clone)]
+pub struct Manifest {
+    pub package: String,
+    pub version_code: Option<u64>,
+    pub version_name: Option<String>,
+    /// The name of the split, if this is a split APK rather than a base APK
+    pub split: Option<String>,
+    pub min_sdk_version: Option<u32>,
+    pub target_sdk_version: Option<u32>,
+    /// The application label, if it is given literally rather than as a resource reference
+    pub label: Option<String>,
+    pub permissions: Vec<String>,
+    /// The hardware and software features the app requires
+    pub features: Vec<String>,
+}
+
+enum AttributeValue {
+    String(String),
+    Integer(u32),
+    Boolean(bool),
+    Reference,
+    Other,
+}
+
+struct Attribute {
+    name: String,
+    resource_id: Option<u32>,
+    value: AttributeValue,
+}
+
+impl Attribute {
+    fn is(&self, name: &str, resource_id: u32) -> bool {
+        self.resource_id == Some(resource_id) || self.name == name
+    }
+
+    fn as_string(&self) -> Option<String> {
+        match &self.value {
+            AttributeValue::String(value) => Some(value.clone()),
+            AttributeValue::Integer(value) => Some(value.to_string()),
+            AttributeValue::Boolean(value) => Some(value.to_string()),
+            AttributeValue::Reference | AttributeValue::Other => None,
+        }
+    }
+
+    fn as_integer(&self) -> Option<u32> {
+        match &self.value {
+            AttributeValue::Integer(value) => Some(*value),
+            AttributeValue::String(value) => value.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+fn truncated() -> Box<dyn Error> {
+    Box::new(SimpleError::new("Binary XML is truncated."))
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Result<u16, Box<dyn Error>> {
+    let bytes = data.get(offset..offset + 2).ok_or_else(truncated)?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32, Box<dyn Error>> {
+    let bytes = data.get(offset..offset + 4).ok_or_else(truncated)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn u8_at(data: &[u8], offset: usize) -> Result<u8, Box<dyn Error>> {
+    data.get(offset).copied().ok_or_else(truncated)
+}
+
+fn utf8_string(chunk: &[u8], mut offset: usize) -> Result<String, Box<dyn Error>> {
+    // The length in UTF-16 code units comes first, then the length in bytes; each takes one byte,
+    // or two if the high bit is set
+    offset += if u8_at(chunk, offset)? & 0x80 != 0 { 2 } else { 1 };
+    let mut length = u8_at(chunk, offset)? as usize;
+    if length & 0x80 != 0 {
+        length = ((length & 0x7f) << 8) | u8_at(chunk, offset + 1)? as usize;
+        offset += 2;
+    } else {
+        offset += 1;
+    }
+    let bytes = chunk.get(offset..offset + length).ok_or_else(truncated)?;
+    Ok(String::from_utf8_lossy(bytes).to_string())
+}
+
+fn utf16_string(chunk: &[u8], mut offset: usize) -> Result<String, Box<dyn Error>> {
+    let mut length = u16_at(chunk, offset)? as usize;
+    offset += 2;
+    if length & 0x8000 != 0 {
+        length = ((length & 0x7fff) << 16) | u16_at(chunk, offset)? as usize;
+        offset += 2;
+    }
+    let bytes = chunk.get(offset..offset + length * 2).ok_or_else(truncated)?;
+    let units = bytes.chunks_exact(2).map(|unit| u16::from_le_bytes([unit[0], unit[1]])).collect::<Vec<u16>>();
+    Ok(String::from_utf16_lossy(&units))
+}
+
+fn parse_string_pool(chunk: &[u8]) -> Result<Vec<String>, Box<dyn Error>> {
+    let header_size = u16_at(chunk, 2)? as usize;
+    let string_count = u32_at(chunk, 8)? as usize;
+    let flags = u32_at(chunk, 16)?;
+    let strings_start = u32_at(chunk, 20)? as usize;
+    let mut strings = vec![];
+    for i in 0..string_count {
+        let offset = strings_start + u32_at(chunk, header_size + i * 4)? as usize;
+        strings.push(if flags & UTF8_FLAG != 0 {
+            utf8_string(chunk, offset)?
+        } else {
+            utf16_string(chunk, offset)?
+        });
+    }
+    Ok(strings)
+}
+
+fn string_at(strings: &[String], index: u32) -> String {
+    strings.get(index as usize).cloned().unwrap_or_default()
+}
+
+fn parse_start_element(chunk: &[u8], strings: &[String], resource_ids: &[u32]) -> Result<(String, Vec<Attribute>), Box<dyn Error>> {
+    let ext = u16_at(chunk, 2)? as usize;
+    let name = string_at(strings, u32_at(chunk, ext + 4)?);
+    let attribute_start = u16_at(chunk, ext + 8)? as usize;
+    let attribute_size = u16_at(chunk, ext + 10)? as usize;
+    let attribute_count = u16_at(chunk, ext + 12)? as usize;
+
+    let mut attributes = vec![];
+    for i in 0..attribute_count {
+        let offset = ext + attribute_start + i * attribute_size;
+        let name_index = u32_at(chunk, offset + 4)?;
+        let raw_value = u32_at(chunk, offset + 8)?;
+        let data_type = u8_at(chunk, offset + 15)?;
+        let data = u32_at(chunk, offset + 16)?;
+        let value = if raw_value != NO_VALUE {
+            AttributeValue::String(string_at(strings, raw_value))
+        } else {
+            match data_type {
+                TYPE_STRING => AttributeValue::String(string_at(strings, data)),
+                TYPE_INT_DEC | TYPE_INT_HEX => AttributeValue::Integer(data),
+                TYPE_INT_BOOLEAN => AttributeValue::Boolean(data != 0),
+                TYPE_REFERENCE => AttributeValue::Reference,
+                _ => AttributeValue::Other,
+            }
+        };
+        attributes.push(Attribute {
+            name: string_at(strings, name_index),
+            resource_id: resource_ids.get(name_index as usize).copied(),
+            value,
+        });
+    }
+    Ok((name, attributes))
+}
+
+/// Parse the contents of a binary `AndroidManifest.xml`.
+pub fn parse(data: &[u8]) -> Result<Manifest, Box<dyn Error>> {
+    if u16_at(data, 0)? != RES_XML_TYPE {
+        return Err(Box::new(SimpleError::new("AndroidManifest.xml is not in binary XML format.")));
+    }
+
+    let mut manifest = Manifest::default();
+    let mut version_code_major = 0;
+    let mut strings = vec![];
+    let mut resource_ids = vec![];
+    let mut offset = u16_at(data, 2)? as usize;
+    while offset + 8 <= data.len() {
+        let chunk_type = u16_at(data, offset)?;
+        let chunk_size = u32_at(data, offset + 4)? as usize;
+        if chunk_size < 8 {
+            return Err(Box::new(SimpleError::new("Binary XML contains a malformed chunk.")));
+        }
+        let chunk = data.get(offset..offset + chunk_size).ok_or_else(truncated)?;
+        match chunk_type {
+            RES_STRING_POOL_TYPE => strings = parse_string_pool(chunk)?,
+            RES_XML_RESOURCE_MAP_TYPE => {
+                let header_size = u16_at(chunk, 2)? as usize;
+                resource_ids = chunk.get(header_size..).ok_or_else(truncated)?
+                    .chunks_exact(4)
+                    .map(|id| u32::from_le_bytes([id[0], id[1], id[2], id[3]]))
+                    .collect();
+            },
+            RES_XML_START_ELEMENT_TYPE => {
+                let (name, attributes) = parse_start_element(chunk, &strings, &resource_ids)?;
+                if name == "uses-feature" {
+                    let feature = attributes.iter().find(|attribute| attribute.is("name", ATTR_NAME)).and_then(Attribute::as_string);
+                    let optional = attributes.iter()
+                        .any(|attribute| attribute.is("required", ATTR_REQUIRED) && matches!(attribute.value, AttributeValue::Boolean(false)));
+                    if let (Some(feature), false) = (feature, optional) {
+                        manifest.features.push(feature);
+                    }
+                }
+                for attribute in attributes {
+                    match name.as_str() {
+                        "manifest" => {
+                            if attribute.name == "package" {
+                                manifest.package = attribute.as_string().unwrap_or_default();
+                            } else if attribute.name == "split" {
+                                manifest.split = attribute.as_string();
+                            } else if attribute.is("versionCode", ATTR_VERSION_CODE) {
+                                manifest.version_code = attribute.as_integer().map(u64::from);
+                            } else if attribute.is("versionCodeMajor", ATTR_VERSION_CODE_MAJOR) {
+                                version_code_major = attribute.as_integer().unwrap_or(0);
+                            } else if attribute.is("versionName", ATTR_VERSION_NAME) {
+                                manifest.version_name = attribute.as_string();
+                            }
+                        },
+                        "uses-sdk" => {
+                            if attribute.is("minSdkVersion", ATTR_MIN_SDK_VERSION) {
+                                manifest.min_sdk_version = attribute.as_integer();
+                            } else if attribute.is("targetSdkVersion", ATTR_TARGET_SDK_VERSION) {
+                                manifest.target_sdk_version = attribute.as_integer();
+                            }
+                        },
+                        "uses-permission" | "uses-permission-sdk-23" => {
+                            if attribute.is("name", ATTR_NAME) {
+                                if let Some(permission) = attribute.as_string() {
+                                    manifest.permissions.push(permission);
+                                }
+                            }
+                        },
+                        "application" => {
+                            if attribute.is("label", ATTR_LABEL) {
+                                manifest.label = attribute.as_string();
+                            }
+                        },
+                        _ => {},
+                    }
+                }
+            },
+            _ => {},
+        }
+        offset += chunk_size;
+    }
+
+    if manifest.package.is_empty() {
+        return Err(Box::new(SimpleError::new("AndroidManifest.xml does not specify a package.")));
+    }
+    if version_code_major > 0 {
+        manifest.version_code = Some((u64::from(version_code_major) << 32) | manifest.version_code.unwrap_or(0));
+    }
+    Ok(manifest)
+}
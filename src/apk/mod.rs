@@ -0,0 +1,75 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use serde_json::Value;
+use simple_error::SimpleError;
+
+use crate::cli::FormFactor;
+
+pub mod diff;
+pub mod manifest;
+pub mod signature;
+pub use manifest::Manifest;
+
+/// Manifests are small; refuse to decompress anything larger than this from an untrusted archive.
+const MAX_MANIFEST_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Read the manifest of an APK, or of the base APK described by an XAPK bundle.
+pub fn read_manifest(path: &Path) -> Result<Manifest, Box<dyn Error>> {
+    let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+    if path.extension().is_some_and(|ext| ext == "xapk") {
+        let mut data = String::new();
+        archive.by_name("manifest.json")?.take(MAX_MANIFEST_SIZE).read_to_string(&mut data)?;
+        return manifest_from_xapk_json(&data);
+    }
+    let mut data = vec![];
+    archive.by_name("AndroidManifest.xml")?.take(MAX_MANIFEST_SIZE).read_to_end(&mut data)?;
+    manifest::parse(&data)
+}
+
+/// The kind of device a build requiring `features` is for, judged by the features which only
+/// watches, cars and TVs have.
+pub fn form_factor<S: AsRef<str>>(features: &[S]) -> FormFactor {
+    let requires = |feature: &str| features.iter().any(|required| required.as_ref() == feature);
+    if requires("android.hardware.type.watch") {
+        FormFactor::Wear
+    } else if requires("android.hardware.type.automotive") {
+        FormFactor::Auto
+    } else if requires("android.software.leanback") || requires("android.hardware.type.television") {
+        FormFactor::Tv
+    } else {
+        FormFactor::Phone
+    }
+}
+
+/// XAPK bundles describe their contents in a `manifest.json`, in which numbers are often given as
+/// strings.
+fn manifest_from_xapk_json(data: &str) -> Result<Manifest, Box<dyn Error>> {
+    let json: Value = serde_json::from_str(data)?;
+    let string_field = |key: &str| match json.get(key) {
+        Some(Value::String(value)) => Some(value.to_string()),
+        Some(Value::Number(value)) => Some(value.to_string()),
+        _ => None,
+    };
+    let package = string_field("package_name")
+        .ok_or_else(|| SimpleError::new("XAPK manifest does not specify a package."))?;
+    Ok(Manifest {
+        package,
+        version_code: string_field("version_code").and_then(|code| code.parse().ok()),
+        version_name: string_field("version_name"),
+        split: None,
+        min_sdk_version: string_field("min_sdk_version").and_then(|sdk| sdk.parse().ok()),
+        target_sdk_version: string_field("target_sdk_version").and_then(|sdk| sdk.parse().ok()),
+        label: string_field("name"),
+        permissions: match json.get("permissions") {
+            Some(Value::Array(permissions)) => permissions.iter()
+                .filter_map(|permission| permission.as_str().map(String::from))
+                .collect(),
+            _ => vec![],
+        },
+        // Not listed in the bundle's manifest
+        features: vec![],
+    })
+}
@@ -0,0 +1,411 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use cryptographic_message_syntax::SignedData;
+use ring::digest;
+use ring::signature::{self, UnparsedPublicKey, VerificationAlgorithm};
+use sha2::{Digest, Sha256};
+use simple_error::SimpleError;
+
+use crate::patterns;
+
+/// Signature block files only hold a few certificates; anything larger is not worth parsing.
+const MAX_SIGNATURE_BLOCK_SIZE: u64 = 1024 * 1024;
+
+const EOCD_SIGNATURE: u32 = 0x06054b50;
+const EOCD_SIZE: usize = 22;
+const MAX_ZIP_COMMENT_SIZE: usize = 0xffff;
+const APK_SIGNING_BLOCK_MAGIC: &[u8] = b"APK Sig Block 42";
+/// Signing blocks hold a few certificates and signatures; anything larger is not worth reading.
+const MAX_SIGNING_BLOCK_SIZE: u64 = 16 * 1024 * 1024;
+const SIGNATURE_SCHEME_V2_BLOCK_ID: u32 = 0x7109871a;
+const SIGNATURE_SCHEME_V3_BLOCK_ID: u32 = 0xf05368c0;
+/// The contents of an APK are digested in chunks of this size, and the chunk digests digested
+const CONTENT_DIGEST_CHUNK_SIZE: usize = 1024 * 1024;
+
+const RSA_ENCRYPTION_OID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+const EC_PUBLIC_KEY_OID: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+const P256_OID: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+const P384_OID: &[u8] = &[0x2b, 0x81, 0x04, 0x00, 0x22];
+
+/// The hex-encoded SHA-256 fingerprints of an APK's signers, taken from its verified v3 or v2
+/// signature if it has one, or else from its v1 signature.
+pub fn signer_fingerprints(path: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    match verify_v2_v3(path)? {
+        Some(verified) => Ok(verified.signers),
+        None => v1_signer_fingerprints(path),
+    }
+}
+
+/// The hex-encoded SHA-256 fingerprints of the certificates in an APK's v1 (JAR) signature.
+pub fn v1_signer_fingerprints(path: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+    let mut fingerprints = vec![];
+    for i in 0..archive.len() {
+        let file = archive.by_index(i)?;
+        if !patterns::SIGNATURE_BLOCK_FILE.is_match(file.name()) {
+            continue;
+        }
+        let mut data = vec![];
+        file.take(MAX_SIGNATURE_BLOCK_SIZE).read_to_end(&mut data)?;
+        let signed_data = SignedData::parse_ber(&data)?;
+        for cert in signed_data.certificates() {
+            fingerprints.push(hex::encode(Sha256::digest(cert.encode_der()?)));
+        }
+    }
+    Ok(fingerprints)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Scheme {
+    V2,
+    V3,
+}
+
+impl fmt::Display for Scheme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Scheme::V2 => write!(f, "v2"),
+            Scheme::V3 => write!(f, "v3"),
+        }
+    }
+}
+
+/// An APK whose v2 or v3 signature checked out: the signatures are valid for their public keys,
+/// the keys are those of the certificates, and the signed digests match the archive's contents.
+pub struct Verified {
+    pub scheme: Scheme,
+    /// The hex-encoded SHA-256 fingerprint of each signer's certificate
+    pub signers: Vec<String>,
+}
+
+/// Verify an APK's APK Signature Scheme v3 signature, or its v2 signature if it has no v3 one,
+/// as Android does on install.  Returns None for an APK with neither, which can only rely on its
+/// v1 (JAR) signature.
+pub fn verify_v2_v3(path: &Path) -> Result<Option<Verified>, Box<dyn Error>> {
+    let mut file = File::open(path)?;
+    let file_size = file.metadata()?.len();
+    if file_size < EOCD_SIZE as u64 {
+        return Err(Box::new(SimpleError::new("not a ZIP archive")));
+    }
+
+    // The End of Central Directory record is at the very end, unless followed by a comment
+    let tail_size = file_size.min((EOCD_SIZE + MAX_ZIP_COMMENT_SIZE) as u64) as usize;
+    let mut tail = vec![0; tail_size];
+    file.seek(SeekFrom::End(-(tail_size as i64)))?;
+    file.read_exact(&mut tail)?;
+    let eocd_position = (0..=tail_size.saturating_sub(EOCD_SIZE)).rev()
+        .find(|&i| match (tail.get(i..i + 4), tail.get(i + 20..i + EOCD_SIZE)) {
+            (Some(signature), Some(comment_size)) => {
+                le_u32(signature) == EOCD_SIGNATURE && le_u16(comment_size) as usize == tail_size - i - EOCD_SIZE
+            },
+            _ => false,
+        })
+        .ok_or_else(|| SimpleError::new("not a ZIP archive"))?;
+    let mut eocd = tail[eocd_position..].to_vec();
+    let eocd_offset = file_size - (tail_size - eocd_position) as u64;
+    let central_directory_offset = le_u32(&eocd[16..]) as u64;
+    if central_directory_offset > eocd_offset {
+        return Err(Box::new(SimpleError::new("the central directory is out of bounds")));
+    }
+
+    // The signing block sits immediately before the central directory, ending with its size and
+    // a magic value
+    if central_directory_offset < 32 {
+        return Ok(None);
+    }
+    let mut footer = [0; 24];
+    file.seek(SeekFrom::Start(central_directory_offset - 24))?;
+    file.read_exact(&mut footer)?;
+    if &footer[8..] != APK_SIGNING_BLOCK_MAGIC {
+        return Ok(None);
+    }
+    let block_size = le_u64(&footer);
+    if block_size > MAX_SIGNING_BLOCK_SIZE || block_size + 8 > central_directory_offset || block_size < 24 {
+        return Err(Box::new(SimpleError::new("the APK Signing Block has an invalid size")));
+    }
+    let block_offset = central_directory_offset - block_size - 8;
+    let mut block = vec![0; block_size as usize + 8];
+    file.seek(SeekFrom::Start(block_offset))?;
+    file.read_exact(&mut block)?;
+    if le_u64(&block) != block_size {
+        return Err(Box::new(SimpleError::new("the APK Signing Block sizes do not match")));
+    }
+
+    let mut pairs = Reader(&block[8..block.len() - 24]);
+    let (mut v2_block, mut v3_block) = (None, None);
+    while !pairs.0.is_empty() {
+        let pair_size = pairs.u64()? as usize;
+        let mut pair = Reader(pairs.take(pair_size)?);
+        match pair.u32()? {
+            SIGNATURE_SCHEME_V2_BLOCK_ID => v2_block = Some(pair.0),
+            SIGNATURE_SCHEME_V3_BLOCK_ID => v3_block = Some(pair.0),
+            _ => {},
+        }
+    }
+    let (scheme, scheme_block) = match (v3_block, v2_block) {
+        (Some(v3_block), _) => (Scheme::V3, v3_block),
+        (None, Some(v2_block)) => (Scheme::V2, v2_block),
+        (None, None) => return Ok(None),
+    };
+
+    let mut expected_digests: Vec<(&'static digest::Algorithm, Vec<u8>)> = vec![];
+    let mut signers = vec![];
+    let mut signer_list = Reader(Reader(scheme_block).prefixed()?);
+    while !signer_list.0.is_empty() {
+        let mut signer = Reader(signer_list.prefixed()?);
+        let signed_data = signer.prefixed()?;
+        if scheme == Scheme::V3 {
+            signer.u32()?; // minSdkVersion
+            signer.u32()?; // maxSdkVersion
+        }
+        let mut signatures = Reader(signer.prefixed()?);
+        let public_key = signer.prefixed()?;
+
+        // Check every signature apkeep knows how to, requiring at least one
+        let mut verified_algorithms = vec![];
+        while !signatures.0.is_empty() {
+            let mut signature = Reader(signatures.prefixed()?);
+            let algorithm_id = signature.u32()?;
+            let signature = signature.prefixed()?;
+            if let Some((verification_algorithm, key)) = verification_algorithm(algorithm_id, public_key) {
+                UnparsedPublicKey::new(verification_algorithm, key).verify(signed_data, signature)
+                    .map_err(|_| SimpleError::new(format!("the {} signature is invalid", scheme)))?;
+                verified_algorithms.push(algorithm_id);
+            }
+        }
+        if verified_algorithms.is_empty() {
+            return Err(Box::new(SimpleError::new(format!("the {} signature uses no algorithm apkeep can check", scheme))));
+        }
+
+        let mut signed_data = Reader(signed_data);
+        let mut digests = Reader(signed_data.prefixed()?);
+        let mut certificates = Reader(signed_data.prefixed()?);
+        let certificate = certificates.prefixed()
+            .map_err(|_| SimpleError::new(format!("the {} signature has no certificate", scheme)))?;
+        if certificate_public_key(certificate) != Some(public_key) {
+            return Err(Box::new(SimpleError::new(format!("the {} signature's public key does not match its certificate", scheme))));
+        }
+        signers.push(hex::encode(Sha256::digest(certificate)));
+
+        while !digests.0.is_empty() {
+            let mut digest = Reader(digests.prefixed()?);
+            let algorithm_id = digest.u32()?;
+            let digest = digest.prefixed()?;
+            if verified_algorithms.contains(&algorithm_id) {
+                if let Some(digest_algorithm) = content_digest_algorithm(algorithm_id) {
+                    expected_digests.push((digest_algorithm, digest.to_vec()));
+                }
+            }
+        }
+    }
+    if signers.is_empty() {
+        return Err(Box::new(SimpleError::new(format!("the {} signature has no signers", scheme))));
+    }
+
+    // The digested End of Central Directory record points at where the central directory would
+    // be without the signing block
+    eocd[16..20].copy_from_slice(&(block_offset as u32).to_le_bytes());
+    for (algorithm, expected) in &expected_digests {
+        let actual = content_digest(&mut file, algorithm, &[(0, block_offset), (central_directory_offset, eocd_offset)], &eocd)?;
+        if actual.as_ref() != expected.as_slice() {
+            return Err(Box::new(SimpleError::new(format!("the contents do not match the {} signature; the APK has been modified or is incomplete", scheme))));
+        }
+    }
+    Ok(Some(Verified { scheme, signers }))
+}
+
+/// The ring algorithm for a signature algorithm ID, and the key in the form ring expects, for
+/// those algorithms ring supports.  DSA, ECDSA with SHA-512 and the verity variants are not.
+fn verification_algorithm(algorithm_id: u32, public_key: &[u8]) -> Option<(&'static dyn VerificationAlgorithm, &[u8])> {
+    let PublicKeyInfo { algorithm_oid, curve_oid, key } = subject_public_key(public_key)?;
+    match (algorithm_id, algorithm_oid, curve_oid) {
+        (0x0101, RSA_ENCRYPTION_OID, _) => Some((&signature::RSA_PSS_2048_8192_SHA256, key)),
+        (0x0102, RSA_ENCRYPTION_OID, _) => Some((&signature::RSA_PSS_2048_8192_SHA512, key)),
+        (0x0103, RSA_ENCRYPTION_OID, _) => Some((&signature::RSA_PKCS1_1024_8192_SHA256_FOR_LEGACY_USE_ONLY, key)),
+        (0x0104, RSA_ENCRYPTION_OID, _) => Some((&signature::RSA_PKCS1_1024_8192_SHA512_FOR_LEGACY_USE_ONLY, key)),
+        (0x0201, EC_PUBLIC_KEY_OID, Some(P256_OID)) => Some((&signature::ECDSA_P256_SHA256_ASN1, key)),
+        (0x0201, EC_PUBLIC_KEY_OID, Some(P384_OID)) => Some((&signature::ECDSA_P384_SHA256_ASN1, key)),
+        _ => None,
+    }
+}
+
+fn content_digest_algorithm(algorithm_id: u32) -> Option<&'static digest::Algorithm> {
+    match algorithm_id {
+        0x0101 | 0x0103 | 0x0201 => Some(&digest::SHA256),
+        0x0102 | 0x0104 => Some(&digest::SHA512),
+        _ => None,
+    }
+}
+
+/// Digest the given ranges of the file and then `trailer` in 1 MiB chunks, then digest the
+/// chunk digests, as described by the APK Signature Scheme v2.
+fn content_digest(file: &mut File, algorithm: &'static digest::Algorithm, ranges: &[(u64, u64)], trailer: &[u8]) -> Result<digest::Digest, Box<dyn Error>> {
+    let mut chunk_digests = vec![];
+    let mut digest_chunk = |chunk: &[u8]| {
+        let mut context = digest::Context::new(algorithm);
+        context.update(&[0xa5]);
+        context.update(&(chunk.len() as u32).to_le_bytes());
+        context.update(chunk);
+        chunk_digests.push(context.finish());
+    };
+    let mut buffer = vec![0; CONTENT_DIGEST_CHUNK_SIZE];
+    for &(start, end) in ranges {
+        file.seek(SeekFrom::Start(start))?;
+        let mut remaining = end - start;
+        while remaining > 0 {
+            let chunk = &mut buffer[..remaining.min(CONTENT_DIGEST_CHUNK_SIZE as u64) as usize];
+            file.read_exact(chunk)?;
+            digest_chunk(chunk);
+            remaining -= chunk.len() as u64;
+        }
+    }
+    for chunk in trailer.chunks(CONTENT_DIGEST_CHUNK_SIZE) {
+        digest_chunk(chunk);
+    }
+
+    let mut context = digest::Context::new(algorithm);
+    context.update(&[0x5a]);
+    context.update(&(chunk_digests.len() as u32).to_le_bytes());
+    for chunk_digest in &chunk_digests {
+        context.update(chunk_digest.as_ref());
+    }
+    Ok(context.finish())
+}
+
+/// Split a DER element into its tag, its contents, and the input following it.
+fn der(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, input) = input.split_first()?;
+    let (&first, input) = input.split_first()?;
+    let (length, input) = if first < 0x80 {
+        (first as usize, input)
+    } else {
+        let num_bytes = (first & 0x7f) as usize;
+        if num_bytes == 0 || num_bytes > 4 || input.len() < num_bytes {
+            return None;
+        }
+        let length = input[..num_bytes].iter().fold(0, |length, &byte| (length << 8) | byte as usize);
+        (length, &input[num_bytes..])
+    };
+    if input.len() < length {
+        return None;
+    }
+    Some((tag, &input[..length], &input[length..]))
+}
+
+/// The parts of a DER SubjectPublicKeyInfo needed to verify with its key
+struct PublicKeyInfo<'a> {
+    algorithm_oid: &'a [u8],
+    /// Given for EC keys
+    curve_oid: Option<&'a [u8]>,
+    key: &'a [u8],
+}
+
+fn subject_public_key(spki: &[u8]) -> Option<PublicKeyInfo<'_>> {
+    let (0x30, spki, _) = der(spki)? else { return None };
+    let (0x30, algorithm, rest) = der(spki)? else { return None };
+    let (0x06, algorithm_oid, parameters) = der(algorithm)? else { return None };
+    let curve_oid = match der(parameters) {
+        Some((0x06, curve_oid, _)) => Some(curve_oid),
+        _ => None,
+    };
+    let (0x03, bits, _) = der(rest)? else { return None };
+    // The first byte of a BIT STRING is the number of unused bits, always zero here
+    Some(PublicKeyInfo { algorithm_oid, curve_oid, key: bits.get(1..)? })
+}
+
+/// The complete SubjectPublicKeyInfo element of a DER X.509 certificate.
+fn certificate_public_key(certificate: &[u8]) -> Option<&[u8]> {
+    let (0x30, certificate, _) = der(certificate)? else { return None };
+    let (0x30, mut tbs_certificate, _) = der(certificate)? else { return None };
+    // Skip the optional version, then the serial number, signature algorithm, issuer, validity
+    // and subject
+    if tbs_certificate.first() == Some(&0xa0) {
+        tbs_certificate = der(tbs_certificate)?.2;
+    }
+    for _ in 0..5 {
+        tbs_certificate = der(tbs_certificate)?.2;
+    }
+    let rest = der(tbs_certificate)?.2;
+    Some(&tbs_certificate[..tbs_certificate.len() - rest.len()])
+}
+
+fn le_u16(bytes: &[u8]) -> u16 {
+    u16::from_le_bytes([bytes[0], bytes[1]])
+}
+
+fn le_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes[..4].try_into().unwrap())
+}
+
+fn le_u64(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes(bytes[..8].try_into().unwrap())
+}
+
+/// Reads the little-endian, length-prefixed structures of an APK Signing Block.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], SimpleError> {
+        if self.0.len() < length {
+            return Err(SimpleError::new("the APK Signing Block is truncated"));
+        }
+        let (taken, rest) = self.0.split_at(length);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> Result<u32, SimpleError> {
+        Ok(le_u32(self.take(4)?))
+    }
+
+    fn u64(&mut self) -> Result<u64, SimpleError> {
+        Ok(le_u64(self.take(8)?))
+    }
+
+    /// A value preceded by its length as a 32-bit integer
+    fn prefixed(&mut self) -> Result<&'a [u8], SimpleError> {
+        let length = self.u32()? as usize;
+        self.take(length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn verify_bytes(bytes: &[u8]) -> Result<Option<Verified>, Box<dyn Error>> {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(bytes).unwrap();
+        verify_v2_v3(file.path())
+    }
+
+    #[test]
+    fn empty_file_is_not_a_zip() {
+        assert_eq!(verify_bytes(&[]).err().unwrap().to_string(), "not a ZIP archive");
+    }
+
+    #[test]
+    fn file_shorter_than_a_signature_is_not_a_zip() {
+        assert_eq!(verify_bytes(&[0x50, 0x4b, 0x05]).err().unwrap().to_string(), "not a ZIP archive");
+    }
+
+    #[test]
+    fn truncated_eocd_is_not_a_zip() {
+        let mut bytes = EOCD_SIGNATURE.to_le_bytes().to_vec();
+        bytes.resize(EOCD_SIZE - 1, 0);
+        assert_eq!(verify_bytes(&bytes).err().unwrap().to_string(), "not a ZIP archive");
+    }
+
+    #[test]
+    fn empty_zip_has_no_v2_v3_signature() {
+        let mut bytes = EOCD_SIGNATURE.to_le_bytes().to_vec();
+        bytes.resize(EOCD_SIZE, 0);
+        assert!(verify_bytes(&bytes).unwrap().is_none());
+    }
+}
@@ -0,0 +1,116 @@
+//! An append-only audit log of every download and verification result, for `--audit-log`, so that
+//! the provenance of an archive can be shown later.  Each entry records the hash of the entry
+//! before it and its own hash, so an entry which is changed, removed or reordered afterwards
+//! breaks the chain, which `apkeep audit` checks.
+
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use chrono::Utc;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use simple_error::SimpleError;
+
+/// The `prev` of the first entry
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+struct AuditLog {
+    path: PathBuf,
+    seq: u64,
+    last_hash: String,
+}
+
+static LOG: Mutex<Option<AuditLog>> = Mutex::new(None);
+
+/// The hash of an entry: SHA-256 over its JSON, with keys in order and without `hash` itself.
+fn entry_hash(entry: &Map<String, Value>) -> String {
+    let mut entry = entry.clone();
+    entry.remove("hash");
+    hex::encode(Sha256::digest(Value::Object(entry).to_string().as_bytes()))
+}
+
+/// Check the chain of an audit log, returning the number of entries and the hash of the last.
+pub fn verify(path: &Path) -> Result<(u64, String), Box<dyn Error>> {
+    let mut seq = 0;
+    let mut last_hash = GENESIS.to_string();
+    for (number, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        let broken = |reason: &str| SimpleError::new(format!("Line {} of {} {}", number + 1, path.display(), reason));
+        let entry = match serde_json::from_str::<Value>(&line) {
+            Ok(Value::Object(entry)) => entry,
+            _ => return Err(Box::new(broken("is not an audit log entry"))),
+        };
+        if entry.get("seq").and_then(Value::as_u64) != Some(seq + 1) {
+            return Err(Box::new(broken("is out of sequence, so entries have been removed or reordered")));
+        }
+        if entry.get("prev").and_then(Value::as_str) != Some(last_hash.as_str()) {
+            return Err(Box::new(broken("does not follow from the entry before it")));
+        }
+        let hash = entry_hash(&entry);
+        if entry.get("hash").and_then(Value::as_str) != Some(hash.as_str()) {
+            return Err(Box::new(broken("has been changed since it was written")));
+        }
+        seq += 1;
+        last_hash = hash;
+    }
+    Ok((seq, last_hash))
+}
+
+/// Start recording to the audit log at `path`, continuing its chain if it exists.  A log whose
+/// chain is already broken is refused rather than extended.
+pub fn open(path: &Path) -> Result<(), Box<dyn Error>> {
+    let (seq, last_hash) = if path.exists() {
+        verify(path)?
+    } else {
+        (0, GENESIS.to_string())
+    };
+    *LOG.lock().unwrap() = Some(AuditLog { path: path.to_path_buf(), seq, last_hash });
+    Ok(())
+}
+
+/// Append an event about an app, with the given details, to the audit log if one is open.  A log
+/// which can't be written to is reported rather than stopping the run.
+pub fn record(event: &str, app_id: &str, details: Value) {
+    let mut log = LOG.lock().unwrap();
+    let log = match log.as_mut() {
+        Some(log) => log,
+        None => return,
+    };
+    let mut entry = match details {
+        Value::Object(details) => details,
+        _ => Map::new(),
+    };
+    entry.insert("seq".to_string(), json!(log.seq + 1));
+    entry.insert("time".to_string(), json!(Utc::now().to_rfc3339()));
+    entry.insert("event".to_string(), json!(event));
+    entry.insert("app_id".to_string(), json!(app_id));
+    entry.insert("prev".to_string(), json!(log.last_hash));
+    let hash = entry_hash(&entry);
+    entry.insert("hash".to_string(), json!(hash));
+
+    let written = OpenOptions::new().create(true).append(true).open(&log.path)
+        .and_then(|mut file| {
+            writeln!(file, "{}", Value::Object(entry))?;
+            file.sync_data()
+        });
+    match written {
+        Ok(()) => {
+            log.seq += 1;
+            log.last_hash = hash;
+        },
+        Err(err) => println!("Could not write to the audit log {}: {}", log.path.display(), err),
+    }
+}
+
+/// Record the result of one of the checks made of a download.
+pub fn check(app_id: &str, check: &str, passed: bool, detail: &str) {
+    record("verify", app_id, json!({ "check": check, "passed": passed, "detail": detail }));
+}
+
+/// Whether events are being recorded, so that callers can skip work such as hashing files
+pub fn enabled() -> bool {
+    LOG.lock().unwrap().is_some()
+}
@@ -0,0 +1,52 @@
+//! An on-disk cache of scraped pages and the app page URLs resolved from searches, so that
+//! repeated runs over the same apps make fewer requests and are less likely to be rate-limited.
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use sha2::{Digest, Sha256};
+
+use crate::config;
+
+pub struct PageCache {
+    /// None when caching is disabled or there is nowhere to cache to
+    dir: Option<PathBuf>,
+    ttl: Duration,
+}
+
+impl PageCache {
+    /// A cache in `pages` under apkeep's cache directory, e.g. `~/.cache/apkeep/pages`.  A TTL of
+    /// zero disables the cache.
+    pub fn new(ttl: Duration) -> PageCache {
+        let dir = if ttl.is_zero() {
+            None
+        } else {
+            config::cache_dir().ok()
+                .map(|cache_dir| cache_dir.join("pages"))
+                .filter(|dir| fs::create_dir_all(dir).is_ok())
+        };
+        PageCache { dir, ttl }
+    }
+
+    fn path(&self, key: &str) -> Option<PathBuf> {
+        self.dir.as_ref().map(|dir| dir.join(hex::encode(Sha256::digest(key.as_bytes()))))
+    }
+
+    /// The cached value for `key`, unless it is older than the TTL.
+    pub fn get(&self, key: &str) -> Option<String> {
+        let path = self.path(key)?;
+        let modified = fs::metadata(&path).and_then(|metadata| metadata.modified()).ok()?;
+        if SystemTime::now().duration_since(modified).map_or(true, |age| age > self.ttl) {
+            return None;
+        }
+        fs::read_to_string(path).ok()
+    }
+
+    /// Store a value.  Failing to cache is never fatal, so errors are ignored.
+    pub fn put(&self, key: &str, value: &str) {
+        if let Some(path) = self.path(key) {
+            let _ = fs::write(path, value);
+        }
+    }
+}
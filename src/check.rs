@@ -0,0 +1,136 @@
+//! `--check-only`, for those who want to know when their apps have updates without downloading
+//! them.  The latest version of each app is found on the source and compared with the newest
+//! already in OUTPATH: by versionCode where the source gives one, and otherwise by whether its
+//! versionName is among those in OUTPATH.  Updates are printed, written to a JSON report with
+//! `--check-report`, handed to the `on_new_version` hook and added to the `--feed`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use chrono::Utc;
+use serde_json::{json, Value};
+
+use crate::apk;
+use crate::cli::DownloadSource;
+use crate::download_sources::LatestVersion;
+use crate::feed;
+use crate::hooks::{self, Hook, Hooks};
+use crate::storage;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Status {
+    /// No version of the app is in OUTPATH
+    NotDownloaded,
+    UpToDate,
+    Update,
+}
+
+impl Status {
+    fn name(&self) -> &'static str {
+        match self {
+            Status::NotDownloaded => "not_downloaded",
+            Status::UpToDate => "up_to_date",
+            Status::Update => "update",
+        }
+    }
+}
+
+/// The versions of an app already in OUTPATH
+#[derive(Default)]
+struct Local {
+    newest_version_code: Option<u64>,
+    versions: BTreeSet<String>,
+}
+
+/// Compare the latest versions with those in `outpath`, reporting which apps have updates.
+pub async fn report(
+    latest: Vec<LatestVersion>,
+    source: DownloadSource,
+    outpath: &Path,
+    report_file: Option<&Path>,
+    feed_file: Option<&Path>,
+    hooks: &Hooks,
+) -> Result<(), Box<dyn Error>> {
+    let local = local_versions(outpath)?;
+    let mut entries = vec![];
+    let mut feed_entries = vec![];
+    let mut updates = 0;
+    for app in &latest {
+        let local = local.get(&app.app_id);
+        let status = match (local, app.version_code, &app.version) {
+            (None, _, _) => Status::NotDownloaded,
+            (Some(local), Some(version_code), _) if local.newest_version_code.is_some() =>
+                if Some(version_code) > local.newest_version_code { Status::Update } else { Status::UpToDate },
+            (Some(local), _, Some(version)) =>
+                if local.versions.contains(version) { Status::UpToDate } else { Status::Update },
+            (Some(_), _, None) => Status::Update,
+        };
+        let available = match (&app.version, app.version_code) {
+            (Some(version), Some(version_code)) => format!("{} ({})", version, version_code),
+            (Some(version), None) => version.to_string(),
+            (None, Some(version_code)) => format!("versionCode {}", version_code),
+            (None, None) => "a version".to_string(),
+        };
+        let newest_local = local.and_then(|local| local.newest_version_code);
+        match (status, newest_local) {
+            (Status::NotDownloaded, _) => println!("{}: {} is available, and no version is in {}.", app.app_id, available, outpath.display()),
+            (Status::UpToDate, _) => println!("{} is up to date ({}).", app.app_id, available),
+            (Status::Update, Some(newest_local)) => println!("{}: {} is available, newer than versionCode {} in {}.", app.app_id, available, newest_local, outpath.display()),
+            (Status::Update, None) => println!("{}: {} is available, which is not in {}.", app.app_id, available, outpath.display()),
+        }
+        if status == Status::Update {
+            updates += 1;
+            let event = hooks::update_event(&app.app_id, source, app.version.as_deref(), app.version_code, newest_local);
+            if let Err(err) = hooks.run(Hook::NewVersion, &event).await {
+                println!("The {} hook for {} failed: {}", Hook::NewVersion.name(), app.app_id, err);
+            }
+            feed_entries.push(feed::Entry {
+                app_id: app.app_id.clone(),
+                source: source.to_string(),
+                version: app.version.clone(),
+                version_code: app.version_code,
+                url: None,
+                detected_at: Utc::now(),
+            });
+        }
+        entries.push(json!({
+            "package": app.app_id,
+            "status": status.name(),
+            "version": app.version,
+            "version_code": app.version_code,
+            "local_version_code": newest_local,
+        }));
+    }
+    println!("{} of {} apps checked have updates.", updates, latest.len());
+
+    if let Some(report_file) = report_file {
+        let report = json!({
+            "source": source.to_string(),
+            "checked_at": Utc::now().to_rfc3339(),
+            "apps": Value::Array(entries),
+        });
+        fs::write(report_file, serde_json::to_vec_pretty(&report)?)?;
+    }
+    if let Some(feed_file) = feed_file {
+        feed::update(feed_file, &feed_entries)?;
+    }
+    Ok(())
+}
+
+/// The newest versionCode of each app in `outpath`, and the versionNames of all of its versions.
+fn local_versions(outpath: &Path) -> Result<BTreeMap<String, Local>, Box<dyn Error>> {
+    let mut local: BTreeMap<String, Local> = BTreeMap::new();
+    for (package, versions) in storage::archived_versions(outpath)? {
+        let entry = local.entry(package).or_default();
+        entry.newest_version_code = versions.keys().next_back().copied();
+        for paths in versions.values() {
+            let version_name = paths.first()
+                .and_then(|path| apk::read_manifest(path).ok())
+                .and_then(|manifest| manifest.version_name);
+            entry.versions.extend(version_name);
+        }
+    }
+    Ok(local)
+}
@@ -1,4 +1,7 @@
-use clap::{value_parser, Command, Arg, ArgAction, ValueEnum, {builder::EnumValueParser}};
+use clap::{value_parser, Command, Arg, ArgAction, ArgGroup, ValueEnum, {builder::EnumValueParser}};
+
+/// The failures retried when `--retry-on` isn't given
+pub const DEFAULT_RETRY_ON: &str = "429,5xx,timeout,connreset";
 
 #[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
 pub enum DownloadSource {
@@ -7,6 +10,8 @@ pub enum DownloadSource {
     FDroid,
     HuaweiAppGallery,
     APKCombo,     // Add this line
+    Plugin,
+    Scraped,
 }
 
 impl std::fmt::Display for DownloadSource {
@@ -18,6 +23,33 @@ impl std::fmt::Display for DownloadSource {
     }
 }
 
+/// How far ahead of the stable release of each app to go
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum Channel {
+    Stable,
+    Beta,
+    Alpha,
+}
+
+/// The kind of device a build of an app is for
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum FormFactor {
+    Phone,
+    Tv,
+    Wear,
+    /// Android Automotive, built into cars
+    Auto,
+}
+
+impl std::fmt::Display for FormFactor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("no values are skipped")
+            .get_name()
+            .fmt(f)
+    }
+}
+
 impl std::str::FromStr for DownloadSource {
     type Err = String;
 
@@ -36,15 +68,17 @@ pub fn app() -> Command {
         .version(env!("CARGO_PKG_VERSION"))
         .author("William Budington <bill@eff.org>")
         .about("Downloads APKs from various sources")
-        .override_usage("apkeep <-a app_id[@version] | -c csv [-f field] [-v version_field]> [-d download_source] [-r parallel] OUTPATH")
+        .override_usage("apkeep <-a app_id[@version] | -c csv [-f field] [-v version_field]> [-d download_source] [-r parallel] OUTPATH\n       apkeep repo build [--name name] [--address url] DIR\n       apkeep index DIR\n       apkeep import-device [--device serial] FILE\n       apkeep export-device-config [--device serial] FILE\n       apkeep import-obtainium FILE DIR\n       apkeep prune <--keep n | --keep-days days> [--archive] [--dry-run] DIR\n       apkeep auth <login | logout> SERVICE\n       apkeep auth google [-e email] [--oauth-token token] [--accept-tos]\n       apkeep diff OLD NEW\n       apkeep verify DIR\n       apkeep audit FILE\n       apkeep list-sources\n       apkeep update-patterns\n       apkeep doctor [-d source] [-a app_id]")
+        .subcommand_negates_reqs(true)
+        .args_conflicts_with_subcommands(true)
         .arg(
             Arg::new("app")
-                .help("Provide the ID and optionally the version of an app directly (e.g. com.instagram.android)")
+                .help("Provide the ID and optionally the version of an app directly (e.g. com.instagram.android), or the URL of its page on a store")
                 .short('a')
                 .long("app")
                 .action(ArgAction::Set)
                 .conflicts_with("csv")
-                .required_unless_present_any(["csv", "google_oauth_token"]),
+                .required_unless_present_any(["csv", "google_oauth_token", "mirror_repo", "category", "developer"]),
         )
         .arg(
             Arg::new("csv")
@@ -53,6 +87,13 @@ pub fn app() -> Command {
                 .long("csv")
                 .action(ArgAction::Set),
         )
+        .arg(
+            Arg::new("aliases")
+                .help("A file of name = app ID lines, so that apps can be given by these names with --app or in the CSV")
+                .long("aliases")
+                .action(ArgAction::Set)
+                .required(false),
+        )
         .arg(
             Arg::new("field")
                 .help("CSV field containing app IDs (used only if CSV is specified)")
@@ -71,6 +112,13 @@ pub fn app() -> Command {
                 .value_parser(value_parser!(usize))
                 .required(false),
         )
+        .arg(
+            Arg::new("first")
+                .help("When an app is given by name rather than ID, download the first app the search finds rather than asking which is meant")
+                .long("first")
+                .action(ArgAction::SetTrue)
+                .required(false),
+        )
         .arg(
             Arg::new("list_versions")
                 .help("List the versions available")
@@ -79,6 +127,102 @@ pub fn app() -> Command {
                 .action(ArgAction::SetTrue)
                 .required(false),
         )
+        .arg(
+            Arg::new("check_only")
+                .help("Report which apps have a newer version than the newest in OUTPATH, without downloading anything; updates are also handed to the on_new_version hook")
+                .long("check-only")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["list_versions", "race", "archive_file", "mirror_repo"])
+                .required(false),
+        )
+        .arg(
+            Arg::new("all_versions")
+                .help("Download every version of each app which the source lists, rather than only the latest, leaving out those already in OUTPATH")
+                .long("all-versions")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["list_versions", "check_only", "race", "mirror_repo", "developer", "category"])
+                .required(false),
+        )
+        .arg(
+            Arg::new("released_after")
+                .help("With --all-versions, only download versions released on or after this date (e.g. 2023-01-01)")
+                .long("released-after")
+                .action(ArgAction::Set)
+                .requires("all_versions")
+                .required(false),
+        )
+        .arg(
+            Arg::new("released_before")
+                .help("With --all-versions, only download versions released before this date (e.g. 2024-01-01)")
+                .long("released-before")
+                .action(ArgAction::Set)
+                .requires("all_versions")
+                .required(false),
+        )
+        .arg(
+            Arg::new("info")
+                .help("Show what the source says about each app, such as its developer, rating, install count and content rating, without downloading it")
+                .long("info")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["list_versions", "check_only", "race", "archive_file", "mirror_repo", "category", "developer"])
+                .required(false),
+        )
+        .arg(
+            Arg::new("output_format")
+                .help("Print what --list-versions, --info and searches for apps given by name find as text, JSON, or tab-separated columns (tsv) with nothing else on stdout")
+                .long("output-format")
+                .value_parser(["text", "json", "tsv"])
+                .action(ArgAction::Set)
+                .required(false),
+        )
+        .arg(
+            Arg::new("check_report")
+                .help("With --check-only, also write what was found to this file as JSON")
+                .long("check-report")
+                .action(ArgAction::Set)
+                .requires("check_only")
+                .required(false),
+        )
+        .arg(
+            Arg::new("mirror_repo")
+                .help("Download every app in the F-Droid repository rather than a list of apps")
+                .long("mirror-repo")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["app", "csv", "category", "developer"])
+                .required(false),
+        )
+        .arg(
+            Arg::new("category")
+                .help("Download the latest version of every app in this category, from F-Droid or a scraped site whose rules give a category_url")
+                .long("category")
+                .action(ArgAction::Set)
+                .conflicts_with_all(["app", "csv", "developer"])
+                .required(false),
+        )
+        .arg(
+            Arg::new("developer")
+                .help("Download the latest version of every app by this developer, from F-Droid (as named in the repository) or a scraped site whose rules give a developer_url")
+                .long("developer")
+                .action(ArgAction::Set)
+                .conflicts_with_all(["app", "csv"])
+                .required(false),
+        )
+        .arg(
+            Arg::new("include")
+                .help("Only download apps whose IDs match one of these comma-separated glob patterns, such as com.mycompany.*; when mirroring F-Droid, categories are matched too (overrides include in the [filter] section of the ini file)")
+                .long("include")
+                .action(ArgAction::Append)
+                .value_delimiter(',')
+                .required(false),
+        )
+        .arg(
+            Arg::new("exclude")
+                .help("Skip apps whose IDs match one of these comma-separated glob patterns; when mirroring F-Droid, categories such as Games are matched too (overrides exclude in the [filter] section of the ini file)")
+                .long("exclude")
+                .action(ArgAction::Append)
+                .value_delimiter(',')
+                .required(false),
+        )
         .arg(
             Arg::new("download_source")
                 .help("Where to download the APKs from")
@@ -89,9 +233,50 @@ pub fn app() -> Command {
                 .value_parser(EnumValueParser::<DownloadSource>::new())
                 .required(false),
         )
+        .arg(
+            Arg::new("plugin")
+                .help("The WebAssembly plugin to download with when the source is plugin, as a path or the name of a .wasm file in the plugins directory of the config dir")
+                .long("plugin")
+                .action(ArgAction::Set)
+                .required_if_eq("download_source", "plugin"),
+        )
+        .arg(
+            Arg::new("rules")
+                .help("The rules describing the site to scrape when the source is scraped, as a path or the name of a .toml file in the rules directory of the config dir")
+                .long("rules")
+                .action(ArgAction::Set)
+                .required_if_eq("download_source", "scraped"),
+        )
+        .arg(
+            Arg::new("race")
+                .help("A comma-separated list of sources to download each app from at once, keeping whichever finishes first")
+                .long("race")
+                .action(ArgAction::Set)
+                .value_delimiter(',')
+                .value_parser(EnumValueParser::<DownloadSource>::new())
+                .conflicts_with("list_versions")
+                .required(false),
+        )
+        .arg(
+            Arg::new("channel")
+                .help("The release channel to download the latest version from, for sources which distinguish them")
+                .long("channel")
+                .default_value("stable")
+                .action(ArgAction::Set)
+                .value_parser(EnumValueParser::<Channel>::new())
+                .required(false),
+        )
+        .arg(
+            Arg::new("form_factor")
+                .help("Download the build of each app for this kind of device, on sources which publish separate builds; downloads for other kinds of device are quarantined")
+                .long("form-factor")
+                .action(ArgAction::Set)
+                .value_parser(EnumValueParser::<FormFactor>::new())
+                .required(false),
+        )
         .arg(
             Arg::new("options")
-                .help("A comma-separated list of additional options to pass to the download source")
+                .help("A comma-separated list of additional options to pass to the download source, overriding those in the [sources.<download_source>] section of the config file")
                 .short('o')
                 .long("options")
                 .action(ArgAction::Set)
@@ -105,6 +290,29 @@ pub fn app() -> Command {
                 .action(ArgAction::Set)
                 .required(false),
         )
+        .arg(
+            Arg::new("lang")
+                .help("The language to print messages in, such as ru or zh-CN (defaults to the system locale; messages not yet translated are printed in English)")
+                .long("lang")
+                .action(ArgAction::Set)
+                .global(true)
+                .required(false),
+        )
+        .arg(
+            Arg::new("state_dir")
+                .help("Keep the cache in this directory, rather than under the user's cache directory")
+                .long("state-dir")
+                .action(ArgAction::Set)
+                .global(true)
+                .required(false),
+        )
+        .arg(
+            Arg::new("profile")
+                .help("The profile in the ini file to use, whose [profile.<name>] section sets the download source and OUTPATH when they aren't given, and whose [profile.<name>.<section>] sections override [<section>]")
+                .long("profile")
+                .action(ArgAction::Set)
+                .required(false),
+        )
         .arg(
             Arg::new("google_oauth_token")
                 .help("Google oauth token, required to retrieve long-lived aas token")
@@ -133,16 +341,103 @@ pub fn app() -> Command {
         )
         .arg(
             Arg::new("sleep_duration")
-                .help("Sleep duration (in ms) before download requests")
+                .help("Sleep duration (in ms) before download and version lookup requests")
                 .short('s')
                 .long("sleep-duration")
                 .action(ArgAction::Set)
                 .value_parser(value_parser!(u64))
                 .default_value("0"),
         )
+        .arg(
+            Arg::new("max_connections_per_host")
+                .help("The most requests to make to any one host at a time; further requests to the host wait their turn, and no more idle connections than this are kept open to it")
+                .long("max-connections-per-host")
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(u64).range(1..))
+                .required(false),
+        )
+        .arg(
+            Arg::new("host_interval")
+                .help("The least time (in milliseconds) between requests to any one host, independent of --sleep-duration, as a comma-separated list of intervals for every host (e.g. 500) or for one (e.g. f-droid.org=2000)")
+                .long("host-interval")
+                .action(ArgAction::Set)
+                .required(false),
+        )
+        .arg(
+            Arg::new("header")
+                .help("An extra header to send with every request to the download source, as 'Name: value' (e.g. 'Accept-Language: de'); may be given more than once")
+                .short('H')
+                .long("header")
+                .action(ArgAction::Append)
+                .required(false),
+        )
+        .arg(
+            Arg::new("keep_alive")
+                .help("How long (in seconds) to keep idle connections open for reuse (0 to close them after each request)")
+                .long("keep-alive")
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(u64))
+                .required(false),
+        )
+        .arg(
+            Arg::new("allow_insecure")
+                .help("Download from plain HTTP links found on sources, and follow redirects to them, rather than upgrading to HTTPS")
+                .long("allow-insecure")
+                .action(ArgAction::SetTrue)
+                .required(false),
+        )
+        .arg(
+            Arg::new("retry_on")
+                .help("Which failures to retry, up to twice: HTTP statuses such as 429, classes such as 5xx, timeout, connreset (a connection cut short), connect (no connection made), or none")
+                .long("retry-on")
+                .action(ArgAction::Set)
+                .default_value(DEFAULT_RETRY_ON)
+                .required(false),
+        )
+        .arg(
+            Arg::new("circuit_breaker")
+                .help("Skip a source for the rest of the run once this many apps in a row have failed on it")
+                .long("circuit-breaker")
+                .value_parser(value_parser!(usize))
+                .action(ArgAction::Set)
+                .required(false),
+        )
+        .arg(
+            Arg::new("per_app_timeout")
+                .help("Give up on an app which takes longer than this many seconds to find and download, deleting its partial files")
+                .long("per-app-timeout")
+                .value_parser(value_parser!(u64).range(1..))
+                .action(ArgAction::Set)
+                .required(false),
+        )
+        .arg(
+            Arg::new("max_buffered_mb")
+                .help("The most data (in MiB) to hold in memory between the network and the disk, across all downloads")
+                .long("max-buffered-mb")
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(u64).range(1..))
+                .default_value("64")
+                .required(false),
+        )
+        .arg(
+            Arg::new("skip_larger_than")
+                .help("Skip apps whose download is larger than this size (e.g. 2G or 500M), as listed by the source or announced by the server before the download starts")
+                .long("skip-larger-than")
+                .action(ArgAction::Set)
+                .required(false),
+        )
+        .arg(
+            Arg::new("cache_ttl")
+                .help("How long (in seconds) to reuse app pages found by searching, cached under the user's cache directory (0 to disable)")
+                .long("cache-ttl")
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(u64))
+                .default_value("3600")
+                .required(false),
+        )
         .arg(
             Arg::new("parallel")
-                .help("The number of parallel APK fetches to run at a time")
+                .help("The number of parallel APK fetches (or version lookups) to run at a time")
                 .short('r')
                 .long("parallel")
                 .action(ArgAction::Set)
@@ -150,11 +445,608 @@ pub fn app() -> Command {
                 .default_value("4")
                 .required(false),
         )
+        .arg(
+            Arg::new("install")
+                .help("Install downloaded apps on a connected device using adb")
+                .long("install")
+                .action(ArgAction::SetTrue)
+                .required(false),
+        )
+        .arg(
+            Arg::new("only_newer_than_device")
+                .help("Only download apps installed on the adb device(s) for which a newer version is available")
+                .long("only-newer-than-device")
+                .action(ArgAction::SetTrue)
+                .required(false),
+        )
+        .arg(
+            Arg::new("only_newer")
+                .help("Skip apps for which nothing newer is available than the newest version already in OUTPATH")
+                .long("only-newer")
+                .action(ArgAction::SetTrue)
+                .required(false),
+        )
+        .arg(
+            Arg::new("keep")
+                .help("After downloading, remove all but this many versions of each app downloaded, as apkeep prune does (overrides keep in the [retention] section of the ini file)")
+                .long("keep")
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(u64).range(1..))
+                .required(false),
+        )
+        .arg(
+            Arg::new("keep_days")
+                .help("After downloading, remove versions of each app downloaded which were downloaded more than this many days ago, as apkeep prune does (overrides keep_days in the [retention] section of the ini file)")
+                .long("keep-days")
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(u64))
+                .required(false),
+        )
+        .arg(
+            Arg::new("device")
+                .help("Serial of the adb device to use (can be repeated, or `all` for every connected device)")
+                .long("device")
+                .action(ArgAction::Append)
+                .required(false),
+        )
+        .arg(
+            Arg::new("metadata")
+                .help("Write a <file>.meta.json next to each download with its source, URL, version, hash and signer")
+                .long("metadata")
+                .action(ArgAction::SetTrue)
+                .required(false),
+        )
+        .arg(
+            Arg::new("verify_signatures")
+                .help("Verify the APK Signature Scheme v2/v3 signature of each downloaded APK, quarantining those which fail or are not signed by their known publisher")
+                .long("verify-signatures")
+                .action(ArgAction::SetTrue)
+                .required(false),
+        )
+        .arg(
+            Arg::new("known_signers")
+                .help("Also check downloads against the package,fingerprint pairs of known publisher certificates in this file")
+                .long("known-signers")
+                .action(ArgAction::Set)
+                .required(false),
+        )
+        .arg(
+            Arg::new("file_name_replacement")
+                .help("What to replace characters which can't be used in file names on some platform, such as : on Windows, with")
+                .long("file-name-replacement")
+                .action(ArgAction::Set)
+                .default_value("_")
+                .required(false),
+        )
+        .arg(
+            Arg::new("max_path")
+                .help("Shorten file names so that paths in OUTPATH are no longer than this (defaults to 259 on Windows, where longer paths fail unless long paths are enabled, and no limit elsewhere; 0 for no limit)")
+                .long("max-path")
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(u64))
+                .required(false),
+        )
+        .arg(
+            Arg::new("pipe")
+                .help("A shell command to run on each downloaded file, with {} replaced by its path, or else given the file on stdin; a file the command fails on is quarantined")
+                .long("pipe")
+                .action(ArgAction::Set)
+                .required(false),
+        )
+        .arg(
+            Arg::new("on_success")
+                .help("A shell command to run after each app is downloaded, given the download as JSON on stdin and APKEEP_* environment variables")
+                .long("on-success")
+                .action(ArgAction::Set)
+                .required(false),
+        )
+        .arg(
+            Arg::new("on_failure")
+                .help("A shell command to run for each app which could not be downloaded or was quarantined, given the failure as JSON on stdin and APKEEP_* environment variables")
+                .long("on-failure")
+                .action(ArgAction::Set)
+                .required(false),
+        )
+        .arg(
+            Arg::new("on_new_version")
+                .help("A shell command to run for each app downloaded which is newer than any version already in OUTPATH, given the download as JSON on stdin and APKEEP_* environment variables")
+                .long("on-new-version")
+                .action(ArgAction::Set)
+                .required(false),
+        )
+        .arg(
+            Arg::new("archive_file")
+                .help("Write all the files of the run into a single archive, a .tar.zst, .tar or .zip, rather than into OUTPATH")
+                .long("archive")
+                .action(ArgAction::Set)
+                .conflicts_with_all(["OUTPATH", "dedup", "latest_links", "keep", "keep_days"])
+                .required(false),
+        )
+        .arg(
+            Arg::new("temp_dir")
+                .help("Download into this directory, possibly on another filesystem, moving each app into OUTPATH once it is complete")
+                .long("temp-dir")
+                .action(ArgAction::Set)
+                .required(false),
+        )
+        .arg(
+            Arg::new("feed")
+                .help("Add each new version found to an Atom feed in this file, such as OUTPATH/feed.xml, so the mirror can be followed in a feed reader")
+                .long("feed")
+                .action(ArgAction::Set)
+                .required(false),
+        )
+        .arg(
+            Arg::new("xattrs")
+                .help("Record each download's source, URL, version and SHA-256 hash in extended attributes on the file (alternate data streams on Windows), so they travel with it")
+                .long("xattrs")
+                .action(ArgAction::SetTrue)
+                .required(false),
+        )
+        .arg(
+            Arg::new("release_mtime")
+                .help("Set the modification time of each download to when its version was released, for sources which say (F-Droid)")
+                .long("release-mtime")
+                .action(ArgAction::SetTrue)
+                .required(false),
+        )
+        .arg(
+            Arg::new("file_mode")
+                .help("The permissions to give each downloaded file, as an octal mode such as 644 (on Windows, only whether the file is read-only)")
+                .long("file-mode")
+                .action(ArgAction::Set)
+                .required(false),
+        )
+        .arg(
+            Arg::new("dedup")
+                .help("Store downloads in a content-addressed pool in OUTPATH, linking identical files rather than keeping copies")
+                .long("dedup")
+                .action(ArgAction::SetTrue)
+                .required(false),
+        )
+        .arg(
+            Arg::new("latest_links")
+                .help("Keep OUTPATH/latest/<app_id>.apk pointing at the newest downloaded version of each app")
+                .long("latest-links")
+                .action(ArgAction::SetTrue)
+                .required(false),
+        )
+        .arg(
+            Arg::new("gpg_sign")
+                .help("Sign SHA256SUMS with this GnuPG key, writing SHA256SUMS.asc")
+                .long("gpg-sign")
+                .action(ArgAction::Set)
+                .required(false),
+        )
+        .arg(
+            Arg::new("extra_checksums")
+                .help("Also record these hashes of each download (md5, sha1), in MD5SUMS and SHA1SUMS and the metadata sidecars")
+                .long("extra-checksums")
+                .action(ArgAction::Set)
+                .required(false),
+        )
+        .arg(
+            Arg::new("upload")
+                .help("Upload downloaded files to a remote destination (s3://bucket/prefix, webdav[s]://host/path or sftp://user@host/path)")
+                .long("upload")
+                .action(ArgAction::Set)
+                .required(false),
+        )
+        .arg(
+            Arg::new("mobsf")
+                .help("Submit downloaded apps to the MobSF instance at this URL for static analysis")
+                .long("mobsf")
+                .action(ArgAction::Set)
+                .required(false),
+        )
+        .arg(
+            Arg::new("mqtt")
+                .help("Publish an event for each download to the MQTT broker at this URL (mqtt[s]://host[:port][/prefix])")
+                .long("mqtt")
+                .action(ArgAction::Set)
+                .required(false),
+        )
+        .arg(
+            Arg::new("timings")
+                .help("Report the time spent searching, resolving, downloading, verifying and writing for each app")
+                .long("timings")
+                .action(ArgAction::SetTrue)
+                .required(false),
+        )
+        .arg(
+            Arg::new("trace_http")
+                .help("Log each HTTP request made and the response to it on stderr, with credentials and tokens redacted, to diagnose a source which stops working")
+                .long("trace-http")
+                .action(ArgAction::SetTrue)
+                .global(true)
+                .required(false),
+        )
+        .arg(
+            Arg::new("trace_http_bodies")
+                .help("With --trace-http, also save the bodies of HTML, JSON and other text responses to numbered files in this directory")
+                .long("trace-http-bodies")
+                .action(ArgAction::Set)
+                .requires("trace_http")
+                .global(true)
+                .required(false),
+        )
+        .args(fixture_args())
+        .arg(
+            Arg::new("respect_robots")
+                .help("Honour the robots.txt of scraped sites: skip the pages it disallows and wait out its crawl delay between requests")
+                .long("respect-robots")
+                .action(ArgAction::SetTrue)
+                .global(true)
+                .required(false),
+        )
+        .arg(
+            Arg::new("progress")
+                .help("Show download progress as progress bars, or as line-delimited JSON events on stderr for programs wrapping apkeep")
+                .long("progress")
+                .value_parser(["bars", "json"])
+                .action(ArgAction::Set)
+                .required(false),
+        )
+        .arg(
+            Arg::new("device_config")
+                .help("Keep only the split APKs the device described in this file would receive, as written by export-device-config")
+                .long("device-config")
+                .action(ArgAction::Set)
+                .required(false),
+        )
+        .arg(
+            Arg::new("audit_log")
+                .help("Append each download and verification result to this hash-chained audit log")
+                .long("audit-log")
+                .action(ArgAction::Set)
+                .required(false),
+        )
         .arg(
             Arg::new("OUTPATH")
                 .help("Path to store output files")
                 .action(ArgAction::Set)
                 .index(1)
-                .required_unless_present_any(["list_versions", "google_oauth_token"]),
+                .required_unless_present_any(["list_versions", "info", "google_oauth_token", "profile", "archive_file"]),
         )
+        .subcommand(
+            Command::new("repo")
+                .about("Manage an F-Droid repository of downloaded apps")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("build")
+                        .about("Write a signed F-Droid repository index for the APKs in a directory")
+                        .arg(
+                            Arg::new("name")
+                                .help("Name of the repository")
+                                .long("name")
+                                .action(ArgAction::Set)
+                                .default_value("apkeep"),
+                        )
+                        .arg(
+                            Arg::new("description")
+                                .help("Description of the repository")
+                                .long("description")
+                                .action(ArgAction::Set)
+                                .required(false),
+                        )
+                        .arg(
+                            Arg::new("address")
+                                .help("URL the repository will be served from")
+                                .long("address")
+                                .action(ArgAction::Set)
+                                .required(false),
+                        )
+                        .arg(
+                            Arg::new("key")
+                                .help("PEM-encoded PKCS#8 private key to sign the index with (a key is generated in the config directory if not given)")
+                                .long("key")
+                                .action(ArgAction::Set)
+                                .requires("cert"),
+                        )
+                        .arg(
+                            Arg::new("cert")
+                                .help("PEM-encoded certificate for the signing key")
+                                .long("cert")
+                                .action(ArgAction::Set)
+                                .requires("key"),
+                        )
+                        .arg(
+                            Arg::new("DIR")
+                                .help("Directory containing the APKs, to which the index is written")
+                                .action(ArgAction::Set)
+                                .index(1)
+                                .required(true),
+                        )
+                )
+        )
+        .subcommand(
+            Command::new("import-device")
+                .about("Write the third-party apps installed on a device to a file usable with --csv")
+                .arg(
+                    Arg::new("device")
+                        .help("Serial of the adb device to read from")
+                        .long("device")
+                        .action(ArgAction::Set)
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("FILE")
+                        .help("File to write the app IDs to, one per line")
+                        .action(ArgAction::Set)
+                        .index(1)
+                        .required(true),
+                )
+        )
+        .subcommand(
+            Command::new("export-device-config")
+                .about("Write the ABIs, screen density, locales and SDK level of a device to a file usable with --device-config")
+                .arg(
+                    Arg::new("device")
+                        .help("Serial of the adb device to read from")
+                        .long("device")
+                        .action(ArgAction::Set)
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("FILE")
+                        .help("File to write the device configuration to, as JSON")
+                        .action(ArgAction::Set)
+                        .index(1)
+                        .required(true),
+                )
+        )
+        .subcommand(
+            Command::new("import-obtainium")
+                .about("Convert an Obtainium export into one file per download source, usable with --csv")
+                .arg(
+                    Arg::new("FILE")
+                        .help("Obtainium export to read")
+                        .action(ArgAction::Set)
+                        .index(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("DIR")
+                        .help("Directory to write the app lists to")
+                        .action(ArgAction::Set)
+                        .index(2)
+                        .required(true),
+                )
+        )
+        .subcommand(
+            Command::new("prune")
+                .about("Remove old versions of the apps in a directory, always keeping the newest")
+                .arg(
+                    Arg::new("keep")
+                        .help("Number of versions of each app to keep")
+                        .long("keep")
+                        .action(ArgAction::Set)
+                        .value_parser(value_parser!(u64).range(1..))
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("keep_days")
+                        .help("Remove versions downloaded more than this many days ago")
+                        .long("keep-days")
+                        .action(ArgAction::Set)
+                        .value_parser(value_parser!(u64))
+                        .required(false),
+                )
+                .group(
+                    ArgGroup::new("retention")
+                        .args(["keep", "keep_days"])
+                        .multiple(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("archive")
+                        .help("Move the versions into archive/<app_id>.tar.zst rather than removing them")
+                        .long("archive")
+                        .action(ArgAction::SetTrue)
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("dry_run")
+                        .help("List the versions which would be removed without removing them")
+                        .long("dry-run")
+                        .action(ArgAction::SetTrue)
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("DIR")
+                        .help("Directory of downloaded apps to prune")
+                        .action(ArgAction::Set)
+                        .index(1)
+                        .required(true),
+                )
+        )
+        .subcommand(
+            Command::new("auth")
+                .about("Store or remove the credentials for a service in the OS keyring")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("login")
+                        .about("Prompt for the credentials for a service and store them in the keyring")
+                        .arg(service_arg())
+                )
+                .subcommand(
+                    Command::new("logout")
+                        .about("Remove the credentials for a service from the keyring")
+                        .arg(service_arg())
+                )
+                .subcommand(
+                    Command::new("google")
+                        .about("Obtain a Google Play AAS token from an OAuth token, check it, and store it in the keyring")
+                        .arg(
+                            Arg::new("email")
+                                .help("Google account email address")
+                                .short('e')
+                                .long("email")
+                                .action(ArgAction::Set)
+                                .required(false),
+                        )
+                        .arg(
+                            Arg::new("oauth_token")
+                                .help("OAuth token from the Google embedded setup page, starting with oauth2_4/")
+                                .long("oauth-token")
+                                .action(ArgAction::Set)
+                                .required(false),
+                        )
+                        .arg(
+                            Arg::new("accept_tos")
+                                .help("Accept Google Play Terms of Service")
+                                .long("accept-tos")
+                                .action(ArgAction::SetTrue)
+                                .required(false),
+                        )
+                        .arg(
+                            Arg::new("token_file")
+                                .help("Write the AAS token to this new file, readable only by you, rather than storing it in the keyring")
+                                .long("token-file")
+                                .action(ArgAction::Set)
+                                .required(false),
+                        )
+                )
+        )
+        .subcommand(
+            Command::new("index")
+                .about("Write a browsable index.html listing the apps in a directory")
+                .arg(
+                    Arg::new("DIR")
+                        .help("Directory containing the APKs, to which index.html is written")
+                        .action(ArgAction::Set)
+                        .index(1)
+                        .required(true),
+                )
+        )
+        .subcommand(
+            Command::new("list-sources")
+                .about("List the download sources and what each of them can do")
+        )
+        .subcommand(
+            Command::new("update-patterns")
+                .about("Download the latest patterns for scraping APKPure and APKCombo from the apkeep repository, so that a site which has changed can be fixed without a new release")
+        )
+        .subcommand(
+            Command::new("doctor")
+                .about("Check which sources are working by finding and downloading an app known to be on each")
+                .arg(
+                    Arg::new("source")
+                        .help("A source to check, rather than all of those which can be checked without an account")
+                        .short('d')
+                        .long("source")
+                        .value_parser(EnumValueParser::<DownloadSource>::new())
+                        .action(ArgAction::Append)
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("app")
+                        .help("The app ID to check the sources with, rather than one known to be on each")
+                        .short('a')
+                        .long("app")
+                        .action(ArgAction::Set)
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("rules")
+                        .help("The rules of a scraped site to check, as a path or the name of a .toml file in the rules directory of the config dir")
+                        .long("rules")
+                        .action(ArgAction::Set)
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("plugin")
+                        .help("A WebAssembly plugin to check, as a path or the name of a .wasm file in the plugins directory of the config dir")
+                        .long("plugin")
+                        .action(ArgAction::Set)
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("allow_insecure")
+                        .help("Download from plain HTTP links found on sources, and follow redirects to them, rather than upgrading to HTTPS")
+                        .long("allow-insecure")
+                        .action(ArgAction::SetTrue)
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("no_download")
+                        .help("Stop once the app's latest version has been found, without downloading it")
+                        .long("no-download")
+                        .action(ArgAction::SetTrue)
+                        .required(false),
+                )
+        )
+        .subcommand(
+            Command::new("diff")
+                .about("Compare two versions of an app: entries, size, permissions and trackers")
+                .arg(
+                    Arg::new("OLD")
+                        .help("The earlier APK")
+                        .action(ArgAction::Set)
+                        .index(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("NEW")
+                        .help("The later APK")
+                        .action(ArgAction::Set)
+                        .index(2)
+                        .required(true),
+                )
+        )
+        .subcommand(
+            Command::new("verify")
+                .about("Check the files in an output directory against SHA256SUMS, their metadata sidecars and their signatures")
+                .arg(
+                    Arg::new("DIR")
+                        .help("Output directory to check")
+                        .action(ArgAction::Set)
+                        .index(1)
+                        .required(true),
+                )
+        )
+        .subcommand(
+            Command::new("audit")
+                .about("Check that no entry of an audit log has been changed, removed or reordered")
+                .arg(
+                    Arg::new("FILE")
+                        .help("Audit log written with --audit-log")
+                        .action(ArgAction::Set)
+                        .index(1)
+                        .required(true),
+                )
+        )
+}
+
+/// `--record-http` and `--replay-http`, which are only built with the `fixtures` feature
+#[cfg(feature = "fixtures")]
+fn fixture_args() -> Vec<Arg> {
+    vec![
+        Arg::new("record_http")
+            .help("Record each HTTP exchange into this directory, to be replayed with --replay-http")
+            .long("record-http")
+            .action(ArgAction::Set)
+            .conflicts_with("replay_http")
+            .required(false),
+        Arg::new("replay_http")
+            .help("Answer HTTP requests from the exchanges recorded in this directory with --record-http, rather than from the network")
+            .long("replay-http")
+            .action(ArgAction::Set)
+            .required(false),
+    ]
+}
+
+#[cfg(not(feature = "fixtures"))]
+fn fixture_args() -> Vec<Arg> {
+    vec![]
+}
+
+/// The services `apkeep auth` can store credentials for, as listed in `credentials::SERVICES`
+fn service_arg() -> Arg {
+    Arg::new("SERVICE")
+        .help("Service to store the credentials of")
+        .action(ArgAction::Set)
+        .value_parser(["google-play", "s3", "webdav", "sftp", "mobsf", "mqtt"])
+        .index(1)
+        .required(true)
 }
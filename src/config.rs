@@ -1,7 +1,12 @@
+use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use configparser::ini::Ini;
+use simple_error::SimpleError;
 
 #[derive(Debug)]
 pub enum ConfigDirError {
@@ -34,3 +39,47 @@ pub fn config_dir() -> Result<PathBuf, ConfigDirError> {
     create_dir(&config_dir)?;
     Ok(config_dir)
 }
+
+/// The directory given with `--state-dir`, which holds the cache in place of the user's cache
+/// directory
+static STATE_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+pub fn set_state_dir(state_dir: &Path) {
+    let _ = STATE_DIR.set(state_dir.to_path_buf());
+}
+
+/// Where apkeep keeps what it can fetch again, such as the F-Droid package index and scraped pages:
+/// `$XDG_CACHE_HOME/apkeep`, e.g. `~/.cache/apkeep`, or `cache` under `--state-dir`.
+pub fn cache_dir() -> Result<PathBuf, ConfigDirError> {
+    let cache_dir = match STATE_DIR.get() {
+        Some(state_dir) => state_dir.join("cache"),
+        None => dirs::cache_dir().ok_or(ConfigDirError::NotFound)?.join("apkeep"),
+    };
+    fs::create_dir_all(&cache_dir).map_err(|_| ConfigDirError::CouldNotCreate)?;
+    Ok(cache_dir)
+}
+
+/// Apply the profile `name` to the config: each `[profile.<name>.<section>]` overrides the
+/// settings of `[<section>]`, so that one config file can hold several sets of credentials and
+/// source options.  Returns the settings of `[profile.<name>]` itself, which stand in for
+/// command-line arguments that weren't given.
+pub fn apply_profile(conf: &mut Ini, name: &str) -> Result<HashMap<String, String>, SimpleError> {
+    let name = name.to_lowercase();
+    let prefix = format!("profile.{}.", name);
+    let sections = conf.get_map_ref().clone();
+    if !sections.keys().any(|section| *section == format!("profile.{}", name) || section.starts_with(&prefix)) {
+        return Err(SimpleError::new(format!("No profile named {} was found in the config file", name)));
+    }
+    for (section, settings) in &sections {
+        if let Some(section) = section.strip_prefix(&prefix) {
+            for (key, value) in settings {
+                conf.set(section, key, value.clone());
+            }
+        }
+    }
+    Ok(sections.get(&format!("profile.{}", name))
+        .map(|settings| settings.iter()
+            .filter_map(|(key, value)| Some((key.clone(), value.clone()?)))
+            .collect())
+        .unwrap_or_default())
+}
@@ -0,0 +1,108 @@
+//! Keeping the secrets apkeep needs, such as Google Play tokens and upload passwords, in the OS
+//! keyring (the macOS Keychain, Windows Credential Manager, or the Secret Service on Linux)
+//! rather than on the command line or in a plain-text config file.
+
+use std::error::Error;
+use std::io::{self, IsTerminal, Write};
+
+use configparser::ini::Ini;
+use keyring::Entry;
+
+const KEYRING_SERVICE: &str = "apkeep";
+
+/// A service apkeep can log in to, and the settings which `auth login` stores for it.  Settings
+/// are stored under the same section and key names as in the config file.
+pub struct Service {
+    pub name: &'static str,
+    section: &'static str,
+    /// Each setting's key, the prompt to ask for it with, and whether it is a secret, which isn't
+    /// shown as it is typed
+    settings: &'static [(&'static str, &'static str, bool)],
+}
+
+pub const SERVICES: &[Service] = &[
+    Service { name: "google-play", section: "google", settings: &[("email", "Email", false), ("aas_token", "AAS Token", true)] },
+    Service { name: "s3", section: "s3", settings: &[("access_key_id", "Access key ID", false), ("secret_access_key", "Secret access key", true)] },
+    Service { name: "webdav", section: "webdav", settings: &[("username", "Username", false), ("password", "Password", true)] },
+    Service { name: "sftp", section: "sftp", settings: &[("username", "Username", false), ("passphrase", "Identity file passphrase", true)] },
+    Service { name: "mobsf", section: "mobsf", settings: &[("api_key", "API key", true)] },
+    Service { name: "mqtt", section: "mqtt", settings: &[("username", "Username", false), ("password", "Password", true)] },
+];
+
+fn entry(section: &str, key: &str) -> keyring::Result<Entry> {
+    Entry::new(KEYRING_SERVICE, &format!("{}.{}", section, key))
+}
+
+/// A setting from the `[section]` of the config file, or else from the keyring.  A keyring which
+/// is unavailable is treated as empty.
+pub fn get(conf: Option<&Ini>, section: &str, key: &str) -> Option<String> {
+    conf.and_then(|conf| conf.get(section, key))
+        .or_else(|| entry(section, key).ok()?.get_password().ok())
+}
+
+pub fn set(section: &str, key: &str, value: &str) -> Result<(), Box<dyn Error>> {
+    Ok(entry(section, key)?.set_password(value)?)
+}
+
+/// Remove a setting from the keyring, returning whether it was there.
+pub fn delete(section: &str, key: &str) -> Result<bool, Box<dyn Error>> {
+    match entry(section, key)?.delete_credential() {
+        Ok(()) => Ok(true),
+        Err(keyring::Error::NoEntry) => Ok(false),
+        Err(err) => Err(Box::new(err)),
+    }
+}
+
+/// Prompt for a secret, which isn't echoed when it is typed at a terminal.
+pub fn read_secret(prompt: &str) -> io::Result<String> {
+    if io::stdin().is_terminal() {
+        rpassword::prompt_password(format!("{}: ", prompt))
+    } else {
+        let mut value = String::new();
+        print!("{}: ", prompt);
+        io::stdout().flush()?;
+        io::stdin().read_line(&mut value)?;
+        Ok(value)
+    }
+}
+
+impl Service {
+    pub fn find(name: &str) -> Option<&'static Service> {
+        SERVICES.iter().find(|service| service.name == name)
+    }
+
+    /// Prompt for each of the service's settings and store those given in the keyring.  Settings
+    /// left blank are kept as they were.  Secrets typed at a terminal aren't echoed.  Returns the
+    /// number stored.
+    pub fn login(&self) -> Result<usize, Box<dyn Error>> {
+        let mut stored = 0;
+        for (key, prompt, secret) in self.settings {
+            let value = if *secret {
+                read_secret(prompt)?
+            } else {
+                let mut value = String::new();
+                print!("{}: ", prompt);
+                io::stdout().flush()?;
+                io::stdin().read_line(&mut value)?;
+                value
+            };
+            let value = value.trim();
+            if !value.is_empty() {
+                set(self.section, key, value)?;
+                stored += 1;
+            }
+        }
+        Ok(stored)
+    }
+
+    /// Remove the service's settings from the keyring, returning the number removed.
+    pub fn logout(&self) -> Result<usize, Box<dyn Error>> {
+        let mut removed = 0;
+        for (key, _, _) in self.settings {
+            if delete(self.section, key)? {
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
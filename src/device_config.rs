@@ -0,0 +1,110 @@
+//! Describing a device's ABIs, screen density, locales and SDK level, so that of the split APKs
+//! a source delivers, only those the device would itself receive are kept.  A configuration can
+//! be exported from a connected device with `apkeep export-device-config`.
+
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::apk;
+
+#[derive(Serialize, Deserialize)]
+pub struct DeviceConfig {
+    /// Supported ABIs, most preferred first, e.g. `arm64-v8a`
+    pub abis: Vec<String>,
+    /// Screen density in dpi
+    pub density: u32,
+    /// Locales, such as `en-US`, of which only the language decides the splits
+    pub locales: Vec<String>,
+    /// The Android API level
+    pub sdk: u32,
+}
+
+/// The densities configuration splits are named after
+const DENSITIES: [(&str, u32); 7] = [
+    ("ldpi", 120),
+    ("mdpi", 160),
+    ("tvdpi", 213),
+    ("hdpi", 240),
+    ("xhdpi", 320),
+    ("xxhdpi", 480),
+    ("xxxhdpi", 640),
+];
+
+enum Split {
+    Abi(usize),
+    Density(u32),
+    Language(String),
+    /// Base APKs, feature splits and anything else which every device receives
+    Other,
+}
+
+impl DeviceConfig {
+    pub fn load(path: &Path) -> Result<DeviceConfig, Box<dyn Error>> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        Ok(fs::write(path, serde_json::to_string_pretty(self)?)?)
+    }
+
+    /// What a configuration split such as `config.arm64_v8a`, `config.xxhdpi` or `config.de` is
+    /// for, judging by its name.  ABIs the device doesn't support are given its number of ABIs.
+    fn classify(&self, split: Option<&str>) -> Split {
+        let config = match split.and_then(|split| split.strip_prefix("config.")) {
+            Some(config) => config,
+            None => return Split::Other,
+        };
+        const ABIS: [&str; 6] = ["armeabi", "armeabi_v7a", "arm64_v8a", "x86", "x86_64", "riscv64"];
+        if ABIS.contains(&config) {
+            let position = self.abis.iter().position(|abi| abi.replace('-', "_") == config);
+            return Split::Abi(position.unwrap_or(self.abis.len()));
+        }
+        if let Some((_, dpi)) = DENSITIES.iter().find(|(name, _)| *name == config) {
+            return Split::Density(*dpi);
+        }
+        if config.len() <= 3 && config.chars().all(|c| c.is_ascii_lowercase()) {
+            return Split::Language(config.to_string());
+        }
+        Split::Other
+    }
+
+    /// Split the files of an app into those this device would be given, and those it would not:
+    /// the ABI split for its most preferred ABI, the density split nearest its density (rounding
+    /// up), and the language splits for its locales.  Files whose manifest can't be read are kept.
+    pub fn select_splits(&self, files: &[PathBuf]) -> (Vec<PathBuf>, Vec<PathBuf>) {
+        let classified = files.iter()
+            .map(|file| {
+                let split = apk::read_manifest(file).ok().and_then(|manifest| manifest.split);
+                (file, self.classify(split.as_deref()))
+            })
+            .collect::<Vec<_>>();
+
+        let best_abi = classified.iter()
+            .filter_map(|(_, split)| match split { Split::Abi(position) => Some(*position), _ => None })
+            .min();
+        let densities = classified.iter()
+            .filter_map(|(_, split)| match split { Split::Density(dpi) => Some(*dpi), _ => None })
+            .collect::<Vec<_>>();
+        let best_density = densities.iter().filter(|dpi| **dpi >= self.density).min()
+            .or_else(|| densities.iter().max())
+            .copied();
+        let languages = self.locales.iter()
+            .filter_map(|locale| locale.split(['-', '_']).next())
+            .map(|language| language.to_lowercase())
+            .collect::<Vec<_>>();
+
+        let (keep, drop): (Vec<_>, Vec<_>) = classified.into_iter().partition(|(_, split)| match split {
+            Split::Abi(position) => Some(*position) == best_abi && *position < self.abis.len(),
+            Split::Density(dpi) => Some(*dpi) == best_density,
+            Split::Language(language) => languages.contains(language),
+            Split::Other => true,
+        });
+        (
+            keep.into_iter().map(|(file, _)| file.clone()).collect(),
+            drop.into_iter().map(|(file, _)| file.clone()).collect(),
+        )
+    }
+}
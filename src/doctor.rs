@@ -0,0 +1,236 @@
+//! `apkeep doctor`, which checks whether each source is working by finding and downloading an app
+//! it is known to have.  Each stage the source has is checked in turn: searching for the app,
+//! finding its latest version and downloading it.  A source whose requests were answered with 429
+//! or 403 is reported as rate-limited, one which couldn't be reached as unreachable, and one which
+//! answered but couldn't be understood as broken, which usually means the site has changed.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use reqwest::StatusCode;
+
+use crate::cache::PageCache;
+use crate::cli::{Channel, DownloadSource};
+use crate::download_sources::{self, apkcombo, apkpure, fdroid, huawei_app_gallery, plugin, scraped, DownloadedApp};
+use crate::filter::PackageFilter;
+use crate::util::http::{self, HttpSettings};
+use crate::util::trace;
+
+/// The app checked on each source when none is given with `--app`, and the name to search for it
+/// by.  Each has been on its source for years and is small enough to download quickly.
+const TEST_APPS: [(DownloadSource, &str, &str); 4] = [
+    (DownloadSource::APKPure, "com.google.android.apps.authenticator2", "Google Authenticator"),
+    (DownloadSource::HuaweiAppGallery, "com.huawei.browser", "Huawei Browser"),
+    (DownloadSource::APKCombo, "com.google.android.apps.authenticator2", "Google Authenticator"),
+    // Last, since a package index which can't be fetched ends the run
+    (DownloadSource::FDroid, "org.fdroid.fdroid", "F-Droid"),
+];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Health {
+    Working,
+    RateLimited,
+    Unreachable,
+    Broken,
+    Skipped,
+}
+
+impl fmt::Display for Health {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Health::Working => write!(f, "working"),
+            Health::RateLimited => write!(f, "rate-limited"),
+            Health::Unreachable => write!(f, "unreachable"),
+            Health::Broken => write!(f, "broken"),
+            Health::Skipped => write!(f, "skipped"),
+        }
+    }
+}
+
+struct Report {
+    source: DownloadSource,
+    health: Health,
+    detail: String,
+}
+
+/// What a source needs to be checked: the app to check it with, and the name to search for
+struct Check<'a> {
+    source: DownloadSource,
+    app_id: String,
+    name: String,
+    site: Option<&'a scraped::Site>,
+    plugin: Option<&'a plugin::Plugin>,
+}
+
+pub async fn run(matches: &clap::ArgMatches) {
+    let app = matches.get_one::<String>("app");
+    let site = match matches.get_one::<String>("rules").map(|rules| scraped::Site::load(rules)) {
+        Some(Ok(site)) => Some(site),
+        Some(Err(err)) => {
+            println!("Could not load the rules for the scraped site: {}. Exiting.", err);
+            std::process::exit(1);
+        },
+        None => None,
+    };
+    let plugin = match matches.get_one::<String>("plugin").map(|plugin| plugin::Plugin::load(plugin)) {
+        Some(Ok(plugin)) => Some(plugin),
+        Some(Err(err)) => {
+            println!("Could not load the plugin: {}. Exiting.", err);
+            std::process::exit(1);
+        },
+        None => None,
+    };
+    let sources = match matches.get_many::<DownloadSource>("source") {
+        Some(sources) => sources.copied().collect::<Vec<_>>(),
+        None => {
+            let mut sources = TEST_APPS.iter().map(|(source, _, _)| *source).collect::<Vec<_>>();
+            if site.is_some() {
+                sources.insert(0, DownloadSource::Scraped);
+            }
+            if plugin.is_some() {
+                sources.insert(0, DownloadSource::Plugin);
+            }
+            sources
+        },
+    };
+    let download = !matches.get_flag("no_download");
+    http::configure(HttpSettings { allow_insecure: matches.get_flag("allow_insecure"), ..HttpSettings::default() });
+
+    trace::start_observing();
+    let mut reports = vec![];
+    for source in sources {
+        let test_app = TEST_APPS.iter().find(|(test_source, _, _)| *test_source == source);
+        let (app_id, name) = match (app, test_app) {
+            (Some(app), _) => (app.to_string(), app.to_string()),
+            (None, Some((_, app_id, name))) => (app_id.to_string(), name.to_string()),
+            (None, None) => {
+                reports.push(skip(source, "no app is known to be on it, so one must be given with --app"));
+                continue;
+            },
+        };
+        let report = match source {
+            DownloadSource::GooglePlay => skip(source, "checking it needs an account"),
+            DownloadSource::Scraped if site.is_none() => skip(source, "the site to check must be given with --rules"),
+            DownloadSource::Plugin if plugin.is_none() => skip(source, "the plugin to check must be given with --plugin"),
+            _ => {
+                println!("Checking {} with {}...", source, app_id);
+                check(Check { source, app_id, name, site: site.as_ref(), plugin: plugin.as_ref() }, download).await
+            },
+        };
+        println!("{}: {}{}", report.source, report.health, detail_suffix(&report.detail));
+        reports.push(report);
+    }
+
+    println!("\n{:<20}{:<14}DETAIL", "SOURCE", "STATUS");
+    for report in &reports {
+        println!("{:<20}{:<14}{}", report.source.to_string(), report.health.to_string(), report.detail);
+    }
+    if reports.iter().any(|report| !matches!(report.health, Health::Working | Health::Skipped)) {
+        std::process::exit(1);
+    }
+}
+
+fn skip(source: DownloadSource, reason: &str) -> Report {
+    Report { source, health: Health::Skipped, detail: reason.to_string() }
+}
+
+fn detail_suffix(detail: &str) -> String {
+    if detail.is_empty() {
+        String::new()
+    } else {
+        format!(" ({})", detail)
+    }
+}
+
+/// Check each stage the source has, stopping at the first which fails.  How the requests of each
+/// stage were answered is forgotten once it passes, so that a failure is judged only by its own.
+async fn check(check: Check<'_>, download: bool) -> Report {
+    let source = check.source;
+    let mut passed = vec![];
+    trace::take_observed();
+
+    let searched = match source {
+        DownloadSource::FDroid => Some(Ok(fdroid::search(&check.name, &HashMap::new()).await)),
+        DownloadSource::APKCombo => Some(apkcombo::search(&check.name).await),
+        DownloadSource::Scraped => Some(check.site.unwrap().search(&check.name).await.map_err(|err| err.to_string())),
+        _ => None,
+    };
+    if let Some(searched) = searched {
+        let found = match searched {
+            Ok(matches) if matches.iter().any(|found| found.app_id == check.app_id) => Ok(()),
+            Ok(matches) => Err(format!("searching for {} didn't find {} among {} results", check.name, check.app_id, matches.len())),
+            Err(err) => Err(format!("searching failed: {}", err)),
+        };
+        if let Err(failure) = found {
+            return failed(source, failure);
+        }
+        passed.push("search");
+        trace::take_observed();
+    }
+
+    let apps = vec![check.app_id.clone()];
+    let latest = match source {
+        DownloadSource::APKPure => Some(apkpure::latest_versions(apps, 1, 0).await),
+        DownloadSource::FDroid => Some(fdroid::latest_versions(apps, &HashMap::new(), Channel::Stable, None).await),
+        DownloadSource::Plugin => Some(plugin::latest_versions(apps, &HashMap::new(), check.plugin.unwrap()).await),
+        DownloadSource::Scraped if check.site.unwrap().has_versions() => Some(scraped::latest_versions(apps, check.site.unwrap()).await),
+        _ => None,
+    };
+    if let Some(latest) = latest {
+        let found = latest.iter().any(|latest| latest.app_id == check.app_id && (latest.version.is_some() || latest.version_code.is_some()));
+        if !found {
+            return failed(source, format!("the latest version of {} could not be found{}", check.app_id, reason()));
+        }
+        passed.push("version");
+        trace::take_observed();
+    }
+
+    if download {
+        let dir = match tempfile::tempdir() {
+            Ok(dir) => dir,
+            Err(err) => return failed(source, format!("there was nowhere to download to: {}", err)),
+        };
+        let apps = vec![(check.app_id.clone(), None)];
+        let downloaded: Vec<DownloadedApp> = match source {
+            DownloadSource::APKPure => apkpure::download_apps(apps, 1, 0, dir.path()).await,
+            DownloadSource::FDroid => fdroid::download_apps(apps, 1, 0, dir.path(), HashMap::new(), &HashMap::new(), None, &PackageFilter::default(), Channel::Stable, None).await,
+            DownloadSource::HuaweiAppGallery => huawei_app_gallery::download_apps(apps, 1, 0, dir.path()).await,
+            DownloadSource::APKCombo => apkcombo::download_apps(apps, 1, 0, dir.path(), HashMap::new(), &PageCache::new(std::time::Duration::ZERO)).await,
+            DownloadSource::Plugin => plugin::download_apps(apps, 1, 0, dir.path(), HashMap::new(), check.plugin.unwrap()).await,
+            DownloadSource::Scraped => scraped::download_apps(apps, 1, 0, dir.path(), check.site.unwrap()).await,
+            DownloadSource::GooglePlay => vec![],
+        };
+        let written = downloaded.iter().any(|app| !app.files.is_empty() && app.files.iter().all(|file| file.metadata().is_ok_and(|metadata| metadata.len() > 0)));
+        if !written {
+            return failed(source, format!("{} could not be downloaded{}", check.app_id, reason()));
+        }
+        passed.push("download");
+        trace::take_observed();
+    }
+
+    // Whatever failed along the way was retried
+    download_sources::take_failures();
+    Report { source, health: Health::Working, detail: format!("{} passed", passed.join(", ")) }
+}
+
+/// The reason the source gave for the failure just seen, if it gave one
+fn reason() -> String {
+    match download_sources::take_failures().pop() {
+        Some((_, reason)) => format!(": {}", reason.trim_end_matches(" Skipping...")),
+        None => String::new(),
+    }
+}
+
+/// A report of a stage which failed, judged by how the source's requests were answered
+fn failed(source: DownloadSource, detail: String) -> Report {
+    let observed = trace::take_observed();
+    let health = if observed.iter().any(|status| matches!(*status, Some(StatusCode::TOO_MANY_REQUESTS) | Some(StatusCode::FORBIDDEN))) {
+        Health::RateLimited
+    } else if !observed.is_empty() && observed.iter().all(Option::is_none) {
+        Health::Unreachable
+    } else {
+        Health::Broken
+    };
+    download_sources::take_failures();
+    Report { source, health, detail }
+}
@@ -1,13 +1,20 @@
-use futures::StreamExt;
-use log::debug;
-use regex::Regex;
+use futures_util::StreamExt;
 use reqwest::header::{HeaderMap, HeaderValue, USER_AGENT};
+use reqwest::Url;
 use std::collections::HashMap;
-use std::fs::{self, File};
-use std::io::Write;
 use std::path::Path;
-use std::time::Duration;
-use tokio::time::sleep;
+use indicatif::MultiProgress;
+use scraper::{Html, Selector};
+
+use crate::cache::PageCache;
+use crate::cli::DownloadSource;
+use crate::download_sources::{attempt, claims, deadline, DownloadedApp, SearchMatch};
+use crate::patterns;
+use crate::util::{http, is_app_id, network_error, robots, retry, safe_file_name, safe_join};
+use crate::util::throttle::{Outcome, Throttle};
+use crate::util::timings::{self, Phase};
+
+const BROWSER_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/91.0.4472.124 Safari/537.36";
 
 pub async fn download_apps(
     app_ids: Vec<(String, Option<String>)>,
@@ -15,104 +22,134 @@ pub async fn download_apps(
     sleep_duration: u64,
     output_path: &Path,
     options: HashMap<&str, &str>,
-) {
-    let sleep_duration = Duration::from_millis(sleep_duration);
-    let mut buffered = futures::stream::iter(app_ids)
-        .map(|(app_id, version)| {
-            async move {
-                if !version.is_none() {
-                    println!("Warning: APKCombo does not support downloading specific versions. Will download the latest version for {}", app_id);
-                }
-                sleep(sleep_duration).await;
-                match download_app(&app_id, output_path, &options).await {
-                    Ok(filename) => {
-                        println!("Successfully downloaded {} as {}", app_id, filename);
+    cache: &PageCache,
+) -> Vec<DownloadedApp> {
+    let throttle = Throttle::new("APKCombo", parallel, sleep_duration, &MultiProgress::new());
+    let (options, throttle) = (&options, &throttle);
+    futures_util::stream::iter(app_ids)
+        .map(|(app_id, _)| {
+            attempt(DownloadSource::APKCombo, app_id.clone(), async move {
+                let _slot = throttle.acquire().await;
+                match download_app(&app_id, output_path, options, cache, throttle).await {
+                    Ok(Some(filename)) => {
+                        println!("{}", tr!("downloaded-as", app = app_id.as_str(), file = filename.as_str()));
+                        Some(DownloadedApp::new(app_id, None, vec![safe_join(output_path, &filename)]))
                     }
+                    Ok(None) => None,
                     Err(e) => {
-                        println!("Error downloading {}: {}", app_id, e);
+                        println!("{}", super::failed(&app_id, tr!("download-error", app = app_id.as_str(), error = e.as_str())));
+                        None
                     }
                 }
-            }
+            })
         })
-        .buffer_unordered(parallel);
-
-    while buffered.next().await.is_some() {}
+        .buffer_unordered(parallel)
+        .filter_map(|downloaded| async { downloaded })
+        .collect::<Vec<DownloadedApp>>()
+        .await
 }
 
 async fn download_app(
     app_id: &str,
     output_path: &Path,
     options: &HashMap<&str, &str>,
-) -> Result<String, String> {
+    cache: &PageCache,
+    throttle: &Throttle,
+) -> Result<Option<String>, String> {
     // Create a client with appropriate headers
-    let client = reqwest::Client::builder()
-        .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/91.0.4472.124 Safari/537.36")
+    let client = http::with_source_headers(http::client_builder().user_agent(BROWSER_USER_AGENT), DownloadSource::APKCombo)
         .build()
         .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
 
-    // First search for the app
-    let search_url = format!("https://apkcombo.com/search/{}/", app_id);
-    println!("Searching for {} on APKCombo", app_id);
-    
-    let response = client.get(&search_url)
-        .send()
-        .await
-        .map_err(|e| format!("Failed to search for app: {}", e))?;
-    
-    if !response.status().is_success() {
-        return Err(format!("Failed to search for app: HTTP {}", response.status()));
-    }
-    
-    let html = response.text()
-        .await
-        .map_err(|e| format!("Failed to read search response: {}", e))?;
-    
-    // Find the app page URL in search results
-    let app_url_re = Regex::new(r#"href="(/[^/]+/[^/]+/[^"]+)"#).unwrap();
-    let app_url = html.lines()
-        .filter(|line| line.contains(app_id))
-        .find_map(|line| {
-            app_url_re.captures(line).map(|cap| cap[1].to_string())
-        })
-        .ok_or_else(|| format!("App {} not found on APKCombo", app_id))?;
+    // First search for the app, unless its page was found recently
+    let app_url_key = format!("apkcombo:app-url:{}", app_id);
+    let timer = timings::start(app_id, Phase::Search);
+    let app_url = match cache.get(&app_url_key) {
+        Some(app_url) => app_url,
+        None => {
+            let search_url = format!("https://apkcombo.com/search/{}/", app_id);
+            println!("{}", tr!("searching", app = app_id, source = "APKCombo"));
+
+            robots::allow(&search_url).await.map_err(|e| e.to_string())?;
+            let _permit = http::host_permit(&search_url).await;
+
+            let response = retry::send(client.get(&search_url)).await
+                .inspect(|response| throttle.record(Outcome::from_status(response.status())))
+                .inspect_err(|_| throttle.record(Outcome::Failure))
+                .map_err(|e| format!("Failed to search for app. {}", network_error::describe(&e)))?;
+
+            if !response.status().is_success() {
+                return Err(format!("Failed to search for app: HTTP {}", response.status()));
+            }
+
+            let html = response.text()
+                .await
+                .map_err(|e| format!("Failed to read search response. {}", network_error::describe(&e)))?;
+
+            // Find the app page URL in search results, which must be that of the app itself rather
+            // than of any result which mentions it
+            let app_url = html.lines()
+                .filter(|line| line.contains(app_id))
+                .find_map(|line| {
+                    patterns::APKCOMBO_APP_URL.captures(line).map(|cap| cap[1].to_string())
+                        .filter(|app_url| app_url.split('/').any(|segment| segment == app_id))
+                })
+                .ok_or_else(|| format!("App {} not found on APKCombo", app_id))?;
+            cache.put(&app_url_key, &app_url);
+            app_url
+        }
+    };
+    drop(timer);
     
     let full_app_url = format!("https://apkcombo.com{}", app_url);
-    println!("Found app page: {}", full_app_url);
+    println!("{}", tr!("found-app-page", url = full_app_url.as_str()));
     
     // Fetch the app page to get the download URL
-    let app_response = client.get(&full_app_url)
-        .send()
-        .await
-        .map_err(|e| format!("Failed to access app page: {}", e))?;
-    
-    if !app_response.status().is_success() {
-        return Err(format!("Failed to access app page: HTTP {}", app_response.status()));
-    }
-    
-    let app_html = app_response.text()
-        .await
-        .map_err(|e| format!("Failed to read app page: {}", e))?;
+    let app_page_key = format!("apkcombo:app-page:{}", full_app_url);
+    let timer = timings::start(app_id, Phase::Resolve);
+    let app_html = match cache.get(&app_page_key) {
+        Some(app_html) => app_html,
+        None => {
+            robots::allow(&full_app_url).await.map_err(|e| e.to_string())?;
+            let _permit = http::host_permit(&full_app_url).await;
+            let app_response = retry::send(client.get(&full_app_url)).await
+                .inspect(|response| throttle.record(Outcome::from_status(response.status())))
+                .inspect_err(|_| throttle.record(Outcome::Failure))
+                .map_err(|e| format!("Failed to access app page. {}", network_error::describe(&e)))?;
+
+            if !app_response.status().is_success() {
+                return Err(format!("Failed to access app page: HTTP {}", app_response.status()));
+            }
+
+            let app_html = app_response.text()
+                .await
+                .map_err(|e| format!("Failed to read app page. {}", network_error::describe(&e)))?;
+            cache.put(&app_page_key, &app_html);
+            app_html
+        }
+    };
     
     // Extract download link from the page
-    let download_url_re = Regex::new(r#"downloadButton"\s+href="([^"]+)"#).unwrap();
-    let download_url = download_url_re
+    let download_url = patterns::APKCOMBO_DOWNLOAD_URL
         .captures(&app_html)
         .and_then(|cap| cap.get(1))
         .map(|m| m.as_str().to_string())
         .ok_or_else(|| format!("Download link not found for {}", app_id))?;
     
     let full_download_url = if download_url.starts_with("http") {
-        download_url
+        http::secure_url(&download_url)
     } else {
         format!("https://apkcombo.com{}", download_url)
     };
-    println!("Found download URL: {}", full_download_url);
+    println!("{}", tr!("found-download-url", url = full_download_url.as_str()));
 
     // Access the download page to get the actual file
-    let download_page_response = client.get(&full_download_url)
-        .send()
-        .await
-        .map_err(|e| format!("Failed to access download page: {}", e))?;
+    robots::allow(&full_download_url).await.map_err(|e| e.to_string())?;
+    let permit = http::host_permit(&full_download_url).await;
+    let download_page_response = retry::send(client.get(&full_download_url)).await
+        .inspect(|response| throttle.record(Outcome::from_status(response.status())))
+        .inspect_err(|_| throttle.record(Outcome::Failure))
+        .map_err(|e| format!("Failed to access download page. {}", network_error::describe(&e)))?;
     
     if !download_page_response.status().is_success() {
         return Err(format!("Failed to access download page: HTTP {}", download_page_response.status()));
@@ -120,17 +157,18 @@ async fn download_app(
     
     let download_page_html = download_page_response.text()
         .await
-        .map_err(|e| format!("Failed to read download page: {}", e))?;
+        .map_err(|e| format!("Failed to read download page. {}", network_error::describe(&e)))?;
+    drop(permit);
     
     // Find the final download link
-    let final_url_re = Regex::new(r#"href="(https://[^"]+\.apk[^"]*)"#).unwrap();
-    let final_download_url = final_url_re
+    let final_download_url = patterns::APKCOMBO_FILE_URL
         .captures(&download_page_html)
         .and_then(|cap| cap.get(1))
         .map(|m| m.as_str().to_string())
         .ok_or_else(|| format!("Final APK download link not found for {}", app_id))?;
+    drop(timer);
     
-    println!("Downloading APK from: {}", final_download_url);
+    println!("{}", tr!("downloading-from", url = final_download_url.as_str()));
     
     // Download the APK file
     let mut headers = HeaderMap::new();
@@ -138,51 +176,99 @@ async fn download_app(
         "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/91.0.4472.124 Safari/537.36"
     ));
     
-    let response = client.get(&final_download_url)
-        .headers(headers)
-        .send()
-        .await
-        .map_err(|e| format!("Failed to download APK: {}", e))?;
+    let _timer = timings::start(app_id, Phase::Download);
+    robots::allow(&final_download_url).await.map_err(|e| e.to_string())?;
+    let _permit = http::host_permit(&final_download_url).await;
+    let response = retry::send(client.get(&final_download_url)
+        .headers(headers)).await
+        .inspect(|response| throttle.record(Outcome::from_status(response.status())))
+        .inspect_err(|_| throttle.record(Outcome::Failure))
+        .map_err(|e| format!("Failed to download APK. {}", network_error::describe(&e)))?;
     
     if !response.status().is_success() {
         return Err(format!("Failed to download APK: HTTP {}", response.status()));
     }
+    if super::too_large(app_id, response.content_length(), &MultiProgress::new()) {
+        return Ok(None);
+    }
     
-    // Generate filename from the response
-    let filename = response
+    // Generate filename from the response, which must not be trusted to name a file within the
+    // output directory
+    let filename = safe_file_name(&response
         .headers()
         .get("content-disposition")
         .and_then(|header| {
             header.to_str().ok().and_then(|s| {
-                let re = Regex::new(r#"filename=(?:"([^"]+)"|([^;]+))"#).unwrap();
-                re.captures(s).map(|cap| {
-                    cap.get(1).unwrap_or_else(|| cap.get(2).unwrap()).as_str()
+                patterns::CONTENT_DISPOSITION_FILENAME.captures(s).map(|cap| {
+                    cap.get(1).unwrap_or_else(|| cap.get(2).unwrap()).as_str().to_string()
                 })
             })
         })
-        .unwrap_or_else(|| format!("{}.apk", app_id).as_str())
-        .to_string();
+        .unwrap_or_else(|| format!("{}.apk", app_id)));
+    let (filename, _claim) = match claims::claim(output_path, &filename, app_id, &MultiProgress::new()) {
+        Some(claimed) => claimed,
+        None => return Ok(None),
+    };
     
-    let output_file_path = output_path.join(&filename);
+    let output_file_path = safe_join(output_path, &filename);
     
-    // Save the APK file
-    let apk_data = response.bytes()
+    // Save the APK file as it arrives, without blocking the runtime on disk writes
+    deadline::writing(&output_file_path);
+    http::write_response_to_file(response, &output_file_path)
         .await
-        .map_err(|e| format!("Failed to read APK data: {}", e))?;
+        .map_err(|e| format!("Failed to save APK data. {}", e))?;
     
-    let mut file = File::create(&output_file_path)
-        .map_err(|e| format!("Failed to create output file: {}", e))?;
-    
-    file.write_all(&apk_data)
-        .map_err(|e| format!("Failed to write APK data to file: {}", e))?;
-    
-    Ok(filename)
+    Ok(Some(filename))
+}
+
+/// Search APKCombo for apps matching a name, in the order it ranks them.  Each result links to
+/// `/<name>/<app_id>/`, with the app's name and developer within the link.
+pub async fn search(query: &str) -> Result<Vec<SearchMatch>, String> {
+    // The query is free text, so it is encoded as a single path segment rather than pasted in
+    let mut search_url = Url::parse("https://apkcombo.com/search/").unwrap();
+    search_url.path_segments_mut().unwrap().pop_if_empty().push(query).push("");
+    let search_url = search_url.to_string();
+    let client = http::with_source_headers(http::client_builder().user_agent(BROWSER_USER_AGENT), DownloadSource::APKCombo)
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+    robots::allow(&search_url).await.map_err(|e| e.to_string())?;
+    let _permit = http::host_permit(&search_url).await;
+    let response = retry::send(client.get(&search_url)).await
+        .map_err(|e| format!("Failed to search for app. {}", network_error::describe(&e)))?;
+    if !response.status().is_success() {
+        return Err(format!("Failed to search for app: HTTP {}", response.status()));
+    }
+    let html = response.text()
+        .await
+        .map_err(|e| format!("Failed to read search response. {}", network_error::describe(&e)))?;
+
+    let page = Html::parse_document(&html);
+    let (link, name, author) = (Selector::parse("a[href]").unwrap(), Selector::parse(".name").unwrap(), Selector::parse(".author").unwrap());
+    let mut matches: Vec<SearchMatch> = vec![];
+    for result in page.select(&link) {
+        let segments = result.value().attr("href").unwrap_or_default().trim_matches('/').split('/').collect::<Vec<_>>();
+        let app_id = match segments.as_slice() {
+            [_, app_id] if is_app_id(app_id) => app_id.to_string(),
+            _ => continue,
+        };
+        if matches.iter().any(|found| found.app_id == app_id) {
+            continue;
+        }
+        let text = |selector: &Selector| result.select(selector).next()
+            .map(|element| element.text().collect::<String>().trim().to_string())
+            .filter(|text| !text.is_empty());
+        matches.push(SearchMatch {
+            name: text(&name).unwrap_or_else(|| app_id.clone()),
+            developer: text(&author),
+            app_id,
+        });
+    }
+    Ok(matches)
 }
 
 pub async fn list_versions(
     app_ids: Vec<(String, Option<String>)>,
     options: HashMap<&str, &str>,
 ) {
-    println!("APKCombo does not support listing versions at this time.");
-    println!("Only the latest version of each app is available for download.");
+    println!("{}", tr!("versions-not-supported", source = "APKCombo"));
 }
\ No newline at end of file
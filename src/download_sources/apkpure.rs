@@ -1,7 +1,7 @@
 use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
 use std::ops::Deref;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
 use futures_util::StreamExt;
@@ -11,9 +11,13 @@ use reqwest::header::{HeaderMap, HeaderValue};
 use reqwest::{Url, Response};
 use serde_json::json;
 use tokio_dl_stream_to_disk::{AsyncDownload, error::ErrorKind as TDSTDErrorKind};
-use tokio::time::{sleep, Duration as TokioDuration};
 
-use crate::util::{OutputFormat, progress_bar::progress_wrapper};
+use crate::cli::DownloadSource;
+use crate::download_sources::{attempt, claims, deadline, DownloadedApp, LatestVersion, needs_download};
+use crate::patterns;
+use crate::util::{OutputFormat, digests, http, network_error, progress_bar::progress_wrapper, retry, safe_file_name, safe_join, trace, tsv_row};
+use crate::util::throttle::{Outcome, Throttle};
+use crate::util::timings::{self, Phase};
 
 fn http_headers() -> HeaderMap {
     let mut headers = HeaderMap::new();
@@ -32,92 +36,154 @@ pub async fn download_apps(
     parallel: usize,
     sleep_duration: u64,
     outpath: &Path,
-) {
+) -> Vec<DownloadedApp> {
     let mp = Rc::new(MultiProgress::new());
-    let http_client = Rc::new(reqwest::Client::new());
+    let http_client = Rc::new(http::source_client(DownloadSource::APKPure));
     let headers = http_headers();
-    let re = Rc::new(Regex::new(crate::consts::APKPURE_DOWNLOAD_URL_REGEX).unwrap());
+    let throttle = Rc::new(Throttle::new("APKPure", parallel, sleep_duration, &mp));
 
     futures_util::stream::iter(
         apps.into_iter().map(|app| {
             let (app_id, app_version) = app;
             let http_client = Rc::clone(&http_client);
-            let re = Rc::clone(&re);
+            let throttle = Rc::clone(&throttle);
             let headers = headers.clone();
             let mp = Rc::clone(&mp);
             let mp_log = Rc::clone(&mp);
-            async move {
+            attempt(DownloadSource::APKPure, app_id.clone(), async move {
                 let app_string = match app_version {
                     Some(ref version) => {
-                        mp_log.suspend(|| println!("Downloading {} version {}...", app_id, version));
+                        mp_log.suspend(|| println!("{}", tr!("downloading-version", app = app_id.as_str(), version = version.as_str())));
                         format!("{}@{}", app_id, version)
                     },
                     None => {
-                        mp_log.suspend(|| println!("Downloading {}...", app_id));
+                        mp_log.suspend(|| println!("{}", tr!("downloading", app = app_id.as_str())));
                         app_id.to_string()
                     },
                 };
-                if sleep_duration > 0 {
-                    sleep(TokioDuration::from_millis(sleep_duration)).await;
-                }
+                let _slot = throttle.acquire().await;
                 let versions_url = Url::parse(&format!("{}{}", crate::consts::APKPURE_VERSIONS_URL_FORMAT, app_id)).unwrap();
-                let versions_response = http_client
-                    .get(versions_url)
-                    .headers(headers)
-                    .send().await.unwrap();
-                if let Some(app_version) = app_version {
-                    let regex_string = format!("[[:^digit:]]{}:(?s:.)+?{}", regex::escape(&app_version), crate::consts::APKPURE_DOWNLOAD_URL_REGEX);
+                let versions_response = {
+                    let _timer = timings::start(&app_id, Phase::Resolve);
+                    let _permit = http::host_permit(versions_url.as_str()).await;
+                    retry::send(http_client
+                        .get(versions_url)
+                        .headers(headers)).await
+                };
+                let versions_response = match versions_response {
+                    Ok(versions_response) => {
+                        throttle.record(Outcome::from_status(versions_response.status()));
+                        versions_response
+                    },
+                    Err(err) => {
+                        throttle.record(Outcome::Failure);
+                        mp_log.println(super::failed(&app_string, tr!("no-versions-retrieved", app = app_string.as_str(), error = network_error::describe(&err)))).unwrap();
+                        return None;
+                    }
+                };
+                let file = if let Some(ref app_version) = app_version {
+                    let regex_string = format!("[[:^digit:]]{}:(?s:.)+?{}", regex::escape(app_version), patterns::apkpure_download_url_pattern());
                     let re = Regex::new(&regex_string).unwrap();
-                    download_from_response(versions_response, Box::new(Box::new(re)), app_string, outpath, mp).await;
+                    download_from_response(versions_response, Box::new(Box::new(re)), &app_id, app_string, outpath, mp, &throttle).await
                 } else {
-                    download_from_response(versions_response, Box::new(re), app_string, outpath, mp).await;
-                }
-            }
+                    download_from_response(versions_response, Box::new(&*patterns::APKPURE_DOWNLOAD_URL), &app_id, app_string, outpath, mp, &throttle).await
+                };
+                file.map(|(file, url)| DownloadedApp::new(app_id, app_version, vec![file]).with_url(url))
+            })
         })
-    ).buffer_unordered(parallel).collect::<Vec<()>>().await;
+    ).buffer_unordered(parallel).filter_map(|downloaded| async { downloaded }).collect::<Vec<DownloadedApp>>().await
 }
 
-async fn download_from_response(response: Response, re: Box<dyn Deref<Target=Regex>>, app_string: String, outpath: &Path, mp: Rc<MultiProgress>) {
+async fn download_from_response(response: Response, re: Box<dyn Deref<Target=Regex>>, app_id: &str, app_string: String, outpath: &Path, mp: Rc<MultiProgress>, throttle: &Throttle) -> Option<(PathBuf, String)> {
     let mp_log = Rc::clone(&mp);
     let mp = Rc::clone(&mp);
     match response.status() {
         reqwest::StatusCode::OK => {
-            let body = response.text().await.unwrap();
+            let body = {
+                let _timer = timings::start(app_id, Phase::Resolve);
+                response.text().await
+            };
+            let body = match body {
+                Ok(body) => body,
+                Err(err) => {
+                    mp_log.println(super::failed(&app_string, tr!("no-app-response", app = app_string.as_str(), error = network_error::describe(&err)))).unwrap();
+                    return None;
+                }
+            };
             match re.captures(&body) {
                 Some(caps) if caps.len() >= 2 => {
                     let apk_xapk = caps.get(1).unwrap().as_str();
-                    let download_url = caps.get(2).unwrap().as_str();
-                    let fname = match apk_xapk {
+                    let download_url = &http::secure_url(caps.get(2).unwrap().as_str());
+                    let fname = safe_file_name(&match apk_xapk {
                         "XAPKJ" => format!("{}.xapk", app_string),
                         _ => format!("{}.apk", app_string),
-                    };
+                    });
 
+                    let (fname, _claim) = match claims::claim(Path::new(outpath), &fname, &app_string, &mp_log) {
+                        Some(claimed) => claimed,
+                        None => return None,
+                    };
+                    if !needs_download(download_url, &safe_join(outpath, &fname), None, &app_string, &mp_log).await {
+                        return None;
+                    }
+                    let _permit = http::host_permit(download_url).await;
+                    let _timer = timings::start(app_id, Phase::Download);
+                    deadline::writing(&safe_join(outpath, &fname));
+                    trace::download(download_url);
                     match AsyncDownload::new(download_url, Path::new(outpath), &fname).get().await {
                         Ok(mut dl) => {
                             let length = dl.length();
+                            if super::too_large(&app_string, length, &mp_log) {
+                                return None;
+                            }
                             let cb = match length {
                                 Some(length) => Some(progress_wrapper(mp)(fname.clone(), length)),
                                 None => None,
                             };
 
-                            match dl.download(&cb).await {
-                                Ok(_) => mp_log.suspend(|| println!("{} downloaded successfully!", app_string)),
+                            match dl.download_and_return_sha256sum(&cb).await {
+                                Ok(sha256sum) => {
+                                    let _ = digests::record_sha256(&safe_join(outpath, &fname), &sha256sum);
+                                    mp_log.suspend(|| println!("{}", tr!("downloaded", app = app_string.as_str())));
+                                    Some((safe_join(outpath, &fname), download_url.to_string()))
+                                },
                                 Err(err) if matches!(err.kind(), TDSTDErrorKind::FileExists) => {
-                                    mp_log.println(format!("File already exists for {}. Skipping...", app_string)).unwrap();
+                                    mp_log.println(tr!("file-exists", app = app_string.as_str())).unwrap();
+                                    None
                                 },
                                 Err(err) if matches!(err.kind(), TDSTDErrorKind::PermissionDenied) => {
-                                    mp_log.println(format!("Permission denied when attempting to write file for {}. Skipping...", app_string)).unwrap();
+                                    mp_log.println(super::failed(&app_string, tr!("permission-denied", app = app_string.as_str()))).unwrap();
+                                    None
+                                },
+                                Err(err) if !retry::download_error(&err) => {
+                                    throttle.record(Outcome::Failure);
+                                    mp_log.println(super::failed(&app_string, tr!("download-not-retried", app = app_string.as_str(), error = err.to_string()))).unwrap();
+                                    None
                                 },
                                 Err(_) => {
-                                    mp_log.println(format!("An error has occurred attempting to download {}.  Retry #1...", app_string)).unwrap();
-                                    match AsyncDownload::new(download_url, Path::new(outpath), &fname).download(&cb).await {
-                                        Ok(_) => mp_log.suspend(|| println!("{} downloaded successfully!", app_string)),
+                                    throttle.record(Outcome::Failure);
+                                    mp_log.println(tr!("download-retry", app = app_string.as_str(), attempt = 1)).unwrap();
+                                    match AsyncDownload::new(download_url, Path::new(outpath), &fname).download_and_return_sha256sum(&cb).await {
+                                        Ok(sha256sum) => {
+                                            let _ = digests::record_sha256(&safe_join(outpath, &fname), &sha256sum);
+                                            mp_log.suspend(|| println!("{}", tr!("downloaded", app = app_string.as_str())));
+                                            Some((safe_join(outpath, &fname), download_url.to_string()))
+                                        },
+                                        Err(err) if !retry::download_error(&err) => {
+                                            mp_log.println(super::failed(&app_string, tr!("download-not-retried", app = app_string.as_str(), error = err.to_string()))).unwrap();
+                                            None
+                                        },
                                         Err(_) => {
-                                            mp_log.println(format!("An error has occurred attempting to download {}.  Retry #2...", app_string)).unwrap();
-                                            match AsyncDownload::new(download_url, Path::new(outpath), &fname).download(&cb).await {
-                                                Ok(_) => mp_log.suspend(|| println!("{} downloaded successfully!", app_string)),
+                                            mp_log.println(tr!("download-retry", app = app_string.as_str(), attempt = 2)).unwrap();
+                                            match AsyncDownload::new(download_url, Path::new(outpath), &fname).download_and_return_sha256sum(&cb).await {
+                                                Ok(sha256sum) => {
+                                                    let _ = digests::record_sha256(&safe_join(outpath, &fname), &sha256sum);
+                                                    mp_log.suspend(|| println!("{}", tr!("downloaded", app = app_string.as_str())));
+                                                    Some((safe_join(outpath, &fname), download_url.to_string()))
+                                                },
                                                 Err(_) => {
-                                                    mp_log.println(format!("An error has occurred attempting to download {}. Skipping...", app_string)).unwrap();
+                                                    mp_log.println(super::failed(&app_string, tr!("download-failed", app = app_string.as_str()))).unwrap();
+                                                    None
                                                 }
                                             }
                                         }
@@ -126,93 +192,220 @@ async fn download_from_response(response: Response, re: Box<dyn Deref<Target=Reg
                             }
                         },
                         Err(_) => {
-                            mp_log.println(format!("Invalid response for {}. Skipping...", app_string)).unwrap();
+                            mp_log.println(super::failed(&app_string, tr!("invalid-response", app = app_string.as_str()))).unwrap();
+                            None
                         }
                     }
                 },
                 _ => {
-                    mp_log.println(format!("Could not get download URL for {}. Skipping...", app_string)).unwrap();
+                    mp_log.println(super::failed(&app_string, tr!("no-download-url", app = app_string.as_str()))).unwrap();
+                    None
                 }
             }
 
         },
         _ => {
-            mp_log.println(format!("Invalid app response for {}. Skipping...", app_string)).unwrap();
+            mp_log.println(super::failed(&app_string, tr!("invalid-app-response", app = app_string.as_str()))).unwrap();
+            None
         }
     }
 }
 
-pub async fn list_versions(apps: Vec<(String, Option<String>)>, options: HashMap<&str, &str>) {
-    let http_client = Rc::new(reqwest::Client::new());
-    let re = Rc::new(Regex::new(r"([[:alnum:]\.-]+):\([[:xdigit:]]{40,}").unwrap());
+/// The latest version of each app, the first the versions API lists.  Only its versionName is
+/// known.
+pub async fn latest_versions(apps: Vec<String>, parallel: usize, sleep_duration: u64) -> Vec<LatestVersion> {
+    let http_client = http::source_client(DownloadSource::APKPure);
     let headers = http_headers();
-    let output_format = match options.get("output_format") {
-        Some(val) if val.to_lowercase() == "json" => OutputFormat::Json,
-        _ => OutputFormat::Plaintext,
-    };
+    let throttle = Throttle::new("APKPure", parallel, sleep_duration, &MultiProgress::new());
+    let (http_client, headers, throttle) = (&http_client, &headers, &throttle);
+    futures_util::stream::iter(apps)
+        .map(|app_id| async move {
+            let _slot = throttle.acquire().await;
+            let versions_url = Url::parse(&format!("{}{}", crate::consts::APKPURE_VERSIONS_URL_FORMAT, app_id)).unwrap();
+            let _permit = http::host_permit(versions_url.as_str()).await;
+            let versions_response = retry::send(http_client.get(versions_url).headers(headers.clone())).await;
+            let body = match versions_response {
+                Ok(versions_response) if versions_response.status() == reqwest::StatusCode::OK => {
+                    throttle.record(Outcome::Success);
+                    versions_response.text().await
+                },
+                Ok(versions_response) => {
+                    throttle.record(Outcome::from_status(versions_response.status()));
+                    println!("{}", super::failed(&app_id, tr!("invalid-app-response", app = app_id.as_str())));
+                    return None;
+                },
+                Err(err) => Err(err),
+            };
+            let body = match body {
+                Ok(body) => body,
+                Err(err) => {
+                    throttle.record(Outcome::Failure);
+                    println!("{}", super::failed(&app_id, tr!("no-versions-retrieved", app = app_id.as_str(), error = network_error::describe(&err))));
+                    return None;
+                },
+            };
+            match patterns::APKPURE_VERSION.captures(&body).and_then(|caps| caps.get(1)) {
+                Some(version) => Some(LatestVersion { version: Some(version.as_str().to_string()), version_code: None, app_id }),
+                None => {
+                    println!("{}", super::failed(&app_id, tr!("version-not-found", version = tr!("latest-version"), app = app_id.as_str())));
+                    None
+                },
+            }
+        })
+        .buffer_unordered(parallel)
+        .filter_map(|latest| async { latest })
+        .collect()
+        .await
+}
+
+/// Every version of each app which the versions API lists, oldest first, as entries of the app
+/// list for `--all-versions`
+pub async fn all_versions(apps: Vec<String>, parallel: usize, sleep_duration: u64) -> Vec<(String, Option<String>)> {
+    let http_client = http::source_client(DownloadSource::APKPure);
+    let headers = http_headers();
+    let throttle = Throttle::new("APKPure", parallel, sleep_duration, &MultiProgress::new());
+    let (http_client, headers, throttle) = (&http_client, &headers, &throttle);
+    futures_util::stream::iter(apps)
+        .map(|app_id| async move {
+            let _slot = throttle.acquire().await;
+            let versions_url = Url::parse(&format!("{}{}", crate::consts::APKPURE_VERSIONS_URL_FORMAT, app_id)).unwrap();
+            let _permit = http::host_permit(versions_url.as_str()).await;
+            let versions_response = retry::send(http_client.get(versions_url).headers(headers.clone())).await;
+            let body = match versions_response {
+                Ok(versions_response) if versions_response.status() == reqwest::StatusCode::OK => {
+                    throttle.record(Outcome::Success);
+                    versions_response.text().await
+                },
+                Ok(versions_response) => {
+                    throttle.record(Outcome::from_status(versions_response.status()));
+                    println!("{}", super::failed(&app_id, tr!("invalid-app-response", app = app_id.as_str())));
+                    return vec![];
+                },
+                Err(err) => Err(err),
+            };
+            let body = match body {
+                Ok(body) => body,
+                Err(err) => {
+                    throttle.record(Outcome::Failure);
+                    println!("{}", super::failed(&app_id, tr!("no-versions-retrieved", app = app_id.as_str(), error = network_error::describe(&err))));
+                    return vec![];
+                },
+            };
+            // The API lists the newest first
+            let mut versions = vec![];
+            for caps in patterns::APKPURE_VERSION.captures_iter(&body) {
+                let version = caps.get(1).unwrap().as_str().to_string();
+                if !versions.contains(&version) {
+                    versions.push(version);
+                }
+            }
+            versions.into_iter().rev().map(|version| (app_id.clone(), Some(version))).collect::<Vec<_>>()
+        })
+        .buffered(parallel)
+        .flat_map(futures_util::stream::iter)
+        .collect()
+        .await
+}
+
+pub async fn list_versions(apps: Vec<(String, Option<String>)>, parallel: usize, sleep_duration: u64, options: HashMap<&str, &str>) {
+    let http_client = Rc::new(http::source_client(DownloadSource::APKPure));
+    let headers = http_headers();
+    let output_format = OutputFormat::from_options(&options);
     let json_root = Rc::new(RefCell::new(match output_format {
         OutputFormat::Json => Some(HashMap::new()),
         _ => None,
     }));
 
-    for app in apps {
-        let (app_id, _) = app;
-        let http_client = Rc::clone(&http_client);
-        let re = Rc::clone(&re);
-        let json_root = Rc::clone(&json_root);
-        let output_format = output_format.clone();
-        let headers = headers.clone();
-        async move {
-            if output_format.is_plaintext() {
-                println!("Versions available for {} on APKPure:", app_id);
-            }
-            let versions_url = Url::parse(&format!("{}{}", crate::consts::APKPURE_VERSIONS_URL_FORMAT, app_id)).unwrap();
-            let versions_response = http_client
-                .get(versions_url)
-                .headers(headers)
-                .send().await.unwrap();
+    let throttle = Rc::new(Throttle::new("APKPure", parallel, sleep_duration, &MultiProgress::new()));
+    futures_util::stream::iter(
+        apps.into_iter().map(|app| {
+            let (app_id, _) = app;
+            let http_client = Rc::clone(&http_client);
+            let throttle = Rc::clone(&throttle);
+            let json_root = Rc::clone(&json_root);
+            let output_format = output_format.clone();
+            let headers = headers.clone();
+            async move {
+                let _slot = throttle.acquire().await;
+                let versions_url = Url::parse(&format!("{}{}", crate::consts::APKPURE_VERSIONS_URL_FORMAT, app_id)).unwrap();
+                let _permit = http::host_permit(versions_url.as_str()).await;
+                let versions_response = match retry::send(http_client
+                    .get(versions_url)
+                    .headers(headers)).await {
+                    Ok(versions_response) => {
+                        let status = versions_response.status();
+                        throttle.record(Outcome::from_status(status));
+                        versions_response.text().await.map(|body| (status, body))
+                    },
+                    Err(err) => {
+                        throttle.record(Outcome::Failure);
+                        Err(err)
+                    },
+                };
+                // Lookups run in parallel, so nothing is printed until the response is complete to
+                // keep each app's lines together
+                if output_format.is_plaintext() {
+                    println!("{}", tr!("versions-available", app = app_id.as_str(), source = "APKPure"));
+                }
 
-            match versions_response.status() {
-                reqwest::StatusCode::OK => {
-                    let body = versions_response.text().await.unwrap();
-                    let mut versions = HashSet::new();
-                    for caps in re.captures_iter(&body) {
-                        if caps.len() >= 2 {
-                            versions.insert(caps.get(1).unwrap().as_str().to_string());
+                match versions_response {
+                    Ok((reqwest::StatusCode::OK, body)) => {
+                        let mut versions = HashSet::new();
+                        for caps in patterns::APKPURE_VERSION.captures_iter(&body) {
+                            if caps.len() >= 2 {
+                                versions.insert(caps.get(1).unwrap().as_str().to_string());
+                            }
                         }
-                    }
-                    let mut versions = versions.drain().collect::<Vec<String>>();
-                    versions.sort();
-                    match output_format {
-                        OutputFormat::Plaintext => {
-                            println!("| {}", versions.join(", "));
-                        },
-                        OutputFormat::Json => {
-                            let mut app_root: HashMap<String, Vec<HashMap<String, String>>> = HashMap::new();
-                            app_root.insert("available_versions".to_string(), versions.into_iter().map(|v| {
-                                let mut version_map = HashMap::new();
-                                version_map.insert("version".to_string(), v);
-                                version_map
-                            }).collect());
-                            json_root.borrow_mut().as_mut().unwrap().insert(app_id.to_string(), json!(app_root));
-                        },
-                    }
-                }
-                _ => {
-                    match output_format {
-                        OutputFormat::Plaintext => {
-                            eprintln!("| Invalid app response for {}. Skipping...", app_id);
-                        },
-                        OutputFormat::Json => {
-                            let mut app_root = HashMap::new();
-                            app_root.insert("error".to_string(), "Invalid app response.".to_string());
-                            json_root.borrow_mut().as_mut().unwrap().insert(app_id.to_string(), json!(app_root));
-                        },
-                    }
+                        let mut versions = versions.drain().collect::<Vec<String>>();
+                        versions.sort();
+                        match output_format {
+                            OutputFormat::Plaintext => {
+                                println!("| {}", versions.join(", "));
+                            },
+                            OutputFormat::Tsv => {
+                                for version in versions {
+                                    println!("{}", tsv_row(&[&app_id, &version, ""]));
+                                }
+                            },
+                            OutputFormat::Json => {
+                                let mut app_root: HashMap<String, Vec<HashMap<String, String>>> = HashMap::new();
+                                app_root.insert("available_versions".to_string(), versions.into_iter().map(|v| {
+                                    let mut version_map = HashMap::new();
+                                    version_map.insert("version".to_string(), v);
+                                    version_map
+                                }).collect());
+                                json_root.borrow_mut().as_mut().unwrap().insert(app_id.to_string(), json!(app_root));
+                            },
+                        }
+                    },
+                    Ok(_) => {
+                        match output_format {
+                            OutputFormat::Plaintext | OutputFormat::Tsv => {
+                                eprintln!("{}", tr!("versions-invalid-app-response", app = app_id.as_str()));
+                            },
+                            OutputFormat::Json => {
+                                let mut app_root = HashMap::new();
+                                app_root.insert("error".to_string(), "Invalid app response.".to_string());
+                                json_root.borrow_mut().as_mut().unwrap().insert(app_id.to_string(), json!(app_root));
+                            },
+                        }
+                    },
+                    Err(err) => {
+                        match output_format {
+                            OutputFormat::Plaintext | OutputFormat::Tsv => {
+                                eprintln!("{}", tr!("versions-error", error = network_error::describe(&err)));
+                            },
+                            OutputFormat::Json => {
+                                let mut app_root = HashMap::new();
+                                app_root.insert("error".to_string(), network_error::describe(&err));
+                                json_root.borrow_mut().as_mut().unwrap().insert(app_id.to_string(), json!(app_root));
+                            },
+                        }
+                    },
                 }
             }
-        }.await;
-    }
+        })
+    ).buffer_unordered(parallel).collect::<Vec<()>>().await;
     if output_format.is_json() {
         println!("{{\"source\":\"APKPure\",\"apps\":{}}}", json!(*json_root));
     };
@@ -0,0 +1,58 @@
+//! A circuit breaker for each source, for `--circuit-breaker`.  When a source fails so many apps in
+//! a row that it is most likely blocked, down or changed beyond what apkeep understands, it is
+//! skipped for the rest of the run rather than timing out on every remaining app, and with
+//! `--race` the other sources carry on without it.  Apps which are skipped, or already
+//! downloaded, count neither way.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::cli::DownloadSource;
+
+use super::DownloadedApp;
+
+/// How many apps in a row may fail on a source before it is skipped; zero never skips one
+static THRESHOLD: AtomicUsize = AtomicUsize::new(0);
+/// The apps failed in a row on each source, or `None` once it has been skipped
+static FAILURES: Mutex<BTreeMap<String, Option<usize>>> = Mutex::new(BTreeMap::new());
+
+pub fn configure(threshold: usize) {
+    THRESHOLD.store(threshold, Ordering::Relaxed);
+}
+
+/// Whether `source` is being skipped for the rest of the run
+pub fn is_open(source: DownloadSource) -> bool {
+    matches!(FAILURES.lock().unwrap().get(&source.to_string()), Some(None))
+}
+
+/// Download an app from `source` with `download`, unless the source is being skipped, noting
+/// whether it succeeded or failed.
+pub async fn guard(source: DownloadSource, app_id: String, download: impl Future<Output = Option<DownloadedApp>>) -> Option<DownloadedApp> {
+    let threshold = THRESHOLD.load(Ordering::Relaxed);
+    if threshold == 0 {
+        return download.await;
+    }
+    if is_open(source) {
+        super::failed(&app_id, tr!("source-skipped", app = app_id.as_str(), source = source.to_string()));
+        return None;
+    }
+    let failures_before = super::failure_count(&app_id);
+    let downloaded = download.await;
+    let failed = super::failure_count(&app_id) > failures_before;
+    let mut failures = FAILURES.lock().unwrap();
+    let in_a_row = failures.entry(source.to_string()).or_insert(Some(0));
+    if let Some(count) = in_a_row {
+        if downloaded.is_some() {
+            *count = 0;
+        } else if failed {
+            *count += 1;
+            if *count >= threshold {
+                *in_a_row = None;
+                println!("{}", tr!("source-tripped", source = source.to_string(), failures = threshold));
+            }
+        }
+    }
+    downloaded
+}
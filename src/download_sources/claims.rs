@@ -0,0 +1,65 @@
+//! Keeping track of the files written in a run, so that two downloads which resolve to the same
+//! file can't write over each other.  An app already being downloaded to a file isn't downloaded
+//! to it again at the same time, and a different app whose file would have the same name, such as
+//! two apps which a site serves as `app.apk`, is saved under a numbered name instead.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use indicatif::MultiProgress;
+
+/// The files claimed in this run, with the app each was claimed for and whether it is still
+/// being written
+static CLAIMED: Mutex<BTreeMap<PathBuf, (String, bool)>> = Mutex::new(BTreeMap::new());
+
+/// A file claimed by a download, which is no longer being written once this is dropped.
+pub struct Claim {
+    path: PathBuf,
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        if let Some((_, writing)) = CLAIMED.lock().unwrap().get_mut(&self.path) {
+            *writing = false;
+        }
+    }
+}
+
+/// Claim the file `file_name` in `outpath` for `app_string`, returning the name to write it
+/// under, or `None` if the app is already being downloaded to it.  The file stays claimed for
+/// the app for the rest of the run, so that another app's file can't replace it later on either.
+pub fn claim(outpath: &Path, file_name: &str, app_string: &str, mp: &MultiProgress) -> Option<(String, Claim)> {
+    let mut claimed = CLAIMED.lock().unwrap();
+    let mut name = file_name.to_string();
+    let mut n = 1;
+    loop {
+        let path = outpath.join(&name);
+        match claimed.get(&path) {
+            Some((app, true)) if app == app_string => {
+                mp.suspend(|| println!("{}", tr!("already-downloading", app = app_string, file = name.as_str())));
+                return None;
+            },
+            Some((app, _)) if app != app_string => {
+                n += 1;
+                name = numbered(file_name, n);
+                continue;
+            },
+            _ => {},
+        }
+        if n > 1 {
+            let other = &claimed[&outpath.join(file_name)].0;
+            mp.suspend(|| println!("{}", tr!("file-name-collision", app = app_string, other = other.as_str(), file = file_name, renamed = name.as_str())));
+        }
+        claimed.insert(path.clone(), (app_string.to_string(), true));
+        return Some((name, Claim { path }));
+    }
+}
+
+/// `file_name` with `-n` before its extension, so that `app.apk` becomes `app-2.apk`.
+fn numbered(file_name: &str, n: usize) -> String {
+    match file_name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => format!("{}-{}.{}", stem, n, extension),
+        _ => format!("{}-{}", file_name, n),
+    }
+}
@@ -0,0 +1,50 @@
+//! A limit on the time spent on each app, for `--per-app-timeout`, so that one stalled connection
+//! can't hang a whole run.  The time counts from when apkeep starts on the app, through finding
+//! its download and downloading it.  An app which runs out of time is cancelled, the files it was
+//! writing are deleted, and it is noted as failed.
+
+use std::cell::RefCell;
+use std::fs;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use super::DownloadedApp;
+
+static TIMEOUT: OnceLock<Duration> = OnceLock::new();
+
+tokio::task_local! {
+    /// The files being written for the app in hand
+    static WRITING: RefCell<Vec<PathBuf>>;
+}
+
+pub fn configure(timeout: Duration) {
+    let _ = TIMEOUT.set(timeout);
+}
+
+/// Note that a file is being written for the app in hand, to be deleted if it runs out of time.
+pub fn writing(path: &Path) {
+    let _ = WRITING.try_with(|writing| writing.borrow_mut().push(path.to_path_buf()));
+}
+
+/// Download an app with `download`, giving up on it once it has taken longer than
+/// `--per-app-timeout`.
+pub async fn limit(app_id: &str, download: impl Future<Output = Option<DownloadedApp>>) -> Option<DownloadedApp> {
+    let timeout = match TIMEOUT.get() {
+        Some(timeout) => *timeout,
+        None => return download.await,
+    };
+    WRITING.scope(RefCell::new(vec![]), async {
+        match tokio::time::timeout(timeout, download).await {
+            Ok(downloaded) => downloaded,
+            Err(_) => {
+                for path in WRITING.with(|writing| writing.take()) {
+                    let _ = fs::remove_file(path);
+                }
+                println!("{}", super::failed(app_id, tr!("app-timed-out", app = app_id, seconds = timeout.as_secs())));
+                None
+            },
+        }
+    }).await
+}
@@ -1,4 +1,4 @@
-use std::collections::{hash_map::DefaultHasher, HashSet, HashMap};
+use std::collections::{hash_map::DefaultHasher, BTreeSet, HashMap};
 use std::error::Error;
 use std::fs::{self, File};
 use std::hash::{Hash, Hasher};
@@ -8,24 +8,33 @@ use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
 use base64::{Engine as _, engine::general_purpose as b64_general_purpose};
+use chrono::DateTime;
 use cryptographic_message_syntax::{SignedData, SignerInfo};
 use futures_util::StreamExt;
 use indicatif::MultiProgress;
-use regex::Regex;
 use ring::digest::{Context, SHA256};
 use serde_json::{json, Value};
 use sha1::{Sha1, Digest as Sha1Digest};
 use sha2::Sha256;
 use simple_error::SimpleError;
 use tempfile::{tempdir, TempDir};
-use tokio::time::{sleep, Duration};
 use tokio_dl_stream_to_disk::{AsyncDownload, error::ErrorKind as TDSTDErrorKind};
 use x509_certificate::certificate::CapturedX509Certificate;
 
+use crate::audit;
+use crate::apk;
+use crate::cli::{Channel, DownloadSource, FormFactor};
 use crate::consts;
+use crate::patterns;
 use crate::config::{self, ConfigDirError};
-use crate::util::{OutputFormat, progress_bar::progress_wrapper};
+use crate::storage;
+use crate::download_sources::{attempt, claims, deadline, AppInfo, DownloadedApp, LatestVersion, ReleaseDates, SearchMatch, needs_download};
+use crate::filter::PackageFilter;
+use crate::util::{OutputFormat, digests, http, network_error, progress_bar::progress_wrapper, retry, safe_file_name, safe_join, trace, tsv_row};
+use crate::util::throttle::{Outcome, Throttle};
+use crate::util::timings::{self, Phase};
 mod error;
+mod fastlane;
 use error::Error as FDroidError;
 
 async fn retrieve_index_or_exit(options: &HashMap<&str, &str>, mp: Rc<MultiProgress>, output_format: OutputFormat) -> Value {
@@ -43,6 +52,10 @@ async fn retrieve_index_or_exit(options: &HashMap<&str, &str>, mp: Rc<MultiProgr
         Some(val) if val == &"1" || val.to_lowercase() == "true" => true,
         _ => false,
     };
+    let verify_index = match options.get("verify-index") {
+        Some(&"false") => false,
+        _ => true,
+    };
     if let Some(full_repo_option) = options.get("repo") {
         custom_repo = true;
         if let Some((repo_option, fingerprint_option)) = full_repo_option.split_once("?fingerprint=") {
@@ -55,32 +68,37 @@ async fn retrieve_index_or_exit(options: &HashMap<&str, &str>, mp: Rc<MultiProgr
             };
             repo = repo_option.to_string();
         } else {
+            // Without a fingerprint, the repo is taken to be an F-Droid mirror signed with F-Droid's key
             repo = full_repo_option.to_string();
         }
     }
+    if !verify_index {
+        eprintln!("{}", tr!("fdroid-unverified"));
+    }
 
     let display_error_and_exit = |err: ConfigDirError| {
         match err {
             ConfigDirError::NotFound => {
-                print_error("Could not find a config directory for apkeep to store F-Droid package index. Exiting.", output_format.clone());
+                print_error("Could not find a cache directory for apkeep to store F-Droid package index. Exiting.", output_format.clone());
             },
             ConfigDirError::CouldNotCreate => {
-                print_error("Could not create a config directory for apkeep to store F-Droid package index. Exiting.", output_format.clone());
+                print_error("Could not create a cache directory for apkeep to store F-Droid package index. Exiting.", output_format.clone());
             },
         }
         std::process::exit(1);
     };
-    let mut config_dir = config::config_dir().map_err(display_error_and_exit).unwrap();
+    let mut cache_dir = config::cache_dir().map_err(display_error_and_exit).unwrap();
     if custom_repo {
-        config_dir.push("fdroid-custom-repos");
-        config::create_dir(&config_dir).map_err(display_error_and_exit).unwrap();
+        cache_dir.push("fdroid-custom-repos");
         let mut s = DefaultHasher::new();
         repo.hash(&mut s);
-        config_dir.push(format!("{}", s.finish()));
-        config::create_dir(&config_dir).map_err(display_error_and_exit).unwrap();
+        cache_dir.push(format!("{}", s.finish()));
+    } else {
+        cache_dir.push("fdroid");
     }
+    fs::create_dir_all(&cache_dir).map_err(|_| ConfigDirError::CouldNotCreate).map_err(display_error_and_exit).unwrap();
 
-    let mut latest_etag_file = PathBuf::from(&config_dir);
+    let mut latest_etag_file = PathBuf::from(&cache_dir);
     if use_entry {
         latest_etag_file.push("latest_entry_etag");
     } else {
@@ -98,15 +116,20 @@ async fn retrieve_index_or_exit(options: &HashMap<&str, &str>, mp: Rc<MultiProgr
         Err(_) => None,
     };
 
-    let http_client = reqwest::Client::new();
+    let http_client = http::source_client(DownloadSource::FDroid);
     let fdroid_jar_url = if use_entry {
         format!("{}/entry.jar", repo)
     } else {
         format!("{}/index-v1.jar", repo)
     };
-    let jar_response = http_client
-        .head(fdroid_jar_url)
-        .send().await.unwrap();
+    let jar_response = match retry::send(http_client
+        .head(fdroid_jar_url)).await {
+        Ok(jar_response) => jar_response,
+        Err(err) => {
+            print_error(&format!("Could not retrieve F-Droid package index. {} Exiting.", network_error::describe(&err)), output_format);
+            std::process::exit(1);
+        }
+    };
 
     let etag = if jar_response.headers().contains_key("ETag") {
         jar_response.headers()["ETag"].to_str().unwrap()
@@ -115,7 +138,7 @@ async fn retrieve_index_or_exit(options: &HashMap<&str, &str>, mp: Rc<MultiProgr
         std::process::exit(1);
     };
 
-    let mut index_file = PathBuf::from(&config_dir);
+    let mut index_file = PathBuf::from(&cache_dir);
     if use_entry {
         index_file.push("index.json");
     } else {
@@ -126,10 +149,6 @@ async fn retrieve_index_or_exit(options: &HashMap<&str, &str>, mp: Rc<MultiProgr
         serde_json::from_str(&index).unwrap()
     } else {
         let files = download_and_extract_to_tempdir(&temp_dir, &repo, Rc::clone(&mp), use_entry, output_format.clone()).await;
-        let verify_index = match options.get("verify-index") {
-            Some(&"false") => false,
-            _ => true,
-        };
         match verify_and_return_json(&temp_dir, &files, &fingerprint, verify_index, use_entry, Rc::clone(&mp)) {
             Ok(json) => {
                 let index = if use_entry {
@@ -137,8 +156,8 @@ async fn retrieve_index_or_exit(options: &HashMap<&str, &str>, mp: Rc<MultiProgr
                         Ok(index_from_entry) => {
                             index_from_entry
                         }
-                        Err(_) => {
-                            print_error("Could verify and return package index from entry JSON. Exiting.", output_format);
+                        Err(err) => {
+                            print_error(&format!("Could not verify and return package index from entry JSON: {} Exiting.", err), output_format);
                             std::process::exit(1);
                         }
                     }
@@ -152,7 +171,10 @@ async fn retrieve_index_or_exit(options: &HashMap<&str, &str>, mp: Rc<MultiProgr
                             print_error("Could not write F-Droid package index to config file. Exiting.", output_format);
                             std::process::exit(1);
                         }
-                        if fs::write(latest_etag_file, etag).is_err() {
+                        // An unverified index is never reused, so that a later verified run fetches it afresh
+                        if !verify_index {
+                            let _ = fs::remove_file(latest_etag_file);
+                        } else if fs::write(latest_etag_file, etag).is_err() {
                             print_error("Could not write F-Droid etag to config file. Exiting.", output_format);
                             std::process::exit(1);
                         }
@@ -164,8 +186,13 @@ async fn retrieve_index_or_exit(options: &HashMap<&str, &str>, mp: Rc<MultiProgr
                     }
                 }
             },
-            Err(_) => {
-                print_error("Could not verify F-Droid package index. Exiting.", output_format);
+            Err(err) => {
+                let hint = if custom_repo && fingerprint == consts::FDROID_INDEX_FINGERPRINT {
+                    " If this is not an F-Droid mirror, give the repository's fingerprint as repo=URL?fingerprint=..."
+                } else {
+                    ""
+                };
+                print_error(&format!("Could not verify F-Droid package index, which may have been tampered with: {}{} Exiting.", err, hint), output_format);
                 std::process::exit(1);
             },
         }
@@ -174,7 +201,7 @@ async fn retrieve_index_or_exit(options: &HashMap<&str, &str>, mp: Rc<MultiProgr
 
 fn print_error(err_msg: &str, output_format: OutputFormat) {
     match output_format {
-        OutputFormat::Plaintext => eprintln!("{}", err_msg),
+        OutputFormat::Plaintext | OutputFormat::Tsv => eprintln!("{}", err_msg),
         OutputFormat::Json => println!("{{\"error\":\"{}\"}}", err_msg),
     }
 }
@@ -186,85 +213,152 @@ fn read_file_to_string(file: PathBuf ) -> String {
     contents
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn download_apps(
     apps: Vec<(String, Option<String>)>,
     parallel: usize,
     sleep_duration: u64,
     outpath: &Path,
     options: HashMap<&str, &str>,
-) {
+    installed_version_codes: &HashMap<String, u64>,
+    mirror: Option<Mirror>,
+    filter: &PackageFilter,
+    channel: Channel,
+    form_factor: Option<FormFactor>,
+) -> Vec<DownloadedApp> {
     let mp = Rc::new(MultiProgress::new());
+    let timer = timings::start_shared(Phase::Resolve);
     let index = retrieve_index_or_exit(&options, Rc::clone(&mp), OutputFormat::Plaintext).await;
+    drop(timer);
+
+    let apps = match mirror {
+        Some(mirror) => {
+            let apps = apps_in_index(&index, &mirror, filter);
+            match mirror {
+                Mirror::Repo => println!("{}", tr!("fdroid-mirroring", count = apps.len())),
+                Mirror::Category(category) => println!("{}", tr!("fdroid-mirroring-category", count = apps.len(), category = category.as_str())),
+                Mirror::Developer(developer) => println!("{}", tr!("fdroid-mirroring-developer", count = apps.len(), developer = developer.as_str())),
+            }
+            apps.into_iter().map(|app_id| (app_id, None)).collect()
+        },
+        None => apps,
+    };
+
+    let listings = match options.get("fastlane") {
+        Some(val) if val == &"1" || val.to_lowercase() == "true" => {
+            fastlane::listings(&index, &apps.iter().map(|(app_id, _)| app_id.as_str()).collect::<Vec<_>>())
+        },
+        _ => fastlane::RemoteListings::new(),
+    };
 
     let app_arch = options.get("arch").map(|x| x.to_string());
-    let (fdroid_apps, repo_address) = match parse_json_for_download_information(index, apps, app_arch.clone(), Rc::clone(&mp)) {
+    let (fdroid_apps, repo_address) = match parse_json_for_download_information(index, apps, app_arch.clone(), channel, form_factor, Rc::clone(&mp)) {
         Ok((fdroid_apps, repo_address)) => (fdroid_apps, repo_address),
         Err(_) => {
-            println!("Could not parse JSON of F-Droid package index. Exiting.");
+            println!("{}", tr!("fdroid-index-unparsable"));
             std::process::exit(1);
         },
     };
 
     let repo_address = Rc::new(repo_address);
-    futures_util::stream::iter(
-        fdroid_apps.into_iter().map(|fdroid_app| {
-            let (app_id, app_version, url_filename, hash) = fdroid_app;
+    let throttle = Rc::new(Throttle::new("F-Droid", parallel, sleep_duration, &mp));
+    let downloaded = futures_util::stream::iter(
+        fdroid_apps.into_iter().filter(|fdroid_app| {
+            match (installed_version_codes.get(&fdroid_app.app_id), fdroid_app.version_code) {
+                (Some(installed_version_code), Some(version_code)) if version_code <= *installed_version_code => {
+                    mp.println(tr!("not-newer", app = fdroid_app.app_id.as_str())).unwrap();
+                    false
+                },
+                _ => true,
+            }
+        }).filter(|fdroid_app| {
+            // The index lists each APK's size, so one which is too large is skipped before it is requested
+            !super::too_large(&fdroid_app.app_id, fdroid_app.size, &mp)
+        }).map(|fdroid_app| {
+            let FDroidApp { app_id, app_version, filename: url_filename, hash, added, changelog, .. } = fdroid_app;
+            let released_at = added.and_then(DateTime::from_timestamp_millis);
             let repo_address = Rc::clone(&repo_address);
+            let throttle = Rc::clone(&throttle);
             let mp_log = Rc::clone(&mp);
             let mp = Rc::clone(&mp);
             let app_arch = app_arch.clone();
-            async move {
-                let app_string = match (app_version, app_arch) {
+            attempt(DownloadSource::FDroid, app_id.clone(), async move {
+                let app_string = match (app_version.clone(), app_arch) {
                     (None, None) => {
-                        mp_log.suspend(|| println!("Downloading {}...", app_id));
+                        mp_log.suspend(|| println!("{}", tr!("downloading", app = app_id.as_str())));
                         app_id.to_string()
                     },
                     (None, Some(arch)) => {
-                        mp_log.suspend(|| println!("Downloading {} arch {}...", app_id, arch));
+                        mp_log.suspend(|| println!("{}", tr!("downloading-arch", app = app_id.as_str(), arch = arch.as_str())));
                         format!("{}@{}", app_id, arch)
                     },
                     (Some(version), None) => {
-                        mp_log.suspend(|| println!("Downloading {} version {}...", app_id, version));
+                        mp_log.suspend(|| println!("{}", tr!("downloading-version", app = app_id.as_str(), version = version.as_str())));
                         format!("{}@{}", app_id, version)
                     },
                     (Some(version), Some(arch)) => {
-                        mp_log.suspend(|| println!("Downloading {} version {} arch {}...", app_id, version, arch));
+                        mp_log.suspend(|| println!("{}", tr!("downloading-version-arch", app = app_id.as_str(), version = version.as_str(), arch = arch.as_str())));
                         format!("{}@{}@{}", app_id, version, arch)
                     },
                 };
-                let fname = format!("{}.apk", app_string);
-                if sleep_duration > 0 {
-                    sleep(Duration::from_millis(sleep_duration)).await;
-                }
+                let fname = safe_file_name(&format!("{}.apk", app_string));
+                let (fname, _claim) = match claims::claim(Path::new(outpath), &fname, &app_string, &mp_log) {
+                    Some(claimed) => claimed,
+                    None => return None,
+                };
+                let _slot = throttle.acquire().await;
                 let download_url = format!("{}/{}", repo_address, url_filename);
+                if !needs_download(&download_url, &safe_join(outpath, &fname), Some(&hash), &app_string, &mp_log).await {
+                    return None;
+                }
+                let _permit = http::host_permit(&download_url).await;
+                let timer = timings::start(&app_id, Phase::Download);
+                deadline::writing(&safe_join(outpath, &fname));
+                trace::download(&download_url);
                 match AsyncDownload::new(&download_url, Path::new(outpath), &fname).get().await {
                     Ok(mut dl) => {
                         let length = dl.length();
+                        if super::too_large(&app_string, length, &mp_log) {
+                            return None;
+                        }
                         let cb = match length {
                             Some(length) => Some(progress_wrapper(mp)(fname.clone(), length)),
                             None => None,
                         };
 
                         let sha256sum = match dl.download_and_return_sha256sum(&cb).await {
-                            Ok(sha256sum) => Some(sha256sum),
+                            Ok(sha256sum) => {
+                                throttle.record(Outcome::Success);
+                                Some(sha256sum)
+                            },
                             Err(err) if matches!(err.kind(), TDSTDErrorKind::FileExists) => {
-                                mp_log.println(format!("File already exists for {}. Skipping...", app_string)).unwrap();
+                                mp_log.println(tr!("file-exists", app = app_string.as_str())).unwrap();
                                 None
                             },
                             Err(err) if matches!(err.kind(), TDSTDErrorKind::PermissionDenied) => {
-                                mp_log.println(format!("Permission denied when attempting to write file for {}. Skipping...", app_string)).unwrap();
+                                mp_log.println(super::failed(&app_string, tr!("permission-denied", app = app_string.as_str()))).unwrap();
+                                None
+                            },
+                            Err(err) if !retry::download_error(&err) => {
+                                throttle.record(Outcome::Failure);
+                                mp_log.println(super::failed(&app_string, tr!("download-not-retried", app = app_string.as_str(), error = err.to_string()))).unwrap();
                                 None
                             },
                             Err(_) => {
-                                mp_log.println(format!("An error has occurred attempting to download {}.  Retry #1...", app_string)).unwrap();
+                                throttle.record(Outcome::Failure);
+                                mp_log.println(tr!("download-retry", app = app_string.as_str(), attempt = 1)).unwrap();
                                 match AsyncDownload::new(&download_url, Path::new(outpath), &fname).download_and_return_sha256sum(&cb).await {
                                     Ok(sha256sum) => Some(sha256sum),
+                                    Err(err) if !retry::download_error(&err) => {
+                                        mp_log.println(super::failed(&app_string, tr!("download-not-retried", app = app_string.as_str(), error = err.to_string()))).unwrap();
+                                        None
+                                    },
                                     Err(_) => {
-                                        mp_log.println(format!("An error has occurred attempting to download {}.  Retry #2...", app_string)).unwrap();
+                                        mp_log.println(tr!("download-retry", app = app_string.as_str(), attempt = 2)).unwrap();
                                         match AsyncDownload::new(&download_url, Path::new(outpath), &fname).download_and_return_sha256sum(&cb).await {
                                             Ok(sha256sum) => Some(sha256sum),
                                             Err(_) => {
-                                                mp_log.println(format!("An error has occurred attempting to download {}. Skipping...", app_string)).unwrap();
+                                                mp_log.println(super::failed(&app_string, tr!("download-failed", app = app_string.as_str()))).unwrap();
                                                 None
                                             }
                                         }
@@ -272,29 +366,103 @@ pub async fn download_apps(
                                 }
                             }
                         };
+                        drop(timer);
                         if let Some(sha256sum) = sha256sum {
+                            audit::check(&app_id, "sha256", sha256sum == hash, &hex::encode(&sha256sum));
                             if sha256sum == hash {
-                                mp_log.suspend(|| println!("{} downloaded successfully!", app_string));
+                                let _ = digests::record_sha256(&safe_join(outpath, &fname), &sha256sum);
+                                mp_log.suspend(|| println!("{}", tr!("downloaded", app = app_string.as_str())));
+                                return Some(DownloadedApp::new(app_id, app_version, vec![safe_join(outpath, &fname)]).with_url(download_url).with_release_date(released_at).with_changelog(changelog));
                             } else {
-                                mp_log.suspend(|| println!("{} downloaded, but the sha256sum does not match the one signed by F-Droid. Proceed with caution.", app_string));
+                                let app = DownloadedApp::new(app_id, app_version, vec![safe_join(outpath, &fname)]).with_url(download_url);
+                                match storage::quarantine(outpath, &app, "The sha256sum does not match the one in the signed F-Droid index.") {
+                                    Ok(path) => mp_log.suspend(|| println!("{}", super::failed(&app_string, tr!("fdroid-hash-mismatch-quarantined", app = app_string.as_str(), path = path.display().to_string())))),
+                                    Err(_) => {
+                                        let _ = app.remove();
+                                        mp_log.suspend(|| println!("{}", super::failed(&app_string, tr!("fdroid-hash-mismatch-deleted", app = app_string.as_str()))));
+                                    },
+                                }
                             }
                         }
                     },
                     Err(_) => {
-                        mp_log.println(format!("Invalid response for {}. Skipping...", app_string)).unwrap();
+                        throttle.record(Outcome::Failure);
+                        mp_log.println(super::failed(&app_string, tr!("invalid-response", app = app_string.as_str()))).unwrap();
                     },
                 }
-            }
+                None
+            })
         })
-    ).buffer_unordered(parallel).collect::<Vec<()>>().await;
+    ).buffer_unordered(parallel).filter_map(|downloaded| async { downloaded }).collect::<Vec<DownloadedApp>>().await;
+
+    for (app_id, locales) in &listings {
+        if let Err(err) = fastlane::mirror(outpath, app_id, locales).await {
+            println!("{}", super::failed(app_id, tr!("fdroid-fastlane-failed", app = app_id.as_str(), error = err.to_string())));
+        }
+    }
+    downloaded
 }
 
-type DownloadInformation = (Vec<(String, Option<String>, String, Vec<u8>)>, String);
+/// Which apps to download when mirroring a repository rather than giving a list of apps.
+pub enum Mirror {
+    Repo,
+    Category(String),
+    Developer(String),
+}
+
+/// The IDs of all apps in the package index, or those in the given category or by the given
+/// developer, which the filter allows.  Categories and the author's name are given per app in
+/// `index-v1.json`, and in each package's metadata in `index-v2.json`.
+fn apps_in_index(index: &Value, mirror: &Mirror, filter: &PackageFilter) -> Vec<String> {
+    let wanted = |app_id: &str, metadata: &Value| {
+        let categories = metadata.get("categories")
+            .and_then(|categories| categories.as_array())
+            .map_or(vec![], |categories| categories.iter().filter_map(Value::as_str).collect::<Vec<_>>());
+        let in_mirror = match mirror {
+            Mirror::Repo => true,
+            Mirror::Category(category) => categories.iter().any(|value| value.eq_ignore_ascii_case(category)),
+            Mirror::Developer(developer) => metadata.get("authorName").and_then(Value::as_str)
+                .is_some_and(|author| author.trim().eq_ignore_ascii_case(developer.trim())),
+        };
+        in_mirror && filter.allows(app_id, &categories)
+    };
+    if let Some(Value::Array(apps)) = index.get("apps") {
+        apps.iter()
+            .filter_map(|app| app.get("packageName").and_then(|name| name.as_str()).map(|name| (name, app)))
+            .filter(|(app_id, app)| wanted(app_id, app))
+            .map(|(app_id, _)| app_id.to_string())
+            .collect()
+    } else if let Some(Value::Object(packages)) = index.get("packages") {
+        packages.iter()
+            .filter(|(app_id, package)| wanted(app_id, package.get("metadata").unwrap_or(&Value::Null)))
+            .map(|(app_id, _)| app_id.to_string())
+            .collect()
+    } else {
+        vec![]
+    }
+}
+
+/// An app resolved from the package index, ready to be downloaded.
+struct FDroidApp {
+    app_id: String,
+    app_version: Option<String>,
+    filename: String,
+    hash: Vec<u8>,
+    version_code: Option<u64>,
+    /// When the version was added to the repository, in milliseconds since the epoch
+    added: Option<i64>,
+    /// The size of the APK in bytes, as the index lists it
+    size: Option<u64>,
+    /// What's new in the version, given only by `index-v2.json`
+    changelog: Option<String>,
+}
+
+type DownloadInformation = (Vec<FDroidApp>, String);
 /// This currently works for `index-v1.json` as well as an index with version `20002`.  It is
 /// flexible enough to parse either, and may work on future index versions as well.  Since `sha256`
 /// digests are checked before proceeding, I don't foresee this having an insecure failure mode, so
 /// checking the index version and making the parsing overly brittle has no substantive advantage.
-fn parse_json_for_download_information(index: Value, apps: Vec<(String, Option<String>)>, app_arch: Option<String>, mp_log: Rc<MultiProgress>) -> Result<DownloadInformation, FDroidError> {
+fn parse_json_for_download_information(index: Value, apps: Vec<(String, Option<String>)>, app_arch: Option<String>, channel: Channel, form_factor: Option<FormFactor>, mp_log: Rc<MultiProgress>) -> Result<DownloadInformation, FDroidError> {
     let index_map = index.as_object().ok_or(FDroidError::Dummy)?;
     let repo_address = index_map
         .get("repo").ok_or(FDroidError::Dummy)?
@@ -304,17 +472,36 @@ fn parse_json_for_download_information(index: Value, apps: Vec<(String, Option<S
     let packages = index_map
         .get("packages").ok_or(FDroidError::Dummy)?
         .as_object().ok_or(FDroidError::Dummy)?;
+    let suggested_version_codes = suggested_version_codes(index_map);
 
-    let fdroid_apps: Vec<(String, Option<String>, String, Vec<u8>)> = apps.into_iter().map(|app| {
+    let fdroid_apps: Vec<FDroidApp> = apps.into_iter().map(|app| {
         let (app_id, app_version) = app;
+        let wanted_version_code = app_version.as_deref().and_then(super::version_code);
         match packages.get(&app_id) {
             Some(Value::Array(app_array)) => {
                 for single_app in app_array {
                     if let Value::Object(fdroid_app) = single_app {
+                        // Versions newer than the one the repository suggests are not yet stable
+                        let unstable = match (fdroid_app.get("versionCode").and_then(|code| code.as_u64()), suggested_version_codes.get(&app_id)) {
+                            (Some(version_code), Some(suggested)) => version_code > *suggested,
+                            _ => false,
+                        };
+                        let release_channels: &[&str] = if unstable { &["Beta"] } else { &[] };
+                        if app_version.is_none() && !on_channel(channel, release_channels) {
+                            continue;
+                        }
+                        if form_factor.is_some_and(|form_factor| form_factor_of(fdroid_app.get("features")) != form_factor) {
+                            continue;
+                        }
                         if let Some(Value::String(version_name)) = fdroid_app.get("versionName") {
-                            if app_version.is_none() || version_name == app_version.as_ref().unwrap() {
+                            let version_code = fdroid_app.get("versionCode").and_then(|code| code.as_u64());
+                            if app_version.is_none() || version_name == app_version.as_ref().unwrap() || (wanted_version_code.is_some() && version_code == wanted_version_code) {
+                                // Files are named for the versionName, even when a versionCode was asked for
+                                let app_version = app_version.as_ref().map(|_| version_name.to_string());
                                 if let (Some(Value::String(filename)), Some(Value::String(hash))) = (fdroid_app.get("apkName"), fdroid_app.get("hash")) {
                                     if let Ok(hash) = hex::decode(hash.to_string()) {
+                                        let added = fdroid_app.get("added").and_then(|added| added.as_i64());
+                                        let size = fdroid_app.get("size").and_then(|size| size.as_u64());
                                         if let Some(arch) = &app_arch {
                                             if let Some(Value::Array(nativecode_array)) = fdroid_app.get("nativecode") {
                                                 if nativecode_array.iter().any(|value| {
@@ -324,11 +511,11 @@ fn parse_json_for_download_information(index: Value, apps: Vec<(String, Option<S
                                                         false
                                                     }
                                                 }) {
-                                                    return Some((app_id, app_version, filename.to_string(), hash));
+                                                    return Some(FDroidApp { app_id, app_version, filename: filename.to_string(), hash, version_code, added, size, changelog: None });
                                                 }
                                             }
                                         } else {
-                                            return Some((app_id, app_version, filename.to_string(), hash));
+                                            return Some(FDroidApp { app_id, app_version, filename: filename.to_string(), hash, version_code, added, size, changelog: None });
                                         }
                                     }
                                 }
@@ -337,7 +524,8 @@ fn parse_json_for_download_information(index: Value, apps: Vec<(String, Option<S
                     }
                 }
                 let arch_str = app_arch.as_ref().map_or("".to_string(), |x| format!(" {}", x));
-                mp_log.println(format!("Could not find version {}{} of {}. Skipping...", app_version.unwrap(), arch_str, app_id)).unwrap();
+                let version = format!("{}{}", app_version.unwrap_or_else(|| tr!("latest-version")), arch_str);
+                mp_log.println(super::failed(&app_id, tr!("version-not-found", version = version, app = app_id.as_str()))).unwrap();
                 return None;
             },
             Some(Value::Object(app_object)) => {
@@ -345,25 +533,39 @@ fn parse_json_for_download_information(index: Value, apps: Vec<(String, Option<S
                     let mut latest_version = 0;
                     let mut filename = String::new();
                     let mut hash = String::new();
+                    let mut added = None;
+                    let mut size = None;
+                    let mut changelog = None;
                     for (_, version_value) in versions {
                         if let Value::Object(version) = version_value {
                             if let (Some(Value::Object(manifest)), Some(Value::Object(file))) = (version.get("manifest"), version.get("file")) {
+                                if form_factor.is_some_and(|form_factor| form_factor_of(manifest.get("features")) != form_factor) {
+                                    continue;
+                                }
                                 if let (Some(Value::String(name)), Some(Value::String(sha256))) = (file.get("name"), file.get("sha256")) {
                                     if app_version.is_some() {
                                         if let Some(Value::String(version_name)) = manifest.get("versionName") {
-                                            if version_name == app_version.as_ref().unwrap() {
+                                            let version_code = manifest.get("versionCode").and_then(|code| code.as_u64());
+                                            if version_name == app_version.as_ref().unwrap() || (wanted_version_code.is_some() && version_code == wanted_version_code) {
                                                 if let Ok(sha256) = hex::decode(sha256.to_string()) {
-                                                    return Some((app_id, app_version, name.to_string(), sha256));
+                                                    let app_version = Some(version_name.to_string());
+                                                    let added = version.get("added").and_then(|added| added.as_i64());
+                                                    let size = file.get("size").and_then(|size| size.as_u64());
+                                                    let changelog = localized(version.get("whatsNew"));
+                                                    return Some(FDroidApp { app_id, app_version, filename: name.to_string(), hash: sha256, version_code, added, size, changelog });
                                                 }
                                             }
                                         }
-                                    } else {
+                                    } else if on_channel(channel, &release_channels(version)) {
                                         if let Some(Value::Number(version_code_number)) = manifest.get("versionCode") {
                                             if let Some(version_code) = version_code_number.as_u64() {
                                                 if version_code > latest_version {
                                                     latest_version = version_code;
                                                     filename = name.to_string();
                                                     hash = sha256.to_string();
+                                                    added = version.get("added").and_then(|added| added.as_i64());
+                                                    size = file.get("size").and_then(|size| size.as_u64());
+                                                    changelog = localized(version.get("whatsNew"));
                                                 }
                                             }
                                         }
@@ -374,12 +576,12 @@ fn parse_json_for_download_information(index: Value, apps: Vec<(String, Option<S
                     }
                     if app_version.is_none() {
                         if let Ok(hash) = hex::decode(hash) {
-                            return Some((app_id, app_version, filename, hash));
+                            return Some(FDroidApp { app_id, app_version, filename, hash, version_code: Some(latest_version), added, size, changelog });
                         }
                     }
                 }
             },
-            _ => mp_log.println(format!("Could not find {} in package list. Skipping...", app_id)).unwrap(),
+            _ => mp_log.println(super::failed(&app_id, tr!("not-in-package-list", app = app_id.as_str()))).unwrap(),
         }
         None
     }).flatten().collect();
@@ -387,16 +589,199 @@ fn parse_json_for_download_information(index: Value, apps: Vec<(String, Option<S
     Ok((fdroid_apps, repo_address.to_string()))
 }
 
-pub async fn list_versions(apps: Vec<(String, Option<String>)>, options: HashMap<&str, &str>) {
+/// The version code each app's metadata suggests, in `index-v1.json`, where it marks the newest
+/// stable version.  Later indexes mark versions with release channels instead.
+fn suggested_version_codes(index_map: &serde_json::Map<String, Value>) -> HashMap<String, u64> {
+    let mut suggested = HashMap::new();
+    if let Some(Value::Array(apps)) = index_map.get("apps") {
+        for app in apps {
+            let version_code = match app.get("suggestedVersionCode") {
+                Some(Value::String(version_code)) => version_code.parse().ok(),
+                Some(version_code) => version_code.as_u64(),
+                None => None,
+            };
+            if let (Some(Value::String(package_name)), Some(version_code)) = (app.get("packageName"), version_code) {
+                suggested.insert(package_name.to_string(), version_code);
+            }
+        }
+    }
+    suggested
+}
+
+/// The kind of device a version is built for, judged by the features it requires: listed by name
+/// in `index-v1.json`, and as objects with a `name` in `index-v2.json`.
+fn form_factor_of(features: Option<&Value>) -> FormFactor {
+    let features = match features {
+        Some(Value::Array(features)) => features.iter()
+            .filter_map(|feature| feature.as_str().or_else(|| feature.get("name").and_then(Value::as_str)))
+            .collect(),
+        _ => vec![],
+    };
+    apk::form_factor(&features)
+}
+
+/// The release channels, such as `Beta`, a version in an index with release channels is in
+fn release_channels(version: &serde_json::Map<String, Value>) -> Vec<&str> {
+    match version.get("releaseChannels") {
+        Some(Value::Array(channels)) => channels.iter().filter_map(|channel| channel.as_str()).collect(),
+        _ => vec![],
+    }
+}
+
+/// Whether a version in the given release channels may be downloaded from `channel`.  Stable
+/// versions are in no release channel; F-Droid only has a `Beta` channel, so the alpha channel
+/// takes anything.
+fn on_channel(channel: Channel, release_channels: &[&str]) -> bool {
+    match channel {
+        Channel::Stable => release_channels.is_empty(),
+        Channel::Beta => release_channels.iter().all(|release_channel| release_channel.eq_ignore_ascii_case("beta")),
+        Channel::Alpha => true,
+    }
+}
+
+/// What the package index says about the apps.  F-Droid has no ratings or install counts, but
+/// gives each app's name, summary, author and license.
+pub async fn app_info(apps: Vec<String>, options: &HashMap<&str, &str>) -> Vec<AppInfo> {
+    let index = retrieve_index_or_exit(options, Rc::new(MultiProgress::new()), OutputFormat::Plaintext).await;
+    // Texts are given directly, or per locale in `localized`, in `index-v1.json`, and per locale in
+    // `index-v2.json`
+    let metadata = |app_id: &str| match (index.get("apps"), index.get("packages")) {
+        (Some(Value::Array(apps)), _) => apps.iter().find(|app| app.get("packageName").and_then(Value::as_str) == Some(app_id)),
+        (_, Some(Value::Object(packages))) => packages.get(app_id).and_then(|package| package.get("metadata")),
+        _ => None,
+    };
+    apps.into_iter().filter_map(|app_id| {
+        let metadata = match metadata(&app_id) {
+            Some(metadata) => metadata,
+            None => {
+                println!("{}", super::failed(&app_id, tr!("not-in-package-list", app = app_id.as_str())));
+                return None;
+            },
+        };
+        let text = |key: &str| localized(metadata.get(key))
+            .or_else(|| metadata.get("localized").and_then(|locales| locales.get("en-US")).and_then(|locale| localized(locale.get(key))));
+        Some(AppInfo {
+            name: text("name"),
+            developer: text("authorName"),
+            summary: text("summary"),
+            license: text("license"),
+            app_id,
+            ..Default::default()
+        })
+    }).collect()
+}
+
+/// A text given directly, or per locale as in `index-v2.json`, in US English if it is given in it.
+fn localized(value: Option<&Value>) -> Option<String> {
+    match value {
+        Some(Value::String(value)) => Some(value.to_string()),
+        Some(Value::Object(locales)) => locales.get("en-US").or_else(|| locales.values().next())
+            .and_then(Value::as_str).map(String::from),
+        _ => None,
+    }
+}
+
+/// Search the package index for apps whose name or ID contains `query`, those whose name is
+/// `query` first, then those whose name starts with it.
+pub async fn search(query: &str, options: &HashMap<&str, &str>) -> Vec<SearchMatch> {
+    let index = retrieve_index_or_exit(options, Rc::new(MultiProgress::new()), OutputFormat::Plaintext).await;
+    // Names are given directly, or per locale in `localized` in `index-v1.json`
+    let apps = if let Some(Value::Array(apps)) = index.get("apps") {
+        apps.iter().filter_map(|app| {
+            let app_id = app.get("packageName")?.as_str()?.to_string();
+            let name = localized(app.get("name"))
+                .or_else(|| app.get("localized").and_then(|locales| locales.get("en-US")).and_then(|locale| localized(locale.get("name"))))
+                .unwrap_or_else(|| app_id.clone());
+            Some(SearchMatch { app_id, name, developer: localized(app.get("authorName")) })
+        }).collect::<Vec<_>>()
+    } else if let Some(Value::Object(packages)) = index.get("packages") {
+        packages.iter().map(|(app_id, package)| {
+            let metadata = package.get("metadata");
+            SearchMatch {
+                app_id: app_id.to_string(),
+                name: localized(metadata.and_then(|metadata| metadata.get("name"))).unwrap_or_else(|| app_id.to_string()),
+                developer: localized(metadata.and_then(|metadata| metadata.get("authorName"))),
+            }
+        }).collect()
+    } else {
+        vec![]
+    };
+    let query = query.to_lowercase();
+    let mut matches = apps.into_iter()
+        .filter(|app| app.name.to_lowercase().contains(&query) || app.app_id.to_lowercase().contains(&query))
+        .collect::<Vec<_>>();
+    matches.sort_by_key(|app| {
+        let name = app.name.to_lowercase();
+        (name != query, !name.starts_with(&query), name.len())
+    });
+    matches
+}
+
+/// The latest version of each app in the package index, on the channel and for the form factor
+/// asked for, as it would be downloaded.  Only its versionCode is known.
+pub async fn latest_versions(apps: Vec<String>, options: &HashMap<&str, &str>, channel: Channel, form_factor: Option<FormFactor>) -> Vec<LatestVersion> {
     let mp = Rc::new(MultiProgress::new());
-    let output_format = match options.get("output_format") {
-        Some(val) if val.to_lowercase() == "json" => OutputFormat::Json,
-        _ => OutputFormat::Plaintext,
+    let index = retrieve_index_or_exit(options, Rc::clone(&mp), OutputFormat::Plaintext).await;
+    let app_arch = options.get("arch").map(|x| x.to_string());
+    let apps = apps.into_iter().map(|app_id| (app_id, None)).collect();
+    match parse_json_for_download_information(index, apps, app_arch, channel, form_factor, mp) {
+        Ok((fdroid_apps, _)) => fdroid_apps.into_iter()
+            .map(|fdroid_app| LatestVersion { app_id: fdroid_app.app_id, version: fdroid_app.app_version, version_code: fdroid_app.version_code })
+            .collect(),
+        Err(_) => {
+            println!("{}", tr!("fdroid-index-unparsable"));
+            std::process::exit(1);
+        },
+    }
+}
+
+/// Every version of each app in the package index released within `release_dates`, oldest first,
+/// as entries of the app list for `--all-versions`.  Each is given by its versionCode, which tells
+/// builds apart exactly.
+pub async fn all_versions(apps: Vec<String>, options: &HashMap<&str, &str>, release_dates: ReleaseDates) -> Vec<(String, Option<String>)> {
+    let mp = Rc::new(MultiProgress::new());
+    let index = retrieve_index_or_exit(options, mp, OutputFormat::Plaintext).await;
+    let packages = match index.get("packages").and_then(Value::as_object) {
+        Some(packages) => packages,
+        None => {
+            println!("{}", tr!("fdroid-index-unparsable"));
+            std::process::exit(1);
+        },
     };
+    let mut all = vec![];
+    for app_id in apps {
+        // `index-v1.json` lists each package's versions in an array, `index-v2.json` by hash.  Both
+        // give when each was added to the repository in milliseconds.
+        let released_at = |version: &Value| version.get("added")
+            .and_then(Value::as_i64)
+            .and_then(DateTime::from_timestamp_millis);
+        let version_codes = match packages.get(&app_id) {
+            Some(Value::Array(app_array)) => app_array.iter()
+                .filter(|version| release_dates.contains(released_at(version)))
+                .filter_map(|version| version.get("versionCode")?.as_u64())
+                .collect::<BTreeSet<u64>>(),
+            Some(Value::Object(app_object)) => app_object.get("versions").and_then(Value::as_object).into_iter()
+                .flat_map(|versions| versions.values())
+                .filter(|version| release_dates.contains(released_at(version)))
+                .filter_map(|version| version.get("manifest")?.get("versionCode")?.as_u64())
+                .collect::<BTreeSet<u64>>(),
+            _ => {
+                println!("{}", super::failed(&app_id, tr!("not-in-package-list", app = app_id.as_str())));
+                continue;
+            },
+        };
+        all.extend(version_codes.into_iter().map(|version_code| (app_id.clone(), Some(format!("code:{}", version_code)))));
+    }
+    all
+}
+
+pub async fn list_versions(apps: Vec<(String, Option<String>)>, options: HashMap<&str, &str>) {
+    let mp = Rc::new(MultiProgress::new());
+    let output_format = OutputFormat::from_options(&options);
     let index = retrieve_index_or_exit(&options, mp, output_format.clone()).await;
 
     if parse_json_display_versions(index, apps, output_format).is_err() {
-        eprintln!("Could not parse JSON of F-Droid package index. Exiting.");
+        eprintln!("{}", tr!("fdroid-index-unparsable"));
         std::process::exit(1);
     };
 }
@@ -417,15 +802,17 @@ fn parse_json_display_versions(index: Value, apps: Vec<(String, Option<String>)>
     for app in apps {
         let (app_id, _) = app;
         if output_format.is_plaintext() {
-            println!("Versions available for {} on F-Droid:", app_id);
+            println!("{}", tr!("versions-available", app = app_id.as_str(), source = "F-Droid"));
         }
-        let mut versions_set = HashSet::new();
+        // Each version's versionCode, and what's new in it, which only `index-v2.json` gives
+        let mut versions_set: HashMap<String, (Option<u64>, Option<String>)> = HashMap::new();
         match packages.get(&app_id) {
             Some(Value::Array(app_array)) => {
                 for single_app in app_array {
                     if let Value::Object(fdroid_app) = single_app {
                         if let Some(Value::String(version_name)) = fdroid_app.get("versionName") {
-                            versions_set.insert(version_name.to_string());
+                            let version_code = fdroid_app.get("versionCode").and_then(|code| code.as_u64());
+                            versions_set.insert(version_name.to_string(), (version_code, None));
                         }
                     }
                 }
@@ -436,7 +823,8 @@ fn parse_json_display_versions(index: Value, apps: Vec<(String, Option<String>)>
                         if let Value::Object(version) = version_value {
                             if let Some(Value::Object(manifest)) = version.get("manifest") {
                                 if let Some(Value::String(version_name)) = manifest.get("versionName") {
-                                    versions_set.insert(version_name.to_string());
+                                    let version_code = manifest.get("versionCode").and_then(|code| code.as_u64());
+                                    versions_set.insert(version_name.to_string(), (version_code, localized(version.get("whatsNew"))));
                                 }
                             }
                         }
@@ -445,8 +833,8 @@ fn parse_json_display_versions(index: Value, apps: Vec<(String, Option<String>)>
             },
             _ => {
                 match output_format {
-                    OutputFormat::Plaintext => {
-                        eprintln!("| Could not find {} in package list. Skipping...", app_id);
+                    OutputFormat::Plaintext | OutputFormat::Tsv => {
+                        eprintln!("{}", tr!("versions-not-in-package-list", app = app_id.as_str()));
                     },
                     OutputFormat::Json => {
                         let mut app_root = HashMap::new();
@@ -457,17 +845,25 @@ fn parse_json_display_versions(index: Value, apps: Vec<(String, Option<String>)>
                 continue;
             }
         }
-        let mut versions_set = versions_set.drain().collect::<Vec<String>>();
+        let mut versions_set = versions_set.drain().collect::<Vec<(String, (Option<u64>, Option<String>))>>();
         versions_set.sort();
         match output_format {
             OutputFormat::Plaintext => {
-                println!("| {}", versions_set.join(", "));
+                println!("| {}", versions_set.into_iter().map(|(v, _)| v).collect::<Vec<_>>().join(", "));
+            },
+            OutputFormat::Tsv => {
+                for (v, (version_code, _)) in versions_set {
+                    println!("{}", tsv_row(&[&app_id, &v, &version_code.map_or(String::new(), |code| code.to_string())]));
+                }
             },
             OutputFormat::Json => {
                 let mut app_root: HashMap<String, Vec<HashMap<String, String>>> = HashMap::new();
-                app_root.insert("available_versions".to_string(), versions_set.into_iter().map(|v| {
+                app_root.insert("available_versions".to_string(), versions_set.into_iter().map(|(v, (_, changelog))| {
                     let mut version_map = HashMap::new();
                     version_map.insert("version".to_string(), v);
+                    if let Some(changelog) = changelog {
+                        version_map.insert("changelog".to_string(), changelog);
+                    }
                     version_map
                 }).collect());
                 json_root.as_mut().unwrap().insert(app_id.to_string(), json!(app_root));
@@ -481,11 +877,10 @@ fn parse_json_display_versions(index: Value, apps: Vec<(String, Option<String>)>
 }
 
 fn verify_and_return_json(dir: &TempDir, files: &[String], fingerprint: &[u8], verify_index: bool, use_entry: bool, mp: Rc<MultiProgress>) -> Result<String, Box<dyn Error>> {
-    let re = Regex::new(consts::FDROID_SIGNATURE_BLOCK_FILE_REGEX).unwrap();
     let cert_file = {
         let mut cert_files = vec![];
         for file in files {
-            if re.is_match(file) {
+            if patterns::SIGNATURE_BLOCK_FILE.is_match(file) {
                 cert_files.push(file.clone());
             }
         }
@@ -506,7 +901,7 @@ fn verify_and_return_json(dir: &TempDir, files: &[String], fingerprint: &[u8], v
     let signed_content = fs::read(signed_file)?;
 
     if verify_index {
-        mp.println("Verifying...").unwrap();
+        mp.println(tr!("fdroid-verifying")).unwrap();
         let signed_data = get_signed_data_from_cert_file(cert_file)?;
         let signer_info = signed_data.signers().next().unwrap();
         signer_info.verify_signature_with_signed_data_and_content(
@@ -526,9 +921,9 @@ fn verify_and_return_json(dir: &TempDir, files: &[String], fingerprint: &[u8], v
     let manifest_file_data = fs::read(manifest_file)?;
     if verify_index {
         let (signed_file_regex, sha_algorithm_name) = if use_entry {
-            (Regex::new(r"\r\nSHA-256-Digest-Manifest: (.*)\r\n").unwrap(), "sha256sum")
+            (&*patterns::SIGNED_MANIFEST_SHA256, "sha256sum")
         } else {
-            (Regex::new(r"\r\nSHA1-Digest-Manifest: (.*)\r\n").unwrap(), "sha1sum")
+            (&*patterns::SIGNED_MANIFEST_SHA1, "sha1sum")
         };
         let signed_file_manifest_shasum = b64_general_purpose::STANDARD.decode(match signed_file_regex.captures(signed_file_string) {
             Some(caps) if caps.len() >= 2 => caps.get(1).unwrap().as_str(),
@@ -559,9 +954,9 @@ fn verify_and_return_json(dir: &TempDir, files: &[String], fingerprint: &[u8], v
     let json_file_data = fs::read(json_file)?;
     if verify_index {
         let (manifest_file_regex, file_algo) = if use_entry {
-            (Regex::new(r"\r\nName: entry\.json\r\nSHA-256-Digest: (.*)\r\n").unwrap(), "entry sha256sum")
+            (&*patterns::MANIFEST_ENTRY_SHA256, "entry sha256sum")
         } else {
-            (Regex::new(r"\r\nName: index-v1\.json\r\nSHA1-Digest: (.*)\r\n").unwrap(), "index sha1sum")
+            (&*patterns::MANIFEST_INDEX_V1_SHA1, "index sha1sum")
         };
         let manifest_file_shasum = b64_general_purpose::STANDARD.decode(match manifest_file_regex.captures(manifest_file_string) {
             Some(caps) if caps.len() >= 2 => caps.get(1).unwrap().as_str(),
@@ -604,6 +999,7 @@ async fn verify_and_return_index_from_entry(dir: &TempDir, repo: &str, json: &st
         }
     };
     let index_url = format!("{}/{}", repo, index_name);
+    trace::download(&index_url);
     let mut dl = AsyncDownload::new(&index_url, dir.path(), &index_name).get().await.unwrap();
     let length = dl.length();
     let cb = match length {
@@ -612,12 +1008,12 @@ async fn verify_and_return_index_from_entry(dir: &TempDir, repo: &str, json: &st
     };
     match dl.download(&cb).await {
         Ok(_) => {
-            mp_log.println(format!("Package index downloaded successfully!")).unwrap();
+            mp_log.println(tr!("fdroid-index-downloaded")).unwrap();
             let index_file = dir.path().join(index_name);
             let index_file_data = fs::read(index_file)?;
 
             if verify_index {
-                mp_log.println("Verifying...").unwrap();
+                mp_log.println(tr!("fdroid-verifying")).unwrap();
                 let actual_index_shasum = {
                     let mut hasher = Sha256::new();
                     hasher.update(index_file_data.clone());
@@ -672,7 +1068,7 @@ fn get_signed_data_from_cert_file(signature_block_file: PathBuf) -> Result<Signe
 
 async fn download_and_extract_to_tempdir(dir: &TempDir, repo: &str, mp: Rc<MultiProgress>, use_entry: bool, output_format: OutputFormat) -> Vec<String> {
     let mp_log = Rc::clone(&mp);
-    mp_log.suspend(|| println!("Downloading F-Droid package repository..."));
+    mp_log.suspend(|| println!("{}", tr!("fdroid-downloading-repository")));
     let mut files = vec![];
     let fdroid_jar_url  = if use_entry {
         format!("{}/entry.jar", repo)
@@ -680,6 +1076,7 @@ async fn download_and_extract_to_tempdir(dir: &TempDir, repo: &str, mp: Rc<Multi
         format!("{}/index-v1.jar", repo)
     };
     let jar_local_file = "jar.zip";
+    trace::download(&fdroid_jar_url);
     let mut dl = AsyncDownload::new(&fdroid_jar_url, dir.path(), jar_local_file).get().await.unwrap();
     let length = dl.length();
     let cb = match length {
@@ -688,7 +1085,7 @@ async fn download_and_extract_to_tempdir(dir: &TempDir, repo: &str, mp: Rc<Multi
     };
     match dl.download(&cb).await {
         Ok(_) => {
-            mp_log.suspend(|| println!("Package repository downloaded successfully!\nExtracting..."));
+            mp_log.suspend(|| println!("{}", tr!("fdroid-repository-downloaded")));
             let file = fs::File::open(dir.path().join(jar_local_file)).unwrap();
             match zip::ZipArchive::new(file) {
                 Ok(mut archive) => {
@@ -0,0 +1,180 @@
+//! Mirroring the listings of apps in an F-Droid repository, their names, summaries, descriptions,
+//! changelogs, icons and screenshots, into OUTPATH in the fastlane layout with `-o fastlane=true`,
+//! so that a repository built from OUTPATH with `repo build` has them too.
+
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+
+use serde_json::Value;
+use simple_error::SimpleError;
+
+use crate::cli::DownloadSource;
+use crate::repo::fastlane::{self, CHANGELOGS_DIR, DESCRIPTION, ICON, IMAGES_DIR, PHONE_SCREENSHOTS_DIR, SUMMARY, TITLE};
+use crate::util::{http, network_error, retry, sha256_file};
+
+/// A graphic in a listing, where the repository serves it from
+struct Graphic {
+    url: String,
+    /// Given by `index-v2.json` only
+    sha256: Option<String>,
+}
+
+/// An app's listing in one locale, as the package index gives it
+#[derive(Default)]
+pub struct RemoteListing {
+    title: Option<String>,
+    summary: Option<String>,
+    description: Option<String>,
+    changelogs: BTreeMap<u64, String>,
+    icon: Option<Graphic>,
+    phone_screenshots: Vec<Graphic>,
+}
+
+/// Listings by package and then by locale
+pub type RemoteListings = BTreeMap<String, BTreeMap<String, RemoteListing>>;
+
+/// The listings of the given apps in the package index.  `index-v1.json` gives them in each app's
+/// `localized`, with graphics beside the APKs in `<package>/<locale>/`, and only what's new in the
+/// suggested version; `index-v2.json` gives each text and graphic by locale in the package's
+/// metadata, and what's new in each version.
+pub fn listings(index: &Value, apps: &[&str]) -> RemoteListings {
+    let address = index.get("repo").and_then(|repo| repo.get("address")).and_then(Value::as_str).unwrap_or_default();
+    let mut listings = RemoteListings::new();
+    if let Some(Value::Array(index_apps)) = index.get("apps") {
+        for app in index_apps {
+            let package = match app.get("packageName").and_then(Value::as_str) {
+                Some(package) if apps.contains(&package) => package,
+                _ => continue,
+            };
+            let suggested_version_code = match app.get("suggestedVersionCode") {
+                Some(Value::String(version_code)) => version_code.parse().ok(),
+                Some(version_code) => version_code.as_u64(),
+                None => None,
+            };
+            let mut locales = BTreeMap::new();
+            for (locale, localized) in app.get("localized").and_then(Value::as_object).into_iter().flatten() {
+                let text = |key: &str| localized.get(key).and_then(Value::as_str).map(String::from);
+                let graphic = |name: &str| Graphic { url: format!("{}/{}/{}/{}", address, package, locale, name), sha256: None };
+                locales.insert(locale.clone(), RemoteListing {
+                    title: text("name"),
+                    summary: text("summary"),
+                    description: text("description"),
+                    changelogs: suggested_version_code.zip(text("whatsNew")).into_iter().collect(),
+                    icon: localized.get("icon").and_then(Value::as_str).map(graphic),
+                    phone_screenshots: localized.get("phoneScreenshots").and_then(Value::as_array).into_iter().flatten()
+                        .filter_map(Value::as_str)
+                        .map(|name| graphic(&format!("{}/{}", PHONE_SCREENSHOTS_DIR, name)))
+                        .collect(),
+                });
+            }
+            // Texts from the app's own metadata, rather than a translation, are given outside of
+            // `localized`
+            let english: &mut RemoteListing = locales.entry("en-US".to_string()).or_default();
+            let text = |key: &str| app.get(key).and_then(Value::as_str).map(String::from);
+            english.title = english.title.take().or_else(|| text("name"));
+            english.summary = english.summary.take().or_else(|| text("summary"));
+            english.description = english.description.take().or_else(|| text("description"));
+            listings.insert(package.to_string(), locales);
+        }
+    } else if let Some(Value::Object(packages)) = index.get("packages") {
+        for (package, entry) in packages.iter().filter(|(package, _)| apps.contains(&package.as_str())) {
+            let metadata = entry.get("metadata").unwrap_or(&Value::Null);
+            let graphic = |file: &Value| file.get("name").and_then(Value::as_str).map(|name| Graphic {
+                url: format!("{}{}", address, name),
+                sha256: file.get("sha256").and_then(Value::as_str).map(String::from),
+            });
+            let mut locales: BTreeMap<String, RemoteListing> = BTreeMap::new();
+            let texts = |key: &str| metadata.get(key).and_then(Value::as_object).into_iter().flatten()
+                .filter_map(|(locale, text)| text.as_str().map(|text| (locale.clone(), text.to_string())));
+            for (locale, title) in texts("name") {
+                locales.entry(locale).or_default().title = Some(title);
+            }
+            for (locale, summary) in texts("summary") {
+                locales.entry(locale).or_default().summary = Some(summary);
+            }
+            for (locale, description) in texts("description") {
+                locales.entry(locale).or_default().description = Some(description);
+            }
+            for version in entry.get("versions").and_then(Value::as_object).into_iter().flatten().map(|(_, version)| version) {
+                let version_code = version.get("manifest").and_then(|manifest| manifest.get("versionCode")).and_then(Value::as_u64);
+                for (locale, whats_new) in version.get("whatsNew").and_then(Value::as_object).into_iter().flatten() {
+                    if let (Some(version_code), Some(whats_new)) = (version_code, whats_new.as_str()) {
+                        locales.entry(locale.clone()).or_default().changelogs.insert(version_code, whats_new.to_string());
+                    }
+                }
+            }
+            for (locale, icon) in metadata.get("icon").and_then(Value::as_object).into_iter().flatten() {
+                locales.entry(locale.clone()).or_default().icon = graphic(icon);
+            }
+            let phone_screenshots = metadata.get("screenshots").and_then(|screenshots| screenshots.get("phone")).and_then(Value::as_object);
+            for (locale, screenshots) in phone_screenshots.into_iter().flatten() {
+                locales.entry(locale.clone()).or_default().phone_screenshots = screenshots.as_array().into_iter().flatten()
+                    .filter_map(graphic)
+                    .collect();
+            }
+            listings.insert(package.clone(), locales);
+        }
+    }
+    listings
+}
+
+/// Write an app's listings into `outpath`, downloading the graphics which are not already there.
+pub async fn mirror(outpath: &Path, package: &str, locales: &BTreeMap<String, RemoteListing>) -> Result<(), Box<dyn Error>> {
+    let client = http::source_client(DownloadSource::FDroid);
+    for (locale, listing) in locales {
+        let dir = fastlane::locale_dir(outpath, package, locale);
+        fastlane::write_text(&dir.join(TITLE), listing.title.as_deref())?;
+        fastlane::write_text(&dir.join(SUMMARY), listing.summary.as_deref())?;
+        fastlane::write_text(&dir.join(DESCRIPTION), listing.description.as_deref())?;
+        for (version_code, changelog) in &listing.changelogs {
+            fastlane::write_text(&dir.join(CHANGELOGS_DIR).join(format!("{}.txt", version_code)), Some(changelog))?;
+        }
+        let images_dir = dir.join(IMAGES_DIR);
+        if let Some(icon) = &listing.icon {
+            download_graphic(&client, icon, &images_dir.join(ICON)).await?;
+        }
+        let screenshots_dir = images_dir.join(PHONE_SCREENSHOTS_DIR);
+        let mut screenshots = HashSet::new();
+        for (number, screenshot) in listing.phone_screenshots.iter().enumerate() {
+            let extension = Path::new(&screenshot.url).extension().and_then(|ext| ext.to_str()).unwrap_or("png").to_ascii_lowercase();
+            let name = format!("{}.{}", number + 1, extension);
+            download_graphic(&client, screenshot, &screenshots_dir.join(&name)).await?;
+            screenshots.insert(OsString::from(name));
+        }
+        // Screenshots the repository no longer lists would otherwise still be served
+        if screenshots_dir.is_dir() {
+            for entry in fs::read_dir(&screenshots_dir)? {
+                let entry = entry?;
+                if !screenshots.contains(&entry.file_name()) {
+                    fs::remove_file(entry.path())?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Download a graphic to `path`, unless it is already there.  Without a hash to check the file
+/// against, any file already at `path` is taken to be the graphic.
+async fn download_graphic(client: &reqwest::Client, graphic: &Graphic, path: &Path) -> Result<(), Box<dyn Error>> {
+    if path.is_file() && graphic.sha256.as_ref().is_none_or(|sha256| sha256_file(path).is_ok_and(|existing| existing == *sha256)) {
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let _permit = http::host_permit(&graphic.url).await;
+    let response = retry::send(client.get(&graphic.url)).await
+        .map_err(|err| SimpleError::new(network_error::describe(&err)))?;
+    if !response.status().is_success() {
+        return Err(Box::new(SimpleError::new(format!("{} returned {}", graphic.url, response.status()))));
+    }
+    if let Err(err) = http::write_response_to_file(response, path).await {
+        let _ = fs::remove_file(path);
+        return Err(err);
+    }
+    Ok(())
+}
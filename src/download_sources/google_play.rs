@@ -1,25 +1,90 @@
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
-use std::path::Path;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::time::{Duration, Instant};
 
 use futures_util::StreamExt;
 use gpapi::error::ErrorKind as GpapiErrorKind;
 use gpapi::Gpapi;
 use indicatif::MultiProgress;
+use simple_error::SimpleError;
 use tokio::time::{sleep, Duration as TokioDuration};
 
+use crate::cli::DownloadSource;
+use crate::download_sources::{attempt, claims, DownloadedApp};
 use crate::util::progress_bar::progress_wrapper;
+use crate::util::timings::{self, Phase};
+
+/// How long an account whose download failed is rested for before it is used again, unless
+/// `-o account_cooldown` says otherwise
+const DEFAULT_ACCOUNT_COOLDOWN: Duration = Duration::from_secs(600);
+
+/// A Google account to download with
+pub struct Account {
+    pub email: String,
+    pub aas_token: String,
+}
+
+/// The accounts logged in to, taken in turn for each download so that the requests are spread
+/// across them rather than using up one account's quota.  An account whose download fails is
+/// rested for the cooldown, and while every account is resting the next download waits for the
+/// first to be ready again.  A single account is never rested, so that it is retried at once.
+struct Accounts {
+    apis: Vec<Gpapi>,
+    next: Cell<usize>,
+    resting_until: RefCell<Vec<Option<Instant>>>,
+    cooldown: Duration,
+}
+
+impl Accounts {
+    /// The index of the account to make the next download with
+    async fn take(&self) -> usize {
+        let count = self.apis.len();
+        let (index, ready) = {
+            let resting_until = self.resting_until.borrow();
+            let now = Instant::now();
+            let start = self.next.get();
+            let ready = (0..count)
+                .map(|offset| (start + offset) % count)
+                .find(|index| resting_until[*index].is_none_or(|until| until <= now));
+            match ready {
+                Some(index) => (index, None),
+                None => {
+                    let index = (0..count).min_by_key(|index| resting_until[*index]).unwrap();
+                    (index, resting_until[index])
+                },
+            }
+        };
+        self.next.set((index + 1) % count);
+        if let Some(ready) = ready {
+            sleep(ready.saturating_duration_since(Instant::now())).await;
+        }
+        index
+    }
+
+    fn api(&self, index: usize) -> &Gpapi {
+        &self.apis[index]
+    }
+
+    fn rest(&self, index: usize) {
+        if self.apis.len() > 1 {
+            self.resting_until.borrow_mut()[index] = Some(Instant::now() + self.cooldown);
+        }
+    }
+}
 
 pub async fn download_apps(
     apps: Vec<(String, Option<String>)>,
     parallel: usize,
     sleep_duration: u64,
-    email: &str,
-    aas_token: &str,
+    accounts: Vec<Account>,
     outpath: &Path,
     accept_tos: bool,
     mut options: HashMap<&str, &str>,
-) {
+) -> Vec<DownloadedApp> {
     let device = options.remove("device").unwrap_or("px_7a");
     let split_apk = match options.remove("split_apk") {
         Some(val) if val == "1" || val.to_lowercase() == "true" => true,
@@ -29,98 +94,193 @@ pub async fn download_apps(
         Some(val) if val == "1" || val.to_lowercase() == "true" => true,
         _ => false,
     };
-    let mut gpa = Gpapi::new(device, email);
-
-    if let Some(locale) = options.remove("locale") {
-        gpa.set_locale(locale);
-    }
-    if let Some(timezone) = options.remove("timezone") {
-        gpa.set_timezone(timezone);
-    }
+    let locale = options.remove("locale");
+    let timezone = options.remove("timezone");
+    let cooldown = match options.remove("account_cooldown").map(str::parse::<u64>) {
+        Some(Ok(seconds)) => Duration::from_secs(seconds),
+        Some(Err(_)) => {
+            eprintln!("account_cooldown must be a number of seconds. Exiting.");
+            std::process::exit(1);
+        },
+        None => DEFAULT_ACCOUNT_COOLDOWN,
+    };
 
-    gpa.set_aas_token(aas_token);
-    if let Err(err) = gpa.login().await {
-        match err.kind() {
-            GpapiErrorKind::TermsOfService => {
-                if accept_tos {
-                    match gpa.accept_tos().await {
-                        Ok(_) => {
-                            if let Err(_) = gpa.login().await {
-                                eprintln!("Could not log in, even after accepting the Google Play Terms of Service");
-                                std::process::exit(1);
-                            }
-                            println!("Google Play Terms of Service accepted.");
-                        },
-                        _ => {
-                            eprintln!("Could not accept Google Play Terms of Service");
-                            std::process::exit(1);
-                        },
-                    }
-                } else {
-                    println!("{}\nPlease read the ToS here: https://play.google.com/about/play-terms/index.html\nIf you accept, please pass the --accept-tos flag.", err);
-                    std::process::exit(1);
-                }
-            },
-            _ => {
-                eprintln!("Could not log in to Google Play.  Please check your credentials and try again later. {}", err);
+    let timer = timings::start_shared(Phase::Resolve);
+    let several = accounts.len() > 1;
+    let mut apis = vec![];
+    for account in accounts {
+        let mut gpa = Gpapi::new(device, account.email.as_str());
+        if let Some(locale) = locale {
+            gpa.set_locale(locale);
+        }
+        if let Some(timezone) = timezone {
+            gpa.set_timezone(timezone);
+        }
+        gpa.set_aas_token(account.aas_token.as_str());
+        match login(&mut gpa, accept_tos).await {
+            Ok(()) => apis.push(gpa),
+            // With several accounts, the rest can carry on without one which can't log in
+            Err(err) if several => eprintln!("Could not log in as {}, so it won't be used: {}", account.email, err),
+            Err(err) => {
+                eprintln!("{}", err);
                 std::process::exit(1);
-            }
+            },
         }
     }
+    if apis.is_empty() {
+        eprintln!("Could not log in to Google Play with any of the accounts given. Exiting.");
+        std::process::exit(1);
+    }
+    drop(timer);
 
     let mp = Rc::new(MultiProgress::new());
-    let gpa = Rc::new(gpa);
+    let accounts = Rc::new(Accounts {
+        resting_until: RefCell::new(vec![None; apis.len()]),
+        apis,
+        next: Cell::new(0),
+        cooldown,
+    });
     futures_util::stream::iter(
         apps.into_iter().map(|app| {
             let (app_id, app_version) = app;
-            let gpa = Rc::clone(&gpa);
+            let accounts = Rc::clone(&accounts);
             let mp_dl1 = Rc::clone(&mp);
             let mp_dl2 = Rc::clone(&mp);
             let mp_dl3 = Rc::clone(&mp);
             let mp_log = Rc::clone(&mp);
 
-            async move {
-                if app_version.is_none() {
-                    mp_log.suspend(|| println!("Downloading {}...", app_id));
+            attempt(DownloadSource::GooglePlay, app_id.clone(), async move {
+                // Google Play can be asked for a versionCode, but not a versionName
+                let version_code = app_version.as_deref()
+                    .and_then(super::version_code)
+                    .and_then(|version_code| i32::try_from(version_code).ok());
+                if app_version.is_none() || version_code.is_some() {
+                    mp_log.suspend(|| println!("{}", tr!("downloading", app = app_id.as_str())));
+                    // Google Play names the file after the app whatever its version, so only the
+                    // same app can claim it, and it can't be renamed
+                    let _claim = match claims::claim(outpath, &format!("{}.apk", app_id), &app_id, &mp_log) {
+                        Some((_, claim)) => claim,
+                        None => return None,
+                    };
                     if sleep_duration > 0 {
                         sleep(TokioDuration::from_millis(sleep_duration)).await;
                     }
-                    match gpa.download(&app_id, None, split_apk, include_additional_files, Path::new(outpath), Some(&progress_wrapper(mp_dl1))).await {
-                        Ok(_) => mp_log.suspend(|| println!("{} downloaded successfully!", app_id)),
+                    // Resolving the delivery URL happens within the download, so both count as downloading
+                    let timer = timings::start(&app_id, Phase::Download);
+                    let account = accounts.take().await;
+                    let downloaded = match accounts.api(account).download(&app_id, version_code, split_apk, include_additional_files, Path::new(outpath), Some(&progress_wrapper(mp_dl1))).await {
+                        Ok(_) => true,
                         Err(err) if matches!(err.kind(), GpapiErrorKind::FileExists) => {
-                            mp_log.println(format!("File already exists for {}. Skipping...", app_id)).unwrap();
+                            mp_log.println(tr!("file-exists", app = app_id.as_str())).unwrap();
+                            false
                         }
                         Err(err) if matches!(err.kind(), GpapiErrorKind::DirectoryExists) => {
-                            mp_log.println(format!("Split APK directory already exists for {}. Skipping...", app_id)).unwrap();
+                            mp_log.println(tr!("split-directory-exists", app = app_id.as_str())).unwrap();
+                            false
                         }
                         Err(err) if matches!(err.kind(), GpapiErrorKind::InvalidApp) => {
-                            mp_log.println(format!("Invalid app response for {}. Skipping...", app_id)).unwrap();
+                            mp_log.println(super::failed(&app_id, tr!("not-offered-to-device", app = app_id.as_str(), device = device.to_string()))).unwrap();
+                            false
                         }
                         Err(err) if matches!(err.kind(), GpapiErrorKind::PermissionDenied) => {
-                            mp_log.println(format!("Permission denied when attempting to write file for {}. Skipping...", app_id)).unwrap();
+                            mp_log.println(super::failed(&app_id, tr!("permission-denied", app = app_id.as_str()))).unwrap();
+                            false
                         }
+                        // Each attempt after a failure is made with another account, if there is one
                         Err(_) => {
-                            mp_log.println(format!("An error has occurred attempting to download {}.  Retry #1...", app_id)).unwrap();
-                            match gpa.download(&app_id, None, split_apk, include_additional_files, Path::new(outpath), Some(&progress_wrapper(mp_dl2))).await {
-                                Ok(_) => mp_log.suspend(|| println!("{} downloaded successfully!", app_id)),
+                            mp_log.println(tr!("download-retry", app = app_id.as_str(), attempt = 1)).unwrap();
+                            accounts.rest(account);
+                            let account = accounts.take().await;
+                            match accounts.api(account).download(&app_id, version_code, split_apk, include_additional_files, Path::new(outpath), Some(&progress_wrapper(mp_dl2))).await {
+                                Ok(_) => true,
                                 Err(_) => {
-                                    mp_log.println(format!("An error has occurred attempting to download {}.  Retry #2...", app_id)).unwrap();
-                                    match gpa.download(&app_id, None, split_apk, include_additional_files, Path::new(outpath), Some(&progress_wrapper(mp_dl3))).await {
-                                        Ok(_) => mp_log.suspend(|| println!("{} downloaded successfully!", app_id)),
+                                    mp_log.println(tr!("download-retry", app = app_id.as_str(), attempt = 2)).unwrap();
+                                    accounts.rest(account);
+                                    let account = accounts.take().await;
+                                    match accounts.api(account).download(&app_id, version_code, split_apk, include_additional_files, Path::new(outpath), Some(&progress_wrapper(mp_dl3))).await {
+                                        Ok(_) => true,
                                         Err(_) => {
-                                            mp_log.println(format!("An error has occurred attempting to download {}. Skipping...", app_id)).unwrap();
+                                            accounts.rest(account);
+                                            mp_log.println(super::failed(&app_id, tr!("download-failed", app = app_id.as_str()))).unwrap();
+                                            false
                                         }
                                     }
                                 }
                             }
                         }
+                    };
+                    drop(timer);
+                    if downloaded {
+                        mp_log.suspend(|| println!("{}", tr!("downloaded", app = app_id.as_str())));
+                        let files = downloaded_files(outpath, &app_id);
+                        return Some(DownloadedApp::new(app_id, None, files));
                     }
                 } else {
-                    mp_log.println(format!("Specific versions can not be downloaded from Google Play ({}@{}). Skipping...", app_id, app_version.unwrap())).unwrap();
+                    mp_log.println(super::failed(&app_id, tr!("no-specific-versions", source = "Google Play", app = format!("{}@{}", app_id, app_version.unwrap())))).unwrap();
                 }
-            }
+                None
+            })
         })
-    ).buffer_unordered(parallel).collect::<Vec<()>>().await;
+    ).buffer_unordered(parallel).filter_map(|downloaded| async { downloaded }).collect::<Vec<DownloadedApp>>().await
+}
+
+/// Google Play writes a single APK as `<app_id>.apk`, but places the base APK and its splits in
+/// a `<app_id>` directory when a split APK is downloaded.
+fn downloaded_files(outpath: &Path, app_id: &str) -> Vec<PathBuf> {
+    let split_dir = outpath.join(app_id);
+    let base_apk = format!("{}.apk", app_id);
+    if split_dir.is_dir() {
+        let mut files = match fs::read_dir(&split_dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "apk"))
+                .collect::<Vec<PathBuf>>(),
+            Err(_) => vec![],
+        };
+        files.sort_by_key(|path| (path.file_name().map_or(true, |name| name != base_apk.as_str()), path.clone()));
+        files
+    } else {
+        vec![outpath.join(base_apk)]
+    }
+}
+
+/// Log in with the AAS token already set, first accepting the Google Play Terms of Service if
+/// they are due and `accept_tos` is given.
+async fn login(gpa: &mut Gpapi, accept_tos: bool) -> Result<(), Box<dyn Error>> {
+    let err = match gpa.login().await {
+        Ok(()) => return Ok(()),
+        Err(err) => err,
+    };
+    match err.kind() {
+        GpapiErrorKind::TermsOfService => {
+            if !accept_tos {
+                return Err(Box::new(SimpleError::new(format!("{}\nPlease read the ToS here: https://play.google.com/about/play-terms/index.html\nIf you accept, please pass the --accept-tos flag.", err))));
+            }
+            if gpa.accept_tos().await.is_err() {
+                return Err(Box::new(SimpleError::new("Could not accept Google Play Terms of Service")));
+            }
+            if gpa.login().await.is_err() {
+                return Err(Box::new(SimpleError::new("Could not log in, even after accepting the Google Play Terms of Service")));
+            }
+            println!("{}", tr!("google-play-tos-accepted"));
+            Ok(())
+        },
+        _ => Err(Box::new(SimpleError::new(format!("Could not log in to Google Play.  Please check your credentials and try again later. {}", err)))),
+    }
+}
+
+/// Exchange a single-use OAuth token for an AAS token, and check that the AAS token can log in
+/// before it is handed back to be kept.
+pub async fn authenticate(email: &str, oauth_token: &str, accept_tos: bool) -> Result<String, Box<dyn Error>> {
+    let mut gpa = Gpapi::new("px_7a", email);
+    if gpa.request_aas_token(oauth_token).await.is_err() {
+        return Err(Box::new(SimpleError::new("Was not able to retrieve an AAS token with the provided OAuth token. OAuth tokens can only be used once, so please obtain a new one and try again.")));
+    }
+    let aas_token = gpa.get_aas_token()
+        .ok_or_else(|| SimpleError::new("Google did not return an AAS token."))?
+        .to_string();
+    login(&mut gpa, accept_tos).await?;
+    Ok(aas_token)
 }
 
 pub async fn request_aas_token(
@@ -133,10 +293,10 @@ pub async fn request_aas_token(
     match api.request_aas_token(oauth_token).await {
         Ok(()) => {
             let aas_token = api.get_aas_token().unwrap();
-            println!("AAS Token: {}", aas_token);
+            println!("{}", tr!("google-play-aas-token", token = aas_token.to_string()));
         },
         Err(_) => {
-            println!("Error: was not able to retrieve AAS token with the provided OAuth token. Please provide new OAuth token and try again.");
+            println!("{}", tr!("google-play-no-aas-token"));
         }
     }
 }
@@ -144,7 +304,7 @@ pub async fn request_aas_token(
 pub fn list_versions(apps: Vec<(String, Option<String>)>) {
     for app in apps {
         let (app_id, _) = app;
-        println!("Versions available for {} on Google Play:", app_id);
-        println!("| Google Play does not make old versions of apps available.");
+        println!("{}", tr!("versions-available", app = app_id.as_str(), source = "Google Play"));
+        println!("{}", tr!("versions-not-available", source = "Google Play"));
     }
 }
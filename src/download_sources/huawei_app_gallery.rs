@@ -1,4 +1,4 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
 use futures_util::StreamExt;
@@ -7,9 +7,12 @@ use reqwest::header::{HeaderMap, HeaderValue};
 use reqwest::{Url, Response};
 use serde_json::Value;
 use tokio_dl_stream_to_disk::{AsyncDownload, error::ErrorKind as TDSTDErrorKind};
-use tokio::time::{sleep, Duration as TokioDuration};
 
-use crate::util::progress_bar::progress_wrapper;
+use crate::cli::DownloadSource;
+use crate::download_sources::{attempt, claims, deadline, DownloadedApp, needs_download};
+use crate::util::{digests, http, network_error, progress_bar::progress_wrapper, retry, safe_file_name, safe_join, trace};
+use crate::util::throttle::{Outcome, Throttle};
+use crate::util::timings::{self, Phase};
 
 fn http_headers() -> HeaderMap {
     let mut headers = HeaderMap::new();
@@ -26,46 +29,65 @@ pub async fn download_apps(
     parallel: usize,
     sleep_duration: u64,
     outpath: &Path,
-) {
-    let http_client = Rc::new(reqwest::Client::new());
+) -> Vec<DownloadedApp> {
+    let http_client = Rc::new(http::source_client(DownloadSource::HuaweiAppGallery));
     let headers = http_headers();
 
     let mp = Rc::new(MultiProgress::new());
+    let throttle = Rc::new(Throttle::new("Huawei AppGallery", parallel, sleep_duration, &mp));
     futures_util::stream::iter(
         apps.into_iter().map(|app| {
             let (app_id, app_version) = app;
             let http_client = Rc::clone(&http_client);
+            let throttle = Rc::clone(&throttle);
             let headers = headers.clone();
             let mp = Rc::clone(&mp);
             let mp_log = Rc::clone(&mp);
-            async move {
+            attempt(DownloadSource::HuaweiAppGallery, app_id.clone(), async move {
                 if app_version.is_none() {
-                    mp_log.suspend(|| println!("Downloading {}...", app_id));
-                    if sleep_duration > 0 {
-                        sleep(TokioDuration::from_millis(sleep_duration)).await;
-                    }
+                    mp_log.suspend(|| println!("{}", tr!("downloading", app = app_id.as_str())));
+                    let _slot = throttle.acquire().await;
                     let client_api_url = Url::parse(crate::consts::HUAWEI_APP_GALLERY_CLIENT_API_URL).unwrap();
-                    let client_api_response = http_client
-                        .post(client_api_url)
-                        .body(client_api_body(&app_id))
-                        .headers(headers)
-                        .send().await.unwrap();
-                    download_from_response(client_api_response, app_id.to_string(), outpath, mp).await;
+                    let client_api_response = {
+                        let _timer = timings::start(&app_id, Phase::Resolve);
+                        let _permit = http::host_permit(client_api_url.as_str()).await;
+                        retry::send(http_client
+                            .post(client_api_url)
+                            .body(client_api_body(&app_id))
+                            .headers(headers)).await
+                    };
+                    let client_api_response = match client_api_response {
+                        Ok(client_api_response) => {
+                            throttle.record(Outcome::from_status(client_api_response.status()));
+                            client_api_response
+                        },
+                        Err(err) => {
+                            throttle.record(Outcome::Failure);
+                            mp_log.println(super::failed(&app_id, tr!("no-app-information", app = app_id.as_str(), error = network_error::describe(&err)))).unwrap();
+                            return None;
+                        }
+                    };
+                    download_from_response(client_api_response, app_id.to_string(), outpath, mp, &throttle).await
+                        .map(|(file, url)| DownloadedApp::new(app_id, None, vec![file]).with_url(url))
                 } else {
-                    mp_log.println(format!("Specific versions can not be downloaded from Huawei AppGallery ({}@{}). Skipping...", app_id, app_version.unwrap())).unwrap();
+                    mp_log.println(super::failed(&app_id, tr!("no-specific-versions", source = "Huawei AppGallery", app = format!("{}@{}", app_id, app_version.unwrap())))).unwrap();
+                    None
                 }
-            }
+            })
         })
-    ).buffer_unordered(parallel).collect::<Vec<()>>().await;
+    ).buffer_unordered(parallel).filter_map(|downloaded| async { downloaded }).collect::<Vec<DownloadedApp>>().await
 }
 
-async fn download_from_response(response: Response, app_string: String, outpath: &Path, mp: Rc<MultiProgress>) {
+async fn download_from_response(response: Response, app_string: String, outpath: &Path, mp: Rc<MultiProgress>, throttle: &Throttle) -> Option<(PathBuf, String)> {
     let mp_log = Rc::clone(&mp);
     let mp = Rc::clone(&mp);
-    let fname = format!("{}.apk", app_string);
+    let fname = safe_file_name(&format!("{}.apk", app_string));
     match response.status() {
         reqwest::StatusCode::OK => {
-            let body = response.text().await.unwrap();
+            let body = {
+                let _timer = timings::start(&app_string, Phase::Resolve);
+                response.text().await.unwrap()
+            };
             let response_value: Value = serde_json::from_str(&body).unwrap();
             if response_value.is_object() {
                 let response_obj = response_value.as_object().unwrap();
@@ -78,33 +100,67 @@ async fn download_from_response(response: Response, app_string: String, outpath:
                             if first_list_entry.contains_key("downurl") {
                                 let downurl = first_list_entry.get("downurl").unwrap();
                                 if downurl.is_string() {
-                                    let download_url = downurl.as_str().unwrap();
+                                    let download_url = &http::secure_url(downurl.as_str().unwrap());
+                                    let (fname, _claim) = match claims::claim(Path::new(outpath), &fname, &app_string, &mp_log) {
+                                        Some(claimed) => claimed,
+                                        None => return None,
+                                    };
+                                    if !needs_download(download_url, &safe_join(outpath, &fname), None, &app_string, &mp_log).await {
+                                        return None;
+                                    }
+                                    let _permit = http::host_permit(download_url).await;
+                                    let _timer = timings::start(&app_string, Phase::Download);
+                                    deadline::writing(&safe_join(outpath, &fname));
+                                    trace::download(download_url);
                                     match AsyncDownload::new(download_url, Path::new(outpath), &fname).get().await {
                                         Ok(mut dl) => {
                                             let length = dl.length();
+                                            if super::too_large(&app_string, length, &mp_log) {
+                                                return None;
+                                            }
                                             let cb = match length {
                                                 Some(length) => Some(progress_wrapper(mp)(fname.clone(), length)),
                                                 None => None,
                                             };
 
-                                            match dl.download(&cb).await {
-                                                Ok(_) => mp_log.suspend(|| println!("{} downloaded successfully!", app_string)),
+                                            match dl.download_and_return_sha256sum(&cb).await {
+                                                Ok(sha256sum) => {
+                                                    let _ = digests::record_sha256(&safe_join(outpath, &fname), &sha256sum);
+                                                    mp_log.suspend(|| println!("{}", tr!("downloaded", app = app_string.as_str())));
+                                                    return Some((safe_join(outpath, &fname), download_url.to_string()));
+                                                },
                                                 Err(err) if matches!(err.kind(), TDSTDErrorKind::FileExists) => {
-                                                    mp_log.println(format!("File already exists for {}. Skipping...", app_string)).unwrap();
+                                                    mp_log.println(tr!("file-exists", app = app_string.as_str())).unwrap();
                                                 },
                                                 Err(err) if matches!(err.kind(), TDSTDErrorKind::PermissionDenied) => {
-                                                    mp_log.println(format!("Permission denied when attempting to write file for {}. Skipping...", app_string)).unwrap();
+                                                    mp_log.println(super::failed(&app_string, tr!("permission-denied", app = app_string.as_str()))).unwrap();
+                                                },
+                                                Err(err) if !retry::download_error(&err) => {
+                                                    throttle.record(Outcome::Failure);
+                                                    mp_log.println(super::failed(&app_string, tr!("download-not-retried", app = app_string.as_str(), error = err.to_string()))).unwrap();
                                                 },
                                                 Err(_) => {
-                                                    mp_log.println(format!("An error has occurred attempting to download {}.  Retry #1...", app_string)).unwrap();
-                                                    match AsyncDownload::new(download_url, Path::new(outpath), &fname).download(&cb).await {
-                                                        Ok(_) => mp_log.suspend(|| println!("{} downloaded successfully!", app_string)),
+                                                    throttle.record(Outcome::Failure);
+                                                    mp_log.println(tr!("download-retry", app = app_string.as_str(), attempt = 1)).unwrap();
+                                                    match AsyncDownload::new(download_url, Path::new(outpath), &fname).download_and_return_sha256sum(&cb).await {
+                                                        Ok(sha256sum) => {
+                                                            let _ = digests::record_sha256(&safe_join(outpath, &fname), &sha256sum);
+                                                            mp_log.suspend(|| println!("{}", tr!("downloaded", app = app_string.as_str())));
+                                                            return Some((safe_join(outpath, &fname), download_url.to_string()));
+                                                        },
+                                                        Err(err) if !retry::download_error(&err) => {
+                                                            mp_log.println(super::failed(&app_string, tr!("download-not-retried", app = app_string.as_str(), error = err.to_string()))).unwrap();
+                                                        },
                                                         Err(_) => {
-                                                            mp_log.println(format!("An error has occurred attempting to download {}.  Retry #2...", app_string)).unwrap();
-                                                            match AsyncDownload::new(download_url, Path::new(outpath), &fname).download(&cb).await {
-                                                                Ok(_) => mp_log.suspend(|| println!("{} downloaded successfully!", app_string)),
+                                                            mp_log.println(tr!("download-retry", app = app_string.as_str(), attempt = 2)).unwrap();
+                                                            match AsyncDownload::new(download_url, Path::new(outpath), &fname).download_and_return_sha256sum(&cb).await {
+                                                                Ok(sha256sum) => {
+                                                                    let _ = digests::record_sha256(&safe_join(outpath, &fname), &sha256sum);
+                                                                    mp_log.suspend(|| println!("{}", tr!("downloaded", app = app_string.as_str())));
+                                                                    return Some((safe_join(outpath, &fname), download_url.to_string()));
+                                                                },
                                                                 Err(_) => {
-                                                                    mp_log.println(format!("An error has occurred attempting to download {}. Skipping...", app_string)).unwrap();
+                                                                    mp_log.println(super::failed(&app_string, tr!("download-failed", app = app_string.as_str()))).unwrap();
                                                                 }
                                                             }
                                                         }
@@ -113,7 +169,7 @@ async fn download_from_response(response: Response, app_string: String, outpath:
                                             }
                                         },
                                         Err(_) => {
-                                            mp_log.println(format!("Invalid response for {}. Skipping...", app_string)).unwrap();
+                                            mp_log.println(super::failed(&app_string, tr!("invalid-response", app = app_string.as_str()))).unwrap();
                                         }
                                     }
                                 }
@@ -124,15 +180,16 @@ async fn download_from_response(response: Response, app_string: String, outpath:
             }
         },
         _ => {
-            mp_log.println(format!("Invalid app response for {}. Skipping...", app_string)).unwrap();
+            mp_log.println(super::failed(&app_string, tr!("invalid-app-response", app = app_string.as_str()))).unwrap();
         }
     }
+    None
 }
 
 pub async fn list_versions(apps: Vec<(String, Option<String>)>) {
     for app in apps {
         let (app_id, _) = app;
-        println!("Versions available for {} on Huawei AppGallery:", app_id);
-        println!("| Huawei AppGallery does not make old versions of apps available.");
+        println!("{}", tr!("versions-available", app = app_id.as_str(), source = "Huawei AppGallery"));
+        println!("{}", tr!("versions-not-available", source = "Huawei AppGallery"));
     }
 }
@@ -2,4 +2,378 @@ pub mod google_play;
 pub mod fdroid;
 pub mod apkpure;
 pub mod huawei_app_gallery;
-pub mod apkcombo;
\ No newline at end of file
+pub mod apkcombo;
+pub mod plugin;
+pub mod scraped;
+pub mod breaker;
+pub mod claims;
+pub mod deadline;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use indicatif::MultiProgress;
+use simple_error::SimpleError;
+
+use crate::cli::DownloadSource;
+use crate::storage;
+use crate::util::http::{self, Preflight};
+use crate::util::progress_events;
+
+/// An app which a download source has successfully written to disk.
+pub struct DownloadedApp {
+    pub app_id: String,
+    pub version: Option<String>,
+    /// The downloaded files, with the base APK (or XAPK bundle) first.
+    pub files: Vec<PathBuf>,
+    /// The URL the app was downloaded from, if the source resolves one
+    pub url: Option<String>,
+    pub downloaded_at: DateTime<Utc>,
+    /// When the version downloaded was released, if the source says
+    pub released_at: Option<DateTime<Utc>>,
+    /// What's new in the version downloaded, if the source says
+    pub changelog: Option<String>,
+    /// Where the results of submitting each of the app's files for analysis can be found
+    pub analysis_reports: BTreeMap<PathBuf, String>,
+    /// The source the app was fetched from, when it was raced between several
+    pub source: Option<DownloadSource>,
+}
+
+impl DownloadedApp {
+    pub fn new(app_id: String, version: Option<String>, files: Vec<PathBuf>) -> DownloadedApp {
+        DownloadedApp {
+            app_id,
+            version,
+            files,
+            url: None,
+            downloaded_at: Utc::now(),
+            released_at: None,
+            changelog: None,
+            analysis_reports: BTreeMap::new(),
+            source: None,
+        }
+    }
+
+    pub fn with_url(mut self, url: String) -> DownloadedApp {
+        self.url = Some(url);
+        self
+    }
+
+    pub fn with_source(mut self, source: DownloadSource) -> DownloadedApp {
+        self.source = Some(source);
+        self
+    }
+
+    pub fn with_release_date(mut self, released_at: Option<DateTime<Utc>>) -> DownloadedApp {
+        self.released_at = released_at;
+        self
+    }
+
+    pub fn with_changelog(mut self, changelog: Option<String>) -> DownloadedApp {
+        self.changelog = changelog;
+        self
+    }
+
+    /// Delete the downloaded files, along with the split APK directory if one was created.
+    pub fn remove(&self) -> io::Result<()> {
+        for file in &self.files {
+            fs::remove_file(file)?;
+        }
+        if self.files.len() > 1 {
+            if let Some(parent) = self.files[0].parent() {
+                if fs::read_dir(parent)?.next().is_none() {
+                    fs::remove_dir(parent)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An app found by searching a source for a name, where an app ID was expected
+pub struct SearchMatch {
+    pub app_id: String,
+    pub name: String,
+    pub developer: Option<String>,
+}
+
+/// The latest version of an app a source offers, as found by `--check-only`.  Sources give its
+/// versionName, its versionCode, or both.
+pub struct LatestVersion {
+    pub app_id: String,
+    pub version: Option<String>,
+    pub version_code: Option<u64>,
+}
+
+/// What a source says about an app, as shown by `--info` so that an unknown app can be looked
+/// into before it is downloaded.  Each is as the source gives it, such as `4.5` for a rating or
+/// `10M+` for installs, and `None` where the source doesn't say.
+#[derive(Default)]
+pub struct AppInfo {
+    pub app_id: String,
+    pub name: Option<String>,
+    pub developer: Option<String>,
+    pub summary: Option<String>,
+    pub license: Option<String>,
+    pub rating: Option<String>,
+    pub installs: Option<String>,
+    pub content_rating: Option<String>,
+}
+
+/// The apps which could not be downloaded during this run, and why, for the `on_failure` hook
+static FAILURES: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+
+/// Note that `app` (an app ID, optionally with `@version`) could not be downloaded, passing on the
+/// message which explains why.
+pub fn failed(app: &str, message: String) -> String {
+    let app_id = app.split('@').next().unwrap_or(app);
+    FAILURES.lock().unwrap().push((app_id.to_string(), message.clone()));
+    progress_events::app_failed(app_id, &message);
+    message
+}
+
+/// Download one app from `source` with `download`, within `--per-app-timeout`, unless the source's
+/// circuit breaker has tripped.
+pub async fn attempt(source: DownloadSource, app_id: String, download: impl Future<Output = Option<DownloadedApp>>) -> Option<DownloadedApp> {
+    let limited_app_id = app_id.clone();
+    let limited = async move { deadline::limit(&limited_app_id, download).await };
+    breaker::guard(source, app_id, limited).await
+}
+
+/// How many times `app_id` has been noted as failed so far.
+pub fn failure_count(app_id: &str) -> usize {
+    FAILURES.lock().unwrap().iter().filter(|(failed, _)| failed == app_id).count()
+}
+
+/// The apps noted as failed so far, in the order they failed.
+pub fn take_failures() -> Vec<(String, String)> {
+    std::mem::take(&mut *FAILURES.lock().unwrap())
+}
+
+/// When the versions to download were released, for `--released-after` and `--released-before`
+#[derive(Clone, Copy, Default)]
+pub struct ReleaseDates {
+    /// The earliest time a version may have been released
+    pub after: Option<DateTime<Utc>>,
+    /// The time the versions must have been released before
+    pub before: Option<DateTime<Utc>>,
+}
+
+impl ReleaseDates {
+    pub fn is_empty(&self) -> bool {
+        self.after.is_none() && self.before.is_none()
+    }
+
+    /// Whether a version released at `released_at` falls in the range.  One whose release date
+    /// isn't known only falls in an empty range.
+    pub fn contains(&self, released_at: Option<DateTime<Utc>>) -> bool {
+        match released_at {
+            Some(released_at) => self.after.is_none_or(|after| released_at >= after)
+                && self.before.is_none_or(|before| released_at < before),
+            None => self.is_empty(),
+        }
+    }
+
+    /// Parse a date given as `YYYY-MM-DD`, meaning the start of that day in UTC, or as an RFC 3339
+    /// time.
+    pub fn parse_date(date: &str) -> Result<DateTime<Utc>, Box<dyn Error>> {
+        if let Ok(day) = NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+            return Ok(day.and_hms_opt(0, 0, 0).unwrap().and_utc());
+        }
+        DateTime::parse_from_rfc3339(date)
+            .map(|time| time.with_timezone(&Utc))
+            .map_err(|_| Box::new(SimpleError::new(format!("{} is not a date such as 2023-01-31", date))) as Box<dyn Error>)
+    }
+}
+
+/// The versionCode asked for by a version given as `code:<versionCode>`, rather than a versionName.
+pub fn version_code(version: &str) -> Option<u64> {
+    version.strip_prefix("code:").and_then(|version_code| version_code.parse().ok())
+}
+
+/// The largest file to download, for `--skip-larger-than`
+static MAX_SIZE: OnceLock<u64> = OnceLock::new();
+
+pub fn skip_larger_than(max_size: u64) {
+    let _ = MAX_SIZE.set(max_size);
+}
+
+/// Whether a file of `size` bytes, as the source lists it or as the Content-Length of its
+/// download, is larger than `--skip-larger-than` allows, in which case the app is reported as
+/// skipped.  A file whose size isn't known is downloaded.
+pub fn too_large(app_string: &str, size: Option<u64>, mp: &MultiProgress) -> bool {
+    match (MAX_SIZE.get(), size) {
+        (Some(max_size), Some(size)) if size > *max_size => {
+            let size = format!("{:.1} MiB", size as f64 / (1024.0 * 1024.0));
+            mp.suspend(|| println!("{}", tr!("too-large", app = app_string, size = size)));
+            true
+        },
+        _ => false,
+    }
+}
+
+/// Check a previous download of `url` at `path` before fetching it again, so that re-syncing a
+/// mirror only transfers what has changed.  A file which is up to date is skipped, and one which
+/// has changed is removed to make way for the new download.  Returns whether to download.
+pub async fn needs_download(url: &str, path: &Path, sha256: Option<&[u8]>, app_string: &str, mp: &MultiProgress) -> bool {
+    let path = &storage::final_path(path);
+    match http::preflight(url, path, sha256).await {
+        Preflight::Missing => true,
+        Preflight::UpToDate => {
+            mp.suspend(|| println!("{}", tr!("file-up-to-date", app = app_string)));
+            false
+        },
+        Preflight::Changed => match fs::remove_file(path) {
+            Ok(()) => {
+                mp.suspend(|| println!("{}", tr!("file-changed", app = app_string)));
+                true
+            },
+            Err(err) => {
+                mp.suspend(|| println!("{}", tr!("file-changed-not-replaced", app = app_string, error = err.to_string())));
+                false
+            },
+        },
+    }
+}
+
+/// What a download source can do, so that `apkeep list-sources` can show it and a request can be
+/// checked against it before any traffic is sent.
+pub struct Capabilities {
+    /// Whether `app_id@version` can be downloaded
+    pub specific_versions: bool,
+    /// Whether `app_id@code:versionCode` can be downloaded
+    pub version_codes: bool,
+    /// Whether `--list-versions` lists the versions available
+    pub list_versions: bool,
+    /// Whether `--check-only` can find the latest version without downloading it
+    pub check_updates: bool,
+    /// Whether `--info` can show what the source says about an app
+    pub app_info: bool,
+    /// Whether `--channel` chooses between stable and pre-release versions
+    pub release_channels: bool,
+    /// Whether `--form-factor` chooses between builds for phones, TVs, watches and cars
+    pub form_factors: bool,
+    /// Whether the source says when each version was released, for `--released-after` and
+    /// `--released-before`
+    pub release_dates: bool,
+    /// How apps split into several APKs are delivered, if at all
+    pub splits: &'static str,
+    pub requires_auth: bool,
+    /// Whether requests are slowed down when the source starts refusing them
+    pub adaptive_throttling: bool,
+    /// The options the source accepts with `-o`
+    pub options: &'static [&'static str],
+}
+
+impl DownloadSource {
+    pub fn capabilities(&self) -> Capabilities {
+        match self {
+            DownloadSource::APKPure => Capabilities {
+                specific_versions: true,
+                version_codes: false,
+                list_versions: true,
+                check_updates: true,
+                app_info: false,
+                release_channels: false,
+                form_factors: false,
+                release_dates: false,
+                splits: "XAPK bundle",
+                requires_auth: false,
+                adaptive_throttling: true,
+                options: &["output_format"],
+            },
+            DownloadSource::GooglePlay => Capabilities {
+                specific_versions: false,
+                version_codes: true,
+                list_versions: false,
+                check_updates: false,
+                app_info: false,
+                release_channels: false,
+                form_factors: false,
+                release_dates: false,
+                splits: "split_apk=true",
+                requires_auth: true,
+                adaptive_throttling: false,
+                options: &["device", "locale", "timezone", "split_apk", "include_additional_files", "account_cooldown"],
+            },
+            DownloadSource::FDroid => Capabilities {
+                specific_versions: true,
+                version_codes: true,
+                list_versions: true,
+                check_updates: true,
+                app_info: true,
+                release_channels: true,
+                form_factors: true,
+                release_dates: true,
+                splits: "no",
+                requires_auth: false,
+                adaptive_throttling: true,
+                options: &["repo", "arch", "use_entry", "verify-index", "output_format", "fastlane"],
+            },
+            DownloadSource::HuaweiAppGallery => Capabilities {
+                specific_versions: false,
+                version_codes: false,
+                list_versions: false,
+                check_updates: false,
+                app_info: false,
+                release_channels: false,
+                form_factors: false,
+                release_dates: false,
+                splits: "no",
+                requires_auth: false,
+                adaptive_throttling: true,
+                options: &[],
+            },
+            DownloadSource::APKCombo => Capabilities {
+                specific_versions: false,
+                version_codes: false,
+                list_versions: false,
+                check_updates: false,
+                app_info: false,
+                release_channels: false,
+                form_factors: false,
+                release_dates: false,
+                splits: "no",
+                requires_auth: false,
+                adaptive_throttling: true,
+                options: &[],
+            },
+            // Whatever the plugin supports; it is handed every option
+            DownloadSource::Plugin => Capabilities {
+                specific_versions: true,
+                version_codes: true,
+                list_versions: false,
+                check_updates: true,
+                app_info: false,
+                release_channels: false,
+                form_factors: false,
+                release_dates: false,
+                splits: "no",
+                requires_auth: false,
+                adaptive_throttling: true,
+                options: &[],
+            },
+            // Versions and versionCodes can be chosen if the rules for the site list them, and
+            // ratings and installs shown if the rules pick them out
+            DownloadSource::Scraped => Capabilities {
+                specific_versions: true,
+                version_codes: true,
+                list_versions: true,
+                check_updates: true,
+                app_info: true,
+                release_channels: false,
+                form_factors: false,
+                release_dates: false,
+                splits: "no",
+                requires_auth: false,
+                adaptive_throttling: true,
+                options: &["output_format"],
+            },
+        }
+    }
+}
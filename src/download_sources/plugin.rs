@@ -0,0 +1,300 @@
+//! Download sources provided as WebAssembly plugins, so that a mirror which isn't built in, or
+//! whose pages change too often for a release to keep up, can be supported by dropping a `.wasm`
+//! file into the `plugins` directory of the config dir and running with `-d plugin --plugin NAME`.
+//!
+//! Plugins run in a sandbox: they are given no imports, so they can't touch the network or the
+//! filesystem themselves, and each call is limited in the instructions it may execute and the
+//! memory it may use.  Plugins run on blocking threads, so that one busy computing can't hold up
+//! the other downloads.  apkeep makes every request on a plugin's behalf, through the same client,
+//! host limits and throttling as the built-in sources.
+//!
+//! A plugin exports its `memory` and three functions, exchanging UTF-8 JSON with apkeep:
+//!
+//! * `alloc(len: i32) -> i32` returns the address of `len` bytes which apkeep may write input to
+//! * `resolve(ptr: i32, len: i32) -> i64` is given `{"app_id", "version", "options"}`, `version`
+//!   being null for the latest
+//! * `parse(ptr: i32, len: i32) -> i64` is given the same, along with the `url`, `status` and
+//!   `body` of a page the plugin asked for
+//!
+//! `resolve` and `parse` return the address of their output in the upper 32 bits and its length in
+//! the lower, the output being one of `{"fetch": {"url"}}` to be handed that page next,
+//! `{"download": {"url", "version", "file_name"}}` (`version` and `file_name` being optional) once
+//! the APK has been found, or `{"not_found": {"reason"}}`.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use futures_util::StreamExt;
+use indicatif::MultiProgress;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use simple_error::SimpleError;
+use wasmi::{Config, Engine, Instance, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+use crate::config;
+use crate::cli::DownloadSource;
+use crate::download_sources::{attempt, claims, deadline, needs_download, DownloadedApp, LatestVersion};
+use crate::util::{http, network_error, retry, robots, safe_file_name, safe_join};
+use crate::util::throttle::{Outcome, Throttle};
+use crate::util::timings::{self, Phase};
+
+/// Instructions (roughly) each call into a plugin may execute
+const FUEL_PER_CALL: u64 = 1_000_000_000;
+/// The most memory a plugin may grow to
+const MAX_MEMORY_BYTES: usize = 256 * 1024 * 1024;
+/// The most pages a plugin may ask for to resolve one app
+const MAX_FETCHES: usize = 16;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Step {
+    Fetch { url: String },
+    Download { url: String, version: Option<String>, file_name: Option<String> },
+    NotFound { reason: String },
+}
+
+#[derive(Clone)]
+pub struct Plugin {
+    pub name: String,
+    engine: Engine,
+    module: Arc<Module>,
+}
+
+/// A plugin instantiated for resolving one app
+struct Session {
+    store: Store<StoreLimits>,
+    instance: Instance,
+    memory: Memory,
+}
+
+impl Plugin {
+    /// Load a plugin from a path, or by name from the `plugins` directory of the config dir.
+    pub fn load(plugin: &str) -> Result<Plugin, Box<dyn Error>> {
+        let path = if Path::new(plugin).is_file() {
+            PathBuf::from(plugin)
+        } else {
+            let mut path = config::config_dir()?;
+            path.push("plugins");
+            path.push(format!("{}.wasm", plugin));
+            path
+        };
+        let wasm = fs::read(&path)
+            .map_err(|err| SimpleError::new(format!("Could not read the plugin {}: {}", path.display(), err)))?;
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, &wasm[..])?;
+        let plugin = Plugin {
+            name: path.file_stem().map_or(plugin.to_string(), |name| name.to_string_lossy().to_string()),
+            engine,
+            module: Arc::new(module),
+        };
+        // Fail early, rather than once per app, if the plugin doesn't export what it should
+        plugin.instantiate()?;
+        Ok(plugin)
+    }
+
+    fn instantiate(&self) -> Result<Session, Box<dyn Error>> {
+        let limits = StoreLimitsBuilder::new().memory_size(MAX_MEMORY_BYTES).build();
+        let mut store = Store::new(&self.engine, limits);
+        store.limiter(|limits| limits);
+        store.set_fuel(FUEL_PER_CALL).map_err(|err| SimpleError::new(err.to_string()))?;
+        let instance = Linker::<StoreLimits>::new(&self.engine)
+            .instantiate(&mut store, &self.module)?
+            .start(&mut store)?;
+        let memory = instance.get_memory(&store, "memory")
+            .ok_or_else(|| SimpleError::new(format!("The plugin {} does not export its memory", self.name)))?;
+        for function in ["alloc", "resolve", "parse"] {
+            if instance.get_func(&store, function).is_none() {
+                return Err(Box::new(SimpleError::new(format!("The plugin {} does not export {}", self.name, function))));
+            }
+        }
+        Ok(Session { store, instance, memory })
+    }
+}
+
+impl Session {
+    /// Instantiate `plugin` on a blocking thread.
+    async fn start(plugin: &Plugin) -> Result<Session, Box<dyn Error>> {
+        let plugin = plugin.clone();
+        tokio::task::spawn_blocking(move || plugin.instantiate().map_err(|err| err.to_string())).await?
+            .map_err(|err| Box::new(SimpleError::new(err)) as Box<dyn Error>)
+    }
+
+    /// Call one of the plugin's functions on a blocking thread, handing the session back along
+    /// with what the plugin asks for next.
+    async fn run(mut self, function: &'static str, input: Value) -> Result<(Session, Step), Box<dyn Error>> {
+        tokio::task::spawn_blocking(move || match self.call(function, &input) {
+            Ok(step) => Ok((self, step)),
+            Err(err) => Err(err.to_string()),
+        }).await?
+            .map_err(|err| Box::new(SimpleError::new(err)) as Box<dyn Error>)
+    }
+
+    /// Hand `input` to one of the plugin's functions, returning what it asks for next.
+    fn call(&mut self, function: &str, input: &Value) -> Result<Step, Box<dyn Error>> {
+        self.store.set_fuel(FUEL_PER_CALL).map_err(|err| SimpleError::new(err.to_string()))?;
+        let input = serde_json::to_vec(input)?;
+        let len = i32::try_from(input.len())?;
+        let alloc = self.instance.get_typed_func::<i32, i32>(&self.store, "alloc")?;
+        let ptr = alloc.call(&mut self.store, len)?;
+        self.memory.write(&mut self.store, ptr as u32 as usize, &input)
+            .map_err(|err| SimpleError::new(format!("The plugin gave an invalid address for its input: {}", err)))?;
+
+        let function = self.instance.get_typed_func::<(i32, i32), i64>(&self.store, function)?;
+        let packed = function.call(&mut self.store, (ptr, len))? as u64;
+        let mut output = vec![0; (packed & 0xffff_ffff) as usize];
+        self.memory.read(&self.store, (packed >> 32) as usize, &mut output)
+            .map_err(|err| SimpleError::new(format!("The plugin gave an invalid address for its output: {}", err)))?;
+        serde_json::from_slice(&output)
+            .map_err(|err| Box::new(SimpleError::new(format!("The plugin returned an invalid step: {}", err))) as Box<dyn Error>)
+    }
+}
+
+pub async fn download_apps(
+    apps: Vec<(String, Option<String>)>,
+    parallel: usize,
+    sleep_duration: u64,
+    outpath: &Path,
+    options: HashMap<&str, &str>,
+    plugin: &Plugin,
+) -> Vec<DownloadedApp> {
+    let mp = MultiProgress::new();
+    let throttle = Throttle::new("The plugin source", parallel, sleep_duration, &mp);
+    let client = http::source_client(DownloadSource::Plugin);
+    let (options, throttle, client, mp) = (&options, &throttle, &client, &mp);
+    futures_util::stream::iter(apps)
+        .map(|(app_id, app_version)| attempt(DownloadSource::Plugin, app_id.clone(), async move {
+            let app_string = match &app_version {
+                Some(version) => format!("{}@{}", app_id, version),
+                None => app_id.clone(),
+            };
+            mp.suspend(|| println!("{}", tr!("downloading", app = app_string.as_str())));
+            let _slot = throttle.acquire().await;
+            match download_app(&app_id, app_version, &app_string, outpath, options, plugin, client, throttle, mp).await {
+                Ok(Some(downloaded)) => {
+                    mp.suspend(|| println!("{}", tr!("downloaded", app = app_string.as_str())));
+                    Some(downloaded)
+                },
+                Ok(None) => None,
+                Err(err) => {
+                    mp.suspend(|| println!("{}", super::failed(&app_string, tr!("plugin-download-failed", app = app_string.as_str(), plugin = plugin.name.as_str(), error = err.to_string()))));
+                    None
+                },
+            }
+        }))
+        .buffer_unordered(parallel)
+        .filter_map(|downloaded| async { downloaded })
+        .collect::<Vec<DownloadedApp>>()
+        .await
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn download_app(
+    app_id: &str,
+    app_version: Option<String>,
+    app_string: &str,
+    outpath: &Path,
+    options: &HashMap<&str, &str>,
+    plugin: &Plugin,
+    client: &reqwest::Client,
+    throttle: &Throttle,
+    mp: &MultiProgress,
+) -> Result<Option<DownloadedApp>, Box<dyn Error>> {
+    let timer = timings::start(app_id, Phase::Resolve);
+    let (url, version, file_name) = resolve(app_id, app_version.as_deref(), options, plugin, client, throttle).await?;
+    drop(timer);
+
+    let version = version.or(app_version);
+    let file_name = safe_file_name(&file_name.unwrap_or_else(|| match &version {
+        Some(version) => format!("{}@{}.apk", app_id, version),
+        None => format!("{}.apk", app_id),
+    }));
+    let (file_name, _claim) = match claims::claim(outpath, &file_name, app_string, mp) {
+        Some(claimed) => claimed,
+        None => return Ok(None),
+    };
+    let path = safe_join(outpath, &file_name);
+    if !needs_download(&url, &path, None, app_string, mp).await {
+        return Ok(None);
+    }
+    robots::allow(&url).await?;
+    let _permit = http::host_permit(&url).await;
+    let _timer = timings::start(app_id, Phase::Download);
+    let response = retry::send(client.get(&url)).await
+        .inspect(|response| throttle.record(Outcome::from_status(response.status())))
+        .inspect_err(|_| throttle.record(Outcome::Failure))
+        .map_err(|err| SimpleError::new(network_error::describe(&err)))?;
+    if !response.status().is_success() {
+        return Err(Box::new(SimpleError::new(format!("the download failed with HTTP {}", response.status()))));
+    }
+    if super::too_large(app_string, response.content_length(), mp) {
+        return Ok(None);
+    }
+    deadline::writing(&path);
+    http::write_response_to_file(response, &path).await?;
+    Ok(Some(DownloadedApp::new(app_id.to_string(), version, vec![path]).with_url(url)))
+}
+
+/// Run the plugin until it finds the download, returning its URL and, if the plugin gives them,
+/// its version and file name.
+async fn resolve(
+    app_id: &str,
+    app_version: Option<&str>,
+    options: &HashMap<&str, &str>,
+    plugin: &Plugin,
+    client: &reqwest::Client,
+    throttle: &Throttle,
+) -> Result<(String, Option<String>, Option<String>), Box<dyn Error>> {
+    let request = json!({ "app_id": app_id, "version": app_version, "options": options });
+    let (mut session, mut step) = Session::start(plugin).await?.run("resolve", request.clone()).await?;
+    let mut fetches = 0;
+    loop {
+        match step {
+            Step::Fetch { url } => {
+                fetches += 1;
+                if fetches > MAX_FETCHES {
+                    return Err(Box::new(SimpleError::new(format!("the plugin asked for more than {} pages", MAX_FETCHES))));
+                }
+                let url = http::secure_url(&url);
+                robots::allow(&url).await?;
+                let response = {
+                    let _permit = http::host_permit(&url).await;
+                    retry::send(client.get(&url)).await
+                        .inspect(|response| throttle.record(Outcome::from_status(response.status())))
+                        .inspect_err(|_| throttle.record(Outcome::Failure))
+                        .map_err(|err| SimpleError::new(network_error::describe(&err)))?
+                };
+                let status = response.status().as_u16();
+                let body = response.text().await
+                    .map_err(|err| SimpleError::new(network_error::describe(&err)))?;
+                let mut page = request.clone();
+                page["url"] = json!(url);
+                page["status"] = json!(status);
+                page["body"] = json!(body);
+                (session, step) = session.run("parse", page).await?;
+            },
+            Step::Download { url, version, file_name } => return Ok((http::secure_url(&url), version, file_name)),
+            Step::NotFound { reason } => return Err(Box::new(SimpleError::new(reason))),
+        }
+    }
+}
+
+/// The latest version of each app, as the plugin names it when resolving its download.  Plugins
+/// which don't give the version can't be checked.
+pub async fn latest_versions(apps: Vec<String>, options: &HashMap<&str, &str>, plugin: &Plugin) -> Vec<LatestVersion> {
+    let throttle = Throttle::new("The plugin source", 1, 0, &MultiProgress::new());
+    let client = http::source_client(DownloadSource::Plugin);
+    let mut latest = vec![];
+    for app_id in apps {
+        match resolve(&app_id, None, options, plugin, &client, &throttle).await {
+            Ok((_, Some(version), _)) => latest.push(LatestVersion { app_id, version: Some(version), version_code: None }),
+            Ok((_, None, _)) => println!("{}", super::failed(&app_id, tr!("check-failed", app = app_id.as_str(), source = plugin.name.as_str(), error = "the plugin does not give the version"))),
+            Err(err) => println!("{}", super::failed(&app_id, tr!("check-failed", app = app_id.as_str(), source = plugin.name.as_str(), error = err.to_string()))),
+        }
+    }
+    latest
+}
@@ -0,0 +1,574 @@
+//! A generic source for sites described by a rules file, so that a new mirror can be scraped, or a
+//! selector fixed after a site is redesigned, without a new release.  The rules are TOML, kept in
+//! the `rules` directory of the config dir or given as a path to `--rules`:
+//!
+//! ```toml
+//! name = "Example Mirror"
+//! # Either the app page itself, or a search whose first result linking to the app is followed
+//! app_url = "https://apks.example.com/app/{app_id}"
+//! search_url = "https://apks.example.com/search?q={app_id}"
+//! app_link = "a.result"
+//! # On the app page: one row per version, with its name and download link, or else a download
+//! # link for the latest version alone
+//! version_rows = "ul.versions li"
+//! version = ".version-name"
+//! # Optionally, the versionCode in each row, so that `app_id@code:<versionCode>` can be asked for
+//! version_code = ".version-code"
+//! # Optionally, what's new in each version, kept in the metadata sidecar; in each row, or on the
+//! # app page if it has no rows
+//! changelog = ".whats-new"
+//! download_link = "a.download"
+//! # If the download link leads to a page, rather than the file, the link to the file on it
+//! file_link = "a#direct-link"
+//! user_agent = "Mozilla/5.0"
+//! # Optionally, pages listing the apps in a category, for `--category`, and by a developer, for
+//! # `--developer`, and the links to the apps on them if they differ from app_link.  Pages are
+//! # followed from 1 if the URL has a {page}
+//! category_url = "https://apks.example.com/category/{category}?page={page}"
+//! category_link = "a.app"
+//! developer_url = "https://apks.example.com/developer/{developer}"
+//! developer_link = "a.app"
+//! # Optionally, what `--info` shows of the app page
+//! rating = ".rating"
+//! installs = ".installs"
+//! content_rating = ".content-rating"
+//! ```
+//!
+//! Selectors are CSS selectors, and links are taken from their `href`.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use futures_util::StreamExt;
+use indicatif::MultiProgress;
+use reqwest::Url;
+use scraper::{ElementRef, Html, Selector};
+use serde::Deserialize;
+use serde_json::{json, Map};
+use simple_error::SimpleError;
+
+use crate::config;
+use crate::cli::DownloadSource;
+use crate::download_sources::{attempt, claims, deadline, needs_download, AppInfo, DownloadedApp, LatestVersion, SearchMatch};
+use crate::patterns;
+use crate::util::{http, is_app_id, network_error, retry, robots, safe_file_name, safe_join, tsv_row, OutputFormat};
+use crate::util::throttle::{Outcome, Throttle};
+use crate::util::timings::{self, Phase};
+
+#[derive(Deserialize)]
+struct Rules {
+    name: String,
+    app_url: Option<String>,
+    search_url: Option<String>,
+    app_link: Option<String>,
+    version_rows: Option<String>,
+    version: Option<String>,
+    version_code: Option<String>,
+    changelog: Option<String>,
+    download_link: String,
+    file_link: Option<String>,
+    user_agent: Option<String>,
+    category_url: Option<String>,
+    category_link: Option<String>,
+    developer_url: Option<String>,
+    developer_link: Option<String>,
+    rating: Option<String>,
+    installs: Option<String>,
+    content_rating: Option<String>,
+}
+
+/// The most pages of a listing to follow, in case a site repeats its last page forever
+const MAX_LISTING_PAGES: usize = 100;
+
+/// A page listing apps, such as those in a category
+struct Listing {
+    /// The URL of the page, with `{<key>}` and optionally `{page}` to be filled in
+    url: String,
+    link: Selector,
+}
+
+/// A site, with the selectors of its rules compiled
+pub struct Site {
+    pub name: String,
+    app_url: Option<String>,
+    search_url: Option<String>,
+    app_link: Option<Selector>,
+    version_rows: Option<(Selector, Selector)>,
+    version_code: Option<Selector>,
+    changelog: Option<Selector>,
+    download_link: Selector,
+    file_link: Option<Selector>,
+    user_agent: Option<String>,
+    category: Option<Listing>,
+    developer: Option<Listing>,
+    rating: Option<Selector>,
+    installs: Option<Selector>,
+    content_rating: Option<Selector>,
+}
+
+fn selector(rule: &str, selector: &str) -> Result<Selector, SimpleError> {
+    Selector::parse(selector).map_err(|err| SimpleError::new(format!("{} is not a valid selector ({}): {}", rule, selector, err)))
+}
+
+impl Site {
+    /// Load the rules for a site from a path, or by name from the `rules` directory of the config
+    /// dir, checking that they are complete and their selectors valid.
+    pub fn load(rules: &str) -> Result<Site, Box<dyn Error>> {
+        let path = if Path::new(rules).is_file() {
+            PathBuf::from(rules)
+        } else {
+            let mut path = config::config_dir()?;
+            path.push("rules");
+            path.push(format!("{}.toml", rules));
+            path
+        };
+        let contents = fs::read_to_string(&path)
+            .map_err(|err| SimpleError::new(format!("Could not read the rules {}: {}", path.display(), err)))?;
+        let rules: Rules = toml::from_str(&contents)?;
+
+        if rules.app_url.is_none() && (rules.search_url.is_none() || rules.app_link.is_none()) {
+            return Err(Box::new(SimpleError::new("either app_url, or both search_url and app_link, must be given")));
+        }
+        let version_rows = match (&rules.version_rows, &rules.version) {
+            (Some(version_rows), Some(version)) => Some((selector("version_rows", version_rows)?, selector("version", version)?)),
+            (None, None) => None,
+            _ => return Err(Box::new(SimpleError::new("version_rows and version must be given together"))),
+        };
+        if rules.version_code.is_some() && version_rows.is_none() {
+            return Err(Box::new(SimpleError::new("version_code can only be given with version_rows and version")));
+        }
+        let listing = |key: &str, url: &Option<String>, link: &Option<String>| match (url, link, &rules.app_link) {
+            (None, Some(_), _) => Err(SimpleError::new(format!("{0}_link can only be given with {0}_url", key))),
+            (None, None, _) => Ok(None),
+            (Some(url), Some(link), _) => Ok(Some(Listing { url: url.clone(), link: selector(&format!("{}_link", key), link)? })),
+            (Some(url), None, Some(app_link)) => Ok(Some(Listing { url: url.clone(), link: selector("app_link", app_link)? })),
+            (Some(_), None, None) => Err(SimpleError::new(format!("{0}_url must be given with {0}_link or app_link", key))),
+        };
+        let category = listing("category", &rules.category_url, &rules.category_link)?;
+        let developer = listing("developer", &rules.developer_url, &rules.developer_link)?;
+        Ok(Site {
+            app_link: rules.app_link.as_deref().map(|app_link| selector("app_link", app_link)).transpose()?,
+            version_rows,
+            version_code: rules.version_code.as_deref().map(|version_code| selector("version_code", version_code)).transpose()?,
+            changelog: rules.changelog.as_deref().map(|changelog| selector("changelog", changelog)).transpose()?,
+            download_link: selector("download_link", &rules.download_link)?,
+            file_link: rules.file_link.as_deref().map(|file_link| selector("file_link", file_link)).transpose()?,
+            rating: rules.rating.as_deref().map(|rating| selector("rating", rating)).transpose()?,
+            installs: rules.installs.as_deref().map(|installs| selector("installs", installs)).transpose()?,
+            content_rating: rules.content_rating.as_deref().map(|content_rating| selector("content_rating", content_rating)).transpose()?,
+            name: rules.name,
+            app_url: rules.app_url,
+            search_url: rules.search_url,
+            user_agent: rules.user_agent,
+            category,
+            developer,
+        })
+    }
+
+    /// Whether the site lists versions, so that specific ones can be downloaded
+    pub fn has_versions(&self) -> bool {
+        self.version_rows.is_some()
+    }
+
+    fn client(&self) -> reqwest::Client {
+        let builder = http::client_builder();
+        let builder = match &self.user_agent {
+            Some(user_agent) => builder.user_agent(user_agent),
+            None => builder,
+        };
+        http::with_source_headers(builder, DownloadSource::Scraped).build().unwrap_or_else(|_| reqwest::Client::new())
+    }
+
+    async fn fetch(&self, client: &reqwest::Client, url: &str, throttle: &Throttle) -> Result<Html, Box<dyn Error>> {
+        robots::allow(url).await?;
+        let _permit = http::host_permit(url).await;
+        let response = retry::send(client.get(url)).await
+            .inspect(|response| throttle.record(Outcome::from_status(response.status())))
+            .inspect_err(|_| throttle.record(Outcome::Failure))
+            .map_err(|err| SimpleError::new(network_error::describe(&err)))?;
+        if !response.status().is_success() {
+            return Err(Box::new(SimpleError::new(format!("{} returned HTTP {}", url, response.status()))));
+        }
+        let body = response.text().await
+            .map_err(|err| SimpleError::new(network_error::describe(&err)))?;
+        Ok(Html::parse_document(&body))
+    }
+
+    /// Find the page of an app, either directly or by searching for it.
+    async fn app_page(&self, client: &reqwest::Client, app_id: &str, throttle: &Throttle) -> Result<(Url, Html), Box<dyn Error>> {
+        let app_url = match (&self.app_url, &self.search_url, &self.app_link) {
+            (Some(app_url), _, _) => Url::parse(&app_url.replace("{app_id}", app_id))?,
+            (None, Some(search_url), Some(app_link)) => {
+                let _timer = timings::start(app_id, Phase::Search);
+                let search_url = Url::parse(&search_url.replace("{app_id}", app_id))?;
+                let results = self.fetch(client, search_url.as_str(), throttle).await?;
+                let link = results.select(app_link)
+                    .filter_map(|link| link.value().attr("href"))
+                    .find(|link| link.contains(app_id))
+                    .ok_or_else(|| SimpleError::new(format!("no search result links to {}", app_id)))?;
+                search_url.join(link)?
+            },
+            _ => unreachable!("checked when the rules were loaded"),
+        };
+        let app_url = Url::parse(&http::secure_url(app_url.as_str()))?;
+        let page = self.fetch(client, app_url.as_str(), throttle).await?;
+        Ok((app_url, page))
+    }
+
+    /// Search the site for apps matching a name, taking the app ID from each result's link.
+    pub async fn search(&self, query: &str) -> Result<Vec<SearchMatch>, Box<dyn Error>> {
+        let (search_url, app_link) = match (&self.search_url, &self.app_link) {
+            (Some(search_url), Some(app_link)) => (search_url, app_link),
+            _ => return Err(Box::new(SimpleError::new("the rules for this site don't describe a search"))),
+        };
+        let throttle = Throttle::new("The scraped site", 1, 0, &MultiProgress::new());
+        let search_url = Url::parse(&search_url.replace("{app_id}", query))?;
+        let results = self.fetch(&self.client(), search_url.as_str(), &throttle).await?;
+        let mut matches: Vec<SearchMatch> = vec![];
+        for (app_id, result) in app_links(&results, &search_url, app_link) {
+            if matches.iter().all(|found| found.app_id != app_id) {
+                let name = result.text().collect::<String>().trim().to_string();
+                matches.push(SearchMatch {
+                    name: if name.is_empty() { app_id.clone() } else { name },
+                    developer: None,
+                    app_id,
+                });
+            }
+        }
+        Ok(matches)
+    }
+
+    /// The IDs of the apps listed in a category.
+    pub async fn category(&self, category: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        match &self.category {
+            Some(listing) => self.listed_apps(listing, "category", category).await,
+            None => Err(Box::new(SimpleError::new("the rules for this site don't give a category_url"))),
+        }
+    }
+
+    /// The IDs of the apps listed as by a developer.
+    pub async fn developer(&self, developer: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        match &self.developer {
+            Some(listing) => self.listed_apps(listing, "developer", developer).await,
+            None => Err(Box::new(SimpleError::new("the rules for this site don't give a developer_url"))),
+        }
+    }
+
+    /// The IDs of the apps listed on a page, following its pages until one lists no new apps.
+    async fn listed_apps(&self, listing: &Listing, key: &str, value: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        let paged = listing.url.contains("{page}");
+        let client = self.client();
+        let throttle = Throttle::new("The scraped site", 1, 0, &MultiProgress::new());
+        let mut app_ids: Vec<String> = vec![];
+        for page in 1..=MAX_LISTING_PAGES {
+            let url = Url::parse(&listing.url.replace(&format!("{{{}}}", key), value).replace("{page}", &page.to_string()))?;
+            let listed = self.fetch(&client, url.as_str(), &throttle).await?;
+            let count = app_ids.len();
+            for (app_id, _) in app_links(&listed, &url, &listing.link) {
+                if !app_ids.contains(&app_id) {
+                    app_ids.push(app_id);
+                }
+            }
+            if !paged || app_ids.len() == count {
+                break;
+            }
+        }
+        Ok(app_ids)
+    }
+
+    /// The versions listed on an app page, newest (first listed) first.
+    fn versions<'a>(&self, page: &'a Html) -> Vec<ListedVersion<'a>> {
+        let (version_rows, version) = match &self.version_rows {
+            Some(selectors) => selectors,
+            None => return vec![],
+        };
+        page.select(version_rows)
+            .filter_map(|row| {
+                let name = row.select(version).next()?.text().collect::<String>().trim().to_string();
+                // The versionCode may be shown with other text around it, such as `(12345)`
+                let version_code = self.version_code.as_ref()
+                    .and_then(|version_code| row.select(version_code).next())
+                    .and_then(|version_code| version_code.text().collect::<String>().chars().filter(char::is_ascii_digit).collect::<String>().parse().ok());
+                Some(ListedVersion {
+                    name,
+                    version_code,
+                    download_link: link(row, &self.download_link),
+                    changelog: self.changelog.as_ref().and_then(|changelog| text(row, changelog)),
+                })
+            })
+            .collect()
+    }
+}
+
+/// The links matching a selector on a page which lead to apps, with the app ID each leads to,
+/// taken from the first segment of its path which is one.
+fn app_links<'a>(page: &'a Html, base: &Url, selector: &'a Selector) -> impl Iterator<Item = (String, ElementRef<'a>)> + 'a {
+    let base = base.clone();
+    page.select(selector).filter_map(move |link| {
+        let url = base.join(link.value().attr("href")?).ok()?;
+        let app_id = url.path_segments()?.find(|segment| is_app_id(segment))?.to_string();
+        Some((app_id, link))
+    })
+}
+
+/// A version listed on an app page, with its versionCode and changelog if the rules pick them out
+struct ListedVersion<'a> {
+    name: String,
+    version_code: Option<u64>,
+    download_link: Option<&'a str>,
+    changelog: Option<String>,
+}
+
+/// The text of the first element matching a selector within an element, a line for each run of
+/// text, or `None` if there is none.
+fn text(element: ElementRef, selector: &Selector) -> Option<String> {
+    let lines = element.select(selector).next()?
+        .text()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>();
+    if lines.is_empty() { None } else { Some(lines.join("\n")) }
+}
+
+/// The first link matching a selector within an element
+fn link<'a>(element: ElementRef<'a>, selector: &Selector) -> Option<&'a str> {
+    element.select(selector).find_map(|link| link.value().attr("href"))
+}
+
+pub async fn download_apps(
+    apps: Vec<(String, Option<String>)>,
+    parallel: usize,
+    sleep_duration: u64,
+    outpath: &Path,
+    site: &Site,
+) -> Vec<DownloadedApp> {
+    let mp = MultiProgress::new();
+    let throttle = Throttle::new("The scraped site", parallel, sleep_duration, &mp);
+    let client = site.client();
+    let (throttle, client, mp) = (&throttle, &client, &mp);
+    futures_util::stream::iter(apps)
+        .map(|(app_id, app_version)| attempt(DownloadSource::Scraped, app_id.clone(), async move {
+            let app_string = match &app_version {
+                Some(version) => format!("{}@{}", app_id, version),
+                None => app_id.clone(),
+            };
+            mp.suspend(|| println!("{}", tr!("downloading", app = app_string.as_str())));
+            let _slot = throttle.acquire().await;
+            match download_app(&app_id, app_version, &app_string, outpath, site, client, throttle, mp).await {
+                Ok(Some(downloaded)) => {
+                    mp.suspend(|| println!("{}", tr!("downloaded", app = app_string.as_str())));
+                    Some(downloaded)
+                },
+                Ok(None) => None,
+                Err(err) => {
+                    mp.suspend(|| println!("{}", super::failed(&app_string, tr!("download-failed-reason", app = app_string.as_str(), source = site.name.as_str(), error = err.to_string()))));
+                    None
+                },
+            }
+        }))
+        .buffer_unordered(parallel)
+        .filter_map(|downloaded| async { downloaded })
+        .collect::<Vec<DownloadedApp>>()
+        .await
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn download_app(
+    app_id: &str,
+    app_version: Option<String>,
+    app_string: &str,
+    outpath: &Path,
+    site: &Site,
+    client: &reqwest::Client,
+    throttle: &Throttle,
+    mp: &MultiProgress,
+) -> Result<Option<DownloadedApp>, Box<dyn Error>> {
+    let timer = timings::start(app_id, Phase::Resolve);
+    let (app_url, page) = site.app_page(client, app_id, throttle).await?;
+    let (version, download_url, changelog) = if site.has_versions() {
+        let versions = site.versions(&page);
+        let listed = match (&app_version, app_version.as_deref().and_then(super::version_code)) {
+            (Some(_), Some(_)) if site.version_code.is_none() => {
+                return Err(Box::new(SimpleError::new("the rules for this site don't list versionCodes")));
+            },
+            (Some(_), Some(wanted)) => versions.into_iter().find(|listed| listed.version_code == Some(wanted))
+                .ok_or_else(|| SimpleError::new(format!("versionCode {} is not listed", wanted)))?,
+            (Some(app_version), None) => versions.into_iter().find(|listed| listed.name == *app_version)
+                .ok_or_else(|| SimpleError::new(format!("version {} is not listed", app_version)))?,
+            (None, _) => versions.into_iter().next()
+                .ok_or_else(|| SimpleError::new("no versions are listed"))?,
+        };
+        let download_link = listed.download_link.ok_or_else(|| SimpleError::new(format!("version {} has no download link", listed.name)))?;
+        (Some(listed.name), app_url.join(download_link)?, listed.changelog)
+    } else {
+        if app_version.is_some() {
+            return Err(Box::new(SimpleError::new("the rules for this site don't list versions")));
+        }
+        let download_link = link(page.root_element(), &site.download_link)
+            .ok_or_else(|| SimpleError::new("no download link was found"))?;
+        let changelog = site.changelog.as_ref().and_then(|changelog| text(page.root_element(), changelog));
+        (None, app_url.join(download_link)?, changelog)
+    };
+    drop(page);
+    let download_url = match &site.file_link {
+        Some(file_link) => {
+            let download_page = site.fetch(client, &http::secure_url(download_url.as_str()), throttle).await?;
+            let file_url = link(download_page.root_element(), file_link)
+                .ok_or_else(|| SimpleError::new("no link to the file was found on the download page"))?;
+            download_url.join(file_url)?
+        },
+        None => download_url,
+    };
+    let download_url = http::secure_url(download_url.as_str());
+    drop(timer);
+
+    robots::allow(&download_url).await?;
+    let _permit = http::host_permit(&download_url).await;
+    let _timer = timings::start(app_id, Phase::Download);
+    let response = retry::send(client.get(&download_url)).await
+        .inspect(|response| throttle.record(Outcome::from_status(response.status())))
+        .inspect_err(|_| throttle.record(Outcome::Failure))
+        .map_err(|err| SimpleError::new(network_error::describe(&err)))?;
+    if !response.status().is_success() {
+        return Err(Box::new(SimpleError::new(format!("the download failed with HTTP {}", response.status()))));
+    }
+    // The name the site gives the file must not be trusted to name a file within the output directory
+    let file_name = safe_file_name(&response.headers().get("content-disposition")
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| patterns::CONTENT_DISPOSITION_FILENAME.captures(header))
+        .and_then(|cap| cap.get(1).or_else(|| cap.get(2)).map(|name| name.as_str().to_string()))
+        .unwrap_or_else(|| match &version {
+            Some(version) => format!("{}@{}.apk", app_id, version),
+            None => format!("{}.apk", app_id),
+        }));
+    let (file_name, _claim) = match claims::claim(outpath, &file_name, app_string, mp) {
+        Some(claimed) => claimed,
+        None => return Ok(None),
+    };
+    let path = safe_join(outpath, &file_name);
+    if !needs_download(&download_url, &path, None, app_string, mp).await {
+        return Ok(None);
+    }
+    if super::too_large(app_string, response.content_length(), mp) {
+        return Ok(None);
+    }
+    deadline::writing(&path);
+    http::write_response_to_file(response, &path).await?;
+    Ok(Some(DownloadedApp::new(app_id.to_string(), version, vec![path]).with_url(download_url).with_changelog(changelog)))
+}
+
+pub async fn list_versions(apps: Vec<(String, Option<String>)>, site: &Site, options: &HashMap<&str, &str>) {
+    let output_format = OutputFormat::from_options(options);
+    if !site.has_versions() {
+        match output_format {
+            OutputFormat::Plaintext | OutputFormat::Tsv => eprintln!("{}", tr!("versions-not-listed", source = site.name.as_str())),
+            OutputFormat::Json => println!("{}", json!({"source": site.name, "error": "The rules for this site don't list versions."})),
+        }
+        return;
+    }
+    let throttle = Throttle::new("The scraped site", 1, 0, &MultiProgress::new());
+    let client = site.client();
+    let mut json_root = Map::new();
+    for (app_id, _) in apps {
+        if output_format.is_plaintext() {
+            println!("{}", tr!("versions-available", app = app_id.as_str(), source = site.name.as_str()));
+        }
+        match (site.app_page(&client, &app_id, &throttle).await, &output_format) {
+            (Ok((_, page)), OutputFormat::Plaintext) => {
+                let versions = site.versions(&page).into_iter().map(|listed| listed.name).collect::<Vec<_>>();
+                println!("| {}", versions.join(", "));
+            },
+            (Ok((_, page)), OutputFormat::Tsv) => {
+                for listed in site.versions(&page) {
+                    println!("{}", tsv_row(&[&app_id, &listed.name, &listed.version_code.map_or(String::new(), |code| code.to_string())]));
+                }
+            },
+            (Ok((_, page)), OutputFormat::Json) => {
+                let versions = site.versions(&page).into_iter().map(|listed| json!({
+                    "version": listed.name,
+                    "version_code": listed.version_code,
+                    "changelog": listed.changelog,
+                })).collect::<Vec<_>>();
+                json_root.insert(app_id, json!({"available_versions": versions}));
+            },
+            (Err(err), OutputFormat::Plaintext | OutputFormat::Tsv) => eprintln!("{}", tr!("versions-not-found", app = app_id.as_str(), error = err.to_string())),
+            (Err(err), OutputFormat::Json) => {
+                json_root.insert(app_id, json!({"error": err.to_string()}));
+            },
+        }
+    }
+    if output_format.is_json() {
+        println!("{}", json!({"source": site.name, "apps": json_root}));
+    }
+}
+
+/// The latest version of each app, the first its page lists, with its versionCode if the rules
+/// pick them out.
+pub async fn latest_versions(apps: Vec<String>, site: &Site) -> Vec<LatestVersion> {
+    if !site.has_versions() {
+        println!("{}", tr!("versions-not-listed", source = site.name.as_str()));
+        return vec![];
+    }
+    let throttle = Throttle::new("The scraped site", 1, 0, &MultiProgress::new());
+    let client = site.client();
+    let mut latest = vec![];
+    for app_id in apps {
+        let version = match site.app_page(&client, &app_id, &throttle).await {
+            Ok((_, page)) => site.versions(&page).into_iter().next().map(|listed| (listed.name, listed.version_code)),
+            Err(err) => {
+                println!("{}", super::failed(&app_id, tr!("check-failed", app = app_id.as_str(), source = site.name.as_str(), error = err.to_string())));
+                continue;
+            },
+        };
+        match version {
+            Some((version, version_code)) => latest.push(LatestVersion { app_id, version: Some(version), version_code }),
+            None => println!("{}", super::failed(&app_id, tr!("version-not-found", version = tr!("latest-version"), app = app_id.as_str()))),
+        }
+    }
+    latest
+}
+
+/// Every version of each app its page lists, oldest first, as entries of the app list for
+/// `--all-versions`
+pub async fn all_versions(apps: Vec<String>, site: &Site) -> Vec<(String, Option<String>)> {
+    if !site.has_versions() {
+        println!("{}", tr!("versions-not-listed", source = site.name.as_str()));
+        return vec![];
+    }
+    let throttle = Throttle::new("The scraped site", 1, 0, &MultiProgress::new());
+    let client = site.client();
+    let mut all = vec![];
+    for app_id in apps {
+        match site.app_page(&client, &app_id, &throttle).await {
+            // Pages list the newest first
+            Ok((_, page)) => all.extend(site.versions(&page).into_iter().rev().map(|listed| (app_id.clone(), Some(listed.name)))),
+            Err(err) => println!("{}", super::failed(&app_id, tr!("check-failed", app = app_id.as_str(), source = site.name.as_str(), error = err.to_string()))),
+        }
+    }
+    all
+}
+
+/// What the app pages of a site say about the apps, as far as the rules pick it out.
+pub async fn app_info(apps: Vec<String>, site: &Site) -> Vec<AppInfo> {
+    let throttle = Throttle::new("The scraped site", 1, 0, &MultiProgress::new());
+    let client = site.client();
+    let mut infos = vec![];
+    for app_id in apps {
+        match site.app_page(&client, &app_id, &throttle).await {
+            Ok((_, page)) => {
+                let text = |selector: &Option<Selector>| selector.as_ref().and_then(|selector| text(page.root_element(), selector));
+                infos.push(AppInfo {
+                    rating: text(&site.rating),
+                    installs: text(&site.installs),
+                    content_rating: text(&site.content_rating),
+                    app_id,
+                    ..Default::default()
+                });
+            },
+            Err(err) => println!("{}", super::failed(&app_id, tr!("info-failed", app = app_id.as_str(), source = site.name.as_str(), error = err.to_string()))),
+        }
+    }
+    infos
+}
@@ -0,0 +1,116 @@
+//! An Atom feed of the new versions each run finds, written with `--feed`, so that a mirror can be
+//! followed in any feed reader.  Written into OUTPATH, the feed is served along with the mirror.
+//! Each run adds its entries to those already in the feed, keeping the newest [`MAX_ENTRIES`].
+
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+
+/// The most entries the feed keeps
+const MAX_ENTRIES: usize = 100;
+
+/// A new version of an app
+pub struct Entry {
+    pub app_id: String,
+    pub source: String,
+    pub version: Option<String>,
+    pub version_code: Option<u64>,
+    /// Where it was downloaded from, if it was downloaded rather than found by `--check-only`
+    pub url: Option<String>,
+    pub detected_at: DateTime<Utc>,
+}
+
+impl Entry {
+    /// The entry's ID, the same each time the version is found
+    fn id(&self) -> String {
+        format!(
+            "urn:apkeep:{}:{}:{}",
+            self.source,
+            self.app_id,
+            self.version_code.map_or_else(|| self.version.clone().unwrap_or_default(), |version_code| version_code.to_string()),
+        )
+    }
+
+    fn to_xml(&self) -> String {
+        let version = match (&self.version, self.version_code) {
+            (Some(version), Some(version_code)) => format!("{} ({})", version, version_code),
+            (Some(version), None) => version.to_string(),
+            (None, Some(version_code)) => format!("versionCode {}", version_code),
+            (None, None) => "a new version".to_string(),
+        };
+        let summary = match &self.url {
+            Some(_) => format!("{} {} was downloaded from {}.", self.app_id, version, self.source),
+            None => format!("{} {} is available from {}.", self.app_id, version, self.source),
+        };
+        let link = self.url.as_ref().map_or(String::new(), |url| format!("\n    <link href=\"{}\"/>", escape(url)));
+        format!(
+            "  <entry>\n    <id>{}</id>\n    <title>{} {}</title>\n    <updated>{}</updated>{}\n    <category term=\"{}\"/>\n    <summary>{}</summary>\n  </entry>",
+            escape(&self.id()),
+            escape(&self.app_id),
+            escape(&version),
+            self.detected_at.to_rfc3339(),
+            link,
+            escape(&self.source),
+            escape(&summary),
+        )
+    }
+}
+
+/// Add entries to the feed at `path`, creating it if need be.  Versions already in the feed, such as
+/// an update `--check-only` finds run after run, are not added again.
+pub fn update(path: &Path, entries: &[Entry]) -> Result<(), Box<dyn Error>> {
+    let existing = match fs::read_to_string(path) {
+        Ok(existing) => existing,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(Box::new(err)),
+    };
+    // The entries already in the feed are kept as they were written
+    let existing = existing_entries(&existing);
+    let mut xml_entries = entries.iter()
+        .filter(|entry| {
+            let id = format!("<id>{}</id>", escape(&entry.id()));
+            !existing.iter().any(|existing| existing.contains(&id))
+        })
+        .map(Entry::to_xml)
+        .collect::<Vec<_>>();
+    xml_entries.extend(existing);
+    xml_entries.truncate(MAX_ENTRIES);
+
+    let feed = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<feed xmlns=\"http://www.w3.org/2005/Atom\">\n  <id>urn:apkeep:feed:{}</id>\n  <title>New versions</title>\n  <generator>apkeep</generator>\n  <updated>{}</updated>\n{}\n</feed>\n",
+        escape(&path.file_name().unwrap_or_default().to_string_lossy()),
+        Utc::now().to_rfc3339(),
+        xml_entries.join("\n"),
+    );
+    let temp_path = path.with_extension("tmp");
+    fs::write(&temp_path, feed)?;
+    fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+/// The `<entry>` elements of a feed, newest first as they were written.
+fn existing_entries(feed: &str) -> Vec<String> {
+    let mut entries = vec![];
+    let mut rest = feed;
+    while let Some(start) = rest.find("  <entry>") {
+        match rest[start..].find("</entry>") {
+            Some(end) => {
+                let end = start + end + "</entry>".len();
+                entries.push(rest[start..end].to_string());
+                rest = &rest[end..];
+            },
+            None => break,
+        }
+    }
+    entries
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,63 @@
+//! Include and exclude patterns for the apps to download, so that a list or a mirrored repository
+//! can be narrowed down to, say, only `com.mycompany.*`, or everything but the games.  Patterns
+//! are globs, where `*` matches any run of characters and `?` any one, compared without regard to
+//! case.  When mirroring F-Droid they are also compared with the categories of each app.
+
+#[derive(Default)]
+pub struct PackageFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl PackageFilter {
+    pub fn new(include: Vec<String>, exclude: Vec<String>) -> PackageFilter {
+        let normalize = |patterns: Vec<String>| patterns.into_iter()
+            .map(|pattern| pattern.trim().to_ascii_lowercase())
+            .filter(|pattern| !pattern.is_empty())
+            .collect();
+        PackageFilter {
+            include: normalize(include),
+            exclude: normalize(exclude),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Whether to download `app_id`: it must match an include pattern, if there are any, and no
+    /// exclude pattern.  Its categories, if known, are matched as well as its ID.
+    pub fn allows(&self, app_id: &str, categories: &[&str]) -> bool {
+        let names = [app_id].iter().chain(categories).map(|name| name.to_ascii_lowercase()).collect::<Vec<_>>();
+        let matches = |patterns: &[String]| patterns.iter()
+            .any(|pattern| names.iter().any(|name| glob_match(pattern.as_bytes(), name.as_bytes())));
+        (self.include.is_empty() || matches(&self.include)) && !matches(&self.exclude)
+    }
+}
+
+fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    // Where to resume after the last `*`, should what follows it fail to match
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            },
+            Some(c) if *c == b'?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            },
+            _ => match backtrack {
+                Some((star, start)) => {
+                    p = star + 1;
+                    n = start + 1;
+                    backtrack = Some((star, start + 1));
+                },
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == b'*')
+}
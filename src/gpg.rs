@@ -0,0 +1,35 @@
+use std::error::Error;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use simple_error::SimpleError;
+use tokio::process::Command;
+
+/// Write an ASCII-armored detached signature of `file` alongside it, as `<file>.asc`, using the
+/// given key from the user's GnuPG keyring.
+pub async fn detach_sign(file: &Path, key: &str) -> Result<PathBuf, Box<dyn Error>> {
+    let mut signature = file.as_os_str().to_owned();
+    signature.push(".asc");
+    let signature = PathBuf::from(signature);
+
+    let output = Command::new("gpg")
+        .args(["--batch", "--yes", "--armor", "--local-user", key, "--output"])
+        .arg(&signature)
+        .arg("--detach-sign")
+        .arg(file)
+        .output().await
+        .map_err(|err| -> Box<dyn Error> {
+            if err.kind() == io::ErrorKind::NotFound {
+                Box::new(SimpleError::new("gpg could not be found. Make sure GnuPG is installed and in your PATH."))
+            } else {
+                Box::new(err)
+            }
+        })?;
+    if output.status.success() {
+        Ok(signature)
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = stderr.lines().last().unwrap_or("gpg exited unsuccessfully").trim().to_string();
+        Err(Box::new(SimpleError::new(reason)))
+    }
+}
@@ -0,0 +1,140 @@
+//! Commands run after a download succeeds, fails or brings a new version, so that notifications,
+//! database inserts or installs can be wired up without patching apkeep.  Each hook is given the
+//! event as JSON on stdin, and its main fields as `APKEEP_*` environment variables.
+
+use std::error::Error;
+use std::io;
+use std::process::Stdio;
+
+use serde_json::{json, Value};
+use simple_error::SimpleError;
+use tokio::io::AsyncWriteExt;
+
+use crate::cli::DownloadSource;
+use crate::download_sources::DownloadedApp;
+use crate::pipe;
+
+#[derive(Clone, Copy)]
+pub enum Hook {
+    /// Run for each app downloaded
+    Success,
+    /// Run for each app which could not be downloaded, or whose download was rejected
+    Failure,
+    /// Run for each app downloaded which is newer than any version already in OUTPATH, or with
+    /// `--check-only`, for each such version found
+    NewVersion,
+}
+
+impl Hook {
+    /// The name of the hook, as its key in the `[hooks]` section of the config file
+    pub fn name(&self) -> &'static str {
+        match self {
+            Hook::Success => "on_success",
+            Hook::Failure => "on_failure",
+            Hook::NewVersion => "on_new_version",
+        }
+    }
+
+    fn event(&self) -> &'static str {
+        match self {
+            Hook::Success => "success",
+            Hook::Failure => "failure",
+            Hook::NewVersion => "new_version",
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Hooks {
+    pub on_success: Option<String>,
+    pub on_failure: Option<String>,
+    pub on_new_version: Option<String>,
+}
+
+impl Hooks {
+    pub fn command(&self, hook: Hook) -> Option<&str> {
+        match hook {
+            Hook::Success => self.on_success.as_deref(),
+            Hook::Failure => self.on_failure.as_deref(),
+            Hook::NewVersion => self.on_new_version.as_deref(),
+        }
+    }
+
+    /// Run the command for `hook`, if there is one, with `event`, failing if it exits unsuccessfully.
+    pub async fn run(&self, hook: Hook, event: &Value) -> Result<(), Box<dyn Error>> {
+        let command = match self.command(hook) {
+            Some(command) => command,
+            None => return Ok(()),
+        };
+        let mut event = event.clone();
+        event["event"] = json!(hook.event());
+        let field = |key: &str| match &event[key] {
+            Value::String(value) => value.clone(),
+            Value::Number(value) => value.to_string(),
+            _ => String::new(),
+        };
+        let file = event["files"].get(0).and_then(Value::as_str).unwrap_or_default().to_string();
+        let mut child = pipe::shell(command)
+            .env("APKEEP_EVENT", hook.event())
+            .env("APKEEP_APP_ID", field("package"))
+            .env("APKEEP_SOURCE", field("source"))
+            .env("APKEEP_VERSION", field("version"))
+            .env("APKEEP_VERSION_CODE", field("version_code"))
+            .env("APKEEP_FILE", file)
+            .env("APKEEP_REASON", field("reason"))
+            .stdin(Stdio::piped())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            match stdin.write_all(&serde_json::to_vec(&event)?).await {
+                // The command need not read the event
+                Err(err) if err.kind() != io::ErrorKind::BrokenPipe => return Err(Box::new(err)),
+                _ => {},
+            }
+        }
+        let status = child.wait().await?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(Box::new(SimpleError::new(format!("`{}` failed ({})", command, status))))
+        }
+    }
+}
+
+/// The event for an app downloaded, given the versionCode of its base APK and the newest
+/// versionCode already in OUTPATH.
+pub fn download_event(app: &DownloadedApp, source: DownloadSource, version_code: Option<u64>, previous_version_code: Option<u64>) -> Value {
+    json!({
+        "source": app.source.unwrap_or(source).to_string(),
+        "package": app.app_id,
+        "version": app.version,
+        "version_code": version_code,
+        "previous_version_code": previous_version_code,
+        "url": app.url,
+        "files": app.files.iter().map(|file| file.display().to_string()).collect::<Vec<String>>(),
+        "downloaded_at": app.downloaded_at.to_rfc3339(),
+        "released_at": app.released_at.map(|released_at| released_at.to_rfc3339()),
+        "changelog": app.changelog,
+    })
+}
+
+/// The event for an app which could not be downloaded.
+pub fn failure_event(app_id: &str, source: DownloadSource, reason: &str) -> Value {
+    json!({
+        "source": source.to_string(),
+        "package": app_id,
+        "reason": reason,
+    })
+}
+
+/// The event for an update found by `--check-only`, which has not been downloaded, given the
+/// newest versionCode already in OUTPATH.
+pub fn update_event(app_id: &str, source: DownloadSource, version: Option<&str>, version_code: Option<u64>, previous_version_code: Option<u64>) -> Value {
+    json!({
+        "source": source.to_string(),
+        "package": app_id,
+        "version": version,
+        "version_code": version_code,
+        "previous_version_code": previous_version_code,
+        "files": [],
+    })
+}
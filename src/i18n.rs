@@ -0,0 +1,101 @@
+//! Translations of the messages printed while apps are downloaded, so that apkeep can be followed
+//! by those who don't read English well.  The messages are kept as Fluent resources in `locales/`,
+//! one per language, and looked up by ID with [`tr!`]:
+//!
+//! ```ignore
+//! println!("{}", tr!("downloaded", app = app_string.as_str()));
+//! ```
+//!
+//! The language is given with `--lang`, or else taken from the system locale (`LC_ALL`,
+//! `LC_MESSAGES` or `LANG` on Unix).  A message which hasn't been translated into it yet is
+//! printed in English.
+
+use std::sync::OnceLock;
+
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::{FluentArgs, FluentResource};
+use unic_langid::LanguageIdentifier;
+
+/// The languages messages are translated into, English first
+const LOCALES: [(&str, &str); 3] = [
+    ("en", include_str!("../locales/en.ftl")),
+    ("ru", include_str!("../locales/ru.ftl")),
+    ("zh-CN", include_str!("../locales/zh-CN.ftl")),
+];
+
+struct Translations {
+    /// The bundle for the language chosen, unless that is English
+    selected: Option<FluentBundle<FluentResource>>,
+    english: FluentBundle<FluentResource>,
+}
+
+static TRANSLATIONS: OnceLock<Translations> = OnceLock::new();
+
+/// Look up a message, with its arguments, in the language chosen.
+macro_rules! tr {
+    ($id:literal) => {
+        crate::i18n::message($id, None)
+    };
+    ($id:literal, $($name:ident = $value:expr),+ $(,)?) => {{
+        let mut args = fluent_bundle::FluentArgs::new();
+        $(args.set(stringify!($name), $value);)+
+        crate::i18n::message($id, Some(&args))
+    }};
+}
+
+fn bundle(locale: &str, source: &str) -> FluentBundle<FluentResource> {
+    let langid: LanguageIdentifier = locale.parse().expect("the locales are valid language identifiers");
+    let mut bundle = FluentBundle::new_concurrent(vec![langid]);
+    // The marks Fluent puts around arguments for bidirectional text only clutter a terminal
+    bundle.set_use_isolating(false);
+    let resource = FluentResource::try_new(source.to_string()).expect("the locales are valid Fluent");
+    bundle.add_resource(resource).expect("the locales don't repeat messages");
+    bundle
+}
+
+/// Find the translation best matching a locale such as `ru_RU.UTF-8` or `zh-Hans-CN`: one for the
+/// same language and region if there is one, or else one for the same language.
+fn negotiate(requested: &str) -> Option<&'static str> {
+    let requested = requested.split(['.', '@']).next().unwrap_or_default().replace('_', "-");
+    let requested: LanguageIdentifier = requested.parse().ok()?;
+    let candidates = LOCALES.iter()
+        .map(|(locale, _)| (*locale, locale.parse::<LanguageIdentifier>().expect("the locales are valid language identifiers")))
+        .filter(|(_, langid)| langid.language == requested.language)
+        .collect::<Vec<_>>();
+    candidates.iter()
+        .find(|(_, langid)| langid.region == requested.region)
+        .or(candidates.first())
+        .map(|(locale, _)| *locale)
+}
+
+/// Choose the language of messages: `lang` if given, or else the system locale.
+pub fn init(lang: Option<&str>) {
+    let requested = lang.map(str::to_string).or_else(sys_locale::get_locale);
+    let locale = requested.as_deref().and_then(negotiate);
+    if let (Some(lang), None) = (lang, locale) {
+        eprintln!("Messages have not been translated into {}, so they will be printed in English.", lang);
+    }
+    let _ = TRANSLATIONS.set(translations(locale));
+}
+
+fn translations(locale: Option<&str>) -> Translations {
+    let [english, translated @ ..] = LOCALES;
+    Translations {
+        selected: translated.iter()
+            .find(|(candidate, _)| Some(*candidate) == locale)
+            .map(|(locale, source)| bundle(locale, source)),
+        english: bundle(english.0, english.1),
+    }
+}
+
+/// Format the message `id` with `args`, falling back to English if it hasn't been translated.
+pub fn message(id: &str, args: Option<&FluentArgs>) -> String {
+    let translations = TRANSLATIONS.get_or_init(|| translations(sys_locale::get_locale().as_deref().and_then(negotiate)));
+    for bundle in translations.selected.iter().chain([&translations.english]) {
+        if let Some(pattern) = bundle.get_message(id).and_then(|message| message.value()) {
+            let mut errors = vec![];
+            return bundle.format_pattern(pattern, args, &mut errors).into_owned();
+        }
+    }
+    id.to_string()
+}
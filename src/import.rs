@@ -0,0 +1,103 @@
+//! Converting the app lists kept by other tools into files apkeep can download from.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use reqwest::Url;
+use serde_json::Value;
+use simple_error::SimpleError;
+
+use crate::consts;
+
+/// The apkeep download source an app tracked elsewhere can be fetched from.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum Origin {
+    FDroid,
+    IzzyOnDroid,
+    GooglePlay,
+    APKPure,
+    HuaweiAppGallery,
+}
+
+impl Origin {
+    fn from_url(url: &str) -> Option<Origin> {
+        let url = Url::parse(url).ok()?;
+        let host = url.host_str()?.trim_start_matches("www.");
+        match host {
+            "f-droid.org" => Some(Origin::FDroid),
+            "apt.izzysoft.de" | "android.izzysoft.de" => Some(Origin::IzzyOnDroid),
+            "play.google.com" => Some(Origin::GooglePlay),
+            "apkpure.com" | "apkpure.net" => Some(Origin::APKPure),
+            "appgallery.huawei.com" | "appgallery.cloud.huawei.com" => Some(Origin::HuaweiAppGallery),
+            _ => None,
+        }
+    }
+
+    fn file_name(&self) -> &'static str {
+        match self {
+            Origin::FDroid => "f-droid.csv",
+            Origin::IzzyOnDroid => "izzyondroid.csv",
+            Origin::GooglePlay => "google-play.csv",
+            Origin::APKPure => "apk-pure.csv",
+            Origin::HuaweiAppGallery => "huawei-app-gallery.csv",
+        }
+    }
+
+    /// The arguments to pass along with `-c` to download the list.
+    fn arguments(&self) -> String {
+        match self {
+            Origin::FDroid => String::from("-d f-droid"),
+            Origin::IzzyOnDroid => format!("-d f-droid -o 'repo={}'", consts::IZZYONDROID_REPO),
+            Origin::GooglePlay => String::from("-d google-play"),
+            Origin::APKPure => String::from("-d apk-pure"),
+            Origin::HuaweiAppGallery => String::from("-d huawei-app-gallery"),
+        }
+    }
+}
+
+/// A list of app IDs written by an import, along with how to download it.
+pub struct ImportedList {
+    pub path: PathBuf,
+    pub arguments: String,
+    pub num_apps: usize,
+}
+
+/// The result of an import: the lists written, and the apps tracked from sources apkeep cannot
+/// download from (such as GitHub releases or direct links) along with their URLs.
+pub struct Import {
+    pub lists: Vec<ImportedList>,
+    pub unsupported: Vec<(String, String)>,
+}
+
+/// Convert an Obtainium export into one list of app IDs per download source, written to `dir`.
+/// Both the current export format, an object with an `apps` array, and a bare array are read.
+pub fn obtainium(export: &Path, dir: &Path) -> Result<Import, Box<dyn Error>> {
+    let export: Value = serde_json::from_str(&fs::read_to_string(export)?)?;
+    let apps = export.get("apps").unwrap_or(&export).as_array()
+        .ok_or_else(|| SimpleError::new("The file is not an Obtainium export."))?;
+
+    let mut origins: BTreeMap<Origin, Vec<String>> = BTreeMap::new();
+    let mut unsupported = vec![];
+    for app in apps {
+        let (id, url) = match (app["id"].as_str(), app["url"].as_str()) {
+            (Some(id), Some(url)) => (id, url),
+            _ => continue,
+        };
+        match Origin::from_url(url) {
+            Some(origin) => origins.entry(origin).or_default().push(id.to_string()),
+            None => unsupported.push((id.to_string(), url.to_string())),
+        }
+    }
+
+    let mut lists = vec![];
+    for (origin, mut ids) in origins {
+        ids.sort();
+        ids.dedup();
+        let path = dir.join(origin.file_name());
+        fs::write(&path, ids.iter().map(|id| format!("{}\n", id)).collect::<String>())?;
+        lists.push(ImportedList { path, arguments: origin.arguments(), num_apps: ids.len() });
+    }
+    Ok(Import { lists, unsupported })
+}
@@ -0,0 +1,61 @@
+//! `--info`, for looking into an unknown app before downloading it.  What the source says about
+//! each app, such as its developer, rating, install count and content rating, is printed, or
+//! written as JSON or TSV with `--output-format`.  Sources only give some of it; F-Droid, for
+//! instance, has no ratings, and a scraped site gives what its rules pick out.
+
+use serde_json::{json, Map};
+
+use crate::download_sources::AppInfo;
+use crate::util::{tsv_row, OutputFormat};
+
+/// The fields of an app's information, with the labels they are printed with and their keys in
+/// the JSON, in the order of the columns of the TSV.
+fn fields(info: &AppInfo) -> [(&'static str, &'static str, &Option<String>); 7] {
+    [
+        ("Name", "name", &info.name),
+        ("Developer", "developer", &info.developer),
+        ("Summary", "summary", &info.summary),
+        ("License", "license", &info.license),
+        ("Rating", "rating", &info.rating),
+        ("Installs", "installs", &info.installs),
+        ("Content rating", "content_rating", &info.content_rating),
+    ]
+}
+
+/// Print the information on each app, `source` being the name of the source or scraped site.
+pub fn print(infos: &[AppInfo], source: &str, output_format: OutputFormat) {
+    match output_format {
+        OutputFormat::Plaintext => {
+            for info in infos {
+                println!("Information on {} from {}:", info.app_id, source);
+                let known = fields(info).into_iter()
+                    .filter_map(|(label, _, value)| value.as_ref().map(|value| (label, value)))
+                    .collect::<Vec<_>>();
+                if known.is_empty() {
+                    println!("| {} gives nothing more about it.", source);
+                }
+                for (label, value) in known {
+                    // Descriptions may run over several lines, which are kept together
+                    println!("| {}: {}", label, value.replace('\n', " "));
+                }
+            }
+        },
+        // A column for the app ID, then one for each field, empty where it isn't known
+        OutputFormat::Tsv => {
+            for info in infos {
+                let mut columns = vec![info.app_id.as_str()];
+                columns.extend(fields(info).iter().map(|(_, _, value)| value.as_deref().unwrap_or_default()));
+                println!("{}", tsv_row(&columns));
+            }
+        },
+        OutputFormat::Json => {
+            let apps = infos.iter().map(|info| {
+                let fields = fields(info).into_iter()
+                    .map(|(_, key, value)| (key.to_string(), json!(value)))
+                    .collect::<Map<_, _>>();
+                (info.app_id.clone(), json!(fields))
+            }).collect::<Map<_, _>>();
+            println!("{}", json!({ "source": source, "apps": apps }));
+        },
+    }
+}
@@ -0,0 +1,122 @@
+//! Checking an existing archive for `apkeep verify`, so that bit rot or tampering in a long-lived
+//! mirror is noticed: files are compared with `SHA256SUMS` and their metadata sidecars, APK
+//! signatures are verified again, and files and sidecars which have lost their counterparts are
+//! reported.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use crate::apk;
+use crate::util::{find_files, relative_name, sha256_file};
+
+pub enum Problem {
+    /// Listed in `SHA256SUMS`, but not there
+    Missing(String),
+    /// Its contents no longer match its recorded hash
+    Corrupted(String, &'static str),
+    /// Its signature no longer verifies, or its signers are not those recorded
+    Tampered(String, String),
+    /// Not listed in `SHA256SUMS`
+    Unlisted(String),
+    /// A metadata sidecar without the file it describes
+    OrphanedSidecar(String),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::Missing(name) => write!(f, "missing: {} is listed in SHA256SUMS but is not there", name),
+            Problem::Corrupted(name, record) => write!(f, "corrupted: {} does not match its {}", name, record),
+            Problem::Tampered(name, reason) => write!(f, "tampered: {} {}", name, reason),
+            Problem::Unlisted(name) => write!(f, "orphaned: {} is not listed in SHA256SUMS", name),
+            Problem::OrphanedSidecar(name) => write!(f, "orphaned: {} describes a file which is not there", name),
+        }
+    }
+}
+
+fn sidecar_path(file: &Path) -> PathBuf {
+    let mut sidecar = file.as_os_str().to_owned();
+    sidecar.push(".meta.json");
+    PathBuf::from(sidecar)
+}
+
+/// Check every APK and XAPK in the output directory, returning the number checked and the
+/// problems found.
+pub fn verify(outpath: &Path) -> Result<(usize, Vec<Problem>), Box<dyn Error>> {
+    let mut problems = vec![];
+
+    let checksums_file = outpath.join("SHA256SUMS");
+    let checksums = if checksums_file.is_file() {
+        Some(fs::read_to_string(&checksums_file)?.lines()
+            .filter_map(|line| line.split_once("  "))
+            .map(|(hash, name)| (name.to_string(), hash.to_string()))
+            .collect::<BTreeMap<String, String>>())
+    } else {
+        None
+    };
+
+    let mut files = vec![];
+    find_files(outpath, &["apk", "xapk"], &mut files)?;
+    let latest_dir = outpath.join("latest");
+    files.retain(|file| !file.starts_with(&latest_dir));
+
+    for file in &files {
+        let name = relative_name(outpath, file)?;
+        let hash = sha256_file(file)?;
+        match checksums.as_ref().map(|checksums| checksums.get(&name)) {
+            Some(Some(recorded)) if *recorded != hash => problems.push(Problem::Corrupted(name.clone(), "entry in SHA256SUMS")),
+            Some(None) => problems.push(Problem::Unlisted(name.clone())),
+            _ => {},
+        }
+
+        let metadata = fs::read_to_string(sidecar_path(file)).ok()
+            .and_then(|metadata| serde_json::from_str::<Value>(&metadata).ok());
+        if let Some(recorded) = metadata.as_ref().and_then(|metadata| metadata.get("sha256")).and_then(Value::as_str) {
+            if recorded != hash {
+                problems.push(Problem::Corrupted(name.clone(), "metadata sidecar"));
+            }
+        }
+
+        // XAPK bundles aren't signed themselves
+        if file.extension().is_some_and(|ext| ext == "apk") {
+            match apk::signature::verify_v2_v3(file) {
+                Ok(Some(verified)) => {
+                    let recorded = metadata.as_ref().and_then(|metadata| metadata.get("signers")).and_then(Value::as_array);
+                    if let Some(recorded) = recorded {
+                        let recorded = recorded.iter().filter_map(Value::as_str).collect::<Vec<_>>();
+                        if !recorded.is_empty() && verified.signers.iter().any(|signer| !recorded.contains(&signer.as_str())) {
+                            problems.push(Problem::Tampered(name.clone(), "is signed by a different key than when it was downloaded".to_string()));
+                        }
+                    }
+                },
+                // Only a v1 signature, which is checked on install
+                Ok(None) => {},
+                Err(err) => problems.push(Problem::Tampered(name.clone(), format!("has a signature which does not verify: {}", err))),
+            }
+        }
+    }
+
+    if let Some(checksums) = &checksums {
+        for name in checksums.keys() {
+            if !outpath.join(name).exists() {
+                problems.push(Problem::Missing(name.clone()));
+            }
+        }
+    }
+
+    let mut sidecars = vec![];
+    find_files(outpath, &["json"], &mut sidecars)?;
+    for sidecar in sidecars {
+        if let Some(file) = sidecar.to_str().and_then(|sidecar| sidecar.strip_suffix(".meta.json")) {
+            if !Path::new(file).exists() {
+                problems.push(Problem::OrphanedSidecar(relative_name(outpath, &sidecar)?));
+            }
+        }
+    }
+    Ok((files.len(), problems))
+}
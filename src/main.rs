@@ -72,15 +72,569 @@
 //! apkeep -a com.instagram.android@1.2.3 .
 //! ```
 //!
+//! A versionName can be ambiguous, or shared by several builds, so a versionCode can be given
+//! instead as `@code:<versionCode>`. F-Droid and Google Play take versionCodes, as do sites scraped
+//! with rules which pick them out; `apkeep list-sources` shows which sources can:
+//!
+//! ```shell
+//! apkeep -a org.example.app@code:42 -d f-droid .
+//! ```
+//!
+//! To build up the history of an app, such as for a longitudinal study, `--all-versions` downloads
+//! every version the source lists rather than only the latest. It works with the sources which can
+//! list versions: APKPure, F-Droid and sites scraped with rules which pick out versions. Versions
+//! already in OUTPATH are left out, so running it again only fetches releases published since:
+//!
+//! ```shell
+//! apkeep -c apps.csv -d f-droid --all-versions /srv/history
+//! ```
+//!
+//! `--released-after` and `--released-before` narrow `--all-versions` to the versions released in a
+//! range of dates, given as `YYYY-MM-DD` (the start of that day in UTC) or as an RFC 3339 time. A
+//! version released on the `--released-after` date is included, and one released on the
+//! `--released-before` date is not. Only F-Droid says when each version was released, so only it can
+//! be narrowed this way. To download every version of an app published in 2023:
+//!
+//! ```shell
+//! apkeep -a org.fdroid.fdroid -d f-droid --all-versions --released-after 2023-01-01 --released-before 2024-01-01 .
+//! ```
+//!
+//! A link to an app's page on Google Play, F-Droid, APKPure or APKCombo can be given in place of
+//! its ID, with `-a` or in a CSV. Unless `-d` is given, an app given as a link with `-a` is
+//! downloaded from the store it links to, and a link to an APK in the F-Droid repository downloads
+//! that version:
+//!
+//! ```shell
+//! apkeep -a https://f-droid.org/packages/org.fdroid.fdroid/ .
+//! ```
+//!
+//! An app can also be given by name. The source is searched for it, F-Droid through its index, a
+//! scraped site through its rules and other sources through APKCombo, and the closest matches are
+//! listed with their IDs and developers to choose from. Use `--first` to take the first match without
+//! being asked, as is needed when apkeep is not run from a terminal:
+//!
+//! ```shell
+//! apkeep -a whatsapp --first .
+//! ```
+//!
+//! For apps kept in lists by hand, names can instead be given their app IDs once, in
+//! `aliases.txt` in the config directory or a file given with `--aliases`, as `name = app ID` lines.
+//! The names can then be used with `-a` or in the CSV, regardless of case, and an app which changes
+//! its ID only needs the one line updating:
+//!
+//! ```ini
+//! signal = org.thoughtcrime.securesms
+//! tor-browser = org.torproject.torbrowser
+//! ```
+//!
+//! ```shell
+//! apkeep -a signal --aliases aliases.txt .
+//! ```
+//!
 //! Or, to list what versions are available, use `-l`:
 //!
 //! ```shell
 //! apkeep -l -a org.mozilla.fennec_fdroid -d f-droid
 //! ```
 //!
+//! For scripts, `--output-format json` prints what `-l`, `--info` and searches for apps given by name
+//! find as JSON, and `--output-format tsv` as tab-separated columns with nothing else on stdout, so
+//! that `cut` and `awk` can take them apart. Versions are listed as app ID, versionName and
+//! versionCode (empty where the source doesn't give one), and search matches as the name searched
+//! for, app ID, app name and developer:
+//!
+//! ```shell
+//! apkeep -l -a org.mozilla.fennec_fdroid -d f-droid --output-format tsv | cut -f2
+//! ```
+//!
+//! To download only some of the apps in a list, `--include` and `--exclude` take comma-separated
+//! glob patterns matched against app IDs, where `*` matches any run of characters and `?` any one.
+//! An app must match an include pattern, if any are given, and no exclude pattern. They can also be
+//! set as `include` and `exclude` in the `[filter]` section of the config file:
+//!
+//! ```shell
+//! apkeep -c apps.csv --include 'com.mycompany.*' --exclude '*.beta' .
+//! ```
+//!
+//! Rather than passing a long `-o` string on every run, a source's options can be kept in a
+//! `[sources.<download_source>]` section of the config file, named as for `-d`. Options given with
+//! `-o` take precedence:
+//!
+//! ```ini
+//! [sources.f-droid]
+//! repo = https://guardianproject.info/fdroid/repo?fingerprint=B7C2EEFD8DAC7806AF67DFCD92EB18126BC08312A7F2D6F3862E46013C7A6135
+//! use_entry = true
+//! ```
+//!
+//! Some mirrors only serve their content to requests with particular headers, such as an
+//! `Accept-Language`, a `Referer` or an API key for a private mirror. Extra headers for a source can be
+//! kept in a `[headers.<download_source>]` section of the config file, or given with `-H`/`--header`,
+//! which takes precedence and applies to whichever source is used. They are sent with every request
+//! the source makes itself, but not with the downloads which F-Droid, APKPure and the Huawei AppGallery
+//! leave to a library, nor to Google Play:
+//!
+//! ```ini
+//! [headers.scraped]
+//! Accept-Language = de
+//! X-Api-Key = 0123456789abcdef
+//! ```
+//!
+//! ```shell
+//! apkeep -a com.example -d apk-combo -H 'Accept-Language: de' .
+//! ```
+//!
+//! To keep several archives in one config file, settings can be grouped into profiles chosen with
+//! `--profile`. A `[profile.<name>]` section gives the `download_source` and `outpath` to use when
+//! `-d` and OUTPATH aren't given, and each `[profile.<name>.<section>]` section overrides
+//! `[<section>]`, such as a source's options or Google Play credentials:
+//!
+//! ```ini
+//! [profile.mirror]
+//! download_source = f-droid
+//! outpath = /srv/fdroid-mirror
+//!
+//! [profile.mirror.sources.f-droid]
+//! repo = https://f-droid.org/archive
+//! ```
+//!
+//! ```shell
+//! apkeep --profile mirror -c apps.csv
+//! ```
+//!
+//! To see which sources can download specific versions or list them, how they deliver split apps,
+//! whether they need an account, and which `-o` options they accept, run `apkeep list-sources`.
+//!
+//! To see which sources are working right now, `apkeep doctor` searches each for an app it has long
+//! had, finds its latest version and downloads it to a temporary directory, and reports each source
+//! as working, rate-limited, unreachable or broken, which usually means the site has changed. Google
+//! Play is skipped, as it needs an account. A scraped site or plugin can be checked by giving its
+//! `--rules` or `--plugin` and an app it has with `-a`, and `--no-download` stops once the latest
+//! version is found. apkeep exits with an error if any source checked isn't working, so `doctor` can
+//! be run on a schedule to notice when a source breaks:
+//!
+//! ```shell
+//! apkeep doctor
+//! apkeep doctor -d scraped --rules mysite -a com.example.app
+//! ```
+//!
+//! The patterns apkeep picks apart APKPure's and APKCombo's pages with are kept in `patterns.toml`.
+//! When one of those sites changes its layout, a fix may be published there before the next release;
+//! `apkeep update-patterns` downloads it into the cache directory, and it is used from then on. The
+//! patterns are only used if they are signed with the project's key, which release builds of apkeep
+//! are built to trust, and are newer than those built in:
+//!
+//! ```shell
+//! apkeep update-patterns
+//! ```
+//!
+//! To see what changed between two downloaded versions of an app, `apkeep diff` lists the entries
+//! added, removed and changed, the change in size, the permissions added or dropped, and trackers
+//! which appear or disappear, recognised by the package names of their classes:
+//!
+//! ```shell
+//! apkeep diff org.example.app@1.0.apk org.example.app@1.1.apk
+//! ```
+//!
+//! By default the latest stable release of each app is downloaded. With `--channel beta` (or
+//! `alpha`), pre-releases are taken as well, for sources which mark them. F-Droid does: versions
+//! newer than the one a repository suggests, or in its `Beta` release channel, are pre-releases.
+//! Google Play instead serves whichever testing track the account has joined for each app.
+//!
+//! Some apps publish separate builds for TVs, watches and cars. `--form-factor tv` (or `wear`, or
+//! `auto` for Android Automotive) picks that build on F-Droid, judged by the features each version
+//! requires. Google Play serves the build for the device profile given with `-o device`, so choose a
+//! profile of that kind. Whatever the source, a download built for another kind of device, such as
+//! a phone build where none for the TV was published, is quarantined:
+//!
+//! ```shell
+//! apkeep -a org.jellyfin.androidtv -d f-droid --form-factor tv .
+//! ```
+//!
+//! When a mirror is flaky, or which one is fastest depends on where you are, `--race` downloads
+//! each app from several sources at once, keeps whichever finishes first and cancels the rest.
+//! Google Play can't take part, as it needs credentials of its own:
+//!
+//! ```shell
+//! apkeep -a org.mozilla.fennec_fdroid --race f-droid,apk-pure,apk-combo .
+//! ```
+//!
+//! Sources which aren't built in can be added as WebAssembly plugins, by dropping a `.wasm` file into
+//! the `plugins` directory of the config dir (e.g. `~/.config/apkeep/plugins`) and downloading with
+//! `-d plugin --plugin NAME`. Plugins only parse the pages apkeep fetches for them, with no access to
+//! the network or filesystem of their own; the interface they implement is described in
+//! `src/download_sources/plugin.rs`.
+//!
+//! ```shell
+//! apkeep -a com.example.app -d plugin --plugin my-mirror .
+//! ```
+//!
+//! A site can also be described in a TOML rules file, giving the URL of an app's page (or of a search
+//! for it) and the CSS selectors of its links, and downloaded from with `-d scraped --rules NAME`, so
+//! that a new mirror can be added, or a selector fixed after a redesign, without writing Rust. Rules
+//! are kept in the `rules` directory of the config dir; their format is described in
+//! `src/download_sources/scraped.rs`.
+//!
+//! ```shell
+//! apkeep -a com.example.app -d scraped --rules my-mirror .
+//! ```
+//!
+//! If the rules also give a `category_url`, `--category` downloads the latest version of every app
+//! listed in a category of the site, following its pages, as it does for an F-Droid repository.
+//! `--include` and `--exclude` narrow the category down:
+//!
+//! ```shell
+//! apkeep --category games -d scraped --rules my-mirror --exclude 'com.example.*' .
+//! ```
+//!
+//! Likewise, `--developer` downloads every app by one developer, from F-Droid by the author named in
+//! the repository, or from a site whose rules give a `developer_url`:
+//!
+//! ```shell
+//! apkeep --developer "The Tor Project" -d f-droid .
+//! ```
+//!
 //! Refer to [`USAGE`](https://github.com/EFForg/apkeep/blob/master/USAGE) to download multiple
 //! APKs in a single run.
 //!
+//! To install each app on a connected device with [`adb`](https://developer.android.com/tools/adb)
+//! after it is downloaded, add `--install`. Split APKs and XAPK bundles are installed with
+//! `adb install-multiple`:
+//!
+//! ```shell
+//! apkeep -a org.mozilla.fennec_fdroid -d f-droid --install .
+//! ```
+//!
+//! With several devices connected, choose which to install to with `--device` (repeatable), or
+//! install to every connected device with `--device all`:
+//!
+//! ```shell
+//! apkeep -c apps.csv -d f-droid --install --device all .
+//! ```
+//!
+//! To keep a device up to date, `--only-newer-than-device` skips any app which is not installed on
+//! the device, or for which the source offers nothing newer than the installed versionCode:
+//!
+//! ```shell
+//! apkeep -c apps.csv -d f-droid --only-newer-than-device --install .
+//! ```
+//!
+//! Likewise, so that re-syncing an archive doesn't fetch the same latest versions again,
+//! `--only-newer` skips any app for which the source offers nothing newer than the newest versionCode
+//! already in OUTPATH. F-Droid knows the versionCode in advance, so skips the download altogether;
+//! other sources can only tell once it has arrived, and discard it then.
+//!
+//! To only find out which apps have updates, `--check-only` looks up the latest version of each on the
+//! source and compares it with the newest in OUTPATH, without downloading anything. Updates are
+//! printed, handed to the `on_new_version` hook (with no files), and written as JSON to the file given
+//! with `--check-report`. F-Droid, APKPure, plugins and scraped sites which list versions can be
+//! checked; `apkeep list-sources` shows which:
+//!
+//! ```shell
+//! apkeep -c apps.csv -d f-droid --check-only --check-report updates.json .
+//! ```
+//!
+//! To look into an unknown app before downloading it, `--info` shows what the source says about it:
+//! its name, developer, summary and license from F-Droid, and its rating, install count and content
+//! rating from scraped sites whose rules pick them out with `rating`, `installs` and
+//! `content_rating`. No OUTPATH is needed, and `--output-format` prints it as JSON or TSV:
+//!
+//! ```shell
+//! apkeep -a org.torproject.android -d f-droid --info
+//! ```
+//!
+//! To follow a mirror in a feed reader, `--feed` adds each new version a run downloads, or finds with
+//! `--check-only`, to an Atom feed. Written into OUTPATH, the feed is served along with the mirror; it
+//! keeps the newest 100 entries:
+//!
+//! ```shell
+//! apkeep -c apps.csv -d f-droid --only-newer --feed /srv/mirror/feed.xml /srv/mirror
+//! ```
+//!
+//! When collecting the same apps from several sources or over many runs, `--dedup` stores each
+//! download once in a content-addressed pool (`OUTPATH/.pool`, named by SHA-256 hash) and links it
+//! into place, so identical APKs only take up space once:
+//!
+//! ```shell
+//! apkeep -c apps.csv -d apk-pure --dedup .
+//! ```
+//!
+//! When two apps in a run would be saved as the same file, such as two apps which a site serves as
+//! `app.apk`, the later one is saved with a number added (`app-2.apk`) and the collision is reported,
+//! so that neither overwrites the other. An app listed twice is only downloaded once at a time.
+//!
+//! After each run, the SHA-256 hash of every new download is added to `SHA256SUMS` in the output
+//! directory, so the archive can be checked later with `sha256sum -c SHA256SUMS`.
+//!
+//! Hashes are computed from each file as it is written, so that checksums, metadata sidecars and
+//! verification don't read large downloads from disk again. For interop with archives which list
+//! files by other hashes, `--extra-checksums md5,sha1` also writes `MD5SUMS` and `SHA1SUMS` and adds
+//! `md5` and `sha1` to the sidecars.
+//!
+//! Downloading into a directory which already holds earlier downloads only transfers what has
+//! changed. Before each download from APKPure, F-Droid or Huawei AppGallery, an existing file of the
+//! same name is compared with the source: against the hash in the F-Droid index, or against the size
+//! reported to a `HEAD` request. Files which match are skipped, and those which don't are replaced.
+//!
+//! To be sure that downloads, particularly those from third-party sites, are intact and have not been
+//! tampered with since they were signed, `--verify-signatures` checks the APK Signature Scheme v3
+//! signature of each APK, or its v2 signature for those signed before v3, as Android does on install.
+//! Downloads with an invalid signature, or whose contents don't match it, are quarantined. APKs signed with
+//! only a v1 (JAR) signature are kept, with a note that their signature was not checked.
+//!
+//! A valid signature only shows that an APK hasn't changed since someone signed it. To catch apps
+//! repackaged by a mirror, the signer of each download is also compared with the known publisher
+//! certificates of its package, from a list bundled with apkeep, `known-signers.csv` in the config
+//! directory, and any file given with `--known-signers`. Each line of these lists is a
+//! `package,fingerprint` pair, where the fingerprint is the SHA-256 hash of the certificate as printed
+//! by `apksigner verify --print-certs`. A download signed by anyone else is reported, and with
+//! `--verify-signatures` it is quarantined.
+//!
+//! Downloads which fail verification are not simply deleted: they are moved to `quarantine/` in
+//! OUTPATH, keeping their name, with a `.reason.txt` file beside them saying what went wrong. This
+//! covers invalid signatures and unknown signers as above, F-Droid downloads whose hash does not
+//! match the signed index, and downloads which turn out to be a different app than the one asked for.
+//! Quarantined files are left out of `prune`, `index` and `repo build`.
+//!
+//! For organizations which must show where their sideloaded apps came from, `--audit-log FILE`
+//! appends a JSON line to FILE for every download, with its source, URL and SHA-256 hashes, and for
+//! the result of every check made of it. Each entry includes the hash of the one before it, so
+//! `apkeep audit FILE` can tell if any entry has since been changed, removed or reordered. It prints
+//! the hash of the last entry; keeping a copy of that elsewhere also shows that none were removed
+//! from the end:
+//!
+//! ```shell
+//! apkeep -c apps.csv --verify-signatures --audit-log audit.log .
+//! apkeep audit audit.log
+//! ```
+//!
+//! Some mirrors link to their downloads over plain HTTP, where a file can be swapped in transit. Such
+//! links are upgraded to HTTPS, and redirects from HTTPS to plain HTTP are refused, unless
+//! `--allow-insecure` is given. F-Droid repositories are used at the address given, as their downloads
+//! are checked against the hashes in the signed index.
+//!
+//! File names are built from app IDs, versions and names given by the source, none of which are
+//! trusted to name a file: path separators and characters Windows doesn't allow are replaced with `_`,
+//! names Windows reserves such as `CON` are prefixed with `_`, and overlong names are shortened, so
+//! every download lands directly in OUTPATH and can be copied to any platform.
+//!
+//! Another replacement can be chosen with `--file-name-replacement`. Shortened names keep their
+//! extension and part of a hash of the full name, so that long versions differing only at the end
+//! don't collide. On Windows, where paths longer than 259 characters fail unless long paths are
+//! enabled, names are also shortened to keep the whole path within that limit; `--max-path` sets
+//! another limit, or `0` for none:
+//!
+//! ```shell
+//! apkeep -a com.instagram.android --file-name-replacement - --max-path 200 .
+//! ```
+//!
+//! To let others verify where a shared mirror came from, `--gpg-sign` signs `SHA256SUMS` with the
+//! given GnuPG key, writing the detached signature to `SHA256SUMS.asc`:
+//!
+//! ```shell
+//! apkeep -c apps.csv -d f-droid --gpg-sign mirror@example.org .
+//! gpg --verify SHA256SUMS.asc && sha256sum -c SHA256SUMS
+//! ```
+//!
+//! To keep an archive interpretable on its own, `--metadata` writes a `<file>.meta.json` next to
+//! each download recording the source, the URL it was downloaded from, its version and versionCode,
+//! SHA-256 hash, signer certificate fingerprints, when it was downloaded and, if the source says,
+//! when it was released and what is new in it. Release notes come from F-Droid repositories with
+//! an `index-v2.json`, and from scraped sites whose rules pick them out with `changelog`; they are
+//! also listed by `-l --output-format json`.
+//!
+//! So that provenance travels with a file even once it has been copied elsewhere, `--xattrs` records
+//! the source, package, URL, version, versionCode, SHA-256 hash and download time in `user.apkeep.*`
+//! extended attributes on the file itself (alternate data streams of the same names on NTFS), which
+//! can be read with `getfattr -d`:
+//!
+//! ```shell
+//! apkeep -a com.instagram.android --xattrs .
+//! getfattr -d com.instagram.android.apk
+//! ```
+//!
+//! To hand a set of apps to an air-gapped environment as a single file, `--archive` writes everything
+//! a run produces, including `SHA256SUMS` and any metadata sidecars, into a `.tar.zst`, `.tar` or
+//! `.zip` archive instead of OUTPATH. The downloads are staged in a directory beside the archive,
+//! which is removed once the archive is written; quarantined files are left out:
+//!
+//! ```shell
+//! apkeep -c apps.csv -d f-droid --metadata --archive bundle.tar.zst
+//! ```
+//!
+//! When OUTPATH is on network storage, or anywhere a half-written file shouldn't appear, `--temp-dir`
+//! downloads into a directory there instead and moves each app into OUTPATH once the run has
+//! downloaded it. Files are renamed into place, or, when `--temp-dir` is on another filesystem, copied
+//! beside their destination and then renamed, so that OUTPATH never holds a partial file. Files
+//! already in OUTPATH are still compared with the source and skipped when up to date. With
+//! `--archive`, the downloads are staged in `--temp-dir` rather than beside the archive.
+//!
+//! ```shell
+//! apkeep -c apps.csv -d f-droid --temp-dir /var/tmp /mnt/nas/apks
+//! ```
+//!
+//! To hand each download to another tool, `--pipe` runs a shell command on every downloaded file.
+//! `{}` in the command is replaced with the file's path; without it, the file is streamed to the
+//! command's stdin. `APKEEP_APP_ID`, `APKEEP_VERSION` and `APKEEP_FILE` are set for the command. A
+//! file the command fails on is treated as a failed download, and its app is quarantined:
+//!
+//! ```shell
+//! apkeep -c apps.csv -d apk-pure --pipe 'clamscan --no-summary {}' .
+//! ```
+//!
+//! Hooks run a shell command after each run: `--on-success` for every app downloaded, `--on-failure`
+//! for every app which could not be downloaded or was quarantined, and `--on-new-version` for every
+//! app downloaded which is newer than any version already in the output directory. They can also be
+//! set as `on_success`, `on_failure` and `on_new_version` in the `[hooks]` section of the config file.
+//! Each hook is given the event as JSON on stdin, and `APKEEP_EVENT`, `APKEEP_APP_ID`,
+//! `APKEEP_SOURCE`, `APKEEP_VERSION`, `APKEEP_VERSION_CODE`, `APKEEP_FILE` and `APKEEP_REASON` as
+//! environment variables:
+//!
+//! ```shell
+//! apkeep -c apps.csv -d f-droid --on-new-version 'notify-send "New version of $APKEEP_APP_ID"' .
+//! ```
+//!
+//! To catch bit rot or tampering in a long-lived mirror, `apkeep verify` checks every APK in an
+//! output directory against `SHA256SUMS` and its sidecar, verifies its v2/v3 signature again and
+//! compares the signers with those recorded, and reports files missing from either side. It exits
+//! with an error if it finds any problem:
+//!
+//! ```shell
+//! apkeep verify /srv/mirror
+//! ```
+//!
+//! So that archive tooling and rsync-based replication see meaningful timestamps, `--release-mtime`
+//! sets the modification time of each download to when its version was released, for sources which
+//! say (F-Droid), and `--file-mode` gives each download the permissions of an octal mode:
+//!
+//! ```shell
+//! apkeep -c apps.csv -d f-droid --release-mtime --file-mode 644 .
+//! ```
+//!
+//! For provisioning scripts which need a stable path, `--latest-links` keeps a link at
+//! `OUTPATH/latest/<app_id>.apk` (or `.xapk`) pointing to the newest version downloaded of each app.
+//! On Windows the file is copied instead.
+//!
+//! So that a mirror which is synced continuously doesn't grow without bound, `apkeep prune` removes
+//! the versions of each app beyond the newest `--keep`, as well as those downloaded more than
+//! `--keep-days` days ago. The newest version of an app is never removed, and `--dry-run` lists what
+//! would be removed. Files stored in the `--dedup` pool are only unlinked, not removed from the pool:
+//!
+//! ```shell
+//! apkeep prune --keep 3 --keep-days 90 --dry-run .
+//! ```
+//!
+//! To keep the history without paying for it in full, `--archive` moves the pruned versions into a
+//! zstd-compressed tarball per app, `archive/<app_id>.tar.zst`, rather than removing them. Later
+//! prunes add to the same tarball, which can be extracted with `tar --zstd -xf`:
+//!
+//! ```shell
+//! apkeep prune --keep 1 --archive .
+//! ```
+//!
+//! To prune as part of every run instead, give `--keep` and `--keep-days` when downloading, or set
+//! them in the `[retention]` section of the config file. After each run, the old versions of the
+//! apps just downloaded are removed, as `apkeep prune` would remove them:
+//!
+//! ```ini
+//! [retention]
+//! keep = 3
+//! ```
+//!
+//! To copy each download to S3-compatible storage as well, give a destination with `--upload`.
+//! Credentials are read from `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY` (and optionally
+//! `AWS_SESSION_TOKEN`, `AWS_REGION` and `AWS_ENDPOINT_URL` for services other than AWS), or from
+//! the `[s3]` section of the config file:
+//!
+//! ```shell
+//! apkeep -a com.instagram.android --upload s3://my-bucket/apks .
+//! ```
+//!
+//! Uploads can also go to a WebDAV server such as Nextcloud, using `webdavs://` for HTTPS or
+//! `webdav://` for plain HTTP. Credentials may be given in the URL, with `APKEEP_WEBDAV_USERNAME` and
+//! `APKEEP_WEBDAV_PASSWORD`, or in the `[webdav]` section of the config file:
+//!
+//! ```shell
+//! apkeep -a com.instagram.android --upload webdavs://nas.local/remote.php/dav/files/me/apks .
+//! ```
+//!
+//! To send downloads to a remote archive server over SSH, use an `sftp://` destination. Only
+//! key-based authentication is supported: keys are taken from `ssh-agent` or `~/.ssh`, or from the
+//! file given by `APKEEP_SFTP_IDENTITY_FILE` or `identity_file` in the `[sftp]` section of the config
+//! file. The server's host key must already be in `~/.ssh/known_hosts`:
+//!
+//! ```shell
+//! apkeep -a com.instagram.android --upload sftp://archive@archive.example.org/srv/apks .
+//! ```
+//!
+//! To share the downloaded apps with a fleet of devices, `apkeep repo build` turns a directory of
+//! APKs into an F-Droid repository by writing signed `index-v1` and `index-v2` indexes alongside
+//! them. Serve the directory over HTTP and add it to the F-Droid client along with the printed
+//! fingerprint. A signing key is generated in the config directory on first use, or one can be
+//! given with `--key` and `--cert`:
+//!
+//! ```shell
+//! apkeep repo build --name "Our apps" --address https://apps.example.org/repo ./repo
+//! ```
+//!
+//! So that the apps have their names, descriptions, changelogs, icons and screenshots in the client,
+//! `repo build` adds any listings it finds in the directory's `metadata/`, laid out as fastlane
+//! lays them out (`metadata/<package>/<locale>/title.txt`, `short_description.txt`,
+//! `full_description.txt`, `changelogs/<versionCode>.txt`, `images/icon.png` and
+//! `images/phoneScreenshots/`). Downloads from F-Droid with `-o fastlane=true` mirror the listings
+//! there from the repository downloaded from:
+//!
+//! ```shell
+//! apkeep -d f-droid --mirror-repo -o fastlane=true ./repo
+//! apkeep repo build --name "F-Droid mirror" --address https://apps.example.org/repo ./repo
+//! ```
+//!
+//! For a simpler way to share an archive over a plain web server, `apkeep index` writes an
+//! `index.html` listing every app in a directory with its versions, sizes, SHA-256 hashes and
+//! download links:
+//!
+//! ```shell
+//! apkeep index ./apks
+//! ```
+//!
+//! For building analysis corpora, `--mobsf` submits each downloaded app to a
+//! [MobSF](https://mobsf.github.io/docs/) instance for static analysis and prints the URL of the
+//! report. Each APK of a split APK is submitted, and reported on, separately. The API key is read from
+//! `MOBSF_API_KEY`, or `api_key` in the `[mobsf]` section of the config file:
+//!
+//! ```shell
+//! apkeep -c apps.csv -d f-droid --mobsf http://localhost:8000 .
+//! ```
+//!
+//! So that home-automation and fleet-management setups can react to new releases, `--mqtt`
+//! publishes a JSON `download` event for each downloaded app to `<prefix>/<app_id>` on an MQTT
+//! broker. The prefix is taken from the URL's path and defaults to `apkeep`. Use `mqtts://` for TLS;
+//! credentials can be given in the URL or as `username` and `password` in the `[mqtt]` section of the
+//! config file:
+//!
+//! ```shell
+//! apkeep -c apps.csv -d f-droid --mqtt mqtt://broker.local/apkeep .
+//! ```
+//!
+//! Rather than keeping passwords and tokens in the config file, `apkeep auth login` prompts for the
+//! credentials of a service (`google-play`, `s3`, `webdav`, `sftp`, `mobsf` or `mqtt`) and stores
+//! them in the OS keyring: the macOS Keychain, Windows Credential Manager, or the Secret Service on
+//! Linux. Tokens, keys and passwords aren't shown as they are typed. The keyring is consulted for any
+//! setting missing from the config file. `apkeep auth logout` removes them again:
+//!
+//! ```shell
+//! apkeep auth login google-play
+//! apkeep -a com.instagram.android -d google-play .
+//! ```
+//!
+//! The messages printed while apps are downloaded are translated into Russian and Simplified
+//! Chinese. The language is taken from the system locale, or can be chosen with `--lang`; messages
+//! not yet translated are printed in English. Translations live in `locales/` as
+//! [Fluent](https://projectfluent.org/) files, and new languages are welcome:
+//!
+//! ```shell
+//! apkeep --lang ru -a com.instagram.android -d apk-pure .
+//! ```
+//!
 //! All the above examples can also be used in Docker with minimal changes. For example, to
 //! download a single APK to your chosen output directory:
 //!
@@ -96,6 +650,24 @@
 //! use the --field option as well.  If you have a simple file with one app ID per line, you can
 //! just treat it as a CSV with a single field.
 //!
+//! To snapshot the apps on a phone and download them all later, `apkeep import-device` writes the
+//! third-party apps installed on a device connected with `adb` to such a file:
+//!
+//! ```shell
+//! apkeep import-device --device emulator-5554 apps.csv
+//! apkeep -c apps.csv .
+//! ```
+//!
+//! Apps tracked on-device with [Obtainium](https://github.com/ImranR98/Obtainium) can be moved over
+//! with `apkeep import-obtainium`, which reads an Obtainium export and writes one list per download
+//! source into a directory, printing the command to download each. Apps tracked from F-Droid,
+//! IzzyOnDroid, Google Play, APKPure and Huawei AppGallery are imported; those tracked from other
+//! sources, such as GitHub releases, are listed so they can be handled separately:
+//!
+//! ```shell
+//! apkeep import-obtainium obtainium-export.json ./lists
+//! ```
+//!
 //! # Download Sources
 //!
 //! You can use this tool to download from a few distinct sources.
@@ -106,12 +678,112 @@
 //! verifies that these APKs are signed by the F-Droid maintainers, and alerts the user if an APK
 //! was downloaded but could not be verified
 //! * The Huawei AppGallery (`-d huawei-app-gallery`), an app store popular in China
+//! * APKCombo (`-d apk-combo`), a third-party site found by searching. The app pages found are cached
+//! under the user's cache directory (e.g. `~/.cache/apkeep`) for an hour, or as long as
+//! `--cache-ttl` seconds; use `--cache-ttl 0` to disable the cache
+//! * WebAssembly plugins (`-d plugin --plugin NAME`), for sources maintained outside apkeep
+//! * Sites described by a rules file (`-d scraped --rules NAME`), scraped with CSS selectors
+//!
+//! What apkeep can fetch again, such as the F-Droid package index and the cached pages, is kept in
+//! `$XDG_CACHE_HOME/apkeep` (e.g. `~/.cache/apkeep`). In a container, `--state-dir` keeps it in the
+//! `cache` directory of another directory instead, which can be a volume:
+//!
+//! ```shell
+//! apkeep -c apps.csv -d f-droid --state-dir /data/apkeep /data/apks
+//! ```
 //!
 //! # Usage Note
 //!
 //! Users should not use app lists or choose so many parallel APK fetches as to place unreasonable
 //! or disproportionately large load on the infrastructure of the app distributor.
 //!
+//! To keep a large parallel batch from opening too many connections to one mirror, which is a quick
+//! way to get an IP address banned, `--max-connections-per-host` limits how many requests are made to
+//! any one host at a time, further requests waiting their turn, and how many idle connections are kept
+//! open to it. `--keep-alive` sets how long idle connections are kept open for reuse. Host names are
+//! only looked up once per run.
+//!
+//! `--sleep-duration` spaces out the apps fetched from a source, but says nothing of how often each
+//! host is asked. `--host-interval` sets the least time in milliseconds between requests to any one
+//! host, for every host (`500`) or for particular ones (`f-droid.org=2000`), so that a run can be gentle
+//! with a public mirror while fetching freely from a local one:
+//!
+//! ```shell
+//! apkeep -c apps.csv -d f-droid --host-interval 1000,fdroid.lan=0 .
+//! ```
+//!
+//! Where a site's policies call for it, `--respect-robots` has apkeep honour the `robots.txt` of
+//! the sites it scrapes: APKCombo, sites described by `--rules` and plugins' pages. A page which
+//! the group for `apkeep`, or else the one for every crawler, disallows is not requested and its
+//! app is skipped, and a `Crawl-delay`, of up to ten minutes, is waited out between requests to the
+//! site. A site whose `robots.txt` can't be fetched because of a server or network error is not
+//! scraped at all.
+//!
+//! On a small server, `--max-buffered-mb` caps how much downloaded data is held in memory waiting to
+//! be written to disk, across all parallel downloads, so that a fast link with a high `--parallel`
+//! can't exhaust memory.
+//!
+//! `--skip-larger-than` skips apps whose download is larger than the given size, such as `2G` or
+//! `500M` (in powers of 1024), so that mirroring a whole category doesn't pull in multi-gigabyte games.
+//! F-Droid's size is taken from its index, before anything is requested; for other sources it is the
+//! size the server announces when the download starts, and an app whose size isn't announced is
+//! downloaded anyway. Google Play doesn't give its sizes, so its apps are never skipped.
+//!
+//! When a source starts failing requests or rate-limiting them (HTTP 429), apkeep halves the number
+//! of apps fetched from it at a time and doubles the delay between requests, then ramps back up to
+//! `--parallel` and `--sleep-duration` as requests succeed again, so that a big batch slows down
+//! rather than collapsing into a wall of failures.
+//!
+//! Failed requests are tried again, up to twice, when they fail in a way which usually succeeds on
+//! another attempt: by default rate limiting (HTTP 429), server errors (5xx), timeouts and connections
+//! reset partway through (`connreset`). `--retry-on` chooses which, as a comma-separated list of
+//! statuses such as `503`, classes such as `5xx`, `timeout`, `connreset`, `connect` for connections
+//! which could not be made at all, or `none`; anything else, such as a 404, fails at once. A
+//! `Retry-After` header of up to a minute is waited out before trying again.
+//!
+//! A source which is blocked, down or has changed its pages fails every app, and waiting on each can
+//! take hours in a big batch. With `--circuit-breaker N`, a source on which N apps in a row fail is
+//! reported once and skipped for the rest of the run; with `--race`, the other sources carry on
+//! without it.
+//!
+//! So that one stalled connection can't hang a nightly sync, `--per-app-timeout SECONDS` limits the
+//! time spent finding and downloading each app. An app which runs out of time is cancelled, the files
+//! it was writing are deleted, and it is reported as failed while the rest of the run carries on.
+//!
+//! To see whether a slow run is spent scraping pages or transferring files, `--timings` reports the
+//! seconds spent searching, resolving download URLs, downloading, verifying and writing for each app,
+//! and in total once the run is done. Work which serves every app at once, such as fetching the F-Droid
+//! index, is shown as `(shared)`.
+//!
+//! Programs wrapping apkeep, such as a GUI, can follow a run with `--progress json`, which writes a
+//! line of JSON to stderr for each event in place of the progress bars: `file_started`,
+//! `file_progress` (each whole percent) and `file_finished` as files are transferred, `app_failed` with
+//! the reason, `app_downloaded` with the files written, and `finished` at the end of the run. Each
+//! event has its name in `event` and the time it happened in `time`.
+//!
+//! When a source stops working, `--trace-http` logs each request apkeep makes and the status, URL and
+//! headers of the response to stderr, so a bug report can show what the source actually sent back. With
+//! `--trace-http-bodies DIR`, the bodies of HTML, JSON and other text responses are saved to numbered
+//! files in `DIR` as well; APKs are not. The values of `Authorization`, `Cookie` and similar headers, the
+//! passwords of URLs and query parameters such as `token` and `signature` are redacted, so the log can
+//! be attached as it is:
+//!
+//! ```shell
+//! apkeep -a com.example -d scraped --rules mysite --trace-http --trace-http-bodies trace . 2> trace.log
+//! ```
+//!
+//! To test changes to a scraper without depending on the site, apkeep can be built with the `fixtures`
+//! feature, which adds `--record-http DIR` to record each exchange with the site into `DIR`, and
+//! `--replay-http DIR` to answer the same requests from the recording later, offline. A request which
+//! wasn't recorded is answered with a 404. Downloads which F-Droid, APKPure and the Huawei AppGallery
+//! stream to disk themselves are neither recorded nor replayed:
+//!
+//! ```shell
+//! cargo build --release --features fixtures
+//! apkeep -a com.example -d scraped --rules mysite --record-http fixtures/mysite .
+//! apkeep -a com.example -d scraped --rules mysite --replay-http fixtures/mysite .
+//! ```
+//!
 //! When using with the Google Play Store as the download source, a few considerations should be
 //! made:
 //!
@@ -121,18 +793,57 @@
 //! * Paid and DRM apps will not be available.
 //! * Using Tor will make it a lot more likely that the download will fail.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::error::Error;
 use std::fs::{self, File};
 use std::io::{self, Write, Read};
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
+use clap::parser::ValueSource;
+use clap::ValueEnum;
 use configparser::ini::Ini;
+use reqwest::header::HeaderMap;
+use serde_json::json;
+
+#[macro_use]
+mod i18n;
+
+mod adb;
+mod aliases;
+mod apk;
+mod audit;
+mod cache;
+mod check;
 
 mod cli;
-use cli::DownloadSource;
+use cli::{Channel, DownloadSource, FormFactor};
+use hooks::Hook;
 
 mod config;
+mod credentials;
+mod device_config;
+mod doctor;
+mod feed;
+mod filter;
+mod gpg;
+mod hooks;
+mod import;
+mod info;
+mod integrity;
+mod metadata;
+mod mobsf;
+mod mqtt;
+mod patterns;
+mod pipe;
+mod provenance;
+mod race;
+mod repo;
+mod search;
+mod signers;
+mod storage;
+mod store_url;
+mod upload;
 mod consts;
 mod util;
 
@@ -142,12 +853,34 @@ use download_sources::fdroid;
 use download_sources::apkpure;
 use download_sources::huawei_app_gallery;
 use download_sources::apkcombo;
+use download_sources::plugin;
+use download_sources::scraped;
+use download_sources::DownloadedApp;
+use util::OutputFormat;
+use util::progress_events;
+use util::timings::{self, Phase};
 
 type CSVList = Vec<(String, Option<String>)>;
 fn fetch_csv_list(csv: &str, field: usize, version_field: Option<usize>) -> Result<CSVList, Box<dyn Error>> {
     Ok(parse_csv_text(fs::read_to_string(csv)?, field, version_field))
 }
 
+/// Replace the store URLs in a list of apps with the app IDs, and versions, they are for.
+fn resolve_store_urls(list: CSVList) -> CSVList {
+    list.into_iter().filter_map(|(app, version)| {
+        if !store_url::is_url(&app) {
+            return Some((app, version));
+        }
+        match store_url::parse(&app) {
+            Ok(store_app) => Some((store_app.app_id, version.or(store_app.version))),
+            Err(err) => {
+                println!("{}. Skipping...", err);
+                None
+            },
+        }
+    }).collect()
+}
+
 fn parse_csv_text(text: String, field: usize, version_field: Option<usize>) -> Vec<(String, Option<String>)> {
     let field = field - 1;
     let version_field = version_field.map(|version_field| version_field - 1);
@@ -178,6 +911,422 @@ fn parse_csv_text(text: String, field: usize, version_field: Option<usize>) -> V
         .collect()
 }
 
+fn dir_or_exit(matches: &clap::ArgMatches) -> PathBuf {
+    match fs::canonicalize(matches.get_one::<String>("DIR").unwrap()) {
+        Ok(dir) if dir.is_dir() => dir,
+        _ => {
+            println!("DIR is not a valid directory");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn write_html_index(matches: &clap::ArgMatches) {
+    let dir = dir_or_exit(matches);
+    match repo::html::write_index(&dir) {
+        Ok(num_packages) => println!("Index of {} apps written to {}.", num_packages, dir.join("index.html").display()),
+        Err(err) => {
+            println!("Could not write the index: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn prune(matches: &clap::ArgMatches) {
+    let dir = dir_or_exit(matches);
+    let retention = storage::Retention {
+        keep: matches.get_one::<u64>("keep").map(|keep| *keep as usize),
+        keep_days: matches.get_one::<u64>("keep_days").copied(),
+    };
+    let archive = matches.get_flag("archive");
+    let dry_run = matches.get_flag("dry_run");
+    match storage::prune(&dir, &retention, None, archive, dry_run) {
+        Ok(summary) => println!(
+            "{} {} versions ({} files, {:.1} MiB).",
+            match (dry_run, archive) {
+                (true, false) => "Would remove",
+                (true, true) => "Would archive",
+                (false, false) => "Removed",
+                (false, true) => "Archived",
+            },
+            summary.versions,
+            summary.files,
+            summary.bytes as f64 / (1024.0 * 1024.0),
+        ),
+        Err(err) => {
+            println!("Could not prune {}: {}", dir.display(), err);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn diff_apks(matches: &clap::ArgMatches) {
+    let old = matches.get_one::<String>("OLD").unwrap();
+    let new = matches.get_one::<String>("NEW").unwrap();
+    let diff = match apk::diff::diff(Path::new(old), Path::new(new)) {
+        Ok(diff) => diff,
+        Err(err) => {
+            println!("Could not compare {} and {}: {}", old, new, err);
+            std::process::exit(1);
+        }
+    };
+    let (old_size, new_size) = diff.sizes;
+    println!("Size: {} -> {} bytes ({:+})", old_size, new_size, new_size as i64 - old_size as i64);
+    if !diff.added.is_empty() {
+        println!("\nAdded ({}):", diff.added.len());
+        for (name, size) in &diff.added {
+            println!("  + {} ({} bytes)", name, size);
+        }
+    }
+    if !diff.removed.is_empty() {
+        println!("\nRemoved ({}):", diff.removed.len());
+        for (name, size) in &diff.removed {
+            println!("  - {} ({} bytes)", name, size);
+        }
+    }
+    if !diff.changed.is_empty() {
+        println!("\nChanged ({}):", diff.changed.len());
+        for (name, old_size, new_size) in &diff.changed {
+            println!("  ~ {} ({} -> {} bytes, {:+})", name, old_size, new_size, *new_size as i64 - *old_size as i64);
+        }
+    }
+    let lists = [
+        ("Permissions added", diff.permissions_added.iter().map(String::as_str).collect::<Vec<_>>()),
+        ("Permissions removed", diff.permissions_removed.iter().map(String::as_str).collect()),
+        ("Trackers added", diff.trackers_added),
+        ("Trackers removed", diff.trackers_removed),
+    ];
+    for (heading, list) in lists {
+        if !list.is_empty() {
+            println!("\n{} ({}):", heading, list.len());
+            for item in list {
+                println!("  {}", item);
+            }
+        }
+    }
+    if !diff.permissions_compared {
+        println!("\nPermissions were not compared, as the manifest of one of the APKs could not be read.");
+    }
+}
+
+fn verify_archive(matches: &clap::ArgMatches) {
+    let dir = Path::new(matches.get_one::<String>("DIR").unwrap());
+    match integrity::verify(dir) {
+        Ok((checked, problems)) if problems.is_empty() => println!("Checked {} files. No problems were found.", checked),
+        Ok((checked, problems)) => {
+            for problem in &problems {
+                println!("{}", problem);
+            }
+            println!("Checked {} files. {} problems were found.", checked, problems.len());
+            std::process::exit(1);
+        },
+        Err(err) => {
+            println!("Could not verify {}: {}", dir.display(), err);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn list_sources() {
+    let yes_no = |value: bool| if value { "yes" } else { "no" };
+    println!("{:<20}{:<10}{:<7}{:<6}{:<7}{:<6}{:<7}{:<10}{:<7}{:<16}{:<6}{:<11}{}", "SOURCE", "VERSIONS", "CODES", "LIST", "CHECK", "INFO", "DATES", "CHANNELS", "FORMS", "SPLITS", "AUTH", "THROTTLED", "OPTIONS");
+    for source in DownloadSource::value_variants() {
+        let capabilities = source.capabilities();
+        println!(
+            "{:<20}{:<10}{:<7}{:<6}{:<7}{:<6}{:<7}{:<10}{:<7}{:<16}{:<6}{:<11}{}",
+            source.to_string(),
+            yes_no(capabilities.specific_versions),
+            yes_no(capabilities.version_codes),
+            yes_no(capabilities.list_versions),
+            yes_no(capabilities.check_updates),
+            yes_no(capabilities.app_info),
+            yes_no(capabilities.release_dates),
+            yes_no(capabilities.release_channels),
+            yes_no(capabilities.form_factors),
+            capabilities.splits,
+            yes_no(capabilities.requires_auth),
+            yes_no(capabilities.adaptive_throttling),
+            capabilities.options.join(", "),
+        );
+    }
+    println!("\nVERSIONS: app_id@version can be downloaded; CODES: app_id@code:versionCode can be downloaded; LIST: --list-versions lists the versions available; CHECK: --check-only finds updates without downloading them; INFO: --info shows what the source says about an app; DATES: --released-after and --released-before choose versions by when they were released; CHANNELS: --channel chooses between stable and beta releases; FORMS: --form-factor chooses between builds for phones, TVs, watches and cars; THROTTLED: requests slow down when the source starts refusing them.");
+}
+
+async fn update_patterns() {
+    match patterns::update().await {
+        Ok((before, after)) if after > before => println!("Updated the scraping patterns from version {} to version {}.", before, after),
+        Ok((current, _)) => println!("The scraping patterns are up to date, at version {}.", current),
+        Err(err) => {
+            println!("Could not update the scraping patterns: {}. Exiting.", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn check_audit_log(matches: &clap::ArgMatches) {
+    let file = matches.get_one::<String>("FILE").unwrap();
+    match audit::verify(Path::new(file)) {
+        Ok((entries, last_hash)) => println!("{} is intact: {} entries, ending with {}.", file, entries, last_hash),
+        Err(err) => {
+            println!("The audit log is not intact: {}.", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn auth(matches: &clap::ArgMatches) {
+    let (action, matches) = matches.subcommand().unwrap();
+    if action == "google" {
+        auth_google(matches).await;
+        return;
+    }
+    let service = credentials::Service::find(matches.get_one::<String>("SERVICE").unwrap()).unwrap();
+    let result = match action {
+        "login" => service.login().map(|stored| format!("{} settings for {} stored in the keyring.", stored, service.name)),
+        _ => service.logout().map(|removed| format!("{} settings for {} removed from the keyring.", removed, service.name)),
+    };
+    match result {
+        Ok(message) => println!("{}", message),
+        Err(err) => {
+            println!("Could not access the keyring: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Move a download which failed verification into the quarantine directory, or delete it if it
+/// can't be moved, so that it isn't left among the good downloads.
+fn quarantine(outpath: &Path, app: &DownloadedApp, reason: &str) {
+    download_sources::failed(&app.app_id, reason.to_string());
+    match storage::quarantine(outpath, app, reason) {
+        Ok(path) => println!("{} Quarantined as {}.", reason, path.display()),
+        Err(err) => {
+            println!("{} Could not quarantine it ({}), so removing it...", reason, err);
+            if let Err(err) = app.remove() {
+                println!("Could not remove the download for {}: {}", app.app_id, err);
+            }
+        },
+    }
+}
+
+fn prompt(prompt: &str) -> String {
+    let mut value = String::new();
+    print!("{}: ", prompt);
+    io::stdout().flush().unwrap();
+    io::stdin().read_line(&mut value).unwrap();
+    value.trim().to_string()
+}
+
+fn prompt_secret(prompt: &str) -> String {
+    credentials::read_secret(prompt).unwrap().trim().to_string()
+}
+
+/// Walk through exchanging an OAuth token from the Google embedded setup page for an AAS token,
+/// and keep the AAS token in the keyring for the Google Play source to use.
+async fn auth_google(matches: &clap::ArgMatches) {
+    let email = matches.get_one::<String>("email").map(|v| v.to_string())
+        .or_else(|| credentials::get(load_config(None).ok().as_ref(), "google", "email"))
+        .unwrap_or_else(|| prompt("Email"));
+    let oauth_token = match matches.get_one::<String>("oauth_token") {
+        Some(oauth_token) => oauth_token.to_string(),
+        None => {
+            println!("To obtain an OAuth token:\n\n\
+                1. Open https://accounts.google.com/EmbeddedSetup in a browser, with the developer tools open on the Network tab\n\
+                2. Log in as {}, and agree to the Terms of Service if asked (the page may then hang, which is fine)\n\
+                3. Select the last request to accounts.google.com and open its Cookies tab\n\
+                4. Copy the value of the oauth_token cookie, which starts with oauth2_4/\n", email);
+            prompt_secret("OAuth token")
+        }
+    };
+    let aas_token = match google_play::authenticate(&email, &oauth_token, matches.get_flag("accept_tos")).await {
+        Ok(aas_token) => aas_token,
+        Err(err) => {
+            println!("{}", err);
+            std::process::exit(1);
+        }
+    };
+    // Where there is no keyring, the token may be kept in a file only the user can read instead
+    if let Some(token_file) = matches.get_one::<String>("token_file") {
+        match write_private_file(Path::new(token_file), &aas_token) {
+            Ok(()) => println!("Logged in to Google Play as {}. The AAS token is written to {}.", email, token_file),
+            Err(err) => {
+                println!("Logged in to Google Play as {}, but could not write the AAS token to {}: {}", email, token_file, err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    match credentials::set("google", "email", &email).and_then(|_| credentials::set("google", "aas_token", &aas_token)) {
+        Ok(()) => println!("Logged in to Google Play as {}. The AAS token is stored in the keyring, so `-e` and `-t` can now be left out.", email),
+        Err(err) => {
+            println!("Logged in to Google Play as {}, but could not access the keyring: {}\nRun this again with --token-file to write the AAS token to a file instead.", email, err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Write `contents` to a new file which only the user can read.
+fn write_private_file(path: &Path, contents: &str) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    writeln!(options.open(path)?, "{}", contents)
+}
+
+async fn import_device(matches: &clap::ArgMatches) {
+    let device = matches.get_one::<String>("device").map(|v| v.as_str());
+    let file = matches.get_one::<String>("FILE").unwrap();
+    let packages = match adb::third_party_packages(device).await {
+        Ok(packages) => packages,
+        Err(err) => {
+            println!("Could not list the apps installed on {}: {}", device.unwrap_or("the device"), err);
+            std::process::exit(1);
+        }
+    };
+    let contents = packages.iter().map(|package| format!("{}\n", package)).collect::<String>();
+    if let Err(err) = fs::write(file, contents) {
+        println!("Could not write {}: {}", file, err);
+        std::process::exit(1);
+    }
+    println!("{} apps written to {}. Download them with `apkeep -c {} OUTPATH`.", packages.len(), file, file);
+}
+
+async fn export_device_config(matches: &clap::ArgMatches) {
+    let device = matches.get_one::<String>("device").map(|v| v.as_str());
+    let file = matches.get_one::<String>("FILE").unwrap();
+    let config = match adb::device_config(device).await {
+        Ok(config) => config,
+        Err(err) => {
+            println!("Could not read the configuration of {}: {}", device.unwrap_or("the device"), err);
+            std::process::exit(1);
+        }
+    };
+    if let Err(err) = config.save(Path::new(file)) {
+        println!("Could not write {}: {}", file, err);
+        std::process::exit(1);
+    }
+    println!("Configuration of {} written to {}. Use it with `--device-config {}`.", device.unwrap_or("the device"), file, file);
+}
+
+fn import_obtainium(matches: &clap::ArgMatches) {
+    let export = matches.get_one::<String>("FILE").unwrap();
+    let dir = dir_or_exit(matches);
+    let import = match import::obtainium(Path::new(export), &dir) {
+        Ok(import) => import,
+        Err(err) => {
+            println!("Could not import {}: {}", export, err);
+            std::process::exit(1);
+        }
+    };
+    for list in import.lists {
+        println!("{} apps written to {}. Download them with `apkeep -c {} {} OUTPATH`.", list.num_apps, list.path.display(), list.path.display(), list.arguments);
+    }
+    for (app_id, url) in import.unsupported {
+        println!("{} is tracked from {}, which apkeep cannot download from. Skipping...", app_id, url);
+    }
+}
+
+fn build_repo(matches: &clap::ArgMatches) {
+    let dir = dir_or_exit(matches);
+    let settings = repo::RepoSettings {
+        name: matches.get_one::<String>("name").unwrap().to_string(),
+        description: matches.get_one::<String>("description").map(|v| v.to_string()),
+        address: matches.get_one::<String>("address").map(|v| v.to_string()),
+    };
+
+    let key = match (matches.get_one::<String>("key"), matches.get_one::<String>("cert")) {
+        (Some(key_path), Some(cert_path)) => repo::RepoKey::load(Path::new(key_path), Path::new(cert_path)),
+        _ => match config::config_dir() {
+            Ok(config_dir) => {
+                let key_path = config_dir.join("repo-key.pem");
+                let cert_path = config_dir.join("repo-cert.pem");
+                if key_path.exists() {
+                    repo::RepoKey::load(&key_path, &cert_path)
+                } else {
+                    println!("Generating a new repository signing key in {}...", config_dir.display());
+                    repo::RepoKey::generate(&key_path, &cert_path, &settings.name)
+                }
+            },
+            Err(_) => {
+                println!("Could not find the config directory to store the repository signing key in. Use --key and --cert instead.");
+                std::process::exit(1);
+            }
+        },
+    };
+    let key = match key {
+        Ok(key) => key,
+        Err(err) => {
+            println!("Could not load the repository signing key: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    match repo::build(&dir, &settings, &key) {
+        Ok(num_packages) => {
+            println!("Repository index for {} apps written to {}.", num_packages, dir.display());
+            if let Ok(fingerprint) = key.fingerprint() {
+                println!("Repository fingerprint: {}", fingerprint);
+            }
+        },
+        Err(err) => {
+            println!("Could not build the repository: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// The Google accounts to rotate among besides the main one, each in a `[google.<name>]` section
+/// of the config file, whose settings may also be kept in the keyring under that section
+fn more_google_accounts(conf: Option<&Ini>) -> Vec<google_play::Account> {
+    let sections = conf.map(|conf| conf.sections()).unwrap_or_default();
+    sections.iter()
+        .filter(|section| section.starts_with("google."))
+        .filter_map(|section| {
+            let email = credentials::get(conf, section, "email");
+            let aas_token = credentials::get(conf, section, "aas_token");
+            match (email, aas_token) {
+                (Some(email), Some(aas_token)) => Some(google_play::Account { email, aas_token }),
+                _ => {
+                    println!("The [{}] section of the config file needs both an email and an aas_token. Skipping...", section);
+                    None
+                },
+            }
+        })
+        .collect()
+}
+
+/// The extra headers for each source: those in its `[headers.<download_source>]` section of the
+/// config file, and those given with `--header`, which take precedence
+fn source_headers(matches: &clap::ArgMatches, conf: Option<&Ini>) -> Result<BTreeMap<String, HeaderMap>, Box<dyn Error>> {
+    let mut given = HeaderMap::new();
+    for header in matches.get_many::<String>("header").unwrap_or_default() {
+        let (name, value) = util::http::parse_header(header)?;
+        given.append(name, value);
+    }
+    let mut headers = BTreeMap::new();
+    for source in DownloadSource::value_variants() {
+        let mut source_headers = HeaderMap::new();
+        if let Some(section) = conf.and_then(|conf| conf.get_map_ref().get(&format!("headers.{}", source))) {
+            for (name, value) in section {
+                let (name, value) = util::http::parse_header(&format!("{}: {}", name, value.as_deref().unwrap_or_default()))?;
+                source_headers.insert(name, value);
+            }
+        }
+        for name in given.keys() {
+            source_headers.remove(name);
+        }
+        source_headers.extend(given.clone());
+        if !source_headers.is_empty() {
+            headers.insert(source.to_string(), source_headers);
+        }
+    }
+    Ok(headers)
+}
+
 fn load_config(ini_file: Option<PathBuf>) -> Result<Ini, Box<dyn Error>> {
     let mut conf = Ini::new();
     let config_path = match ini_file {
@@ -201,8 +1350,134 @@ async fn main() {
         cli::app().render_usage()
     };
     let matches = cli::app().get_matches();
+    i18n::init(matches.get_one::<String>("lang").map(String::as_str));
+    if let Some(state_dir) = matches.get_one::<String>("state_dir") {
+        config::set_state_dir(Path::new(state_dir));
+    }
+    if let Some(true) = matches.get_one::<bool>("trace_http") {
+        util::trace::enable(matches.get_one::<String>("trace_http_bodies").map(Path::new));
+    }
+    if let Some(true) = matches.get_one::<bool>("respect_robots") {
+        util::robots::enable();
+    }
+    if let Some(skip_larger_than) = matches.get_one::<String>("skip_larger_than") {
+        match util::parse_size(skip_larger_than) {
+            Ok(max_size) => download_sources::skip_larger_than(max_size),
+            Err(err) => {
+                println!("{}\n\nInvalid --skip-larger-than: {}", usage, err);
+                std::process::exit(1);
+            }
+        }
+    }
+    #[cfg(feature = "fixtures")]
+    if let Some(record_http) = matches.get_one::<String>("record_http") {
+        util::fixtures::record(Path::new(record_http));
+    }
+    #[cfg(feature = "fixtures")]
+    if let Some(replay_http) = matches.get_one::<String>("replay_http") {
+        util::fixtures::replay(Path::new(replay_http));
+    }
+
+    match matches.subcommand() {
+        Some(("repo", repo_matches)) => {
+            if let Some(("build", build_matches)) = repo_matches.subcommand() {
+                build_repo(build_matches);
+            }
+            return;
+        },
+        Some(("index", index_matches)) => {
+            write_html_index(index_matches);
+            return;
+        },
+        Some(("import-device", import_matches)) => {
+            import_device(import_matches).await;
+            return;
+        },
+        Some(("export-device-config", export_matches)) => {
+            export_device_config(export_matches).await;
+            return;
+        },
+        Some(("import-obtainium", import_matches)) => {
+            import_obtainium(import_matches);
+            return;
+        },
+        Some(("prune", prune_matches)) => {
+            prune(prune_matches);
+            return;
+        },
+        Some(("diff", diff_matches)) => {
+            diff_apks(diff_matches);
+            return;
+        },
+        Some(("verify", verify_matches)) => {
+            verify_archive(verify_matches);
+            return;
+        },
+        Some(("list-sources", _)) => {
+            list_sources();
+            return;
+        },
+        Some(("update-patterns", _)) => {
+            update_patterns().await;
+            return;
+        },
+        Some(("doctor", doctor_matches)) => {
+            doctor::run(doctor_matches).await;
+            return;
+        },
+        Some(("audit", audit_matches)) => {
+            check_audit_log(audit_matches);
+            return;
+        },
+        Some(("auth", auth_matches)) => {
+            auth(auth_matches).await;
+            return;
+        },
+        _ => {},
+    }
+
+    let ini_file = matches.get_one::<String>("ini").map(|ini_file| {
+        match fs::canonicalize(ini_file) {
+            Ok(ini_file) if Path::new(&ini_file).is_file() => {
+                ini_file
+            },
+            _ => {
+                println!("{}\n\nSpecified ini is not a valid file", usage);
+                std::process::exit(1);
+            },
+        }
+    });
+    let mut conf = load_config(ini_file).ok();
+    let profile = match matches.get_one::<String>("profile") {
+        Some(profile) => match conf.as_mut().map(|conf| config::apply_profile(conf, profile)) {
+            Some(Ok(profile)) => profile,
+            Some(Err(err)) => {
+                println!("{}. Exiting.", err);
+                std::process::exit(1);
+            },
+            None => {
+                println!("The profile {} was asked for, but the config file could not be read. Exiting.", profile);
+                std::process::exit(1);
+            },
+        },
+        None => HashMap::new(),
+    };
 
     let mut download_source = *matches.get_one::<DownloadSource>("download_source").unwrap();
+    if matches.value_source("download_source") == Some(ValueSource::DefaultValue) {
+        if let Some(profile_source) = profile.get("download_source") {
+            download_source = match DownloadSource::from_str(profile_source, true) {
+                Ok(profile_source) => profile_source,
+                Err(_) => {
+                    println!("{}\n\nThe profile's download_source {} is not a download source", usage, profile_source);
+                    std::process::exit(1);
+                },
+            };
+        } else if let Some(Ok(store_app)) = matches.get_one::<String>("app").filter(|app| store_url::is_url(app)).map(|app| store_url::parse(app)) {
+            // An app given as a store URL is downloaded from that store
+            download_source = store_app.source;
+        }
+    }
     let options: HashMap<&str, &str> = match matches.get_one::<String>("options") {
         Some(options) => {
             let mut options_map = HashMap::new();
@@ -223,8 +1498,109 @@ async fn main() {
     if oauth_token.is_some() {
         download_source = DownloadSource::GooglePlay;
     }
-    let list: Vec<(String, Option<String>)> = if oauth_token.is_none() {
-        match matches.get_one::<String>("app") {
+    // Options may also be kept in the `[sources.<download_source>]` section of the config file, with
+    // those given on the command line taking precedence
+    let mut options = options;
+    if let Some(output_format) = matches.get_one::<String>("output_format") {
+        options.insert("output_format", output_format.as_str());
+    }
+    if let Some(section) = conf.as_ref().and_then(|conf| conf.get_map_ref().get(&format!("sources.{}", download_source))) {
+        for (key, value) in section {
+            if let Some(value) = value {
+                options.entry(key.as_str()).or_insert(value.as_str());
+            }
+        }
+    }
+    let mirror = if let Some(true) = matches.get_one::<bool>("mirror_repo") {
+        Some(fdroid::Mirror::Repo)
+    } else if let Some(developer) = matches.get_one::<String>("developer") {
+        Some(fdroid::Mirror::Developer(developer.to_string()))
+    } else {
+        matches.get_one::<String>("category").map(|category| fdroid::Mirror::Category(category.to_string()))
+    };
+    match (&mirror, download_source) {
+        (None, _) | (Some(_), DownloadSource::FDroid) => {},
+        (Some(fdroid::Mirror::Category(_)), DownloadSource::Scraped) | (Some(fdroid::Mirror::Developer(_)), DownloadSource::Scraped) => {},
+        (Some(fdroid::Mirror::Category(_)), _) => {
+            println!("{}\n\n--category can only be used with F-Droid or a scraped site", usage);
+            std::process::exit(1);
+        },
+        (Some(fdroid::Mirror::Developer(_)), _) => {
+            println!("{}\n\n--developer can only be used with F-Droid or a scraped site", usage);
+            std::process::exit(1);
+        },
+        (Some(fdroid::Mirror::Repo), _) => {
+            println!("{}\n\n--mirror-repo can only be used with F-Droid", usage);
+            std::process::exit(1);
+        },
+    }
+    let plugin = match matches.get_one::<String>("plugin") {
+        Some(plugin) if download_source == DownloadSource::Plugin => match plugin::Plugin::load(plugin) {
+            Ok(plugin) => Some(plugin),
+            Err(err) => {
+                println!("Could not load the plugin {}: {}. Exiting.", plugin, err);
+                std::process::exit(1);
+            }
+        },
+        _ => None,
+    };
+    let site = match matches.get_one::<String>("rules") {
+        Some(rules) if download_source == DownloadSource::Scraped => match scraped::Site::load(rules) {
+            Ok(site) => Some(site),
+            Err(err) => {
+                println!("Could not load the rules {}: {}. Exiting.", rules, err);
+                std::process::exit(1);
+            }
+        },
+        _ => None,
+    };
+    // clap only requires --rules or --plugin when the source is given with -d, not by a profile
+    match (download_source, &site, &plugin) {
+        (DownloadSource::Scraped, None, _) => {
+            println!("{}\n\n--rules is required when the download source is scraped", usage);
+            std::process::exit(1);
+        },
+        (DownloadSource::Plugin, _, None) => {
+            println!("{}\n\n--plugin is required when the download source is plugin", usage);
+            std::process::exit(1);
+        },
+        _ => {},
+    }
+    let race = matches.get_many::<DownloadSource>("race").map(|sources| sources.copied().collect::<Vec<_>>());
+    if let Some(sources) = &race {
+        if let Some(source) = sources.iter().find(|source| !race::RACEABLE.contains(source)) {
+            println!("{}\n\n{} can not be raced, as it needs credentials of its own", usage, source);
+            std::process::exit(1);
+        }
+        if mirror.is_some() || oauth_token.is_some() {
+            println!("{}\n\n--race can only be used to download apps", usage);
+            std::process::exit(1);
+        }
+    }
+    // A scraped site's categories and developers are listed here, and their apps downloaded like
+    // any others
+    let listed = match (&mirror, &site) {
+        (Some(fdroid::Mirror::Category(category)), Some(site)) =>
+            Some((site.category(category).await, format!("in the {} category of {}", category, site.name))),
+        (Some(fdroid::Mirror::Developer(developer)), Some(site)) =>
+            Some((site.developer(developer).await, format!("by {} on {}", developer, site.name))),
+        _ => None,
+    };
+    let list: Vec<(String, Option<String>)> = if let Some((app_ids, description)) = listed {
+        match app_ids {
+            Ok(app_ids) => {
+                println!("Downloading the {} apps {}...", app_ids.len(), description);
+                app_ids.into_iter().map(|app_id| (app_id, None)).collect()
+            },
+            Err(err) => {
+                println!("Could not list the apps {}: {}. Exiting.", description, err);
+                std::process::exit(1);
+            },
+        }
+    } else if oauth_token.is_none() && mirror.is_none() {
+        let list = match matches.get_one::<String>("app") {
+            // A URL may contain an @, so isn't split into the app and its version
+            Some(app) if store_url::is_url(app) => vec![(app.to_string(), None)],
             Some(app) => {
                 let mut app_vec: Vec<String> = app.splitn(2, '@').map(String::from).collect();
                 let app_id = app_vec.remove(0);
@@ -260,13 +1636,184 @@ async fn main() {
                     }
                 }
             }
-        }
+        };
+        resolve_store_urls(list)
     } else { Vec::new() };
 
+    // Apps may be given by the names in an aliases file, and those given by any other name rather
+    // than ID are searched for
+    let list = match aliases::Aliases::load(matches.get_one::<String>("aliases").map(Path::new)) {
+        Ok(aliases) => aliases.resolve(list),
+        Err(err) => {
+            println!("{}\n\nCould not load the aliases: {}", usage, err);
+            std::process::exit(1);
+        }
+    };
+    let list = if list.iter().any(|(app_id, _)| !util::is_app_id(app_id)) {
+        let first = matches!(matches.get_one::<bool>("first"), Some(true));
+        search::resolve_names(list, download_source, &options, site.as_ref(), first).await
+    } else {
+        list
+    };
+
+    // Apps may be narrowed down by patterns given on the command line or in the `[filter]` section
+    // of the config file; those of a mirrored repository are filtered as the index is read
+    let filter_patterns = |key: &str| match matches.get_many::<String>(key) {
+        Some(patterns) => patterns.cloned().collect(),
+        None => conf.as_ref().and_then(|conf| conf.get("filter", key))
+            .map_or(vec![], |patterns| patterns.split(',').map(String::from).collect()),
+    };
+    let filter = filter::PackageFilter::new(filter_patterns("include"), filter_patterns("exclude"));
+    let list = if filter.is_empty() {
+        list
+    } else {
+        list.into_iter().filter(|(app_id, _)| {
+            let allowed = filter.allows(app_id, &[]);
+            if !allowed {
+                println!("{} is excluded by --include or --exclude. Skipping...", app_id);
+            }
+            allowed
+        }).collect()
+    };
+
+    // Check the request against what the source can do before any traffic is sent
+    let capabilities = download_source.capabilities();
+    let channel = *matches.get_one::<Channel>("channel").unwrap();
+    if channel != Channel::Stable && !capabilities.release_channels {
+        println!("Warning: {} does not distinguish release channels, so --channel will be ignored.", download_source);
+    }
+    let form_factor = matches.get_one::<FormFactor>("form_factor").copied();
+    if let (Some(form_factor), false) = (form_factor, capabilities.form_factors) {
+        println!("Warning: {} does not publish a build of each app for each form factor, so downloads which are not for a {} will be quarantined.", download_source, form_factor);
+    }
+    let check_only = matches!(matches.get_one::<bool>("check_only"), Some(true));
+    if check_only && !capabilities.check_updates {
+        println!("{}\n\n{} can not find the latest version of an app without downloading it, so --check-only can not be used with it", usage, download_source);
+        std::process::exit(1);
+    }
+    let info = matches!(matches.get_one::<bool>("info"), Some(true));
+    if info && !capabilities.app_info {
+        println!("{}\n\n{} does not say anything about an app before it is downloaded, so --info can not be used with it", usage, download_source);
+        std::process::exit(1);
+    }
+    let all_versions = matches!(matches.get_one::<bool>("all_versions"), Some(true));
+    if all_versions && !capabilities.list_versions {
+        println!("{}\n\n{} can not list the versions of an app, so --all-versions can not be used with it", usage, download_source);
+        std::process::exit(1);
+    }
+    let release_date = |arg: &str| matches.get_one::<String>(arg).map(|date| match download_sources::ReleaseDates::parse_date(date) {
+        Ok(date) => date,
+        Err(err) => {
+            println!("{}\n\nInvalid --{}: {}", usage, arg.replace('_', "-"), err);
+            std::process::exit(1);
+        },
+    });
+    let release_dates = download_sources::ReleaseDates { after: release_date("released_after"), before: release_date("released_before") };
+    if !release_dates.is_empty() && !capabilities.release_dates {
+        println!("{}\n\n{} does not say when versions were released, so --released-after and --released-before can not be used with it", usage, download_source);
+        std::process::exit(1);
+    }
+    if check_only && mirror.is_some() && download_source == DownloadSource::FDroid {
+        println!("{}\n\n--check-only needs a list of apps when used with F-Droid", usage);
+        std::process::exit(1);
+    }
+    for key in options.keys() {
+        // --output-format also applies to searches, which every source has
+        if *key == "output_format" && matches.get_one::<String>("output_format").is_some() {
+            continue;
+        }
+        if !capabilities.options.contains(key) && download_source != DownloadSource::Plugin {
+            println!("Warning: {} does not use the option {}, so it will be ignored.", download_source, key);
+        }
+    }
+    let list = if matches!(matches.get_one::<bool>("list_versions"), Some(true)) {
+        list
+    } else {
+        // A version may be a versionName, or a versionCode given as `code:<versionCode>`
+        let (list, unsupported): (Vec<_>, Vec<_>) = list.into_iter().partition(|(_, version)| match version {
+            Some(version) if download_sources::version_code(version).is_some() => capabilities.version_codes,
+            Some(_) => capabilities.specific_versions,
+            None => true,
+        });
+        for (app_id, version) in unsupported {
+            let version = version.unwrap();
+            let kind = if download_sources::version_code(&version).is_some() { "versionCodes" } else { "versions" };
+            println!("{}", download_sources::failed(&app_id, format!("Specific {} can not be downloaded from {} ({}@{}). Skipping...", kind, download_source, app_id, version)));
+        }
+        list
+    };
+
+    let parallel = matches.get_one::<usize>("parallel").map(|v| *v).unwrap();
+    let sleep_duration = matches.get_one::<u64>("sleep_duration").map(|v| *v).unwrap();
+    let (host_interval, host_intervals) = match matches.get_one::<String>("host_interval").map(|v| util::http::parse_host_intervals(v)) {
+        Some(Ok(intervals)) => intervals,
+        Some(Err(err)) => {
+            println!("{}\n\nInvalid --host-interval: {}", usage, err);
+            std::process::exit(1);
+        },
+        None => (None, Default::default()),
+    };
+    let headers = match source_headers(&matches, conf.as_ref()) {
+        Ok(headers) => headers,
+        Err(err) => {
+            println!("{}\n\nInvalid header: {}", usage, err);
+            std::process::exit(1);
+        },
+    };
+    util::http::configure(util::http::HttpSettings {
+        max_connections_per_host: matches.get_one::<u64>("max_connections_per_host").map(|v| *v as usize),
+        host_interval,
+        host_intervals,
+        keep_alive: matches.get_one::<u64>("keep_alive").map(|v| std::time::Duration::from_secs(*v)),
+        max_buffered_bytes: *matches.get_one::<u64>("max_buffered_mb").unwrap() as usize * 1024 * 1024,
+        allow_insecure: matches!(matches.get_one::<bool>("allow_insecure"), Some(true)),
+        headers,
+    });
+    let file_name_replacement = matches.get_one::<String>("file_name_replacement").unwrap();
+    if !util::valid_replacement(file_name_replacement) {
+        println!("{}\n\n--file-name-replacement can't itself contain characters which can't be used in file names", usage);
+        std::process::exit(1);
+    }
+    util::configure_file_names(util::FileNameSettings {
+        replacement: file_name_replacement.to_string(),
+        max_path: match matches.get_one::<u64>("max_path") {
+            Some(0) => None,
+            Some(max_path) => Some(*max_path as usize),
+            None => util::FileNameSettings::default().max_path,
+        },
+    });
+    if let Err(err) = util::retry::configure(matches.get_one::<String>("retry_on").unwrap()) {
+        println!("{}\n\n--retry-on: {}", usage, err);
+        std::process::exit(1);
+    }
+    if let Some(threshold) = matches.get_one::<usize>("circuit_breaker") {
+        download_sources::breaker::configure(*threshold);
+    }
+    if let Some(timeout) = matches.get_one::<u64>("per_app_timeout") {
+        download_sources::deadline::configure(std::time::Duration::from_secs(*timeout));
+    }
+    if let Some(extra_checksums) = matches.get_one::<String>("extra_checksums") {
+        if let Err(err) = util::digests::configure_extra(extra_checksums) {
+            println!("{}\n\n--extra-checksums: {}", usage, err);
+            std::process::exit(1);
+        }
+    }
+    if let Some(true) = matches.get_one::<bool>("timings") {
+        timings::enable();
+    }
+    if matches.get_one::<String>("progress").is_some_and(|progress| progress == "json") {
+        progress_events::enable();
+    }
+    if let Some(audit_log) = matches.get_one::<String>("audit_log") {
+        if let Err(err) = audit::open(Path::new(audit_log)) {
+            println!("Could not open the audit log {}: {}. Exiting.", audit_log, err);
+            std::process::exit(1);
+        }
+    }
     if let Some(true) = matches.get_one::<bool>("list_versions") {
         match download_source {
             DownloadSource::APKPure => {
-                apkpure::list_versions(list, options).await;
+                apkpure::list_versions(list, parallel, sleep_duration, options).await;
             }
             DownloadSource::GooglePlay => {
                 google_play::list_versions(list);
@@ -280,114 +1827,806 @@ async fn main() {
             DownloadSource::APKCombo => {
                 apkcombo::list_versions(list, options).await;
             }
+            DownloadSource::Plugin => {
+                println!("Plugins can not list versions at this time.");
+            }
+            DownloadSource::Scraped => {
+                scraped::list_versions(list, site.as_ref().unwrap(), &options).await;
+            }
+        }
+    } else if info {
+        let output_format = OutputFormat::from_options(&options);
+        let app_ids = list.into_iter().map(|(app_id, _)| app_id).collect::<Vec<_>>();
+        let infos = match download_source {
+            DownloadSource::FDroid => fdroid::app_info(app_ids, &options).await,
+            DownloadSource::Scraped => scraped::app_info(app_ids, site.as_ref().unwrap()).await,
+            _ => unreachable!("checked against the capabilities of the source"),
+        };
+        let source_name = site.as_ref().map_or_else(|| download_source.to_string(), |site| site.name.clone());
+        info::print(&infos, &source_name, output_format);
+    } else if check_only {
+        let outpath = match matches.get_one::<String>("OUTPATH").or(profile.get("outpath")).map(fs::canonicalize) {
+            Some(Ok(outpath)) if outpath.is_dir() => outpath,
+            Some(_) => {
+                println!("{}\n\nOUTPATH is not a valid directory", usage);
+                std::process::exit(1);
+            },
+            None => {
+                println!("{}\n\nOUTPATH must be specified to compare the latest versions with", usage);
+                std::process::exit(1);
+            },
+        };
+        let hooks = hooks::Hooks {
+            on_new_version: matches.get_one::<String>(Hook::NewVersion.name()).cloned()
+                .or_else(|| conf.as_ref().and_then(|conf| conf.get("hooks", Hook::NewVersion.name()))),
+            ..Default::default()
+        };
+        // Only the latest version is checked for, whatever version is asked for
+        let app_ids = list.into_iter().map(|(app_id, _)| app_id).collect::<Vec<_>>();
+        println!("Checking {} apps on {} for updates...", app_ids.len(), download_source);
+        let latest = match download_source {
+            DownloadSource::APKPure => apkpure::latest_versions(app_ids, parallel, sleep_duration).await,
+            DownloadSource::FDroid => fdroid::latest_versions(app_ids, &options, channel, form_factor).await,
+            DownloadSource::Plugin => plugin::latest_versions(app_ids, &options, plugin.as_ref().unwrap()).await,
+            DownloadSource::Scraped => scraped::latest_versions(app_ids, site.as_ref().unwrap()).await,
+            _ => unreachable!("checked against the capabilities of the source"),
+        };
+        let report_file = matches.get_one::<String>("check_report").map(Path::new);
+        let feed_file = matches.get_one::<String>("feed").map(Path::new);
+        if let Err(err) = check::report(latest, download_source, &outpath, report_file, feed_file, &hooks).await {
+            println!("Could not check for updates in {}: {}. Exiting.", outpath.display(), err);
+            std::process::exit(1);
         }
     } else {
-        let parallel = matches.get_one::<usize>("parallel").map(|v| *v).unwrap();
-        let sleep_duration = matches.get_one::<u64>("sleep_duration").map(|v| *v).unwrap();
-        let outpath = matches.get_one::<String>("OUTPATH").map_or_else(|| {
-            if oauth_token.is_none() {
-                println!("{}\n\nOUTPATH must be specified when downloading files", usage);
+        let temp_dir = matches.get_one::<String>("temp_dir").map(Path::new);
+        if let Some(temp_dir) = temp_dir {
+            if !temp_dir.is_dir() {
+                println!("{}\n\n--temp-dir is not a valid directory", usage);
+                std::process::exit(1);
+            }
+        }
+        // With --archive, the run downloads into a directory beside the archive, or in --temp-dir,
+        // which is written into the archive and removed at the end
+        let archive = matches.get_one::<String>("archive_file").map(|archive_file| {
+            let archive_file = PathBuf::from(archive_file);
+            let format = storage::ArchiveFormat::from_path(&archive_file).unwrap_or_else(|| {
+                println!("{}\n\n--archive must name a .tar.zst, .tar or .zip file", usage);
                 std::process::exit(1);
+            });
+            let parent = archive_file.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+            match tempfile::Builder::new().prefix(".apkeep-").tempdir_in(temp_dir.unwrap_or(parent)) {
+                Ok(staging) => (archive_file, format, staging),
+                Err(err) => {
+                    println!("Could not create a directory beside {} to download into: {}. Exiting.", archive_file.display(), err);
+                    std::process::exit(1);
+                }
+            }
+        });
+        let outpath = match &archive {
+            Some((_, _, staging)) => Some(fs::canonicalize(staging.path()).unwrap_or_else(|_| staging.path().to_path_buf())),
+            None => matches.get_one::<String>("OUTPATH").or(profile.get("outpath")).map_or_else(|| {
+                if oauth_token.is_none() {
+                    println!("{}\n\nOUTPATH must be specified when downloading files", usage);
+                    std::process::exit(1);
+                }
+                None
+            }, |outpath| {
+                match fs::canonicalize(outpath) {
+                    Ok(outpath) if Path::new(&outpath).is_dir() => {
+                        Some(outpath)
+                    },
+                    _ => {
+                        println!("{}\n\nOUTPATH is not a valid directory", usage);
+                        std::process::exit(1);
+                    }
+                }
+            }),
+        };
+
+        let install = matches!(matches.get_one::<bool>("install"), Some(true));
+        let only_newer_than_device = matches!(matches.get_one::<bool>("only_newer_than_device"), Some(true));
+        let only_newer = matches!(matches.get_one::<bool>("only_newer"), Some(true));
+        let devices = if install || only_newer_than_device {
+            let requested_devices = matches.get_many::<String>("device")
+                .map_or(vec![], |devices| devices.cloned().collect());
+            match adb::select_devices(requested_devices).await {
+                Ok(devices) => devices,
+                Err(err) => {
+                    println!("Could not determine which adb devices to use: {}", err);
+                    std::process::exit(1);
+                }
+            }
+        } else {
+            vec![]
+        };
+
+        let upload_destination = matches.get_one::<String>("upload").map(|location| {
+            match upload::Destination::parse(location, conf.as_ref()) {
+                Ok(destination) => destination,
+                Err(err) => {
+                    println!("{}\n\n{}", usage, err);
+                    std::process::exit(1);
+                }
             }
-            None
-        }, |outpath| {
-            match fs::canonicalize(outpath) {
-                Ok(outpath) if Path::new(&outpath).is_dir() => {
-                    Some(outpath)
+        });
+
+        // Old versions of the apps downloaded may be pruned after each run, as set on the command
+        // line or in the `[retention]` section of the config file
+        let retention_setting = |key: &str| match matches.get_one::<u64>(key) {
+            Some(value) => Some(*value),
+            None => match conf.as_ref().map(|conf| conf.getuint("retention", key)) {
+                Some(Ok(value)) => value,
+                Some(Err(err)) => {
+                    println!("{}\n\nInvalid {} in the [retention] section of the ini file: {}", usage, key, err);
+                    std::process::exit(1);
                 },
+                None => None,
+            },
+        };
+        let retention = storage::Retention {
+            keep: retention_setting("keep").map(|keep| keep.max(1) as usize),
+            keep_days: retention_setting("keep_days"),
+        };
+
+        // Hooks may be given on the command line or in the `[hooks]` section of the config file
+        let hook_command = |hook: Hook| matches.get_one::<String>(hook.name()).cloned()
+            .or_else(|| conf.as_ref().and_then(|conf| conf.get("hooks", hook.name())));
+        let hooks = hooks::Hooks {
+            on_success: hook_command(Hook::Success),
+            on_failure: hook_command(Hook::Failure),
+            on_new_version: hook_command(Hook::NewVersion),
+        };
+
+        let file_mode = matches.get_one::<String>("file_mode").map(|mode| {
+            match u32::from_str_radix(mode, 8) {
+                Ok(mode) if mode <= 0o7777 => mode,
                 _ => {
-                    println!("{}\n\nOUTPATH is not a valid directory", usage);
+                    println!("{}\n\n--file-mode must be an octal mode such as 644", usage);
                     std::process::exit(1);
                 }
             }
         });
 
-        match download_source {
-            DownloadSource::APKPure => {
-                apkpure::download_apps(list, parallel, sleep_duration, &outpath.unwrap()).await;
+        let mobsf = matches.get_one::<String>("mobsf").map(|url| {
+            match mobsf::MobSF::new(url, conf.as_ref()) {
+                Ok(mobsf) => mobsf,
+                Err(err) => {
+                    println!("{}\n\n{}", usage, err);
+                    std::process::exit(1);
+                }
             }
-            DownloadSource::GooglePlay => {
-                let mut email = matches.get_one::<String>("google_email").map(|v| v.to_string());
+        });
 
-                if email.is_some() && oauth_token.is_some() {
-                    google_play::request_aas_token(
-                        &email.unwrap(),
-                        &oauth_token.unwrap(),
-                        options,
-                    ).await;
+        let mqtt = matches.get_one::<String>("mqtt").map(|location| {
+            match mqtt::Publisher::parse(location, conf.as_ref()) {
+                Ok(mqtt) => mqtt,
+                Err(err) => {
+                    println!("{}\n\n{}", usage, err);
+                    std::process::exit(1);
+                }
+            }
+        });
+
+        let known_signers = match signers::KnownSigners::load(matches.get_one::<String>("known_signers").map(Path::new)) {
+            Ok(known_signers) => known_signers,
+            Err(err) => {
+                println!("{}\n\nCould not load the known signers: {}", usage, err);
+                std::process::exit(1);
+            }
+        };
+
+        let device_config = matches.get_one::<String>("device_config").map(|file| {
+            match device_config::DeviceConfig::load(Path::new(file)) {
+                Ok(device_config) => device_config,
+                Err(err) => {
+                    println!("{}\n\nCould not load the device configuration {}: {}", usage, file, err);
+                    std::process::exit(1);
+                }
+            }
+        });
+
+        let mut list = list;
+        let mut installed_version_codes: HashMap<String, u64> = HashMap::new();
+        if only_newer_than_device {
+            for device in &devices {
+                match adb::installed_version_codes(device.as_deref()).await {
+                    Ok(version_codes) => {
+                        // With several devices, anything newer than the oldest installed version is wanted
+                        for (package, version_code) in version_codes {
+                            installed_version_codes.entry(package)
+                                .and_modify(|installed| *installed = (*installed).min(version_code))
+                                .or_insert(version_code);
+                        }
+                    },
+                    Err(err) => {
+                        println!("Could not query the apps installed on {}: {}", device.as_deref().unwrap_or("the device"), err);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            list.retain(|(app_id, _)| {
+                if installed_version_codes.contains_key(app_id) {
+                    true
                 } else {
-                    let mut aas_token = matches.get_one::<String>("google_aas_token").map(|v| v.to_string());
-                    let accept_tos = match matches.get_one::<bool>("list_versions") {
-                        Some(true) => true,
-                        _ => false,
-                    };
-
-                    let ini_file = matches.get_one::<String>("ini").map(|ini_file| {
-                        match fs::canonicalize(ini_file) {
-                            Ok(ini_file) if Path::new(&ini_file).is_file() => {
-                                ini_file
-                            },
-                            _ => {
-                                println!("{}\n\nSpecified ini is not a valid file", usage);
-                                std::process::exit(1);
-                            },
+                    println!("{} is not installed on the device. Skipping...", app_id);
+                    false
+                }
+            });
+        }
+        // The newest version of each app already downloaded, and the files of every version, which
+        // a re-download of the same version may have replaced in place
+        let mut archived_version_codes: HashMap<String, u64> = HashMap::new();
+        let mut archived_files: HashSet<PathBuf> = HashSet::new();
+        // Every versionCode of each app already downloaded, which --all-versions leaves out
+        let mut archived: HashSet<(String, u64)> = HashSet::new();
+        let feed_file = matches.get_one::<String>("feed").map(Path::new);
+        if only_newer || all_versions || hooks.on_new_version.is_some() || feed_file.is_some() {
+            match storage::archived_versions(outpath.as_ref().unwrap()) {
+                Ok(packages) => for (package, versions) in packages {
+                    archived.extend(versions.keys().map(|version_code| (package.clone(), *version_code)));
+                    if let Some(version_code) = versions.keys().max() {
+                        archived_version_codes.insert(package, *version_code);
+                    }
+                    archived_files.extend(versions.into_values().flatten());
+                },
+                Err(err) => {
+                    println!("Could not read the versions already in {}: {}. Exiting.", outpath.as_ref().unwrap().display(), err);
+                    std::process::exit(1);
+                }
+            }
+        }
+        if only_newer {
+            // Sources which know the versionCode before downloading skip anything not newer than this
+            for (package, version_code) in &archived_version_codes {
+                installed_version_codes.entry(package.to_string())
+                    .and_modify(|installed| *installed = (*installed).max(*version_code))
+                    .or_insert(*version_code);
+            }
+        }
+
+        if all_versions {
+            let app_ids = list.into_iter().map(|(app_id, _)| app_id).collect::<Vec<_>>();
+            println!("Listing the versions of {} apps on {}...", app_ids.len(), download_source);
+            let versions = match download_source {
+                DownloadSource::APKPure => apkpure::all_versions(app_ids, parallel, sleep_duration).await,
+                DownloadSource::FDroid => fdroid::all_versions(app_ids, &options, release_dates).await,
+                DownloadSource::Scraped => scraped::all_versions(app_ids, site.as_ref().unwrap()).await,
+                _ => unreachable!("checked against the capabilities of the source"),
+            };
+            let found = versions.len();
+            // Versions listed by versionCode can be left out now; those listed by versionName are
+            // skipped once their file is found to be there already
+            list = versions.into_iter()
+                .filter(|(app_id, version)| !version.as_deref()
+                    .and_then(download_sources::version_code)
+                    .is_some_and(|version_code| archived.contains(&(app_id.clone(), version_code))))
+                .collect();
+            match found - list.len() {
+                0 => println!("Found {} versions.", found),
+                present => println!("Found {} versions, {} of which are already in OUTPATH.", found, present),
+            }
+        }
+
+        // With --temp-dir, apps are downloaded into a directory there and moved into OUTPATH once
+        // they are complete
+        let staging = match temp_dir.filter(|_| archive.is_none() && outpath.is_some()) {
+            Some(temp_dir) => match tempfile::Builder::new().prefix(".apkeep-").tempdir_in(temp_dir) {
+                Ok(staging) => {
+                    let staging_path = fs::canonicalize(staging.path()).unwrap_or_else(|_| staging.path().to_path_buf());
+                    storage::stage(&staging_path, outpath.as_ref().unwrap());
+                    Some((staging, staging_path))
+                },
+                Err(err) => {
+                    println!("Could not create a directory in {} to download into: {}. Exiting.", temp_dir.display(), err);
+                    std::process::exit(1);
+                }
+            },
+            None => None,
+        };
+        let final_outpath = outpath;
+        let outpath = match &staging {
+            Some((_, staging_path)) => Some(staging_path.clone()),
+            None => final_outpath.clone(),
+        };
+
+        let downloaded = if let Some(sources) = race {
+            let cache_ttl = *matches.get_one::<u64>("cache_ttl").unwrap();
+            let page_cache = cache::PageCache::new(std::time::Duration::from_secs(cache_ttl));
+            race::download_apps(list, &sources, parallel, sleep_duration, outpath.as_ref().unwrap(), &options, &installed_version_codes, channel, form_factor, &page_cache).await
+        } else {
+            match download_source {
+                DownloadSource::APKPure => {
+                    apkpure::download_apps(list, parallel, sleep_duration, outpath.as_ref().unwrap()).await
+                }
+                DownloadSource::GooglePlay => {
+                    let mut email = matches.get_one::<String>("google_email").map(|v| v.to_string());
+
+                    if email.is_some() && oauth_token.is_some() {
+                        google_play::request_aas_token(
+                            &email.unwrap(),
+                            &oauth_token.unwrap(),
+                            options,
+                        ).await;
+                        Vec::new()
+                    } else {
+                        let mut aas_token = matches.get_one::<String>("google_aas_token").map(|v| v.to_string());
+                        let accept_tos = match matches.get_one::<bool>("list_versions") {
+                            Some(true) => true,
+                            _ => false,
+                        };
+
+                        if email.is_none() {
+                            email = credentials::get(conf.as_ref(), "google", "email");
+                        }
+                        if aas_token.is_none() {
+                            aas_token = credentials::get(conf.as_ref(), "google", "aas_token");
                         }
-                    });
 
-                    if email.is_none() || aas_token.is_none() {
-                        if let Ok(conf) = load_config(ini_file) {
-                            if email.is_none() {
-                                email = conf.get("google", "email");
-                            }
-                            if aas_token.is_none() {
-                                aas_token = conf.get("google", "aas_token");
-                            }
+                        if email.is_none() {
+                            email = Some(prompt("Email"));
                         }
-                    }
 
-                    if email.is_none() {
-                        let mut prompt_email = String::new();
-                        print!("Email: ");
-                        io::stdout().flush().unwrap();
-                        io::stdin().read_line(&mut prompt_email).unwrap();
-                        email = Some(prompt_email.trim().to_string());
-                    }
+                        if aas_token.is_none() {
+                            aas_token = Some(prompt_secret("AAS Token"));
+                        }
 
-                    if aas_token.is_none() {
-                        let mut prompt_aas_token = String::new();
-                        print!("AAS Token: ");
-                        io::stdout().flush().unwrap();
-                        io::stdin().read_line(&mut prompt_aas_token).unwrap();
-                        aas_token = Some(prompt_aas_token.trim().to_string());
+                        let mut accounts = vec![google_play::Account { email: email.unwrap(), aas_token: aas_token.unwrap() }];
+                        accounts.extend(more_google_accounts(conf.as_ref()));
+                        google_play::download_apps(
+                            list,
+                            parallel,
+                            sleep_duration,
+                            accounts,
+                            outpath.as_ref().unwrap(),
+                            accept_tos,
+                            options,
+                        )
+                        .await
                     }
-
-                    google_play::download_apps(
-                        list,
+                }
+                DownloadSource::FDroid => {
+                    fdroid::download_apps(list,
                         parallel,
                         sleep_duration,
-                        &email.unwrap(),
-                        &aas_token.unwrap(),
-                        &outpath.unwrap(),
-                        accept_tos,
+                        outpath.as_ref().unwrap(),
                         options,
-                    )
-                    .await;
+                        &installed_version_codes,
+                        mirror,
+                        &filter,
+                        channel,
+                        form_factor,
+                    ).await
+                }
+                DownloadSource::HuaweiAppGallery => {
+                    huawei_app_gallery::download_apps(list, parallel, sleep_duration, outpath.as_ref().unwrap()).await
+                }
+                DownloadSource::APKCombo => {
+                    let cache_ttl = *matches.get_one::<u64>("cache_ttl").unwrap();
+                    let page_cache = cache::PageCache::new(std::time::Duration::from_secs(cache_ttl));
+                    apkcombo::download_apps(list, parallel, sleep_duration, outpath.as_ref().unwrap(), options, &page_cache).await
+                }
+                DownloadSource::Plugin => {
+                    plugin::download_apps(list, parallel, sleep_duration, outpath.as_ref().unwrap(), options, plugin.as_ref().unwrap()).await
+                }
+                DownloadSource::Scraped => {
+                    scraped::download_apps(list, parallel, sleep_duration, outpath.as_ref().unwrap(), site.as_ref().unwrap()).await
                 }
             }
-            DownloadSource::FDroid => {
-                fdroid::download_apps(list,
-                    parallel,
-                    sleep_duration,
-                    &outpath.unwrap(),
-                    options,
-                ).await;
+        };
+        let outpath = final_outpath;
+        let downloaded = match staging {
+            Some((staging, staging_path)) => {
+                let _timer = timings::start_shared(Phase::Write);
+                match storage::finalize_staged(&staging_path, outpath.as_ref().unwrap()) {
+                    Ok(moved) => downloaded.into_iter().map(|mut app| {
+                        app.files = app.files.iter().map(|file| moved.get(file).cloned().unwrap_or_else(|| file.clone())).collect();
+                        app
+                    }).collect(),
+                    Err(err) => {
+                        println!("Could not move the downloads into {}: {}. They have been left in {}. Exiting.", outpath.as_ref().unwrap().display(), err, staging_path.display());
+                        let _ = staging.keep();
+                        std::process::exit(1);
+                    },
+                }
+            },
+            None => downloaded,
+        };
+
+        if audit::enabled() {
+            for app in &downloaded {
+                let files = app.files.iter()
+                    .map(|file| json!({ "file": file.display().to_string(), "sha256": util::sha256_file(file).ok() }))
+                    .collect::<Vec<_>>();
+                audit::record("download", &app.app_id, json!({
+                    "source": app.source.unwrap_or(download_source).to_string(),
+                    "version": app.version,
+                    "url": app.url,
+                    "files": files,
+                }));
             }
-            DownloadSource::HuaweiAppGallery => {
-                huawei_app_gallery::download_apps(list, parallel, sleep_duration, &outpath.unwrap()).await;
+        }
+
+        // Sources other than F-Droid don't know the versionCode before downloading, so check the
+        // downloaded APK itself
+        let downloaded = if only_newer_than_device || only_newer {
+            downloaded.into_iter().filter(|app| {
+                let _timer = timings::start(&app.app_id, Phase::Verify);
+                let version_code = app.files.first()
+                    .and_then(|file| apk::read_manifest(file).ok())
+                    .and_then(|manifest| manifest.version_code);
+                match (version_code, installed_version_codes.get(&app.app_id)) {
+                    (Some(version_code), Some(installed_version_code)) if version_code <= *installed_version_code => {
+                        let archived = archived_version_codes.get(&app.app_id).is_some_and(|archived| version_code <= *archived);
+                        if archived && app.files.iter().any(|file| archived_files.contains(file)) {
+                            println!("{} is not newer than the version already downloaded. Skipping...", app.app_id);
+                            return false;
+                        }
+                        if archived {
+                            println!("{} is not newer than the version already downloaded. Removing...", app.app_id);
+                        } else {
+                            println!("{} is not newer than the version installed on the device. Removing...", app.app_id);
+                        }
+                        if let Err(err) = app.remove() {
+                            println!("Could not remove the download for {}: {}", app.app_id, err);
+                        }
+                        false
+                    },
+                    _ => true,
+                }
+            }).collect::<Vec<_>>()
+        } else {
+            downloaded
+        };
+
+        // Sources deliver the splits for every device they might be installed on, or for the
+        // device profile they were asked to pretend to be
+        let downloaded = match &device_config {
+            Some(device_config) => downloaded.into_iter().map(|mut app| {
+                if app.files.len() > 1 {
+                    let (keep, drop) = device_config.select_splits(&app.files);
+                    for file in &drop {
+                        if let Err(err) = fs::remove_file(file) {
+                            println!("Could not remove {}, which the device would not receive: {}", file.display(), err);
+                        }
+                    }
+                    if !drop.is_empty() {
+                        println!("Kept {} of the {} split APKs of {} for the device.", keep.len(), app.files.len(), app.app_id);
+                    }
+                    app.files = keep;
+                }
+                let min_sdk_version = app.files.first()
+                    .and_then(|file| apk::read_manifest(file).ok())
+                    .and_then(|manifest| manifest.min_sdk_version);
+                if let Some(min_sdk_version) = min_sdk_version {
+                    if min_sdk_version > device_config.sdk {
+                        println!("{} needs API level {}, but the device has {}, so it can not be installed there.", app.app_id, min_sdk_version, device_config.sdk);
+                    }
+                }
+                app
+            }).collect::<Vec<_>>(),
+            None => downloaded,
+        };
+
+        // Mirrors have been known to serve a different app than the one asked for
+        let downloaded = downloaded.into_iter().filter(|app| {
+            let _timer = timings::start(&app.app_id, Phase::Verify);
+            match app.files.first().and_then(|file| apk::read_manifest(file).ok()) {
+                Some(manifest) if manifest.package != app.app_id => {
+                    audit::check(&app.app_id, "package", false, &manifest.package);
+                    quarantine(outpath.as_ref().unwrap(), app, &format!("The download for {} is of a different app, {}.", app.app_id, manifest.package));
+                    false
+                },
+                Some(manifest) => {
+                    audit::check(&app.app_id, "package", true, &manifest.package);
+                    true
+                },
+                None => true,
             }
-            DownloadSource::APKCombo => {
-                apkcombo::download_apps(list, parallel, sleep_duration, &outpath.unwrap(), options).await;
+        }).collect::<Vec<_>>();
+
+        // Sources which don't say which kind of device a build is for may deliver the phone build
+        let downloaded = match form_factor {
+            Some(form_factor) => downloaded.into_iter().filter(|app| {
+                let _timer = timings::start(&app.app_id, Phase::Verify);
+                // XAPK bundles don't list the features their APKs require
+                let manifest = app.files.first()
+                    .filter(|file| file.extension().is_some_and(|ext| ext == "apk"))
+                    .and_then(|file| apk::read_manifest(file).ok());
+                match manifest.map(|manifest| apk::form_factor(&manifest.features)) {
+                    Some(built_for) if built_for != form_factor => {
+                        quarantine(outpath.as_ref().unwrap(), app, &format!("The download for {} is built for a {}, not a {}.", app.app_id, built_for, form_factor));
+                        false
+                    },
+                    _ => true,
+                }
+            }).collect::<Vec<_>>(),
+            None => downloaded,
+        };
+
+        let verify_signatures = matches!(matches.get_one::<bool>("verify_signatures"), Some(true));
+        let downloaded = if verify_signatures {
+            downloaded.into_iter().filter(|app| {
+                let _timer = timings::start(&app.app_id, Phase::Verify);
+                // XAPK bundles are not themselves signed; the APKs within them are
+                for file in app.files.iter().filter(|file| file.extension().is_some_and(|ext| ext == "apk")) {
+                    match apk::signature::verify_v2_v3(file) {
+                        Ok(Some(verified)) => {
+                            println!("{} has a valid {} signature.", file.display(), verified.scheme);
+                            audit::check(&app.app_id, "signature", true, &format!("{}: valid {} signature", file.display(), verified.scheme));
+                        },
+                        Ok(None) => {
+                            println!("{} has no v2 or v3 signature, so only its v1 signature protects it.", file.display());
+                            audit::check(&app.app_id, "signature", true, &format!("{}: v1 signature only, not checked", file.display()));
+                        },
+                        Err(err) => {
+                            audit::check(&app.app_id, "signature", false, &format!("{}: {}", file.display(), err));
+                            quarantine(outpath.as_ref().unwrap(), app, &format!("Could not verify the signature of {}: {}.", file.display(), err));
+                            return false;
+                        },
+                    }
+                }
+                true
+            }).collect::<Vec<_>>()
+        } else {
+            downloaded
+        };
+
+        // Apps repackaged by a mirror can't be signed with the publisher's key
+        let downloaded = downloaded.into_iter().filter(|app| {
+            let base_apk = match app.files.first() {
+                Some(file) if known_signers.knows(&app.app_id) && file.extension().is_some_and(|ext| ext == "apk") => file,
+                _ => return true,
+            };
+            let _timer = timings::start(&app.app_id, Phase::Verify);
+            let signers = apk::signature::signer_fingerprints(base_apk).unwrap_or_default();
+            let known = known_signers.is_known_signer(&app.app_id, &signers);
+            audit::check(&app.app_id, "known_signer", known, &signers.join(","));
+            if known {
+                true
+            } else if verify_signatures {
+                quarantine(outpath.as_ref().unwrap(), app, &format!("{} is not signed by the known publisher of {}.", base_apk.display(), app.app_id));
+                false
+            } else {
+                println!("{} is not signed by the known publisher of {}. Proceed with caution.", base_apk.display(), app.app_id);
+                true
+            }
+        }).collect::<Vec<_>>();
+
+        // A download the --pipe command fails on is treated as having failed itself
+        let downloaded = match matches.get_one::<String>("pipe").map(|command| pipe::Pipe::new(command)) {
+            Some(pipe) => {
+                let mut passed = vec![];
+                'apps: for app in downloaded {
+                    for file in &app.files {
+                        if let Err(err) = pipe.run(&app, file).await {
+                            quarantine(outpath.as_ref().unwrap(), &app, &format!("{} was rejected by the --pipe command: {}.", file.display(), err));
+                            continue 'apps;
+                        }
+                    }
+                    passed.push(app);
+                }
+                passed
+            },
+            None => downloaded,
+        };
+
+        // Before the files' permissions are set, and before deduplication makes them read-only
+        if let Some(true) = matches.get_one::<bool>("xattrs") {
+            for app in &downloaded {
+                let _timer = timings::start(&app.app_id, Phase::Write);
+                for file in &app.files {
+                    if let Err(err) = provenance::record(app, file, &app.source.unwrap_or(download_source).to_string()) {
+                        println!("Could not record the provenance of {}: {}", file.display(), err);
+                    }
+                }
+            }
+        }
+
+        // Before deduplication, so that files first stored in the pool take on these too
+        let release_mtime = matches.get_flag("release_mtime");
+        if release_mtime || file_mode.is_some() {
+            for app in &downloaded {
+                let modified = app.released_at.filter(|_| release_mtime).map(SystemTime::from);
+                for file in &app.files {
+                    if let Err(err) = storage::set_file_attributes(file, modified, file_mode) {
+                        println!("Could not set the modification time or permissions of {}: {}", file.display(), err);
+                    }
+                }
+            }
+        }
+
+        if let Some(true) = matches.get_one::<bool>("dedup") {
+            let pool = storage::pool_dir(outpath.as_ref().unwrap());
+            let mut deduplicated = 0;
+            for (app, file) in downloaded.iter().flat_map(|app| app.files.iter().map(move |file| (app, file))) {
+                let _timer = timings::start(&app.app_id, Phase::Write);
+                match storage::deduplicate(&pool, file) {
+                    Ok(true) => deduplicated += 1,
+                    Ok(false) => {},
+                    Err(err) => println!("Could not move {} into the storage pool: {}", file.display(), err),
+                }
+            }
+            if deduplicated > 0 {
+                println!("{} downloaded files were already stored, and have been linked instead.", deduplicated);
+            }
+        }
+
+        if !downloaded.is_empty() {
+            let files = downloaded.iter().flat_map(|app| app.files.iter()).collect::<Vec<&PathBuf>>();
+            let _timer = timings::start_shared(Phase::Write);
+            match storage::update_checksums(outpath.as_ref().unwrap(), &files) {
+                Ok(checksums_file) => {
+                    if let Some(key) = matches.get_one::<String>("gpg_sign") {
+                        if let Err(err) = gpg::detach_sign(&checksums_file, key).await {
+                            println!("Could not sign SHA256SUMS: {}", err);
+                        }
+                    }
+                },
+                Err(err) => println!("Could not update SHA256SUMS: {}", err),
+            }
+        }
+
+        let mut downloaded = downloaded;
+        if let Some(mobsf) = mobsf {
+            for app in downloaded.iter_mut() {
+                // The base APK and each split of a split APK are analysed on their own; files
+                // such as OBBs can't be analysed at all
+                for file in &app.files {
+                    let file_name = file.file_name().map_or(String::new(), |name| name.to_string_lossy().to_string());
+                    if !file.extension().is_some_and(|extension| extension == "apk" || extension == "xapk") {
+                        println!("Not submitting {} of {} to MobSF, as it is not an APK.", file_name, app.app_id);
+                        continue;
+                    }
+                    println!("Submitting {} of {} to MobSF...", file_name, app.app_id);
+                    match mobsf.submit(file).await {
+                        Ok(report) => {
+                            println!("MobSF report for {} of {}: {}", file_name, app.app_id, report);
+                            app.analysis_reports.insert(file.clone(), report);
+                        },
+                        Err(err) => println!("Could not submit {} of {} to MobSF: {}", file_name, app.app_id, err),
+                    }
+                }
+            }
+        }
+
+        if let Some(true) = matches.get_one::<bool>("metadata") {
+            for app in &downloaded {
+                let _timer = timings::start(&app.app_id, Phase::Write);
+                for file in &app.files {
+                    if let Err(err) = metadata::write_sidecar(app, file, &app.source.unwrap_or(download_source).to_string()) {
+                        println!("Could not write metadata for {}: {}", file.display(), err);
+                    }
+                }
+            }
+        }
+
+        if let Some(true) = matches.get_one::<bool>("latest_links") {
+            for app in &downloaded {
+                let _timer = timings::start(&app.app_id, Phase::Write);
+                if let Err(err) = storage::update_latest_link(outpath.as_ref().unwrap(), app) {
+                    println!("Could not update the latest link for {}: {}", app.app_id, err);
+                }
+            }
+        }
+
+        if !downloaded.is_empty() && (retention.keep.is_some() || retention.keep_days.is_some()) {
+            let packages = downloaded.iter().map(|app| app.app_id.clone()).collect::<BTreeSet<String>>();
+            let _timer = timings::start_shared(Phase::Write);
+            match storage::prune(outpath.as_ref().unwrap(), &retention, Some(&packages), false, false) {
+                Ok(summary) if summary.versions > 0 => println!("{}", tr!(
+                    "retention-removed",
+                    versions = summary.versions,
+                    files = summary.files,
+                    size = format!("{:.1}", summary.bytes as f64 / (1024.0 * 1024.0)),
+                )),
+                Ok(_) => {},
+                Err(err) => println!("{}", tr!("retention-failed", error = err.to_string())),
+            }
+        }
+
+        if let Some(upload_destination) = upload_destination {
+            let http_client = reqwest::Client::new();
+            for app in &downloaded {
+                for file in &app.files {
+                    let remote_path = file.strip_prefix(outpath.as_ref().unwrap()).unwrap_or(file);
+                    println!("Uploading {}...", remote_path.display());
+                    match upload_destination.upload(&http_client, app, file, &remote_path.to_string_lossy()).await {
+                        Ok(()) => println!("{} uploaded successfully!", remote_path.display()),
+                        Err(err) => println!("Could not upload {}: {}", remote_path.display(), err),
+                    }
+                }
+            }
+        }
+
+        if let Some(mqtt) = mqtt {
+            if let Err(err) = mqtt.publish(&downloaded, download_source).await {
+                println!("Could not publish download events to MQTT: {}", err);
+            }
+        }
+
+        if install {
+            for device in devices {
+                let device_string = device.as_deref().map_or(String::new(), |serial| format!(" on {}", serial));
+                let mut installed = 0;
+                for app in &downloaded {
+                    println!("Installing {}{}...", app.app_id, device_string);
+                    match adb::install(app, device.as_deref()).await {
+                        Ok(()) => {
+                            println!("{} installed successfully{}!", app.app_id, device_string);
+                            installed += 1;
+                        },
+                        Err(err) => println!("Could not install {}{}: {}", app.app_id, device_string, err),
+                    }
+                }
+                if let Some(serial) = device {
+                    println!("{}: {} of {} apps installed.", serial, installed, downloaded.len());
+                }
+            }
+        }
+
+        if hooks.on_success.is_some() || hooks.on_new_version.is_some() || feed_file.is_some() {
+            let mut feed_entries = vec![];
+            for app in &downloaded {
+                let version_code = app.files.first()
+                    .and_then(|file| apk::read_manifest(file).ok())
+                    .and_then(|manifest| manifest.version_code);
+                let previous_version_code = archived_version_codes.get(&app.app_id).copied();
+                let new_version = match (version_code, previous_version_code) {
+                    (Some(version_code), Some(previous_version_code)) => version_code > previous_version_code,
+                    (_, previous_version_code) => previous_version_code.is_none(),
+                };
+                if new_version {
+                    feed_entries.push(feed::Entry {
+                        app_id: app.app_id.clone(),
+                        source: app.source.unwrap_or(download_source).to_string(),
+                        version: app.version.clone(),
+                        version_code,
+                        url: app.url.clone(),
+                        detected_at: app.downloaded_at,
+                    });
+                }
+                let event = hooks::download_event(app, download_source, version_code, previous_version_code);
+                for hook in [Hook::Success, Hook::NewVersion] {
+                    if matches!(hook, Hook::NewVersion) && !new_version {
+                        continue;
+                    }
+                    if let Err(err) = hooks.run(hook, &event).await {
+                        println!("The {} hook failed for {}: {}", hook.name(), app.app_id, err);
+                    }
+                }
+            }
+            if let Some(feed_file) = feed_file {
+                if let Err(err) = feed::update(feed_file, &feed_entries) {
+                    println!("Could not update the feed {}: {}", feed_file.display(), err);
+                }
+            }
+        }
+        if hooks.on_failure.is_some() {
+            // An app raced between sources may have failed on some before succeeding on another;
+            // only the last reason an app failed for is passed on
+            let failures = download_sources::take_failures().into_iter()
+                .filter(|(app_id, _)| !downloaded.iter().any(|app| app.app_id == *app_id))
+                .collect::<BTreeMap<String, String>>();
+            for (app_id, reason) in failures {
+                if let Err(err) = hooks.run(Hook::Failure, &hooks::failure_event(&app_id, download_source, &reason)).await {
+                    println!("The {} hook failed for {}: {}", Hook::Failure.name(), app_id, err);
+                }
+            }
+        }
+
+        if let Some((archive_file, format, staging)) = archive {
+            let _timer = timings::start_shared(Phase::Write);
+            match storage::write_archive(staging.path(), &archive_file, format) {
+                Ok(files) => println!("Wrote {} files to {}.", files, archive_file.display()),
+                Err(err) => println!("Could not write {}: {}. The downloads have been left in {}.", archive_file.display(), err, staging.keep().display()),
             }
         }
+
+        for app in &downloaded {
+            progress_events::app_downloaded(&app.app_id, &app.source.unwrap_or(download_source).to_string(), app.version.as_deref(), &app.files);
+        }
+        progress_events::finished(downloaded.len());
+
+        timings::report();
     }
 }
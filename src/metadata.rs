@@ -0,0 +1,42 @@
+//! Metadata sidecar files written next to each download, so that an archive remains
+//! interpretable on its own.
+
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::json;
+
+use crate::apk;
+use crate::download_sources::DownloadedApp;
+use crate::util::digests;
+use crate::util::sha256_file;
+
+/// Write `<file>.meta.json` describing one of the files of a downloaded app.
+pub fn write_sidecar(app: &DownloadedApp, file: &Path, source: &str) -> Result<PathBuf, Box<dyn Error>> {
+    let manifest = apk::read_manifest(file).ok();
+    let mut metadata = json!({
+        "source": source,
+        "url": app.url,
+        "package": app.app_id,
+        "version": manifest.as_ref().and_then(|manifest| manifest.version_name.clone()).or_else(|| app.version.clone()),
+        "version_code": manifest.as_ref().and_then(|manifest| manifest.version_code),
+        "split": manifest.as_ref().and_then(|manifest| manifest.split.clone()),
+        "size": fs::metadata(file)?.len(),
+        "sha256": sha256_file(file)?,
+        "signers": apk::signature::signer_fingerprints(file).unwrap_or_default(),
+        "downloaded_at": app.downloaded_at.to_rfc3339(),
+        "released_at": app.released_at.map(|released_at| released_at.to_rfc3339()),
+        "changelog": app.changelog,
+        "analysis_report": app.analysis_reports.get(file),
+    });
+    for algorithm in digests::extra() {
+        metadata[algorithm.name()] = json!(digests::hash(file, *algorithm)?);
+    }
+
+    let mut sidecar = file.as_os_str().to_owned();
+    sidecar.push(".meta.json");
+    let sidecar = PathBuf::from(sidecar);
+    fs::write(&sidecar, serde_json::to_string_pretty(&metadata)?)?;
+    Ok(sidecar)
+}
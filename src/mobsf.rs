@@ -0,0 +1,69 @@
+//! Submitting downloads to a [MobSF](https://mobsf.github.io/docs/) instance for automated
+//! static analysis.
+
+use std::env;
+use std::error::Error;
+use std::path::Path;
+
+use configparser::ini::Ini;
+use reqwest::header::AUTHORIZATION;
+use reqwest::multipart::{Form, Part};
+use serde_json::Value;
+use simple_error::SimpleError;
+
+use crate::credentials;
+use crate::util::file_body;
+
+pub struct MobSF {
+    url: String,
+    api_key: String,
+    http_client: reqwest::Client,
+}
+
+impl MobSF {
+    /// The API key is read from `MOBSF_API_KEY`, or `api_key` in the `[mobsf]` section of the
+    /// config file or the keyring.
+    pub fn new(url: &str, conf: Option<&Ini>) -> Result<MobSF, Box<dyn Error>> {
+        let api_key = env::var("MOBSF_API_KEY").ok()
+            .filter(|api_key| !api_key.is_empty())
+            .or_else(|| credentials::get(conf, "mobsf", "api_key"))
+            .ok_or_else(|| SimpleError::new("No MobSF API key was found. Set MOBSF_API_KEY or `api_key` in the [mobsf] section of the config file, or run `apkeep auth login mobsf`."))?;
+        Ok(MobSF {
+            url: url.trim_end_matches('/').to_string(),
+            api_key,
+            http_client: reqwest::Client::new(),
+        })
+    }
+
+    async fn post(&self, endpoint: &str, request: impl FnOnce(reqwest::RequestBuilder) -> reqwest::RequestBuilder) -> Result<Value, Box<dyn Error>> {
+        let response = request(self.http_client.post(format!("{}/api/v1/{}", self.url, endpoint)))
+            .header(AUTHORIZATION, &self.api_key)
+            .send().await?;
+        if !response.status().is_success() {
+            return Err(Box::new(SimpleError::new(format!("MobSF responded with HTTP {} to {}", response.status(), endpoint))));
+        }
+        Ok(serde_json::from_str(&response.text().await?)?)
+    }
+
+    /// Upload a file and scan it, returning the URL of the resulting report.  MobSF only responds
+    /// to the scan request once the analysis is complete, which can take a few minutes.
+    pub async fn submit(&self, file: &Path) -> Result<String, Box<dyn Error>> {
+        let file_name = file.file_name().map_or(String::new(), |name| name.to_string_lossy().to_string());
+        let length = tokio::fs::metadata(file).await?.len();
+        let part = Part::stream_with_length(file_body(tokio::fs::File::open(file).await?), length)
+            .file_name(file_name)
+            .mime_str("application/octet-stream")?;
+        let upload = self.post("upload", |request| request.multipart(Form::new().part("file", part))).await?;
+
+        let field = |name: &str| upload.get(name).and_then(|value| value.as_str()).map(String::from);
+        let hash = field("hash").ok_or_else(|| SimpleError::new("MobSF did not return a hash for the upload."))?;
+        let scan_form = [
+            ("hash", hash.clone()),
+            ("scan_type", field("scan_type").unwrap_or_default()),
+            ("file_name", field("file_name").unwrap_or_default()),
+        ];
+        self.post("scan", |request| request.form(&scan_form)).await?;
+
+        Ok(format!("{}/static_analyzer/{}/", self.url, hash))
+    }
+}
@@ -0,0 +1,98 @@
+//! Publishing an event to an MQTT broker for each download, so that home-automation and
+//! fleet-management setups can react to new releases.
+
+use std::error::Error;
+use std::time::Duration;
+
+use configparser::ini::Ini;
+use reqwest::Url;
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS, Transport};
+use serde_json::json;
+use simple_error::SimpleError;
+
+use crate::credentials;
+use crate::cli::DownloadSource;
+use crate::download_sources::DownloadedApp;
+
+const DEFAULT_TOPIC_PREFIX: &str = "apkeep";
+/// How long to wait for the broker to acknowledge all events before giving up.
+const PUBLISH_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct Publisher {
+    options: MqttOptions,
+    prefix: String,
+}
+
+impl Publisher {
+    /// Parse a broker URL, `mqtt://host[:port][/prefix]` or `mqtts://` for TLS.  Credentials are
+    /// taken from the URL, or `username` and `password` in the `[mqtt]` section of the config file or
+    /// the keyring.
+    pub fn parse(location: &str, conf: Option<&Ini>) -> Result<Publisher, Box<dyn Error>> {
+        let url = Url::parse(location)?;
+        let tls = match url.scheme() {
+            "mqtt" => false,
+            "mqtts" => true,
+            _ => return Err(Box::new(SimpleError::new("MQTT brokers must be given as mqtt://host[:port][/prefix] or mqtts://host[:port][/prefix]."))),
+        };
+        let host = url.host_str().ok_or_else(|| SimpleError::new("The MQTT broker URL does not specify a host."))?;
+        let port = url.port().unwrap_or(if tls { 8883 } else { 1883 });
+
+        let mut options = MqttOptions::new(format!("apkeep-{}", std::process::id()), host, port);
+        options.set_keep_alive(Duration::from_secs(30));
+        if tls {
+            options.set_transport(Transport::tls_with_default_config());
+        }
+        let conf_value = |key: &str| credentials::get(conf, "mqtt", key);
+        let username = Some(url.username()).filter(|username| !username.is_empty()).map(String::from)
+            .or_else(|| conf_value("username"));
+        if let Some(username) = username {
+            let password = url.password().map(String::from).or_else(|| conf_value("password")).unwrap_or_default();
+            options.set_credentials(username, password);
+        }
+
+        let prefix = url.path().trim_matches('/');
+        Ok(Publisher {
+            options,
+            prefix: if prefix.is_empty() { DEFAULT_TOPIC_PREFIX.to_string() } else { prefix.to_string() },
+        })
+    }
+
+    /// Publish a `download` event for each app to `<prefix>/<app_id>`, waiting until the broker
+    /// has acknowledged them all.
+    pub async fn publish(&self, apps: &[DownloadedApp], source: DownloadSource) -> Result<(), Box<dyn Error>> {
+        if apps.is_empty() {
+            return Ok(());
+        }
+        let (client, mut event_loop) = AsyncClient::new(self.options.clone(), apps.len() + 1);
+        for app in apps {
+            let event = json!({
+                "event": "download",
+                "source": app.source.unwrap_or(source).to_string(),
+                "package": app.app_id,
+                "version": app.version,
+                "url": app.url,
+                "files": app.files.iter()
+                    .filter_map(|file| file.file_name().map(|name| name.to_string_lossy().to_string()))
+                    .collect::<Vec<String>>(),
+                "downloaded_at": app.downloaded_at.to_rfc3339(),
+            });
+            client.publish(format!("{}/{}", self.prefix, app.app_id), QoS::AtLeastOnce, false, serde_json::to_vec(&event)?).await?;
+        }
+
+        let acknowledged = tokio::time::timeout(PUBLISH_TIMEOUT, async {
+            let mut acknowledged = 0;
+            while acknowledged < apps.len() {
+                if let Event::Incoming(Packet::PubAck(_)) = event_loop.poll().await? {
+                    acknowledged += 1;
+                }
+            }
+            client.disconnect().await?;
+            while !matches!(event_loop.poll().await, Ok(Event::Outgoing(rumqttc::Outgoing::Disconnect)) | Err(_)) {}
+            Ok::<(), Box<dyn Error>>(())
+        }).await;
+        match acknowledged {
+            Ok(result) => result,
+            Err(_) => Err(Box::new(SimpleError::new("The MQTT broker did not acknowledge the events in time."))),
+        }
+    }
+}
@@ -0,0 +1,155 @@
+//! The regular expressions used to scrape download sources and to read signed archives.  They
+//! are compiled once per run rather than for every app, and kept together so that when a source
+//! changes its pages there is one place to audit.
+//!
+//! Those which pick apart the pages of APKPure and APKCombo are kept in `patterns.toml`, which is
+//! built in, and which `apkeep update-patterns` replaces with a newer copy from the project's
+//! repository, so that a fix for a site which has changed its layout needn't wait for a release.
+//! A downloaded copy is only used if it is signed with the key apkeep was built to trust, is newer
+//! than the one built in, and all its patterns compile.
+
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::LazyLock;
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use regex::Regex;
+use ring::signature::{UnparsedPublicKey, ED25519};
+use serde::Deserialize;
+use simple_error::SimpleError;
+
+use crate::config;
+use crate::util::{http, retry};
+
+const BUILT_IN: &str = include_str!("../patterns.toml");
+const PATTERNS_URL: &str = "https://raw.githubusercontent.com/EFForg/apkeep/master/patterns.toml";
+/// The base64 Ed25519 public key which signs the patterns published in the repository, given when
+/// apkeep is built.  Without it, only the patterns built in are used.
+const PUBLIC_KEY: Option<&str> = option_env!("APKEEP_PATTERNS_PUBLIC_KEY");
+
+#[derive(Deserialize)]
+pub struct Scraping {
+    pub version: u64,
+    apkpure: ApkPure,
+    apkcombo: ApkCombo,
+}
+
+#[derive(Deserialize)]
+struct ApkPure {
+    download_url: String,
+    version: String,
+}
+
+#[derive(Deserialize)]
+struct ApkCombo {
+    app_url: String,
+    download_url: String,
+    file_url: String,
+}
+
+impl Scraping {
+    /// Parse a patterns file, checking that every pattern compiles.
+    fn parse(contents: &str) -> Result<Scraping, Box<dyn Error>> {
+        let scraping: Scraping = toml::from_str(contents)?;
+        for pattern in [&scraping.apkpure.download_url, &scraping.apkpure.version, &scraping.apkcombo.app_url, &scraping.apkcombo.download_url, &scraping.apkcombo.file_url] {
+            Regex::new(pattern)?;
+        }
+        Ok(scraping)
+    }
+}
+
+/// The patterns in use: those downloaded by `apkeep update-patterns` when they can be trusted and
+/// are newer, and otherwise those built in.
+pub static SCRAPING: LazyLock<Scraping> = LazyLock::new(|| {
+    let built_in = Scraping::parse(BUILT_IN).unwrap();
+    match downloaded() {
+        Some(downloaded) if downloaded.version > built_in.version => downloaded,
+        _ => built_in,
+    }
+});
+
+/// The file type (`APKJ` or `XAPKJ`) and download URL of a version in an APKPure versions
+/// response.  Left uncompiled so that it can be prefixed to pick out a particular version.
+pub fn apkpure_download_url_pattern() -> &'static str {
+    &SCRAPING.apkpure.download_url
+}
+
+pub static APKPURE_DOWNLOAD_URL: LazyLock<Regex> = LazyLock::new(|| Regex::new(&SCRAPING.apkpure.download_url).unwrap());
+
+/// The version names listed in an APKPure versions response
+pub static APKPURE_VERSION: LazyLock<Regex> = LazyLock::new(|| Regex::new(&SCRAPING.apkpure.version).unwrap());
+
+/// Links to app pages in APKCombo search results
+pub static APKCOMBO_APP_URL: LazyLock<Regex> = LazyLock::new(|| Regex::new(&SCRAPING.apkcombo.app_url).unwrap());
+
+/// The download button on an APKCombo app page
+pub static APKCOMBO_DOWNLOAD_URL: LazyLock<Regex> = LazyLock::new(|| Regex::new(&SCRAPING.apkcombo.download_url).unwrap());
+
+/// The link to the file itself on an APKCombo download page
+pub static APKCOMBO_FILE_URL: LazyLock<Regex> = LazyLock::new(|| Regex::new(&SCRAPING.apkcombo.file_url).unwrap());
+
+pub static CONTENT_DISPOSITION_FILENAME: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"filename=(?:"([^"]+)"|([^;]+))"#).unwrap());
+
+/// The signature block files of a JAR-signed archive
+pub static SIGNATURE_BLOCK_FILE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^META-INF/.*\.(DSA|EC|RSA)$").unwrap());
+
+/// The digests of the whole manifest in the signature file of an F-Droid index
+pub static SIGNED_MANIFEST_SHA256: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\r\nSHA-256-Digest-Manifest: (.*)\r\n").unwrap());
+pub static SIGNED_MANIFEST_SHA1: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\r\nSHA1-Digest-Manifest: (.*)\r\n").unwrap());
+
+/// The digests of the index files in the manifest of an F-Droid index
+pub static MANIFEST_ENTRY_SHA256: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\r\nName: entry\.json\r\nSHA-256-Digest: (.*)\r\n").unwrap());
+pub static MANIFEST_INDEX_V1_SHA1: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\r\nName: index-v1\.json\r\nSHA1-Digest: (.*)\r\n").unwrap());
+
+/// Where the patterns downloaded by `apkeep update-patterns` and their signature are kept
+fn downloaded_paths() -> Option<(PathBuf, PathBuf)> {
+    let dir = config::cache_dir().ok()?;
+    Some((dir.join("patterns.toml"), dir.join("patterns.toml.sig")))
+}
+
+/// The downloaded patterns, if there are any and they can be trusted
+fn downloaded() -> Option<Scraping> {
+    let (path, signature_path) = downloaded_paths()?;
+    let contents = fs::read_to_string(path).ok()?;
+    let signature = fs::read_to_string(signature_path).ok()?;
+    verify(contents.as_bytes(), &signature).ok()?;
+    Scraping::parse(&contents).ok()
+}
+
+fn public_key() -> Result<Vec<u8>, Box<dyn Error>> {
+    match PUBLIC_KEY {
+        Some(public_key) => Ok(STANDARD.decode(public_key.trim())?),
+        None => Err(Box::new(SimpleError::new("this build of apkeep has no key to verify downloaded patterns with, so only its built-in patterns can be used"))),
+    }
+}
+
+/// Check the base64 signature of a patterns file.
+fn verify(contents: &[u8], signature: &str) -> Result<(), Box<dyn Error>> {
+    let public_key = public_key()?;
+    let signature = STANDARD.decode(signature.trim())?;
+    UnparsedPublicKey::new(&ED25519, public_key).verify(contents, &signature)
+        .map_err(|_| SimpleError::new("the signature of the patterns is not valid"))?;
+    Ok(())
+}
+
+/// Download the latest patterns from the project's repository, keeping them if they are signed
+/// and newer than those in use.  Returns the versions in use before and after.
+pub async fn update() -> Result<(u64, u64), Box<dyn Error>> {
+    public_key()?;
+    let current = SCRAPING.version;
+    let client = http::client();
+    let contents = retry::send(client.get(PATTERNS_URL)).await?.error_for_status()?.text().await?;
+    let signature = retry::send(client.get(format!("{}.sig", PATTERNS_URL))).await?.error_for_status()?.text().await?;
+    verify(contents.as_bytes(), &signature)?;
+    let latest = Scraping::parse(&contents)
+        .map_err(|err| SimpleError::new(format!("the latest patterns can't be used: {}", err)))?;
+    if latest.version <= current {
+        return Ok((current, current));
+    }
+    let (path, signature_path) = downloaded_paths()
+        .ok_or_else(|| SimpleError::new("there is no cache directory to keep them in"))?;
+    fs::write(path, &contents)?;
+    fs::write(signature_path, &signature)?;
+    Ok((current, latest.version))
+}
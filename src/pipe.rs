@@ -0,0 +1,75 @@
+//! Handing each downloaded file to an external command with `--pipe`, so that apkeep can feed
+//! uploaders, scanners and installers it doesn't know about.  Any `{}` in the command is replaced
+//! with the path of the file; otherwise the file's contents are streamed to the command's stdin.
+
+use std::error::Error;
+use std::io;
+use std::path::Path;
+use std::process::Stdio;
+
+use simple_error::SimpleError;
+use tokio::process::Command;
+
+use crate::download_sources::DownloadedApp;
+
+pub struct Pipe {
+    command: String,
+}
+
+impl Pipe {
+    pub fn new(command: &str) -> Pipe {
+        Pipe { command: command.to_string() }
+    }
+
+    /// Run the command for one of the files of a downloaded app, failing if it exits unsuccessfully.
+    pub async fn run(&self, app: &DownloadedApp, file: &Path) -> Result<(), Box<dyn Error>> {
+        let pass_path = self.command.contains("{}");
+        let command = self.command.replace("{}", &quote(file));
+        let mut child = shell(&command)
+            .env("APKEEP_APP_ID", &app.app_id)
+            .env("APKEEP_VERSION", app.version.as_deref().unwrap_or_default())
+            .env("APKEEP_FILE", file)
+            .stdin(if pass_path { Stdio::null() } else { Stdio::piped() })
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            let mut contents = tokio::fs::File::open(file).await?;
+            match tokio::io::copy(&mut contents, &mut stdin).await {
+                // The command need not read all of the file
+                Err(err) if err.kind() != io::ErrorKind::BrokenPipe => return Err(Box::new(err)),
+                _ => {},
+            }
+        }
+        let status = child.wait().await?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(Box::new(SimpleError::new(format!("`{}` failed ({})", self.command, status))))
+        }
+    }
+}
+
+#[cfg(unix)]
+pub fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(windows)]
+pub fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+/// Quote a path for the shell, so that names with spaces or quotes pass through as one argument.
+#[cfg(unix)]
+fn quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', "'\\''"))
+}
+
+/// Paths on Windows can't contain `"`, so quoting them is enough.
+#[cfg(windows)]
+fn quote(path: &Path) -> String {
+    format!("\"{}\"", path.display())
+}
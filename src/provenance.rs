@@ -0,0 +1,50 @@
+//! Provenance recorded on each downloaded file itself, so that where it came from travels with it
+//! even once it has been copied out of OUTPATH: extended attributes on Unix, and alternate data
+//! streams of the same names on NTFS.
+
+use std::error::Error;
+use std::path::Path;
+
+use crate::apk;
+use crate::download_sources::DownloadedApp;
+use crate::util::sha256_file;
+
+/// The prefix of each attribute's name, `user.` being the namespace unprivileged processes may use
+const PREFIX: &str = "user.apkeep.";
+
+/// Record the source, URL, version and hash of one of the files of a downloaded app on the file.
+pub fn record(app: &DownloadedApp, file: &Path, source: &str) -> Result<(), Box<dyn Error>> {
+    let manifest = apk::read_manifest(file).ok();
+    let version = manifest.as_ref().and_then(|manifest| manifest.version_name.clone()).or_else(|| app.version.clone());
+    let version_code = manifest.as_ref().and_then(|manifest| manifest.version_code).map(|version_code| version_code.to_string());
+    let sha256 = sha256_file(file)?;
+    let downloaded_at = app.downloaded_at.to_rfc3339();
+    let attributes = [
+        ("source", Some(source)),
+        ("package", Some(app.app_id.as_str())),
+        ("url", app.url.as_deref()),
+        ("version", version.as_deref()),
+        ("version_code", version_code.as_deref()),
+        ("sha256", Some(sha256.as_str())),
+        ("downloaded_at", Some(downloaded_at.as_str())),
+    ];
+    for (name, value) in attributes {
+        if let Some(value) = value {
+            set(file, &format!("{}{}", PREFIX, name), value)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set(file: &Path, name: &str, value: &str) -> Result<(), Box<dyn Error>> {
+    Ok(xattr::set(file, name, value.as_bytes())?)
+}
+
+#[cfg(windows)]
+fn set(file: &Path, name: &str, value: &str) -> Result<(), Box<dyn Error>> {
+    let mut stream = file.as_os_str().to_owned();
+    stream.push(":");
+    stream.push(name);
+    Ok(std::fs::write(stream, value)?)
+}
@@ -0,0 +1,123 @@
+//! Fetching each app from several sources at once for `--race`, keeping whichever finishes first
+//! and cancelling the rest, for when mirrors are flaky or their speed varies by region.
+//!
+//! The sources resolve and download an app in one step, so each writes to a directory of its own
+//! within OUTPATH, and the winner's files are moved into place once it has finished.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use futures_util::future::{self, FutureExt};
+use futures_util::StreamExt;
+use tempfile::TempDir;
+
+use crate::cache::PageCache;
+use crate::cli::{Channel, DownloadSource, FormFactor};
+use crate::download_sources::{self, apkcombo, apkpure, breaker, fdroid, huawei_app_gallery, DownloadedApp};
+use crate::filter::PackageFilter;
+
+/// The sources which can take part in a race: Google Play needs credentials and a login of its
+/// own, and plugins and scraped sites a file of their own, so they are left out.
+pub const RACEABLE: [DownloadSource; 4] = [
+    DownloadSource::APKPure,
+    DownloadSource::FDroid,
+    DownloadSource::HuaweiAppGallery,
+    DownloadSource::APKCombo,
+];
+
+#[allow(clippy::too_many_arguments)]
+pub async fn download_apps(
+    apps: Vec<(String, Option<String>)>,
+    sources: &[DownloadSource],
+    parallel: usize,
+    sleep_duration: u64,
+    outpath: &Path,
+    options: &HashMap<&str, &str>,
+    installed_version_codes: &HashMap<String, u64>,
+    channel: Channel,
+    form_factor: Option<FormFactor>,
+    page_cache: &PageCache,
+) -> Vec<DownloadedApp> {
+    futures_util::stream::iter(apps.into_iter().map(|app| {
+        race_app(app, sources, sleep_duration, outpath, options, installed_version_codes, channel, form_factor, page_cache)
+    })).buffer_unordered(parallel).filter_map(|downloaded| async { downloaded }).collect::<Vec<DownloadedApp>>().await
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn race_app(
+    app: (String, Option<String>),
+    sources: &[DownloadSource],
+    sleep_duration: u64,
+    outpath: &Path,
+    options: &HashMap<&str, &str>,
+    installed_version_codes: &HashMap<String, u64>,
+    channel: Channel,
+    form_factor: Option<FormFactor>,
+    page_cache: &PageCache,
+) -> Option<DownloadedApp> {
+    let app_string = match &app.1 {
+        Some(version) => format!("{}@{}", app.0, version),
+        None => app.0.clone(),
+    };
+    let mut entrants = vec![];
+    for source in sources {
+        // Sources which have tripped their circuit breaker leave the race to the rest
+        if breaker::is_open(*source) {
+            continue;
+        }
+        let dir = match tempfile::Builder::new().prefix(".race-").tempdir_in(outpath) {
+            Ok(dir) => dir,
+            Err(err) => {
+                println!("{}", download_sources::failed(&app_string, tr!("race-directory-failed", app = app_string.as_str(), source = source.to_string(), error = err.to_string())));
+                continue;
+            }
+        };
+        let apps = vec![app.clone()];
+        let source = *source;
+        let options = options.clone();
+        entrants.push(async move {
+            let downloaded = match source {
+                DownloadSource::APKPure => apkpure::download_apps(apps, 1, sleep_duration, dir.path()).await,
+                DownloadSource::FDroid => fdroid::download_apps(apps, 1, sleep_duration, dir.path(), options, installed_version_codes, None, &PackageFilter::default(), channel, form_factor).await,
+                DownloadSource::HuaweiAppGallery => huawei_app_gallery::download_apps(apps, 1, sleep_duration, dir.path()).await,
+                DownloadSource::APKCombo => apkcombo::download_apps(apps, 1, sleep_duration, dir.path(), options, page_cache).await,
+                DownloadSource::GooglePlay | DownloadSource::Plugin | DownloadSource::Scraped => vec![],
+            };
+            (source, dir, downloaded)
+        }.boxed_local());
+    }
+
+    // Take each source as it finishes, until one has the app; dropping the rest cancels them
+    while !entrants.is_empty() {
+        let ((source, dir, downloaded), _, rest) = future::select_all(entrants).await;
+        entrants = rest;
+        if let Some(app) = downloaded.into_iter().next() {
+            drop(entrants);
+            return finish(app, source, &dir, outpath, &app_string);
+        }
+    }
+    println!("{}", download_sources::failed(&app_string, tr!("race-all-failed", app = app_string.as_str())));
+    None
+}
+
+/// Move the winning download from its source's directory into OUTPATH.
+fn finish(mut app: DownloadedApp, source: DownloadSource, dir: &TempDir, outpath: &Path, app_string: &str) -> Option<DownloadedApp> {
+    let mut files = vec![];
+    for file in &app.files {
+        let target = outpath.join(file.strip_prefix(dir.path()).ok()?);
+        if target.exists() {
+            println!("{}", tr!("file-exists", app = app_string));
+            return None;
+        }
+        let moved = target.parent().map_or(Ok(()), fs::create_dir_all).and_then(|_| fs::rename(file, &target));
+        if let Err(err) = moved {
+            println!("{}", download_sources::failed(&app_string, tr!("race-move-failed", app = app_string, error = err.to_string())));
+            return None;
+        }
+        files.push(target);
+    }
+    println!("{}", tr!("race-won", app = app_string, source = source.to_string()));
+    app.files = files;
+    Some(app.with_source(source))
+}
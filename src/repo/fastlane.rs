@@ -0,0 +1,144 @@
+//! App listings in the fastlane layout F-Droid's tools read: `metadata/<package>/<locale>/` with
+//! `title.txt`, `short_description.txt` and `full_description.txt`, what's new in each version in
+//! `changelogs/<versionCode>.txt`, and graphics in `images/icon.png` and
+//! `images/phoneScreenshots/`.  F-Droid downloads write them into OUTPATH with `-o fastlane=true`,
+//! and `repo build` adds those it finds to the index, so that the apps have their descriptions and
+//! screenshots in F-Droid clients.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::util::{relative_name, safe_file_name};
+
+/// The directory in the repository the listings are kept in
+pub const METADATA_DIR: &str = "metadata";
+pub const TITLE: &str = "title.txt";
+pub const SUMMARY: &str = "short_description.txt";
+pub const DESCRIPTION: &str = "full_description.txt";
+pub const CHANGELOGS_DIR: &str = "changelogs";
+pub const IMAGES_DIR: &str = "images";
+pub const ICON: &str = "icon.png";
+pub const PHONE_SCREENSHOTS_DIR: &str = "phoneScreenshots";
+
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "webp"];
+
+/// An app's listing in one locale
+#[derive(Default)]
+pub struct Listing {
+    pub title: Option<String>,
+    pub summary: Option<String>,
+    pub description: Option<String>,
+    /// What's new in each version, by versionCode
+    pub changelogs: BTreeMap<u64, String>,
+    /// Paths relative to the repository, with forward slashes
+    pub icon: Option<String>,
+    pub phone_screenshots: Vec<String>,
+}
+
+/// Listings by package and then by locale
+pub type Listings = BTreeMap<String, BTreeMap<String, Listing>>;
+
+/// The directory of an app's listing in a locale.  Packages and locales are named by the index of
+/// the repository mirrored, so they are made safe to use as file names.
+pub fn locale_dir(root: &Path, package: &str, locale: &str) -> PathBuf {
+    root.join(METADATA_DIR).join(safe_file_name(package)).join(safe_file_name(locale))
+}
+
+/// Write one of the texts of a listing, if the repository mirrored gives it.
+pub fn write_text(path: &Path, text: Option<&str>) -> io::Result<()> {
+    match text.map(str::trim).filter(|text| !text.is_empty()) {
+        Some(text) => {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, format!("{}\n", text))
+        },
+        None => Ok(()),
+    }
+}
+
+/// The listings in `dir`.
+pub fn read(dir: &Path) -> Result<Listings, Box<dyn Error>> {
+    let mut listings = Listings::new();
+    let metadata_dir = dir.join(METADATA_DIR);
+    if !metadata_dir.is_dir() {
+        return Ok(listings);
+    }
+    for package in subdirs(&metadata_dir)? {
+        let mut locales = BTreeMap::new();
+        for locale in subdirs(&package)? {
+            let listing = read_listing(dir, &locale)?;
+            locales.insert(file_name(&locale), listing);
+        }
+        if !locales.is_empty() {
+            listings.insert(file_name(&package), locales);
+        }
+    }
+    Ok(listings)
+}
+
+fn read_listing(dir: &Path, locale_dir: &Path) -> Result<Listing, Box<dyn Error>> {
+    let text = |name: &str| fs::read_to_string(locale_dir.join(name)).ok()
+        .map(|text| text.trim().to_string())
+        .filter(|text| !text.is_empty());
+    let mut changelogs = BTreeMap::new();
+    let changelogs_dir = locale_dir.join(CHANGELOGS_DIR);
+    if changelogs_dir.is_dir() {
+        for entry in fs::read_dir(&changelogs_dir)? {
+            let path = entry?.path();
+            let version_code = path.file_stem().and_then(|stem| stem.to_str()).and_then(|stem| stem.parse::<u64>().ok());
+            if let (Some(version_code), Some("txt")) = (version_code, path.extension().and_then(|ext| ext.to_str())) {
+                let changelog = fs::read_to_string(&path)?;
+                if !changelog.trim().is_empty() {
+                    changelogs.insert(version_code, changelog.trim().to_string());
+                }
+            }
+        }
+    }
+    let images_dir = locale_dir.join(IMAGES_DIR);
+    let icon = images_dir.join(ICON);
+    let icon = if icon.is_file() { Some(relative_name(dir, &icon)?) } else { None };
+    let mut phone_screenshots = vec![];
+    let screenshots_dir = images_dir.join(PHONE_SCREENSHOTS_DIR);
+    if screenshots_dir.is_dir() {
+        for entry in fs::read_dir(&screenshots_dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()).is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str())) {
+                phone_screenshots.push(path);
+            }
+        }
+    }
+    // Screenshots are numbered in the order they are shown, which is not the order of their names
+    // once there are ten of them
+    phone_screenshots.sort_by_key(|path| {
+        let number = path.file_stem().and_then(|stem| stem.to_str()).and_then(|stem| stem.parse::<u64>().ok());
+        (number.is_none(), number, path.clone())
+    });
+    Ok(Listing {
+        title: text(TITLE),
+        summary: text(SUMMARY),
+        description: text(DESCRIPTION),
+        changelogs,
+        icon,
+        phone_screenshots: phone_screenshots.iter().map(|path| relative_name(dir, path)).collect::<Result<_, _>>()?,
+    })
+}
+
+fn subdirs(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut dirs = vec![];
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            dirs.push(entry.path());
+        }
+    }
+    dirs.sort();
+    Ok(dirs)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().unwrap_or_default().to_string_lossy().into_owned()
+}
@@ -0,0 +1,132 @@
+//! A static, browsable HTML page listing every app in an output directory.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::apk::{self, Manifest};
+use crate::util::{find_files, relative_name, safe_file_name, sha256_file};
+
+/// Launcher icons by density, best first.  Icons are only found when resources keep their usual
+/// names, since resolving the manifest's icon reference would mean parsing `resources.arsc`.
+const ICON_DENSITIES: [&str; 5] = ["xxxhdpi", "xxhdpi", "xhdpi", "hdpi", "mdpi"];
+const MAX_ICON_SIZE: u64 = 1024 * 1024;
+
+struct IndexedFile {
+    name: String,
+    manifest: Manifest,
+    size: u64,
+    sha256: String,
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn format_size(size: u64) -> String {
+    if size >= 1024 * 1024 {
+        format!("{:.1} MiB", size as f64 / (1024.0 * 1024.0))
+    } else {
+        format!("{:.1} KiB", size as f64 / 1024.0)
+    }
+}
+
+/// Copy the launcher icon out of an APK as `icons/<package>.png`.
+fn extract_icon(dir: &Path, path: &Path, package: &str) -> Option<String> {
+    let mut archive = zip::ZipArchive::new(File::open(path).ok()?).ok()?;
+    let name = ICON_DENSITIES.iter().find_map(|density| {
+        ["mipmap", "drawable"].iter()
+            .map(|kind| format!("res/{}-{}-v4/ic_launcher.png", kind, density))
+            .chain(["mipmap", "drawable"].iter().map(|kind| format!("res/{}-{}/ic_launcher.png", kind, density)))
+            .find(|name| archive.by_name(name).is_ok())
+    })?;
+    let mut icon = vec![];
+    archive.by_name(&name).ok()?.take(MAX_ICON_SIZE).read_to_end(&mut icon).ok()?;
+
+    let icon_name = safe_file_name(&format!("{}.png", package));
+    fs::create_dir_all(dir.join("icons")).ok()?;
+    fs::write(dir.join("icons").join(&icon_name), icon).ok()?;
+    Some(format!("icons/{}", icon_name))
+}
+
+fn render_package(html: &mut String, package: &str, files: &[IndexedFile], icon: Option<String>) {
+    let label = files.iter()
+        .find_map(|file| file.manifest.label.clone())
+        .unwrap_or_else(|| package.to_string());
+    html.push_str("<section>\n<h2>");
+    if let Some(icon) = icon {
+        html.push_str(&format!("<img src=\"{}\" alt=\"\" width=\"48\" height=\"48\"> ", escape(&icon)));
+    }
+    html.push_str(&format!("{} <small>{}</small></h2>\n", escape(&label), escape(package)));
+    html.push_str("<table>\n<tr><th>Version</th><th>Version code</th><th>Size</th><th>SHA-256</th><th>File</th></tr>\n");
+    for file in files {
+        let version_name = file.manifest.version_name.as_deref().unwrap_or("");
+        let version_code = file.manifest.version_code.map_or(String::new(), |code| code.to_string());
+        let split = file.manifest.split.as_ref().map_or(String::new(), |split| format!(" (split {})", escape(split)));
+        html.push_str(&format!(
+            "<tr><td>{}{}</td><td>{}</td><td>{}</td><td><code>{}</code></td><td><a href=\"{}\">{}</a></td></tr>\n",
+            escape(version_name),
+            split,
+            version_code,
+            format_size(file.size),
+            file.sha256,
+            escape(&file.name),
+            escape(file.name.rsplit('/').next().unwrap_or(&file.name)),
+        ));
+    }
+    html.push_str("</table>\n</section>\n");
+}
+
+/// Write `index.html` for the APKs and XAPKs in `dir`, returning the number of packages listed.
+pub fn write_index(dir: &Path) -> Result<usize, Box<dyn Error>> {
+    let mut paths = vec![];
+    find_files(dir, &["apk", "xapk"], &mut paths)?;
+
+    let mut packages: BTreeMap<String, Vec<(IndexedFile, PathBuf)>> = BTreeMap::new();
+    for path in paths {
+        let manifest = match apk::read_manifest(&path) {
+            Ok(manifest) => manifest,
+            Err(err) => {
+                println!("Could not read the manifest of {}: {}. Skipping...", path.display(), err);
+                continue;
+            }
+        };
+        let file = IndexedFile {
+            name: relative_name(dir, &path)?,
+            size: fs::metadata(&path)?.len(),
+            sha256: sha256_file(&path)?,
+            manifest,
+        };
+        packages.entry(file.manifest.package.clone()).or_default().push((file, path));
+    }
+
+    let mut html = String::from(concat!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>APKs</title>\n",
+        "<style>body{font-family:sans-serif;margin:2em}h2 img{vertical-align:middle}",
+        "table{border-collapse:collapse}td,th{padding:.2em .6em;text-align:left}",
+        "code{font-size:.8em;word-break:break-all}</style>\n</head>\n<body>\n<h1>APKs</h1>\n",
+    ));
+    let num_packages = packages.len();
+    for (package, mut files) in packages {
+        // Newest first, with each base APK ahead of its splits
+        files.sort_by(|(a, _), (b, _)| {
+            b.manifest.version_code.cmp(&a.manifest.version_code)
+                .then(a.manifest.split.is_some().cmp(&b.manifest.split.is_some()))
+                .then(a.name.cmp(&b.name))
+        });
+        let icon = files.iter()
+            .filter(|(file, _)| file.manifest.split.is_none())
+            .find_map(|(_, path)| extract_icon(dir, path, &package));
+        let files = files.into_iter().map(|(file, _)| file).collect::<Vec<IndexedFile>>();
+        render_package(&mut html, &package, &files, icon);
+    }
+    html.push_str(&format!("<p><small>Generated by apkeep on {}</small></p>\n</body>\n</html>\n", chrono::Utc::now().format("%Y-%m-%d %H:%M UTC")));
+
+    fs::write(dir.join("index.html"), html)?;
+    Ok(num_packages)
+}
@@ -0,0 +1,292 @@
+//! Building an F-Droid repository out of a directory of downloaded APKs, so that devices can
+//! install and update them with the F-Droid client.
+
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::apk::{self, Manifest};
+use crate::util::{find_files, relative_name, sha256_file};
+
+pub mod fastlane;
+pub mod html;
+pub mod jar;
+pub use jar::RepoKey;
+use fastlane::{Listing, Listings};
+
+/// The index format version written, as understood by F-Droid clients since 1.x.
+const INDEX_VERSION: u64 = 20002;
+
+pub struct RepoSettings {
+    pub name: String,
+    pub description: Option<String>,
+    /// The URL the repository will be served from
+    pub address: Option<String>,
+}
+
+struct RepoApk {
+    /// Path relative to the repository, with forward slashes
+    name: String,
+    manifest: Manifest,
+    version_code: u64,
+    sha256: String,
+    size: u64,
+    signers: Vec<String>,
+    added: u64,
+}
+
+impl RepoApk {
+    fn label(&self) -> String {
+        self.manifest.label.clone().unwrap_or_else(|| self.manifest.package.clone())
+    }
+}
+
+fn read_apk(dir: &Path, path: &Path, manifest: Manifest) -> Result<Option<RepoApk>, Box<dyn Error>> {
+    let name = relative_name(dir, path)?;
+    let version_code = match manifest.version_code {
+        Some(version_code) => version_code,
+        None => {
+            println!("{} does not specify a versionCode. Skipping...", name);
+            return Ok(None);
+        }
+    };
+    let metadata = fs::metadata(path)?;
+    Ok(Some(RepoApk {
+        version_code,
+        sha256: sha256_file(path)?,
+        size: metadata.len(),
+        signers: apk::signature::signer_fingerprints(path).unwrap_or_default(),
+        added: metadata.modified()?.duration_since(UNIX_EPOCH)?.as_millis() as u64,
+        name,
+        manifest,
+    }))
+}
+
+/// Scan `dir` for APKs, grouped by package with the newest version first.  Split APKs cannot be
+/// installed by the F-Droid client, so directories containing them are skipped entirely.
+fn scan(dir: &Path) -> Result<BTreeMap<String, Vec<RepoApk>>, Box<dyn Error>> {
+    let mut paths = vec![];
+    find_files(dir, &["apk"], &mut paths)?;
+    let mut manifests = vec![];
+    for path in paths {
+        match apk::read_manifest(&path) {
+            Ok(manifest) => manifests.push((path, manifest)),
+            Err(err) => println!("Could not read the manifest of {}: {}. Skipping...", path.display(), err),
+        }
+    }
+    let split_dirs = manifests.iter()
+        .filter(|(_, manifest)| manifest.split.is_some())
+        .filter_map(|(path, _)| path.parent().map(Path::to_path_buf))
+        .collect::<HashSet<PathBuf>>();
+    for split_dir in &split_dirs {
+        println!("{} contains split APKs, which F-Droid cannot install. Skipping...", split_dir.display());
+    }
+
+    let mut packages: BTreeMap<String, Vec<RepoApk>> = BTreeMap::new();
+    for (path, manifest) in manifests {
+        if path.parent().is_some_and(|parent| split_dirs.contains(parent)) {
+            continue;
+        }
+        if let Some(apk) = read_apk(dir, &path, manifest)? {
+            packages.entry(apk.manifest.package.clone()).or_default().push(apk);
+        }
+    }
+    for apks in packages.values_mut() {
+        apks.sort_by(|a, b| b.version_code.cmp(&a.version_code));
+    }
+    Ok(packages)
+}
+
+/// One of the texts of an app's listings by locale, or `None` if no listing gives it.
+fn listing_texts(listings: Option<&BTreeMap<String, Listing>>, text: impl Fn(&Listing) -> Option<&str>) -> Option<Value> {
+    let texts = listings?.iter()
+        .filter_map(|(locale, listing)| text(listing).map(|text| (locale.clone(), json!(text))))
+        .collect::<Map<String, Value>>();
+    if texts.is_empty() { None } else { Some(Value::Object(texts)) }
+}
+
+/// A file in the repository, as the v2 index describes one.
+fn file_entry(dir: &Path, name: &str) -> Result<Value, Box<dyn Error>> {
+    let path = dir.join(name);
+    Ok(json!({
+        "name": format!("/{}", name),
+        "sha256": sha256_file(&path)?,
+        "size": fs::metadata(&path)?.len(),
+    }))
+}
+
+fn index_v1(settings: &RepoSettings, packages: &BTreeMap<String, Vec<RepoApk>>, listings: &Listings, timestamp: u64) -> Value {
+    let mut repo = json!({
+        "timestamp": timestamp,
+        "version": INDEX_VERSION,
+        "name": settings.name,
+        "description": settings.description.clone().unwrap_or_default(),
+    });
+    if let Some(address) = &settings.address {
+        repo["address"] = json!(address);
+    }
+    let apps = packages.iter().map(|(package, apks)| {
+        // Graphics are left to the v2 index, since v1 clients look for them beside the APKs
+        let localized = listings.get(package).map(|listings| listings.iter().map(|(locale, listing)| {
+            (locale.clone(), json!({
+                "name": listing.title,
+                "summary": listing.summary,
+                "description": listing.description,
+                "whatsNew": listing.changelogs.get(&apks[0].version_code),
+            }))
+        }).collect::<Map<String, Value>>());
+        let default_listing = listings.get(package)
+            .and_then(|listings| listings.get("en-US").or_else(|| listings.values().next()));
+        json!({
+            "packageName": package,
+            "name": default_listing.and_then(|listing| listing.title.clone()).unwrap_or_else(|| apks[0].label()),
+            "summary": default_listing.and_then(|listing| listing.summary.as_deref()),
+            "description": default_listing.and_then(|listing| listing.description.as_deref()),
+            "localized": localized,
+            "license": "Unknown",
+            "suggestedVersionCode": apks[0].version_code.to_string(),
+            "suggestedVersionName": apks[0].manifest.version_name,
+            "added": apks.iter().map(|apk| apk.added).min(),
+            "lastUpdated": apks.iter().map(|apk| apk.added).max(),
+        })
+    }).collect::<Vec<Value>>();
+    let versions = packages.iter().map(|(package, apks)| {
+        (package.clone(), Value::Array(apks.iter().map(|apk| {
+            json!({
+                "apkName": apk.name,
+                "hash": apk.sha256,
+                "hashType": "sha256",
+                "packageName": package,
+                "versionCode": apk.version_code,
+                "versionName": apk.manifest.version_name,
+                "size": apk.size,
+                "minSdkVersion": apk.manifest.min_sdk_version,
+                "targetSdkVersion": apk.manifest.target_sdk_version,
+                "signer": apk.signers.first(),
+                "uses-permission": apk.manifest.permissions.iter()
+                    .map(|permission| json!([permission, null]))
+                    .collect::<Vec<Value>>(),
+                "added": apk.added,
+            })
+        }).collect()))
+    }).collect::<Map<String, Value>>();
+    without_nulls(json!({
+        "repo": repo,
+        "requests": { "install": [], "uninstall": [] },
+        "apps": apps,
+        "packages": versions,
+    }))
+}
+
+fn index_v2(dir: &Path, settings: &RepoSettings, packages: &BTreeMap<String, Vec<RepoApk>>, listings: &Listings, timestamp: u64) -> Result<Value, Box<dyn Error>> {
+    let localized = |text: &str| json!({ "en-US": text });
+    let mut package_entries = Map::new();
+    for (package, apks) in packages {
+        let listings = listings.get(package);
+        let versions = apks.iter().map(|apk| {
+            (apk.sha256.clone(), json!({
+                "added": apk.added,
+                "whatsNew": listing_texts(listings, |listing| listing.changelogs.get(&apk.version_code).map(String::as_str)),
+                "file": {
+                    "name": format!("/{}", apk.name),
+                    "sha256": apk.sha256,
+                    "size": apk.size,
+                },
+                "manifest": {
+                    "versionName": apk.manifest.version_name,
+                    "versionCode": apk.version_code,
+                    "usesSdk": apk.manifest.min_sdk_version.map(|min_sdk_version| json!({
+                        "minSdkVersion": min_sdk_version,
+                        "targetSdkVersion": apk.manifest.target_sdk_version.unwrap_or(min_sdk_version),
+                    })),
+                    "signer": if apk.signers.is_empty() { None } else { Some(json!({ "sha256": apk.signers })) },
+                    "usesPermission": apk.manifest.permissions.iter()
+                        .map(|permission| json!({ "name": permission }))
+                        .collect::<Vec<Value>>(),
+                },
+            }))
+        }).collect::<Map<String, Value>>();
+        let mut icons = Map::new();
+        let mut phone_screenshots = Map::new();
+        for (locale, listing) in listings.into_iter().flatten() {
+            if let Some(icon) = &listing.icon {
+                icons.insert(locale.clone(), file_entry(dir, icon)?);
+            }
+            if !listing.phone_screenshots.is_empty() {
+                let screenshots = listing.phone_screenshots.iter()
+                    .map(|screenshot| file_entry(dir, screenshot))
+                    .collect::<Result<Vec<Value>, _>>()?;
+                phone_screenshots.insert(locale.clone(), Value::Array(screenshots));
+            }
+        }
+        package_entries.insert(package.clone(), json!({
+            "metadata": {
+                "name": listing_texts(listings, |listing| listing.title.as_deref()).unwrap_or_else(|| localized(&apks[0].label())),
+                "summary": listing_texts(listings, |listing| listing.summary.as_deref()),
+                "description": listing_texts(listings, |listing| listing.description.as_deref()),
+                "icon": if icons.is_empty() { None } else { Some(icons) },
+                "screenshots": if phone_screenshots.is_empty() { None } else { Some(json!({ "phone": phone_screenshots })) },
+                "license": "Unknown",
+                "added": apks.iter().map(|apk| apk.added).min(),
+                "lastUpdated": apks.iter().map(|apk| apk.added).max(),
+            },
+            "versions": versions,
+        }));
+    }
+    Ok(without_nulls(json!({
+        "repo": {
+            "name": localized(&settings.name),
+            "description": settings.description.as_deref().map(localized),
+            "address": settings.address,
+            "timestamp": timestamp,
+        },
+        "packages": package_entries,
+    })))
+}
+
+/// Drop object members which are null, as the F-Droid client treats absent and null differently.
+/// Nulls within arrays are kept, since the v1 `uses-permission` pairs rely on them.
+fn without_nulls(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(map.into_iter()
+            .filter(|(_, value)| !value.is_null())
+            .map(|(key, value)| (key, without_nulls(value)))
+            .collect()),
+        Value::Array(values) => Value::Array(values.into_iter().map(without_nulls).collect()),
+        value => value,
+    }
+}
+
+/// Write signed v1 and v2 indexes for the APKs in `dir`, with the listings in its `metadata/`,
+/// returning the number of packages.
+pub fn build(dir: &Path, settings: &RepoSettings, key: &RepoKey) -> Result<usize, Box<dyn Error>> {
+    let packages = scan(dir)?;
+    let listings = fastlane::read(dir)?;
+    let timestamp = chrono::Utc::now().timestamp_millis() as u64;
+
+    let index_v1 = serde_json::to_vec(&index_v1(settings, &packages, &listings, timestamp))?;
+    fs::write(dir.join("index-v1.json"), &index_v1)?;
+    jar::write_signed_jar(&dir.join("index-v1.jar"), "index-v1.json", &index_v1, key, true)?;
+
+    let index_v2 = serde_json::to_vec(&index_v2(dir, settings, &packages, &listings, timestamp)?)?;
+    fs::write(dir.join("index-v2.json"), &index_v2)?;
+    let entry = serde_json::to_vec(&json!({
+        "timestamp": timestamp,
+        "version": INDEX_VERSION,
+        "index": {
+            "name": "/index-v2.json",
+            "sha256": hex::encode(Sha256::digest(&index_v2)),
+            "size": index_v2.len(),
+            "numPackages": packages.len(),
+        },
+    }))?;
+    fs::write(dir.join("entry.json"), &entry)?;
+    jar::write_signed_jar(&dir.join("entry.jar"), "entry.json", &entry, key, false)?;
+
+    Ok(packages.len())
+}
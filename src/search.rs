@@ -0,0 +1,110 @@
+//! Finding the app meant by a name, such as `whatsapp`, given where an app ID was expected.  The
+//! source is searched and the best matches shown, with their app IDs and developers, for one to
+//! be chosen; `--first` takes the first without asking.  F-Droid is searched through its package
+//! index, and a scraped site through the search its rules describe.  Other sources have no search
+//! of their own, so APKCombo, which lists apps by their Google Play IDs, is searched for them.
+//! With `--output-format tsv`, the matches are printed as rows of the name searched for, app ID,
+//! app name and developer rather than offered to choose between, and everything else goes to
+//! stderr.
+
+use std::collections::HashMap;
+use std::io::{self, IsTerminal, Write};
+
+use crate::cli::DownloadSource;
+use crate::download_sources::{self, apkcombo, fdroid, scraped, SearchMatch};
+use crate::util::{is_app_id, tsv_row, OutputFormat};
+
+/// How many matches to offer to choose between
+const MAX_MATCHES: usize = 5;
+
+/// Replace the names in a list of apps with the app IDs of the apps chosen for them, dropping any
+/// for which no app was found or chosen.
+pub async fn resolve_names(
+    list: Vec<(String, Option<String>)>,
+    source: DownloadSource,
+    options: &HashMap<&str, &str>,
+    site: Option<&scraped::Site>,
+    first: bool,
+) -> Vec<(String, Option<String>)> {
+    let tsv = matches!(OutputFormat::from_options(options), OutputFormat::Tsv);
+    let say = |message: String| if tsv { eprintln!("{}", message) } else { println!("{}", message) };
+    let mut resolved = vec![];
+    for (app, version) in list {
+        if is_app_id(&app) {
+            resolved.push((app, version));
+            continue;
+        }
+        say(format!("{} is not an app ID, so searching {} for it...", app, source));
+        let matches = match source {
+            DownloadSource::FDroid => Ok(fdroid::search(&app, options).await),
+            DownloadSource::Scraped => match site {
+                Some(site) => site.search(&app).await.map_err(|err| err.to_string()),
+                None => Ok(vec![]),
+            },
+            _ => apkcombo::search(&app).await,
+        };
+        let matches = match matches {
+            Ok(matches) if matches.is_empty() => {
+                say(download_sources::failed(&app, format!("No app matching {} was found. Skipping...", app)));
+                continue;
+            },
+            Ok(matches) => matches,
+            Err(err) => {
+                say(download_sources::failed(&app, format!("Could not search for {}: {}. Skipping...", app, err)));
+                continue;
+            },
+        };
+        if tsv && !first {
+            for found in &matches {
+                println!("{}", tsv_row(&[&app, &found.app_id, &found.name, found.developer.as_deref().unwrap_or_default()]));
+            }
+            eprintln!("{}", download_sources::failed(&app, format!("Give the app ID of one of these for {}, or use --first to take the first. Skipping...", app)));
+            continue;
+        }
+        if let Some(chosen) = choose(&app, &matches, first, say) {
+            resolved.push((chosen.to_string(), version));
+        }
+    }
+    resolved
+}
+
+fn describe(found: &SearchMatch) -> String {
+    match &found.developer {
+        Some(developer) => format!("{} ({}) by {}", found.name, found.app_id, developer),
+        None => format!("{} ({})", found.name, found.app_id),
+    }
+}
+
+/// Choose between the matches for `app`: the first if `first`, or else whichever is picked at the
+/// prompt.  Without a terminal to ask at, nothing is chosen.
+fn choose<'a>(app: &str, matches: &'a [SearchMatch], first: bool, say: impl Fn(String)) -> Option<&'a str> {
+    if first {
+        say(format!("Taking {} for {}.", describe(&matches[0]), app));
+        return Some(&matches[0].app_id);
+    }
+    let matches = &matches[..matches.len().min(MAX_MATCHES)];
+    println!("Apps matching {}:", app);
+    for (number, found) in matches.iter().enumerate() {
+        println!("  {}) {}", number + 1, describe(found));
+    }
+    if !io::stdin().is_terminal() {
+        println!("{}", download_sources::failed(app, format!("Give the app ID of one of these for {}, or use --first to take the first. Skipping...", app)));
+        return None;
+    }
+    loop {
+        print!("Which app is {}? [1-{}, or Enter to skip]: ", app, matches.len());
+        io::stdout().flush().ok()?;
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer).ok()?;
+        match answer.trim() {
+            "" => {
+                println!("{}", download_sources::failed(app, format!("No app was chosen for {}. Skipping...", app)));
+                return None;
+            },
+            answer => match answer.parse::<usize>() {
+                Ok(number) if (1..=matches.len()).contains(&number) => return Some(&matches[number - 1].app_id),
+                _ => println!("Please give a number from 1 to {}.", matches.len()),
+            },
+        }
+    }
+}
@@ -0,0 +1,68 @@
+//! Checking downloads against the certificates their publishers are known to sign with, so that
+//! an app repackaged by a third-party mirror, which can't be signed with the publisher's key, is
+//! caught even though its own signature is valid.
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use simple_error::SimpleError;
+
+use crate::config;
+
+const BUNDLED_KNOWN_SIGNERS: &str = include_str!("known_signers.csv");
+const KNOWN_SIGNERS_FILE: &str = "known-signers.csv";
+
+pub struct KnownSigners {
+    signers: HashMap<String, HashSet<String>>,
+}
+
+impl KnownSigners {
+    /// The list bundled with apkeep, extended by `known-signers.csv` in the config directory and
+    /// the file given with `--known-signers`.
+    pub fn load(extra: Option<&Path>) -> Result<KnownSigners, Box<dyn Error>> {
+        let mut known_signers = KnownSigners { signers: HashMap::new() };
+        known_signers.add(BUNDLED_KNOWN_SIGNERS)?;
+        if let Ok(config_dir) = config::config_dir() {
+            let user_file = config_dir.join(KNOWN_SIGNERS_FILE);
+            if user_file.is_file() {
+                known_signers.add(&fs::read_to_string(&user_file)?)
+                    .map_err(|err| SimpleError::new(format!("{}: {}", user_file.display(), err)))?;
+            }
+        }
+        if let Some(extra) = extra {
+            known_signers.add(&fs::read_to_string(extra)?)
+                .map_err(|err| SimpleError::new(format!("{}: {}", extra.display(), err)))?;
+        }
+        Ok(known_signers)
+    }
+
+    /// Read `package,fingerprint` lines, ignoring blank lines and those starting with `#`.
+    /// Fingerprints may be given in either case, with or without colons.
+    fn add(&mut self, text: &str) -> Result<(), SimpleError> {
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (package, fingerprint) = line.split_once(',')
+                .ok_or_else(|| SimpleError::new(format!("line {} is not of the form package,fingerprint", i + 1)))?;
+            let fingerprint = fingerprint.trim().replace(':', "").to_lowercase();
+            if fingerprint.len() != 64 || hex::decode(&fingerprint).is_err() {
+                return Err(SimpleError::new(format!("line {} does not have a SHA-256 fingerprint", i + 1)));
+            }
+            self.signers.entry(package.trim().to_string()).or_default().insert(fingerprint);
+        }
+        Ok(())
+    }
+
+    pub fn knows(&self, package: &str) -> bool {
+        self.signers.contains_key(package)
+    }
+
+    /// Whether any of an APK's signers is a known signer of the package.
+    pub fn is_known_signer(&self, package: &str, signers: &[String]) -> bool {
+        self.signers.get(package).is_some_and(|known| signers.iter().any(|signer| known.contains(signer)))
+    }
+}
@@ -0,0 +1,506 @@
+//! Arranging downloads on disk: content-addressed storage, so that identical files fetched from
+//! several sources or in several runs only take up space once, stable links to the latest
+//! version of each app, a checksum manifest, pruning of old versions, the timestamps and
+//! permissions of downloaded files, and staging downloads in `--temp-dir`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use serde_json::json;
+use simple_error::SimpleError;
+
+use crate::apk;
+use crate::audit;
+use crate::consts;
+use crate::download_sources::DownloadedApp;
+use crate::util::digests::{self, Algorithm};
+use crate::util::{find_files, relative_name, safe_join, sha256_file};
+
+/// The pool of stored files, named by their SHA-256 hash, within the output directory.
+pub fn pool_dir(outpath: &Path) -> PathBuf {
+    outpath.join(".pool")
+}
+
+/// Move a downloaded file into the pool and replace it with a link to the pooled copy.  Returns
+/// true if an identical file was already pooled, in which case the download is discarded.
+pub fn deduplicate(pool: &Path, file: &Path) -> io::Result<bool> {
+    let hash = sha256_file(file)?;
+    let object = pool.join(&hash[..2]).join(&hash);
+    fs::create_dir_all(pool.join(&hash[..2]))?;
+    let already_stored = object.is_file();
+    if already_stored {
+        fs::remove_file(file)?;
+    } else {
+        fs::rename(file, &object)?;
+        // Links share the object's contents, so guard it against being written to through them
+        let mut permissions = fs::metadata(&object)?.permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&object, permissions)?;
+    }
+    link(&object, file)?;
+    Ok(already_stored)
+}
+
+/// Hard links keep the file usable even if the pool is moved, but aren't always possible, e.g.
+/// across filesystems.
+fn link(object: &Path, file: &Path) -> io::Result<()> {
+    fs::hard_link(object, file).or_else(|_| symlink(object, file))
+}
+
+#[cfg(unix)]
+fn symlink(object: &Path, file: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(object, file)
+}
+
+#[cfg(windows)]
+fn symlink(object: &Path, file: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(object, file)
+}
+
+/// Set the modification time of a downloaded file, such as to when its version was released, and
+/// its permissions, given as a Unix mode.
+pub fn set_file_attributes(file: &Path, modified: Option<SystemTime>, mode: Option<u32>) -> io::Result<()> {
+    if let Some(modified) = modified {
+        digests::set_modified(file, modified)?;
+    }
+    if let Some(mode) = mode {
+        set_mode(file, mode)?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set_mode(file: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(file, fs::Permissions::from_mode(mode))
+}
+
+/// Windows only has a read-only attribute, which is set if the mode allows no one to write.
+#[cfg(windows)]
+fn set_mode(file: &Path, mode: u32) -> io::Result<()> {
+    let mut permissions = fs::metadata(file)?.permissions();
+    permissions.set_readonly(mode & 0o222 == 0);
+    fs::set_permissions(file, permissions)
+}
+
+/// The directory downloads are staged in with `--temp-dir`, and the output directory they are
+/// moved into once complete
+static STAGING: OnceLock<(PathBuf, PathBuf)> = OnceLock::new();
+
+/// Stage downloads in `staging` for the rest of the run, rather than writing them into `outpath`.
+pub fn stage(staging: &Path, outpath: &Path) {
+    let _ = STAGING.set((staging.to_path_buf(), outpath.to_path_buf()));
+}
+
+/// Where a file downloaded to `path` will end up, which for a file staged in `--temp-dir` is in the
+/// output directory.  Files already there are compared with this, so that staging doesn't make
+/// every app download again.
+pub fn final_path(path: &Path) -> PathBuf {
+    match STAGING.get().and_then(|(staging, outpath)| Some(outpath.join(path.strip_prefix(staging).ok()?))) {
+        Some(final_path) => final_path,
+        None => path.to_path_buf(),
+    }
+}
+
+/// Move everything staged in `staging` into `outpath`, keeping its path within the directory,
+/// and return where each file was moved to.
+pub fn finalize_staged(staging: &Path, outpath: &Path) -> Result<BTreeMap<PathBuf, PathBuf>, Box<dyn Error>> {
+    let mut moved = BTreeMap::new();
+    let mut dirs = vec![staging.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                dirs.push(path);
+            } else {
+                let target = outpath.join(path.strip_prefix(staging)?);
+                move_file(&path, &target)?;
+                moved.insert(path, target);
+            }
+        }
+    }
+    Ok(moved)
+}
+
+/// Move a file, even to another filesystem, such as network storage, which it can't simply be
+/// renamed onto.  It is then copied beside the target and renamed into place, so that the target
+/// is never left holding part of the file.
+pub fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::rename(from, to).is_err() {
+        let mut partial = to.as_os_str().to_owned();
+        partial.push(".partial");
+        let partial = PathBuf::from(partial);
+        if let Err(err) = fs::copy(from, &partial).and_then(|_| fs::rename(&partial, to)) {
+            let _ = fs::remove_file(&partial);
+            return Err(err);
+        }
+        fs::remove_file(from)?;
+    }
+    digests::moved(from, to)
+}
+
+/// Move a download which failed verification into `quarantine/` in the output directory, keeping
+/// its path within the output directory, and write a `.reason.txt` file beside its base APK
+/// saying what went wrong.  Returns where the base APK was moved to.
+pub fn quarantine(outpath: &Path, app: &DownloadedApp, reason: &str) -> Result<PathBuf, Box<dyn Error>> {
+    let quarantine_dir = outpath.join(consts::QUARANTINE_DIR);
+    let mut moved = vec![];
+    for file in &app.files {
+        let target = quarantine_dir.join(file.strip_prefix(outpath)?);
+        fs::create_dir_all(target.parent().unwrap())?;
+        fs::rename(file, &target)?;
+        moved.push(target);
+    }
+    if app.files.len() > 1 {
+        if let Some(parent) = app.files[0].parent() {
+            if parent != outpath && fs::read_dir(parent)?.next().is_none() {
+                fs::remove_dir(parent)?;
+            }
+        }
+    }
+
+    let base = moved.into_iter().next().ok_or_else(|| SimpleError::new("No files were downloaded."))?;
+    let mut reason_file = base.clone().into_os_string();
+    reason_file.push(".reason.txt");
+    fs::write(reason_file, format!(
+        "app_id: {}\nversion: {}\nurl: {}\ndownloaded_at: {}\nreason: {}\n",
+        app.app_id,
+        app.version.as_deref().unwrap_or(""),
+        app.url.as_deref().unwrap_or(""),
+        app.downloaded_at.to_rfc3339(),
+        reason,
+    ))?;
+    audit::record("quarantine", &app.app_id, json!({ "file": base.display().to_string(), "reason": reason }));
+    Ok(base)
+}
+
+/// Point `latest/<app_id>.<ext>` in the output directory at a downloaded app's base APK (or XAPK),
+/// unless the link already points at a version at least as new.  Symlinks are relative, so the
+/// output directory can be moved; on Windows, where symlinks usually need extra privileges, the
+/// file is copied instead.  Returns whether the link was updated.
+pub fn update_latest_link(outpath: &Path, app: &DownloadedApp) -> Result<bool, Box<dyn Error>> {
+    let file = app.files.first().ok_or_else(|| SimpleError::new("No files were downloaded."))?;
+    let extension = file.extension().and_then(|ext| ext.to_str()).unwrap_or("apk");
+    let latest_dir = outpath.join("latest");
+    let link = safe_join(&latest_dir, &format!("{}.{}", app.app_id, extension));
+
+    if link.exists() {
+        let version_code = |path: &Path| apk::read_manifest(path).ok().and_then(|manifest| manifest.version_code);
+        if let (Some(current), Some(new)) = (version_code(&link), version_code(file)) {
+            if current >= new {
+                return Ok(false);
+            }
+        }
+    }
+
+    fs::create_dir_all(&latest_dir)?;
+    if link.symlink_metadata().is_ok() {
+        fs::remove_file(&link)?;
+    }
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(Path::new("..").join(file.strip_prefix(outpath)?), &link)?;
+    #[cfg(windows)]
+    fs::copy(file, &link)?;
+    Ok(true)
+}
+
+/// Add the given files to `SHA256SUMS` in the output directory, in the format `sha256sum -c`
+/// expects, and to `SHA1SUMS` and `MD5SUMS` with `--extra-checksums`.  Entries for files
+/// downloaded again are replaced rather than duplicated.  Returns the path of `SHA256SUMS`.
+pub fn update_checksums(outpath: &Path, files: &[&PathBuf]) -> Result<PathBuf, Box<dyn Error>> {
+    for algorithm in digests::extra() {
+        update_sums_file(outpath, files, *algorithm)?;
+    }
+    update_sums_file(outpath, files, Algorithm::Sha256)
+}
+
+fn update_sums_file(outpath: &Path, files: &[&PathBuf], algorithm: Algorithm) -> Result<PathBuf, Box<dyn Error>> {
+    let mut new_entries = vec![];
+    for file in files {
+        new_entries.push((relative_name(outpath, file)?, digests::hash(file, algorithm)?));
+    }
+
+    let checksums_file = outpath.join(algorithm.sums_file());
+    let mut contents = match fs::read_to_string(&checksums_file) {
+        Ok(contents) => contents.lines()
+            .filter(|line| match line.split_once("  ") {
+                Some((_, name)) => !new_entries.iter().any(|(new_name, _)| new_name == name),
+                None => true,
+            })
+            .map(|line| format!("{}\n", line))
+            .collect::<String>(),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(Box::new(err)),
+    };
+    for (name, hash) in new_entries {
+        contents.push_str(&format!("{}  {}\n", hash, name));
+    }
+    fs::write(&checksums_file, contents)?;
+    Ok(checksums_file)
+}
+
+/// APKs are already compressed, so higher levels mostly cost time.
+const ARCHIVE_COMPRESSION_LEVEL: i32 = 3;
+
+/// How many versions of each app to keep when pruning.  The newest version of an app is always
+/// kept, whatever its age.
+pub struct Retention {
+    pub keep: Option<usize>,
+    pub keep_days: Option<u64>,
+}
+
+#[derive(Default)]
+pub struct PruneSummary {
+    pub versions: usize,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// The versions of each app in the output directory, by package name and versionCode, with the
+/// files of each version.  Files whose manifest can't be read are reported and left out.
+pub fn archived_versions(outpath: &Path) -> Result<BTreeMap<String, BTreeMap<u64, Vec<PathBuf>>>, Box<dyn Error>> {
+    let mut paths = vec![];
+    find_files(outpath, &["apk", "xapk"], &mut paths)?;
+    // On Windows the latest links are copies, which are not versions of their own
+    let latest_dir = outpath.join("latest");
+    paths.retain(|path| !path.starts_with(&latest_dir));
+
+    let mut packages: BTreeMap<String, BTreeMap<u64, Vec<PathBuf>>> = BTreeMap::new();
+    for path in paths {
+        match apk::read_manifest(&path) {
+            Ok(manifest) => match manifest.version_code {
+                Some(version_code) => packages.entry(manifest.package).or_default()
+                    .entry(version_code).or_default().push(path),
+                None => println!("{}", tr!("prune-no-version-code", path = path.display().to_string())),
+            },
+            Err(err) => println!("{}", tr!("prune-manifest-unreadable", path = path.display().to_string(), error = err.to_string())),
+        }
+    }
+    Ok(packages)
+}
+
+/// Remove the versions of each app in the output directory which fall outside `retention`, along
+/// with their metadata sidecars and `SHA256SUMS` entries.  A split APK is removed together with
+/// the rest of its version.  With `only`, only those apps are pruned.  With `archive`, the
+/// versions are first added to the app's archive (see [`archive_files`]).  With `dry_run`, only
+/// report what would be removed.
+pub fn prune(outpath: &Path, retention: &Retention, only: Option<&BTreeSet<String>>, archive: bool, dry_run: bool) -> Result<PruneSummary, Box<dyn Error>> {
+    let mut packages = archived_versions(outpath)?;
+    if let Some(only) = only {
+        packages.retain(|package, _| only.contains(package));
+    }
+    let cutoff = retention.keep_days.map(|days| SystemTime::now() - Duration::from_secs(days * 24 * 60 * 60));
+    let mut summary = PruneSummary::default();
+    let mut removed = vec![];
+    for (package, versions) in packages {
+        let mut package_files = vec![];
+        for (index, (version_code, files)) in versions.into_iter().rev().enumerate() {
+            if index == 0 {
+                continue;
+            }
+            let beyond_count = retention.keep.is_some_and(|keep| index >= keep);
+            let beyond_age = cutoff.is_some_and(|cutoff| files.iter().all(|file| {
+                fs::metadata(file).and_then(|metadata| metadata.modified()).is_ok_and(|modified| modified < cutoff)
+            }));
+            if !beyond_count && !beyond_age {
+                continue;
+            }
+            let action = match (dry_run, archive) {
+                (true, false) => tr!("prune-would-remove", app = package.as_str(), version_code = version_code),
+                (true, true) => tr!("prune-would-archive", app = package.as_str(), version_code = version_code),
+                (false, false) => tr!("prune-removing", app = package.as_str(), version_code = version_code),
+                (false, true) => tr!("prune-archiving", app = package.as_str(), version_code = version_code),
+            };
+            println!("{}", action);
+            summary.versions += 1;
+            for file in files {
+                summary.files += 1;
+                summary.bytes += fs::metadata(&file)?.len();
+                package_files.push(file);
+            }
+        }
+        if dry_run || package_files.is_empty() {
+            continue;
+        }
+        if archive {
+            archive_files(outpath, &package, &package_files)?;
+        }
+        for file in package_files {
+            remove_version_file(outpath, &file)?;
+            removed.push(relative_name(outpath, &file)?);
+        }
+    }
+
+    let checksums_file = outpath.join("SHA256SUMS");
+    if !removed.is_empty() && checksums_file.is_file() {
+        let contents = fs::read_to_string(&checksums_file)?.lines()
+            .filter(|line| line.split_once("  ").map_or(true, |(_, name)| !removed.iter().any(|removed| removed == name)))
+            .map(|line| format!("{}\n", line))
+            .collect::<String>();
+        fs::write(&checksums_file, contents)?;
+    }
+    Ok(summary)
+}
+
+/// Add files to `archive/<app_id>.tar.zst` in the output directory, named by their path within
+/// it.  The archive is rewritten rather than appended to, so that it remains a single tarball which
+/// any `tar` can extract; files archived before under the same name are replaced.
+fn archive_files(outpath: &Path, package: &str, files: &[PathBuf]) -> Result<PathBuf, Box<dyn Error>> {
+    let archive_dir = outpath.join("archive");
+    fs::create_dir_all(&archive_dir)?;
+    // The package name is read from the APK, so can't be trusted to name a file
+    let archive_path = safe_join(&archive_dir, &format!("{}.tar.zst", package));
+    let temp_path = safe_join(&archive_dir, &format!(".{}.tar.zst.tmp", package));
+
+    let names = files.iter()
+        .map(|file| relative_name(outpath, file))
+        .collect::<Result<Vec<String>, _>>()?;
+    let mut builder = tar::Builder::new(zstd::Encoder::new(File::create(&temp_path)?, ARCHIVE_COMPRESSION_LEVEL)?);
+    if archive_path.is_file() {
+        let mut existing = tar::Archive::new(zstd::Decoder::new(File::open(&archive_path)?)?);
+        for entry in existing.entries()? {
+            let mut entry = entry?;
+            let name = entry.path()?.into_owned();
+            if names.iter().any(|new_name| Path::new(new_name) == name) {
+                continue;
+            }
+            let mut header = entry.header().clone();
+            builder.append_data(&mut header, name, &mut entry)?;
+        }
+    }
+    for (file, name) in files.iter().zip(names) {
+        builder.append_path_with_name(file, name)?;
+    }
+    builder.into_inner()?.finish()?;
+    fs::rename(&temp_path, &archive_path)?;
+    Ok(archive_path)
+}
+
+/// Remove a file along with its metadata sidecar, and its directory if that is left empty, as
+/// happens to the directory of a split APK.
+fn remove_version_file(outpath: &Path, file: &Path) -> io::Result<()> {
+    fs::remove_file(file)?;
+    let mut sidecar = file.as_os_str().to_owned();
+    sidecar.push(".meta.json");
+    match fs::remove_file(sidecar) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+        _ => {}
+    }
+    if let Some(parent) = file.parent().filter(|parent| *parent != outpath) {
+        if fs::read_dir(parent)?.next().is_none() {
+            fs::remove_dir(parent)?;
+        }
+    }
+    Ok(())
+}
+
+/// The kinds of archive a run can be written into with `--archive`, chosen by its extension
+#[derive(Clone, Copy)]
+pub enum ArchiveFormat {
+    TarZstd,
+    Tar,
+    Zip,
+}
+
+impl ArchiveFormat {
+    pub fn from_path(path: &Path) -> Option<ArchiveFormat> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            Some(ArchiveFormat::TarZstd)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveFormat::Tar)
+        } else if name.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// Every file within `dir`, except those in quarantine, which are not to be handed on.
+fn all_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            if entry.file_name() != consts::QUARANTINE_DIR {
+                all_files(&path, files)?;
+            }
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Write every file a run left in `dir` into a single archive at `archive_path`, named by their
+/// path within `dir`, replacing any archive already there.  Returns the number of files written.
+pub fn write_archive(dir: &Path, archive_path: &Path, format: ArchiveFormat) -> Result<usize, Box<dyn Error>> {
+    let mut files = vec![];
+    all_files(dir, &mut files)?;
+    files.sort();
+    let mut temp_path = archive_path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
+
+    if let Err(err) = write_entries(dir, &files, File::create(&temp_path)?, format) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(err);
+    }
+    fs::rename(&temp_path, archive_path)?;
+    Ok(files.len())
+}
+
+fn write_entries(dir: &Path, files: &[PathBuf], archive: File, format: ArchiveFormat) -> Result<(), Box<dyn Error>> {
+    match format {
+        ArchiveFormat::TarZstd => {
+            let mut builder = tar::Builder::new(zstd::Encoder::new(archive, ARCHIVE_COMPRESSION_LEVEL)?);
+            for file in files {
+                builder.append_path_with_name(file, relative_name(dir, file)?)?;
+            }
+            builder.into_inner()?.finish()?;
+        },
+        ArchiveFormat::Tar => {
+            let mut builder = tar::Builder::new(archive);
+            for file in files {
+                builder.append_path_with_name(file, relative_name(dir, file)?)?;
+            }
+            builder.into_inner()?;
+        },
+        ArchiveFormat::Zip => {
+            let mut zip = zip::ZipWriter::new(archive);
+            for file in files {
+                let metadata = fs::metadata(file)?;
+                let modified = DateTime::<Utc>::from(metadata.modified()?);
+                // Zip can't record times before 1980
+                let modified = zip::DateTime::from_date_and_time(
+                    modified.year().try_into().unwrap_or_default(),
+                    modified.month() as u8,
+                    modified.day() as u8,
+                    modified.hour() as u8,
+                    modified.minute() as u8,
+                    modified.second() as u8,
+                ).unwrap_or_default();
+                // APKs are already compressed, so are stored as they are
+                let options = zip::write::SimpleFileOptions::default()
+                    .compression_method(zip::CompressionMethod::Stored)
+                    .last_modified_time(modified)
+                    .large_file(metadata.len() >= u32::MAX as u64);
+                zip.start_file(relative_name(dir, file)?, options)?;
+                io::copy(&mut File::open(file)?, &mut zip)?;
+            }
+            zip.finish()?;
+        },
+    }
+    Ok(())
+}
@@ -0,0 +1,62 @@
+//! App IDs taken from the URLs of store pages, so that a link copied from a browser can be given
+//! in place of an app ID.  The store a URL is from also suggests the source to download from.
+
+use reqwest::Url;
+use simple_error::SimpleError;
+
+use crate::cli::DownloadSource;
+use crate::util::is_app_id;
+
+/// An app, as identified by a store URL
+pub struct StoreApp {
+    pub app_id: String,
+    /// The version, if the URL is of a particular one
+    pub version: Option<String>,
+    /// The source which downloads from the store
+    pub source: DownloadSource,
+}
+
+/// Whether an app given on the command line or in a CSV is a URL rather than an app ID.
+pub fn is_url(app: &str) -> bool {
+    app.starts_with("https://") || app.starts_with("http://")
+}
+
+/// Find the app a store URL is for.
+pub fn parse(url: &str) -> Result<StoreApp, SimpleError> {
+    let parsed = Url::parse(url).map_err(|err| SimpleError::new(format!("{} is not a valid URL: {}", url, err)))?;
+    let host = parsed.host_str().unwrap_or_default();
+    let host = host.strip_prefix("www.").or_else(|| host.strip_prefix("m.")).unwrap_or(host);
+    let segments = parsed.path_segments().map_or(vec![], |segments| segments.filter(|segment| !segment.is_empty()).collect::<Vec<_>>());
+    let app = |app_id: Option<&str>, version: Option<String>, source: DownloadSource| match app_id {
+        Some(app_id) => Ok(StoreApp { app_id: app_id.to_string(), version, source }),
+        None => Err(SimpleError::new(format!("{} does not name an app", url))),
+    };
+    match host {
+        // https://play.google.com/store/apps/details?id=<app_id>
+        "play.google.com" => {
+            let app_id = parsed.query_pairs().find(|(key, _)| key == "id").map(|(_, value)| value.into_owned());
+            app(app_id.as_deref().filter(|app_id| is_app_id(app_id)), None, DownloadSource::GooglePlay)
+        },
+        // https://f-droid.org/[<lang>/]packages/<app_id>/, or an APK in the repo, named
+        // <app_id>_<versionCode>.apk
+        "f-droid.org" => match segments.as_slice() {
+            [.., "repo", file] => {
+                let (app_id, version_code) = file.strip_suffix(".apk").and_then(|name| name.rsplit_once('_'))
+                    .filter(|(_, version_code)| version_code.parse::<u64>().is_ok())
+                    .ok_or_else(|| SimpleError::new(format!("{} is not an APK in the F-Droid repository", url)))?;
+                app(Some(app_id).filter(|app_id| is_app_id(app_id)), Some(format!("code:{}", version_code)), DownloadSource::FDroid)
+            },
+            _ => {
+                let app_id = segments.iter().skip_while(|segment| **segment != "packages").nth(1).copied();
+                app(app_id.filter(|app_id| is_app_id(app_id)), None, DownloadSource::FDroid)
+            },
+        },
+        // https://apkcombo.com/[<lang>/]<name>/<app_id>/[download/apk]
+        "apkcombo.com" => app(segments.iter().copied().find(|segment| is_app_id(segment)), None, DownloadSource::APKCombo),
+        // https://apkpure.com/<name>/<app_id>[/versions]
+        "apkpure.com" | "apkpure.net" => app(segments.iter().copied().find(|segment| is_app_id(segment)), None, DownloadSource::APKPure),
+        // https://www.apkmirror.com/apk/<developer>/<name>/<release>/
+        "apkmirror.com" => Err(SimpleError::new(format!("APKMirror URLs don't give the app ID, so give the app ID shown on {} instead", url))),
+        _ => Err(SimpleError::new(format!("{} is not the URL of a store apkeep recognises", url))),
+    }
+}
@@ -0,0 +1,56 @@
+use std::error::Error;
+use std::path::Path;
+
+use configparser::ini::Ini;
+use simple_error::SimpleError;
+
+use crate::download_sources::DownloadedApp;
+
+pub mod s3;
+pub mod sftp;
+pub mod webdav;
+
+/// A remote location given with `--upload`, to which downloaded files are copied.
+pub enum Destination {
+    S3(s3::S3Destination),
+    WebDav(webdav::WebDavDestination),
+    Sftp(sftp::SftpDestination),
+}
+
+impl Destination {
+    pub fn parse(location: &str, conf: Option<&Ini>) -> Result<Destination, Box<dyn Error>> {
+        match location.split_once("://") {
+            Some(("s3", path)) => Ok(Destination::S3(s3::S3Destination::new(path, conf)?)),
+            Some((scheme @ ("webdav" | "webdavs"), path)) => Ok(Destination::WebDav(webdav::WebDavDestination::new(scheme, path, conf)?)),
+            Some(("sftp", path)) => Ok(Destination::Sftp(sftp::SftpDestination::new(path, conf)?)),
+            _ => Err(Box::new(SimpleError::new(format!("Unsupported upload destination: {}", location)))),
+        }
+    }
+
+    /// Upload a file belonging to a downloaded app, as `remote_path` relative to the destination.
+    pub async fn upload(&self, http_client: &reqwest::Client, app: &DownloadedApp, file: &Path, remote_path: &str) -> Result<(), Box<dyn Error>> {
+        match self {
+            Destination::S3(destination) => destination.upload(http_client, app, file, remote_path).await,
+            Destination::WebDav(destination) => destination.upload(http_client, file, remote_path).await,
+            Destination::Sftp(destination) => destination.upload(file, remote_path).await,
+        }
+    }
+}
+
+fn content_type(file: &Path) -> &'static str {
+    match file.extension().and_then(|ext| ext.to_str()) {
+        Some("apk") => "application/vnd.android.package-archive",
+        Some("xapk") => "application/zip",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Percent-encode a string for use in a URL path, optionally leaving slashes intact so that
+/// remote paths keep their directory structure.
+fn uri_encode(value: &str, keep_slashes: bool) -> String {
+    value.bytes().map(|byte| match byte {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (byte as char).to_string(),
+        b'/' if keep_slashes => "/".to_string(),
+        _ => format!("%{:02X}", byte),
+    }).collect()
+}
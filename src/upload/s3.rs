@@ -0,0 +1,135 @@
+use std::env;
+use std::error::Error;
+use std::path::Path;
+
+use chrono::Utc;
+use configparser::ini::Ini;
+use reqwest::header::{AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE};
+use reqwest::Url;
+use ring::hmac;
+use sha2::{Digest, Sha256};
+use simple_error::SimpleError;
+
+use crate::credentials;
+use crate::download_sources::DownloadedApp;
+use crate::util::file_body;
+use super::{content_type, uri_encode};
+
+/// An S3-compatible bucket and key prefix, e.g. `s3://bucket/some/prefix`.  Credentials are taken
+/// from the usual `AWS_*` environment variables, falling back to the `[s3]` section of the config
+/// file.  Setting an endpoint allows using other S3-compatible services such as MinIO.
+pub struct S3Destination {
+    bucket: String,
+    prefix: String,
+    region: String,
+    endpoint: Option<String>,
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data.as_bytes()).as_ref().to_vec()
+}
+
+impl S3Destination {
+    pub fn new(location: &str, conf: Option<&Ini>) -> Result<S3Destination, Box<dyn Error>> {
+        let (bucket, prefix) = location.split_once('/').unwrap_or((location, ""));
+        if bucket.is_empty() {
+            return Err(Box::new(SimpleError::new("No bucket was specified for the S3 upload destination.")));
+        }
+        let setting = |env_var: &str, key: &str| {
+            env::var(env_var).ok()
+                .filter(|value| !value.is_empty())
+                .or_else(|| credentials::get(conf, "s3", key))
+        };
+        let access_key_id = setting("AWS_ACCESS_KEY_ID", "access_key_id")
+            .ok_or_else(|| SimpleError::new("No S3 access key id was found. Set AWS_ACCESS_KEY_ID or `access_key_id` in the [s3] section of the config file, or run `apkeep auth login s3`."))?;
+        let secret_access_key = setting("AWS_SECRET_ACCESS_KEY", "secret_access_key")
+            .ok_or_else(|| SimpleError::new("No S3 secret access key was found. Set AWS_SECRET_ACCESS_KEY or `secret_access_key` in the [s3] section of the config file, or run `apkeep auth login s3`."))?;
+        Ok(S3Destination {
+            bucket: bucket.to_string(),
+            prefix: prefix.trim_matches('/').to_string(),
+            region: setting("AWS_REGION", "region")
+                .or_else(|| env::var("AWS_DEFAULT_REGION").ok())
+                .unwrap_or_else(|| "us-east-1".to_string()),
+            endpoint: setting("AWS_ENDPOINT_URL", "endpoint").map(|endpoint| endpoint.trim_end_matches('/').to_string()),
+            access_key_id,
+            secret_access_key,
+            session_token: setting("AWS_SESSION_TOKEN", "session_token"),
+        })
+    }
+
+    fn object_url(&self, key: &str) -> String {
+        match &self.endpoint {
+            // Path-style addressing, which S3-compatible services generally expect
+            Some(endpoint) => format!("{}/{}/{}", endpoint, uri_encode(&self.bucket, false), uri_encode(key, true)),
+            None => format!("https://{}.s3.{}.amazonaws.com/{}", self.bucket, self.region, uri_encode(key, true)),
+        }
+    }
+
+    pub async fn upload(&self, http_client: &reqwest::Client, app: &DownloadedApp, file: &Path, remote_path: &str) -> Result<(), Box<dyn Error>> {
+        let key = if self.prefix.is_empty() {
+            remote_path.to_string()
+        } else {
+            format!("{}/{}", self.prefix, remote_path)
+        };
+        let url = Url::parse(&self.object_url(&key))?;
+        let host = match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            _ => return Err(Box::new(SimpleError::new("The S3 endpoint has no host."))),
+        };
+
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date_stamp = now.format("%Y%m%d").to_string();
+
+        // Every x-amz-* header has to be signed, including the object metadata
+        let mut headers = vec![
+            ("host".to_string(), host),
+            ("x-amz-content-sha256".to_string(), "UNSIGNED-PAYLOAD".to_string()),
+            ("x-amz-date".to_string(), amz_date.clone()),
+            ("x-amz-meta-package".to_string(), app.app_id.clone()),
+        ];
+        if let Some(version) = app.version.as_ref().filter(|version| version.chars().all(|c| c.is_ascii_graphic() || c == ' ')) {
+            headers.push(("x-amz-meta-version".to_string(), version.clone()));
+        }
+        if let Some(session_token) = &self.session_token {
+            headers.push(("x-amz-security-token".to_string(), session_token.clone()));
+        }
+        headers.sort();
+
+        let canonical_headers = headers.iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+            .collect::<String>();
+        let signed_headers = headers.iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<&str>>()
+            .join(";");
+        let canonical_request = format!("PUT\n{}\n\n{}\n{}\nUNSIGNED-PAYLOAD", url.path(), canonical_headers, signed_headers);
+        let scope = format!("{}/{}/s3/aws4_request", date_stamp, self.region);
+        let string_to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", amz_date, scope, hex::encode(Sha256::digest(canonical_request.as_bytes())));
+
+        let date_key = hmac_sha256(format!("AWS4{}", self.secret_access_key).as_bytes(), &date_stamp);
+        let region_key = hmac_sha256(&date_key, &self.region);
+        let service_key = hmac_sha256(&region_key, "s3");
+        let signing_key = hmac_sha256(&service_key, "aws4_request");
+        let signature = hex::encode(hmac_sha256(&signing_key, &string_to_sign));
+
+        let file_length = tokio::fs::metadata(file).await?.len();
+        let mut request = http_client.put(url)
+            .header(AUTHORIZATION, format!("AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}", self.access_key_id, scope, signed_headers, signature))
+            .header(CONTENT_TYPE, content_type(file))
+            .header(CONTENT_LENGTH, file_length);
+        for (name, value) in headers.into_iter().filter(|(name, _)| name != "host") {
+            request = request.header(name, value);
+        }
+        let response = request.body(file_body(tokio::fs::File::open(file).await?)).send().await?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(Box::new(SimpleError::new(format!("S3 responded with HTTP {}", response.status()))))
+        }
+    }
+}
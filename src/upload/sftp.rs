@@ -0,0 +1,126 @@
+use std::env;
+use std::error::Error;
+use std::fs::File;
+use std::io;
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+
+use configparser::ini::Ini;
+use reqwest::Url;
+use simple_error::SimpleError;
+use ssh2::{CheckResult, KnownHostFileKind, Session};
+
+use crate::credentials;
+
+/// Keys tried, in order, when no identity file is configured and no ssh-agent is available.
+const DEFAULT_IDENTITY_FILES: [&str; 3] = ["id_ed25519", "id_ecdsa", "id_rsa"];
+
+/// A directory on a server reachable over SSH, given as `sftp://user@host[:port]/path`.  Only
+/// key-based authentication is supported: an identity file may be given with
+/// `APKEEP_SFTP_IDENTITY_FILE` or `identity_file` in the `[sftp]` section of the config file,
+/// otherwise ssh-agent and then the default keys in `~/.ssh` are tried.  The server's host key
+/// must already be present in `~/.ssh/known_hosts`.
+#[derive(Clone)]
+pub struct SftpDestination {
+    host: String,
+    port: u16,
+    username: String,
+    path: PathBuf,
+    identity_file: Option<PathBuf>,
+    passphrase: Option<String>,
+}
+
+impl SftpDestination {
+    pub fn new(location: &str, conf: Option<&Ini>) -> Result<SftpDestination, Box<dyn Error>> {
+        let url = Url::parse(&format!("sftp://{}", location))?;
+        let host = url.host_str()
+            .ok_or_else(|| SimpleError::new("No host was specified for the SFTP upload destination."))?
+            .to_string();
+
+        let setting = |env_var: &str, key: &str| {
+            env::var(env_var).ok()
+                .filter(|value| !value.is_empty())
+                .or_else(|| credentials::get(conf, "sftp", key))
+        };
+        let username = Some(url.username().to_string())
+            .filter(|username| !username.is_empty())
+            .or_else(|| setting("APKEEP_SFTP_USERNAME", "username"))
+            .or_else(|| env::var("USER").ok())
+            .ok_or_else(|| SimpleError::new("No username was specified for the SFTP upload destination."))?;
+
+        Ok(SftpDestination {
+            host,
+            port: url.port().unwrap_or(22),
+            username,
+            path: PathBuf::from(url.path()),
+            identity_file: setting("APKEEP_SFTP_IDENTITY_FILE", "identity_file").map(PathBuf::from),
+            passphrase: setting("APKEEP_SFTP_PASSPHRASE", "passphrase"),
+        })
+    }
+
+    /// libssh2 is blocking, so the transfer runs on a separate thread.
+    pub async fn upload(&self, file: &Path, remote_path: &str) -> Result<(), Box<dyn Error>> {
+        let destination = self.clone();
+        let file = file.to_path_buf();
+        let remote_path = remote_path.to_string();
+        tokio::task::spawn_blocking(move || {
+            destination.upload_blocking(&file, &remote_path).map_err(|err| err.to_string())
+        }).await?.map_err(SimpleError::new)?;
+        Ok(())
+    }
+
+    fn upload_blocking(&self, file: &Path, remote_path: &str) -> Result<(), Box<dyn Error>> {
+        let mut session = Session::new()?;
+        session.set_tcp_stream(TcpStream::connect((self.host.as_str(), self.port))?);
+        session.handshake()?;
+        self.check_host_key(&session)?;
+        self.authenticate(&session)?;
+
+        let sftp = session.sftp()?;
+        let remote_path = Path::new(remote_path);
+        let mut directory = self.path.clone();
+        for component in remote_path.parent().into_iter().flat_map(|parent| parent.components()) {
+            directory.push(component);
+            if sftp.stat(&directory).is_err() {
+                sftp.mkdir(&directory, 0o755)?;
+            }
+        }
+        let mut remote_file = sftp.create(&self.path.join(remote_path))?;
+        io::copy(&mut File::open(file)?, &mut remote_file)?;
+        Ok(())
+    }
+
+    fn check_host_key(&self, session: &Session) -> Result<(), Box<dyn Error>> {
+        let (key, _) = session.host_key()
+            .ok_or_else(|| SimpleError::new("The SFTP server did not present a host key."))?;
+        let mut known_hosts = session.known_hosts()?;
+        if let Some(home_dir) = dirs::home_dir() {
+            // A missing known_hosts file is treated the same as a missing entry
+            let _ = known_hosts.read_file(&home_dir.join(".ssh").join("known_hosts"), KnownHostFileKind::OpenSSH);
+        }
+        match known_hosts.check_port(&self.host, self.port, key) {
+            CheckResult::Match => Ok(()),
+            CheckResult::NotFound => Err(Box::new(SimpleError::new(format!("The host key of {} is not in ~/.ssh/known_hosts. Connect once with ssh to verify and add it.", self.host)))),
+            CheckResult::Mismatch => Err(Box::new(SimpleError::new(format!("The host key of {} does not match the one in ~/.ssh/known_hosts. Refusing to upload.", self.host)))),
+            CheckResult::Failure => Err(Box::new(SimpleError::new(format!("Could not check the host key of {}.", self.host)))),
+        }
+    }
+
+    fn authenticate(&self, session: &Session) -> Result<(), Box<dyn Error>> {
+        if let Some(identity_file) = &self.identity_file {
+            session.userauth_pubkey_file(&self.username, None, identity_file, self.passphrase.as_deref())?;
+        } else if session.userauth_agent(&self.username).is_err() {
+            let ssh_dir = dirs::home_dir().unwrap_or_default().join(".ssh");
+            for identity_file in DEFAULT_IDENTITY_FILES.iter().map(|name| ssh_dir.join(name)) {
+                if identity_file.is_file() && session.userauth_pubkey_file(&self.username, None, &identity_file, self.passphrase.as_deref()).is_ok() {
+                    break;
+                }
+            }
+        }
+        if session.authenticated() {
+            Ok(())
+        } else {
+            Err(Box::new(SimpleError::new(format!("Could not authenticate to {} as {} with any key.", self.host, self.username))))
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use std::env;
+use std::error::Error;
+use std::path::Path;
+
+use configparser::ini::Ini;
+use reqwest::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use reqwest::{Method, StatusCode, Url};
+use simple_error::SimpleError;
+
+use crate::credentials;
+use crate::util::file_body;
+use super::{content_type, uri_encode};
+
+/// A directory on a WebDAV server such as Nextcloud, given as `webdav://host/path` (or
+/// `webdavs://` for HTTPS).  Credentials may be given in the URL, through the
+/// `APKEEP_WEBDAV_USERNAME` and `APKEEP_WEBDAV_PASSWORD` environment variables, or in the
+/// `[webdav]` section of the config file.
+pub struct WebDavDestination {
+    base_url: Url,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+impl WebDavDestination {
+    pub fn new(scheme: &str, location: &str, conf: Option<&Ini>) -> Result<WebDavDestination, Box<dyn Error>> {
+        let http_scheme = if scheme == "webdavs" { "https" } else { "http" };
+        let mut base_url = Url::parse(&format!("{}://{}", http_scheme, location))?;
+        if !base_url.path().ends_with('/') {
+            base_url.set_path(&format!("{}/", base_url.path()));
+        }
+
+        let setting = |env_var: &str, key: &str| {
+            env::var(env_var).ok()
+                .filter(|value| !value.is_empty())
+                .or_else(|| credentials::get(conf, "webdav", key))
+        };
+        let username = Some(base_url.username().to_string())
+            .filter(|username| !username.is_empty())
+            .or_else(|| setting("APKEEP_WEBDAV_USERNAME", "username"));
+        let password = base_url.password().map(String::from)
+            .or_else(|| setting("APKEEP_WEBDAV_PASSWORD", "password"));
+        let _ = base_url.set_username("");
+        let _ = base_url.set_password(None);
+
+        Ok(WebDavDestination {
+            base_url,
+            username,
+            password,
+        })
+    }
+
+    fn request(&self, http_client: &reqwest::Client, method: Method, url: Url) -> reqwest::RequestBuilder {
+        let request = http_client.request(method, url);
+        match &self.username {
+            Some(username) => request.basic_auth(username, self.password.as_ref()),
+            None => request,
+        }
+    }
+
+    pub async fn upload(&self, http_client: &reqwest::Client, file: &Path, remote_path: &str) -> Result<(), Box<dyn Error>> {
+        // Collections have to exist before anything can be put in them.  The server answers 405
+        // Method Not Allowed for those which already do.
+        let mkcol = Method::from_bytes(b"MKCOL")?;
+        let segments = remote_path.split('/').collect::<Vec<&str>>();
+        let mut collection_url = self.base_url.clone();
+        for directory in &segments[..segments.len() - 1] {
+            collection_url = collection_url.join(&format!("{}/", uri_encode(directory, false)))?;
+            let response = self.request(http_client, mkcol.clone(), collection_url.clone()).send().await?;
+            if !response.status().is_success() && response.status() != StatusCode::METHOD_NOT_ALLOWED {
+                return Err(Box::new(SimpleError::new(format!("WebDAV server responded with HTTP {} when creating {}", response.status(), directory))));
+            }
+        }
+
+        let url = collection_url.join(&uri_encode(segments[segments.len() - 1], false))?;
+        let file_length = tokio::fs::metadata(file).await?.len();
+        let response = self.request(http_client, Method::PUT, url)
+            .header(CONTENT_TYPE, content_type(file))
+            .header(CONTENT_LENGTH, file_length)
+            .body(file_body(tokio::fs::File::open(file).await?))
+            .send().await?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(Box::new(SimpleError::new(format!("WebDAV server responded with HTTP {}", response.status()))))
+        }
+    }
+}
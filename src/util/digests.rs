@@ -0,0 +1,202 @@
+//! Hashes of downloaded files, computed from the chunks as they are written rather than by reading
+//! the files again afterwards.  The hashes are remembered for as long as the file keeps the size and
+//! modification time it had when they were recorded, so that verification, `SHA256SUMS`, the
+//! metadata sidecars and the rest need not each read multi-gigabyte files from disk again.
+//!
+//! SHA-256 is always computed.  With `--extra-checksums`, MD5 and SHA-1 are too, for interop with
+//! archives which list files by those.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
+
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use simple_error::SimpleError;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Algorithm {
+    Sha256,
+    Sha1,
+    Md5,
+}
+
+impl Algorithm {
+    /// The algorithm's key in metadata sidecars
+    pub fn name(&self) -> &'static str {
+        match self {
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Sha1 => "sha1",
+            Algorithm::Md5 => "md5",
+        }
+    }
+
+    /// The file in the output directory listing the hashes, as `sha256sum` and its kin write them
+    pub fn sums_file(&self) -> &'static str {
+        match self {
+            Algorithm::Sha256 => "SHA256SUMS",
+            Algorithm::Sha1 => "SHA1SUMS",
+            Algorithm::Md5 => "MD5SUMS",
+        }
+    }
+}
+
+static EXTRA: OnceLock<Vec<Algorithm>> = OnceLock::new();
+
+/// What was recorded of a file, which is only trusted while the file is unchanged
+struct Recorded {
+    len: u64,
+    modified: Option<SystemTime>,
+    hashes: BTreeMap<Algorithm, String>,
+}
+
+static RECORDED: Mutex<BTreeMap<PathBuf, Recorded>> = Mutex::new(BTreeMap::new());
+
+/// Parse the comma-separated list of `--extra-checksums`, such as `md5,sha1`.
+pub fn configure_extra(algorithms: &str) -> Result<(), Box<dyn Error>> {
+    let mut extra = vec![];
+    for algorithm in algorithms.split(',').map(str::trim).filter(|algorithm| !algorithm.is_empty()) {
+        let algorithm = match algorithm.to_lowercase().as_str() {
+            "md5" => Algorithm::Md5,
+            "sha1" | "sha-1" => Algorithm::Sha1,
+            _ => return Err(Box::new(SimpleError::new(format!("{} is not md5 or sha1", algorithm)))),
+        };
+        if !extra.contains(&algorithm) {
+            extra.push(algorithm);
+        }
+    }
+    let _ = EXTRA.set(extra);
+    Ok(())
+}
+
+/// The algorithms asked for besides SHA-256
+pub fn extra() -> &'static [Algorithm] {
+    EXTRA.get().map_or(&[], Vec::as_slice)
+}
+
+/// Computes the hashes of a file as its chunks are written.
+pub struct StreamHasher {
+    sha256: Sha256,
+    sha1: Option<Sha1>,
+    md5: Option<Md5>,
+}
+
+impl StreamHasher {
+    /// A hasher for SHA-256 and the extra algorithms asked for
+    pub fn new() -> StreamHasher {
+        StreamHasher::with(extra())
+    }
+
+    fn with(algorithms: &[Algorithm]) -> StreamHasher {
+        StreamHasher {
+            sha256: Sha256::new(),
+            sha1: algorithms.contains(&Algorithm::Sha1).then(Sha1::new),
+            md5: algorithms.contains(&Algorithm::Md5).then(Md5::new),
+        }
+    }
+
+    pub fn update(&mut self, chunk: &[u8]) {
+        self.sha256.update(chunk);
+        if let Some(sha1) = &mut self.sha1 {
+            sha1.update(chunk);
+        }
+        if let Some(md5) = &mut self.md5 {
+            md5.update(chunk);
+        }
+    }
+
+    /// Remember the hashes as those of `path`, once everything has been written to it.
+    pub fn record(self, path: &Path) -> io::Result<()> {
+        remember(path, self.finish())
+    }
+
+    fn finish(self) -> BTreeMap<Algorithm, String> {
+        let mut hashes = BTreeMap::new();
+        hashes.insert(Algorithm::Sha256, hex::encode(self.sha256.finalize()));
+        if let Some(sha1) = self.sha1 {
+            hashes.insert(Algorithm::Sha1, hex::encode(sha1.finalize()));
+        }
+        if let Some(md5) = self.md5 {
+            hashes.insert(Algorithm::Md5, hex::encode(md5.finalize()));
+        }
+        hashes
+    }
+}
+
+impl Write for StreamHasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Remember the SHA-256 hash of `path`, for downloads which computed it themselves.
+pub fn record_sha256(path: &Path, sha256: &[u8]) -> io::Result<()> {
+    remember(path, BTreeMap::from([(Algorithm::Sha256, hex::encode(sha256))]))
+}
+
+fn remember(path: &Path, hashes: BTreeMap<Algorithm, String>) -> io::Result<()> {
+    let metadata = fs::metadata(path)?;
+    RECORDED.lock().unwrap().insert(path.to_path_buf(), Recorded {
+        len: metadata.len(),
+        modified: metadata.modified().ok(),
+        hashes,
+    });
+    Ok(())
+}
+
+/// Keep the hashes recorded for a file which has been moved, or copied and the original removed.
+pub fn moved(from: &Path, to: &Path) -> io::Result<()> {
+    let recorded = RECORDED.lock().unwrap().remove(from);
+    match recorded {
+        Some(recorded) => remember(to, recorded.hashes),
+        None => Ok(()),
+    }
+}
+
+/// Set the modification time of `path`.  Its contents are unchanged, so the hashes recorded for it
+/// are kept if they still held, rather than the file being read again to checksum it.
+pub fn set_modified(path: &Path, modified: SystemTime) -> io::Result<()> {
+    let mut recorded = RECORDED.lock().unwrap();
+    let metadata = fs::metadata(path)?;
+    let unchanged = recorded.get(path)
+        .is_some_and(|recorded| recorded.len == metadata.len() && recorded.modified == metadata.modified().ok());
+    File::options().write(true).open(path)?.set_modified(modified)?;
+    if unchanged {
+        if let Some(recorded) = recorded.get_mut(path) {
+            recorded.modified = fs::metadata(path)?.modified().ok();
+        }
+    }
+    Ok(())
+}
+
+/// The hash of `path` with `algorithm`, as recorded while it was written if it hasn't changed
+/// since.  Otherwise the file is read, once for SHA-256, every extra algorithm and `algorithm`, and
+/// the hashes are remembered for the next time.
+pub fn hash(path: &Path, algorithm: Algorithm) -> io::Result<String> {
+    let metadata = fs::metadata(path)?;
+    if let Some(recorded) = RECORDED.lock().unwrap().get(path) {
+        if recorded.len == metadata.len() && recorded.modified == metadata.modified().ok() {
+            if let Some(hash) = recorded.hashes.get(&algorithm) {
+                return Ok(hash.clone());
+            }
+        }
+    }
+    let mut algorithms = extra().to_vec();
+    algorithms.push(algorithm);
+    let mut hasher = StreamHasher::with(&algorithms);
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    let hashes = hasher.finish();
+    let hash = hashes[&algorithm].clone();
+    remember(path, hashes)?;
+    Ok(hash)
+}
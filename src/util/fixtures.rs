@@ -0,0 +1,123 @@
+//! Recording HTTP exchanges to fixture files and replaying them offline, for `--record-http` and
+//! `--replay-http`, which are built with the `fixtures` feature.  Recording a run against a live
+//! site and replaying it later shows whether a change to a scraper's parsing still finds the same
+//! apps, versions and download links, without depending on the site being up or unchanged.
+//!
+//! Each exchange is kept as `<key>.json`, holding the method, URL, status and response headers,
+//! and `<key>.body`, the response body as it was sent, where the key is derived from the method
+//! and URL.  A URL requested more than once keeps its last response.  Headers which may carry
+//! credentials, such as `Set-Cookie`, are not recorded.  Only requests made through
+//! [`trace::send`](super::trace::send) are recorded; downloads which a library makes itself are
+//! not, and are made from the network even when replaying.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use reqwest::header::{HeaderName, HeaderValue};
+use reqwest::{Client, Request, Response, ResponseBuilderExt, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::trace;
+
+enum Mode {
+    Record(PathBuf),
+    Replay(PathBuf),
+}
+
+static MODE: OnceLock<Mode> = OnceLock::new();
+
+#[derive(Serialize, Deserialize)]
+struct Fixture {
+    method: String,
+    url: String,
+    status: u16,
+    headers: BTreeMap<String, String>,
+}
+
+/// Record every exchange into `dir`.
+pub fn record(dir: &Path) {
+    let _ = MODE.set(Mode::Record(dir.to_path_buf()));
+}
+
+/// Answer every request from the exchanges recorded in `dir`, rather than from the network.
+pub fn replay(dir: &Path) {
+    let _ = MODE.set(Mode::Replay(dir.to_path_buf()));
+}
+
+/// Send a request, recording the exchange, or answer it from a recording.
+pub async fn execute(client: Client, request: Request) -> Result<Response, reqwest::Error> {
+    match MODE.get() {
+        Some(Mode::Record(dir)) => {
+            let key = key(request.method().as_str(), request.url());
+            let method = request.method().to_string();
+            let response = client.execute(request).await?;
+            let fixture = Fixture {
+                method,
+                url: trace::redact_url(response.url()),
+                status: response.status().as_u16(),
+                headers: response.headers().iter()
+                    .filter(|(name, _)| !trace::secret(name.as_str()))
+                    .map(|(name, value)| (name.to_string(), String::from_utf8_lossy(value.as_bytes()).to_string()))
+                    .collect(),
+            };
+            let url = response.url().clone();
+            let body = response.bytes().await?;
+            let written = fs::create_dir_all(dir)
+                .and_then(|_| fs::write(dir.join(format!("{}.json", key)), serde_json::to_string_pretty(&fixture)?))
+                .and_then(|_| fs::write(dir.join(format!("{}.body", key)), &body));
+            if let Err(err) = written {
+                eprintln!("Could not record the response from {} in {}: {}", fixture.url, dir.display(), err);
+            }
+            Ok(response_from(&fixture, url, body.to_vec()))
+        },
+        Some(Mode::Replay(dir)) => {
+            let key = key(request.method().as_str(), request.url());
+            let recorded = fs::read_to_string(dir.join(format!("{}.json", key)))
+                .ok()
+                .and_then(|fixture| serde_json::from_str::<Fixture>(&fixture).ok())
+                .zip(fs::read(dir.join(format!("{}.body", key))).ok());
+            match recorded {
+                Some((fixture, body)) => {
+                    let url = Url::parse(&fixture.url).unwrap_or_else(|_| request.url().clone());
+                    Ok(response_from(&fixture, url, body))
+                },
+                None => {
+                    // Answered as not found, rather than sent to the network, so that a replay never
+                    // depends on the site
+                    eprintln!("No response to {} {} was recorded in {}.", request.method(), request.url(), dir.display());
+                    let fixture = Fixture {
+                        method: request.method().to_string(),
+                        url: request.url().to_string(),
+                        status: StatusCode::NOT_FOUND.as_u16(),
+                        headers: BTreeMap::new(),
+                    };
+                    Ok(response_from(&fixture, request.url().clone(), vec![]))
+                },
+            }
+        },
+        None => client.execute(request).await,
+    }
+}
+
+/// The file name recorded exchanges are kept under: the host, to tell them apart when browsing
+/// the fixtures, and a hash of the method and URL.
+fn key(method: &str, url: &Url) -> String {
+    let hash = hex::encode(Sha256::digest(format!("{} {}", method, url).as_bytes()));
+    format!("{}-{}", url.host_str().unwrap_or("local"), &hash[..16])
+}
+
+fn response_from(fixture: &Fixture, url: Url, body: Vec<u8>) -> Response {
+    let mut builder = ::http::Response::builder()
+        .status(fixture.status)
+        .url(url);
+    for (name, value) in &fixture.headers {
+        // A header which no longer parses is dropped, rather than the whole response
+        if let (Ok(name), Ok(value)) = (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_str(value)) {
+            builder = builder.header(name, value);
+        }
+    }
+    Response::from(builder.body(body).unwrap())
+}
@@ -0,0 +1,311 @@
+//! Connection settings shared by the HTTP clients of the download sources, a limit on the
+//! number of requests made to any one host at a time, so that large parallel batches don't open
+//! more connections to a mirror than it will tolerate, a minimum interval between requests to each
+//! host, a cap on the bytes held in memory
+//! between the network and the disk, a DNS cache lasting the run, extra headers for each source,
+//! and the upgrading of scraped download URLs to HTTPS.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use futures_util::StreamExt;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_LENGTH};
+use reqwest::redirect::Policy;
+use reqwest::Url;
+use simple_error::SimpleError;
+use tokio::fs::File;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
+
+use crate::cli::DownloadSource;
+use super::digests::StreamHasher;
+use super::{network_error, sha256_file};
+
+pub const DEFAULT_MAX_BUFFERED_BYTES: usize = 64 * 1024 * 1024;
+/// As many as reqwest follows by default
+const MAX_REDIRECTS: usize = 10;
+/// Chunks queued for each file being written, on top of those counted against the budget.
+const WRITE_QUEUE_LENGTH: usize = 16;
+
+static SETTINGS: OnceLock<HttpSettings> = OnceLock::new();
+static HOSTS: Mutex<BTreeMap<String, Arc<Semaphore>>> = Mutex::new(BTreeMap::new());
+/// When the next request to each host may be made
+static NEXT_REQUEST: Mutex<BTreeMap<String, Instant>> = Mutex::new(BTreeMap::new());
+/// The semaphore counting buffered bytes, and its capacity
+static BUFFER_BUDGET: OnceLock<(Arc<Semaphore>, usize)> = OnceLock::new();
+static DNS_CACHE: Mutex<BTreeMap<String, Vec<SocketAddr>>> = Mutex::new(BTreeMap::new());
+
+#[derive(Clone)]
+pub struct HttpSettings {
+    pub max_connections_per_host: Option<usize>,
+    /// The least time between the starts of requests to any one host
+    pub host_interval: Option<Duration>,
+    /// Intervals for particular hosts, overriding `host_interval`
+    pub host_intervals: BTreeMap<String, Duration>,
+    /// How long idle connections are kept open for reuse; zero disables reuse
+    pub keep_alive: Option<Duration>,
+    /// The most bytes read from the network but not yet written to disk, across all downloads
+    pub max_buffered_bytes: usize,
+    /// Whether to download from plain HTTP URLs as they are, rather than upgrading them to HTTPS
+    pub allow_insecure: bool,
+    /// Extra headers sent with every request made by each source's client, by the name of the
+    /// source as for `-d`
+    pub headers: BTreeMap<String, HeaderMap>,
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        HttpSettings {
+            max_connections_per_host: None,
+            host_interval: None,
+            host_intervals: BTreeMap::new(),
+            keep_alive: None,
+            max_buffered_bytes: DEFAULT_MAX_BUFFERED_BYTES,
+            allow_insecure: false,
+            headers: BTreeMap::new(),
+        }
+    }
+}
+
+/// Set the connection settings for the rest of the run.  Only the first call has any effect.
+pub fn configure(settings: HttpSettings) {
+    let _ = SETTINGS.set(settings);
+}
+
+/// Resolves each host once per run, sharing the addresses between every client, so that thousands
+/// of downloads from a mirror don't each wait on (and hammer) the local resolver.  Failed lookups
+/// are not cached.
+struct CachingResolver;
+
+impl Resolve for CachingResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            if let Some(addrs) = DNS_CACHE.lock().unwrap().get(&host) {
+                return Ok(Box::new(addrs.clone().into_iter()) as Addrs);
+            }
+            // The port is replaced by the connector, so any will do
+            let addrs = tokio::net::lookup_host((host.as_str(), 0)).await?.collect::<Vec<SocketAddr>>();
+            DNS_CACHE.lock().unwrap().insert(host, addrs.clone());
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+pub fn client_builder() -> reqwest::ClientBuilder {
+    let settings = SETTINGS.get().cloned().unwrap_or_default();
+    let allow_insecure = settings.allow_insecure;
+    let mut builder = reqwest::Client::builder()
+        .dns_resolver(Arc::new(CachingResolver))
+        .redirect(Policy::custom(move |attempt| {
+            let downgrade = attempt.url().scheme() == "http"
+                && attempt.previous().last().is_some_and(|previous| previous.scheme() == "https");
+            if downgrade && !allow_insecure {
+                attempt.error("refusing to follow a redirect from HTTPS to plain HTTP")
+            } else if attempt.previous().len() >= MAX_REDIRECTS {
+                attempt.error("too many redirects")
+            } else {
+                attempt.follow()
+            }
+        }));
+    // This only caps the idle connections kept for reuse; requests are held to the limit by
+    // `host_permit`, as the pool itself would open as many connections as there are requests
+    if let Some(max_connections_per_host) = settings.max_connections_per_host {
+        builder = builder.pool_max_idle_per_host(max_connections_per_host);
+    }
+    match settings.keep_alive {
+        Some(keep_alive) if keep_alive.is_zero() => builder.pool_max_idle_per_host(0),
+        Some(keep_alive) => builder.pool_idle_timeout(keep_alive).tcp_keepalive(keep_alive),
+        None => builder,
+    }
+}
+
+pub fn client() -> reqwest::Client {
+    client_builder().build().unwrap_or_else(|_| reqwest::Client::new())
+}
+
+/// Add the extra headers given for `source` to a client builder.  They are added last, so that
+/// they take the place of any the source sets itself, such as its `User-Agent`.
+pub fn with_source_headers(builder: reqwest::ClientBuilder, source: DownloadSource) -> reqwest::ClientBuilder {
+    match SETTINGS.get().and_then(|settings| settings.headers.get(&source.to_string())) {
+        Some(headers) => builder.default_headers(headers.clone()),
+        None => builder,
+    }
+}
+
+/// A client for the requests `source` makes, sending the extra headers given for it
+pub fn source_client(source: DownloadSource) -> reqwest::Client {
+    with_source_headers(client_builder(), source).build().unwrap_or_else(|_| reqwest::Client::new())
+}
+
+/// Parse a header given as `Name: value`, for `--header` and the `[headers.<download_source>]`
+/// sections of the config file.
+pub fn parse_header(header: &str) -> Result<(HeaderName, HeaderValue), Box<dyn Error>> {
+    let (name, value) = header.split_once(':')
+        .ok_or_else(|| SimpleError::new(format!("{} is not of the form Name: value", header)))?;
+    let name = HeaderName::from_bytes(name.trim().as_bytes())
+        .map_err(|_| SimpleError::new(format!("{} is not a valid header name", name.trim())))?;
+    let value = HeaderValue::from_str(value.trim())
+        .map_err(|_| SimpleError::new(format!("The value of the {} header is not valid", name)))?;
+    Ok((name, value))
+}
+
+/// Upgrade a plain HTTP URL scraped from a source to HTTPS, unless `--allow-insecure` was given.
+/// Some mirrors link to their CDNs over plain HTTP, where a download can be swapped in transit.
+pub fn secure_url(url: &str) -> String {
+    let allow_insecure = SETTINGS.get().is_some_and(|settings| settings.allow_insecure);
+    match Url::parse(url) {
+        Ok(mut url) if url.scheme() == "http" && !allow_insecure => {
+            let _ = url.set_scheme("https");
+            url.to_string()
+        },
+        _ => url.to_string(),
+    }
+}
+
+/// How an existing download compares to what its URL would return now.
+pub enum Preflight {
+    Missing,
+    UpToDate,
+    Changed,
+}
+
+/// Compare a previous download at `path` with `url` without transferring it: by hash when the
+/// expected SHA-256 is known, or else by the size reported to a HEAD request.  If the size can't
+/// be learnt the file is assumed to be up to date, as it always was before.
+pub async fn preflight(url: &str, path: &Path, sha256: Option<&[u8]>) -> Preflight {
+    if !path.is_file() {
+        return Preflight::Missing;
+    }
+    if let Some(sha256) = sha256 {
+        return match sha256_file(path) {
+            Ok(local) if local == hex::encode(sha256) => Preflight::UpToDate,
+            _ => Preflight::Changed,
+        };
+    }
+    static PREFLIGHT_CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    let client = PREFLIGHT_CLIENT.get_or_init(client);
+    let remote_size = {
+        let _permit = host_permit(url).await;
+        client.head(url).send().await.ok()
+            .filter(|response| response.status().is_success())
+            .and_then(|response| response.headers().get(CONTENT_LENGTH)?.to_str().ok()?.parse::<u64>().ok())
+    };
+    match (remote_size, fs::metadata(path)) {
+        (Some(remote_size), Ok(metadata)) if remote_size != metadata.len() => Preflight::Changed,
+        _ => Preflight::UpToDate,
+    }
+}
+
+/// Wait until fewer than `--max-connections-per-host` requests are in flight to the host of
+/// `url`, and until its `--host-interval` has passed since the last request to it began.  The
+/// request may proceed while the returned permit is held.  Without a limit, or for a URL without
+/// a host, no permit is returned.
+pub async fn host_permit(url: &str) -> Option<OwnedSemaphorePermit> {
+    let settings = SETTINGS.get()?;
+    let host = Url::parse(url).ok()?.host_str()?.to_string();
+    let permit = match settings.max_connections_per_host {
+        Some(limit) => {
+            let semaphore = Arc::clone(HOSTS.lock().unwrap()
+                .entry(host.clone())
+                .or_insert_with(|| Arc::new(Semaphore::new(limit))));
+            semaphore.acquire_owned().await.ok()
+        },
+        None => None,
+    };
+    if let Some(interval) = settings.host_intervals.get(&host).or(settings.host_interval.as_ref()) {
+        // Each request takes the next free slot, so concurrent requests queue up in turn
+        let slot = {
+            let mut next_request = NEXT_REQUEST.lock().unwrap();
+            let now = Instant::now();
+            let slot = next_request.get(&host).map_or(now, |next| (*next).max(now));
+            next_request.insert(host, slot + *interval);
+            slot
+        };
+        tokio::time::sleep_until(slot.into()).await;
+    }
+    permit
+}
+
+/// Parse a comma-separated list of intervals in milliseconds for `--host-interval`, each either
+/// for every host, such as `1000`, or for one, such as `f-droid.org=2000`.
+pub fn parse_host_intervals(intervals: &str) -> Result<(Option<Duration>, BTreeMap<String, Duration>), Box<dyn Error>> {
+    let mut default = None;
+    let mut hosts = BTreeMap::new();
+    for interval in intervals.split(',').map(str::trim).filter(|interval| !interval.is_empty()) {
+        let (host, millis) = match interval.split_once('=') {
+            Some((host, millis)) => (Some(host.trim().to_lowercase()), millis.trim()),
+            None => (None, interval),
+        };
+        let millis = millis.parse::<u64>()
+            .map_err(|_| SimpleError::new(format!("{} is not a number of milliseconds", millis)))?;
+        match host {
+            Some(host) => { hosts.insert(host, Duration::from_millis(millis)); },
+            None => default = Some(Duration::from_millis(millis)),
+        }
+    }
+    Ok((default, hosts))
+}
+
+fn buffer_budget() -> (Arc<Semaphore>, usize) {
+    let (budget, capacity) = BUFFER_BUDGET.get_or_init(|| {
+        let max_buffered_bytes = SETTINGS.get().map_or(DEFAULT_MAX_BUFFERED_BYTES, |settings| settings.max_buffered_bytes);
+        let capacity = max_buffered_bytes.clamp(1, Semaphore::MAX_PERMITS.min(u32::MAX as usize));
+        (Arc::new(Semaphore::new(capacity)), capacity)
+    });
+    (Arc::clone(budget), *capacity)
+}
+
+/// Write the body of a response to `path` as it arrives, returning the number of bytes written.
+/// Reading and writing are decoupled by a bounded queue, and every chunk in between counts
+/// against the global buffer budget, so a slow disk holds back the network rather than letting
+/// memory grow.  The file's hashes are computed from the chunks as they are written.
+pub async fn write_response_to_file(response: reqwest::Response, path: &Path) -> Result<u64, Box<dyn Error>> {
+    // A file already there may be a read-only link into the `--dedup` pool, which is replaced
+    // rather than written through
+    match fs::remove_file(path) {
+        Ok(()) => {},
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {},
+        Err(err) => return Err(Box::new(err)),
+    }
+    let mut file = File::create(path).await?;
+    let (budget, capacity) = buffer_budget();
+    let (sender, mut receiver) = mpsc::channel(WRITE_QUEUE_LENGTH);
+
+    let read = async move {
+        let mut stream = response.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|err| SimpleError::new(network_error::describe(&err)))?;
+            // A chunk larger than the whole budget could never be admitted, so it waits for all of it
+            let permits = chunk.len().min(capacity) as u32;
+            let permit = Arc::clone(&budget).acquire_many_owned(permits).await?;
+            if sender.send((chunk, permit)).await.is_err() {
+                break;
+            }
+        }
+        Ok::<(), Box<dyn Error>>(())
+    };
+    let write = async {
+        let mut written = 0;
+        let mut hasher = StreamHasher::new();
+        while let Some((chunk, _permit)) = receiver.recv().await {
+            file.write_all(&chunk).await?;
+            hasher.update(&chunk);
+            written += chunk.len() as u64;
+        }
+        file.flush().await?;
+        Ok::<(u64, StreamHasher), Box<dyn Error>>((written, hasher))
+    };
+    let (read, write) = futures_util::join!(read, write);
+    read?;
+    let (written, hasher) = write?;
+    hasher.record(path)?;
+    Ok(written)
+}
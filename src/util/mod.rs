@@ -1,12 +1,99 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{self, Path, PathBuf};
+use std::sync::OnceLock;
+
+use sha2::{Digest, Sha256};
+use simple_error::SimpleError;
+use tokio::io::AsyncReadExt;
+
+use crate::consts;
+
+pub mod digests;
+#[cfg(feature = "fixtures")]
+pub mod fixtures;
+pub mod http;
+pub mod network_error;
 pub mod progress_bar;
+pub mod progress_events;
+pub mod retry;
+pub mod robots;
+pub mod throttle;
+pub mod timings;
+pub mod trace;
+
+const FILE_BODY_CHUNK_SIZE: usize = 64 * 1024;
+/// Characters which Windows doesn't allow in file names, besides control characters
+const RESERVED_FILE_NAME_CHARS: &[char] = &['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+/// Device names which Windows reserves, whatever the extension
+const RESERVED_FILE_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL",
+    "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+/// File systems generally allow 255 bytes; this leaves room for suffixes such as `.meta.json`.
+const MAX_FILE_NAME_LENGTH: usize = 200;
+/// The longest path Windows allows, less its terminating NUL, unless long paths are enabled
+const WINDOWS_MAX_PATH: usize = 259;
+/// Room left after a name shortened to fit within the longest path, for suffixes such as
+/// `.meta.json`
+const PATH_SUFFIX_ROOM: usize = 16;
+/// A name is not shortened to fit within the longest path if less than this would be left of it.
+const MIN_SHORTENED_LENGTH: usize = 32;
+
+static FILE_NAMES: OnceLock<FileNameSettings> = OnceLock::new();
+
+#[derive(Clone)]
+pub struct FileNameSettings {
+    /// What each character which can't be used in a file name is replaced with
+    pub replacement: String,
+    /// The longest a path may be, in UTF-16 units as Windows counts them
+    pub max_path: Option<usize>,
+}
+
+impl Default for FileNameSettings {
+    fn default() -> Self {
+        FileNameSettings {
+            replacement: "_".to_string(),
+            max_path: cfg!(windows).then_some(WINDOWS_MAX_PATH),
+        }
+    }
+}
+
+/// Set how file names are made safe for the rest of the run.  Only the first call has any effect.
+pub fn configure_file_names(settings: FileNameSettings) {
+    let _ = FILE_NAMES.set(settings);
+}
+
+fn file_name_settings() -> &'static FileNameSettings {
+    FILE_NAMES.get_or_init(FileNameSettings::default)
+}
+
+/// Whether `replacement` can stand in for characters in file names, containing none itself.
+pub fn valid_replacement(replacement: &str) -> bool {
+    !replacement.chars().any(|c| c.is_control() || RESERVED_FILE_NAME_CHARS.contains(&c))
+}
 
 #[derive(Clone)]
 pub enum OutputFormat {
     Json,
     Plaintext,
+    /// Tab-separated columns, a row for each result, with nothing else printed to stdout
+    Tsv,
 }
 
 impl OutputFormat {
+    /// The output format given by the `output_format` option, or set with `--output-format`.
+    pub fn from_options(options: &HashMap<&str, &str>) -> OutputFormat {
+        match options.get("output_format").map(|val| val.to_lowercase()).as_deref() {
+            Some("json") => OutputFormat::Json,
+            Some("tsv") => OutputFormat::Tsv,
+            _ => OutputFormat::Plaintext,
+        }
+    }
+
     pub fn is_json(&self) -> bool {
         if let Self::Json = self {
             true
@@ -24,4 +111,139 @@ impl OutputFormat {
     }
 }
 
+/// A row of tab-separated columns, with any tabs or line breaks within them made spaces so that
+/// the columns stay where they are.
+pub fn tsv_row(columns: &[&str]) -> String {
+    columns.iter()
+        .map(|column| column.replace(['\t', '\r', '\n'], " "))
+        .collect::<Vec<_>>()
+        .join("\t")
+}
+
+/// The hex-encoded SHA-256 digest of a file, as computed while it was downloaded, or otherwise by
+/// hashing it without reading it into memory.
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    digests::hash(path, digests::Algorithm::Sha256)
+}
+
+/// Whether `candidate` is a valid app ID, such as `org.example.app`, rather than a name or URL.
+pub fn is_app_id(candidate: &str) -> bool {
+    let mut parts = candidate.split('.');
+    candidate.contains('.') && parts.all(|part| {
+        part.starts_with(|c: char| c.is_ascii_alphabetic()) && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+/// Recursively collect the files in `dir` with any of the given extensions.
+pub fn find_files(dir: &Path, extensions: &[&str], files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        // Links such as those in `latest/` would list files twice, and quarantined files are not
+        // to be trusted
+        if entry.file_type()?.is_symlink() || entry.file_name() == consts::QUARANTINE_DIR {
+            continue;
+        }
+        let path = entry.path();
+        if path.is_dir() {
+            find_files(&path, extensions, files)?;
+        } else if path.extension().is_some_and(|ext| extensions.iter().any(|extension| ext == *extension)) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Make a file name built from app IDs, versions or other values taken from a source safe to
+/// create on any platform.  Path separators, control characters and those Windows forbids are
+/// replaced (with `_` unless configured otherwise), trailing dots and spaces are dropped, names
+/// Windows reserves (such as `CON`) are prefixed with `_`, and overlong names are shortened.
+pub fn safe_file_name(name: &str) -> String {
+    let replacement = &file_name_settings().replacement;
+    let mut name = name.chars()
+        .map(|c| if c.is_control() || RESERVED_FILE_NAME_CHARS.contains(&c) { replacement.clone() } else { c.to_string() })
+        .collect::<String>();
+    name.truncate(name.trim_end_matches(['.', ' ']).len());
+    if name.len() > MAX_FILE_NAME_LENGTH {
+        name = shorten(&name, MAX_FILE_NAME_LENGTH);
+    }
+    let stem = name.split('.').next().unwrap_or_default().trim_end();
+    if name.is_empty() || RESERVED_FILE_NAMES.iter().any(|reserved| reserved.eq_ignore_ascii_case(stem)) {
+        name.insert(0, '_');
+    }
+    name
+}
+
+/// Shorten a name to at most `length` bytes, keeping its extension, and adding part of the hash
+/// of the whole name so that names differing only in what was cut off stay distinct.
+fn shorten(name: &str, length: usize) -> String {
+    let extension = name.rfind('.').map(|i| &name[i..]).filter(|ext| ext.len() <= 16).unwrap_or_default();
+    let hash = format!("~{}", &hex::encode(Sha256::digest(name.as_bytes()))[..8]);
+    let mut end = length.saturating_sub(extension.len() + hash.len());
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{}{}", &name[..end], hash, extension)
+}
+
+/// The path of a file named from values taken from a source, which is always directly within
+/// `dir` whatever the name, since it is made safe with `safe_file_name` first.  Where paths are
+/// limited in length, as they are on Windows, the name is shortened so that the path fits.
+pub fn safe_join(dir: &Path, name: &str) -> PathBuf {
+    let mut name = safe_file_name(name);
+    if let Some(max_path) = file_name_settings().max_path {
+        // Relative paths count against the limit once they have been resolved
+        let dir_length = path::absolute(dir).unwrap_or_else(|_| dir.to_path_buf())
+            .to_string_lossy().encode_utf16().count();
+        let room = max_path.saturating_sub(dir_length + 1 + PATH_SUFFIX_ROOM);
+        // Bytes of UTF-8 are never fewer than the UTF-16 units Windows counts
+        if name.encode_utf16().count() > room && room >= MIN_SHORTENED_LENGTH {
+            name = shorten(&name, room);
+        }
+    }
+    dir.join(name)
+}
+
+/// The path of a file relative to `dir`, with forward slashes as used in URLs.
+pub fn relative_name(dir: &Path, path: &Path) -> Result<String, Box<dyn Error>> {
+    Ok(path.strip_prefix(dir)?.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/"))
+}
+
+/// Parse a size such as `2G` or `500M` in bytes, for `--skip-larger-than`.  The suffixes `K`, `M`,
+/// `G` and `T` (optionally followed by `B` or `iB`) count in powers of 1024; a size without one is
+/// in bytes.
+pub fn parse_size(size: &str) -> Result<u64, Box<dyn Error>> {
+    let size = size.trim();
+    let digits = size.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(size.len());
+    let (number, unit) = size.split_at(digits);
+    let shift = match unit.trim().to_uppercase().trim_end_matches("IB").trim_end_matches('B') {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => return Err(Box::new(SimpleError::new(format!("{} is not a unit of size", unit.trim())))),
+    };
+    let number = number.parse::<f64>()
+        .map_err(|_| SimpleError::new(format!("{} is not a size such as 2G or 500M", size)))?;
+    Ok((number * (1u64 << shift) as f64) as u64)
+}
 
+/// Stream a file as a request body rather than reading it into memory.
+pub fn file_body(file: tokio::fs::File) -> reqwest::Body {
+    let stream = futures_util::stream::unfold(Some(file), |file| async move {
+        let mut file = file?;
+        let mut buf = vec![0; FILE_BODY_CHUNK_SIZE];
+        match file.read(&mut buf).await {
+            Ok(0) => None,
+            Ok(read) => {
+                buf.truncate(read);
+                Some((Ok::<Vec<u8>, io::Error>(buf), Some(file)))
+            },
+            Err(err) => Some((Err(err), None)),
+        }
+    });
+    reqwest::Body::wrap_stream(stream)
+}
@@ -0,0 +1,58 @@
+use std::error::Error;
+use std::io;
+
+enum NetworkErrorKind {
+    Dns,
+    ConnectionRefused,
+    Tls,
+    Timeout,
+    Connect,
+    Other,
+}
+
+fn classify(err: &reqwest::Error) -> NetworkErrorKind {
+    if err.is_timeout() {
+        return NetworkErrorKind::Timeout;
+    }
+    // reqwest only exposes coarse categories, so walk the underlying hyper / io / tls errors to
+    // tell the connection-level failures apart.
+    let mut source = err.source();
+    while let Some(inner) = source {
+        if let Some(io_err) = inner.downcast_ref::<io::Error>() {
+            match io_err.kind() {
+                io::ErrorKind::ConnectionRefused => return NetworkErrorKind::ConnectionRefused,
+                io::ErrorKind::TimedOut => return NetworkErrorKind::Timeout,
+                _ => {},
+            }
+        }
+        let message = inner.to_string().to_lowercase();
+        if message.contains("dns error") || message.contains("failed to lookup address") || message.contains("name or service not known") {
+            return NetworkErrorKind::Dns;
+        }
+        if message.contains("certificate") || message.contains("tls") || message.contains("ssl") || message.contains("handshake") {
+            return NetworkErrorKind::Tls;
+        }
+        source = inner.source();
+    }
+    if err.is_connect() {
+        NetworkErrorKind::Connect
+    } else {
+        NetworkErrorKind::Other
+    }
+}
+
+/// Turn a `reqwest` error into a human-readable explanation with a suggestion of what to try,
+/// rather than surfacing the raw error chain.
+pub fn describe(err: &reqwest::Error) -> String {
+    let host = err.url()
+        .and_then(|url| url.host_str())
+        .map_or("the server".to_string(), |host| host.to_string());
+    match classify(err) {
+        NetworkErrorKind::Dns => format!("Could not resolve the address of {}. Check that you are online and that your DNS resolver is working; the site may also be blocked on your network.", host),
+        NetworkErrorKind::ConnectionRefused => format!("The connection to {} was refused. If you are using a proxy, check that it is running and that HTTP_PROXY / HTTPS_PROXY are set correctly.", host),
+        NetworkErrorKind::Tls => format!("A secure (TLS) connection to {} could not be established. A proxy or firewall may be intercepting traffic, or the site may be blocked on your network.", host),
+        NetworkErrorKind::Timeout => format!("The request to {} timed out. The server may be overloaded or your connection may be unreliable; try again later or with fewer parallel downloads.", host),
+        NetworkErrorKind::Connect => format!("Could not connect to {}. Check your internet connection and proxy settings.", host),
+        NetworkErrorKind::Other => format!("The request to {} failed: {}", host, err),
+    }
+}
@@ -3,9 +3,13 @@ use std::rc::Rc;
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 
 use crate::consts;
+use crate::util::progress_events;
 
 pub fn progress_wrapper(mp: Rc<MultiProgress>) -> Box<dyn Fn(String, u64) -> Box<dyn Fn(u64) -> ()>> {
     Box::new(move |filename, length| {
+        if progress_events::enabled() {
+            return progress_events::file_progress(filename, length);
+        }
         let mp1 = Rc::clone(&mp);
         let mp2 = Rc::clone(&mp);
         let pb = ProgressBar::new(length).with_message(filename);
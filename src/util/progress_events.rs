@@ -0,0 +1,63 @@
+//! Line-delimited JSON progress events on stderr, for `--progress json`, so that programs wrapping
+//! apkeep can show its progress without reading the progress bars.  Each line is an object with an
+//! `event` naming what happened: `file_started`, `file_progress`, `file_finished`, `app_failed`,
+//! `app_downloaded` and, at the end of the run, `finished`.  The progress bars are not drawn while
+//! the events are written.
+
+use std::cell::Cell;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use chrono::Utc;
+use serde_json::{json, Value};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+fn emit(event: &str, mut fields: Value) {
+    if !enabled() {
+        return;
+    }
+    fields["event"] = json!(event);
+    fields["time"] = json!(Utc::now().to_rfc3339());
+    eprintln!("{}", fields);
+}
+
+/// Report a file's download as it goes, in place of its progress bar.  Progress is reported each
+/// time another whole percent of the file has arrived, rather than for every chunk.
+pub fn file_progress(file: String, total: u64) -> Box<dyn Fn(u64)> {
+    emit("file_started", json!({ "file": file, "total": total }));
+    let percent = Cell::new(0);
+    let finished = Cell::new(false);
+    Box::new(move |downloaded| {
+        if finished.get() {
+            return;
+        }
+        if downloaded >= total {
+            finished.set(true);
+            emit("file_finished", json!({ "file": file, "total": total }));
+        } else if downloaded * 100 / total.max(1) > percent.get() {
+            percent.set(downloaded * 100 / total.max(1));
+            emit("file_progress", json!({ "file": file, "downloaded": downloaded, "total": total }));
+        }
+    })
+}
+
+pub fn app_failed(app_id: &str, reason: &str) {
+    emit("app_failed", json!({ "app_id": app_id, "reason": reason }));
+}
+
+pub fn app_downloaded(app_id: &str, source: &str, version: Option<&str>, files: &[PathBuf]) {
+    emit("app_downloaded", json!({ "app_id": app_id, "source": source, "version": version, "files": files }));
+}
+
+pub fn finished(downloaded: usize) {
+    emit("finished", json!({ "downloaded": downloaded }));
+}
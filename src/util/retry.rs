@@ -0,0 +1,146 @@
+//! Which failed requests are tried again, for `--retry-on`.  Some failures, such as a 404 or an app
+//! the source doesn't have, will fail however often they are retried, while others, such as rate
+//! limiting or a connection reset halfway through a download, almost always succeed on a second
+//! attempt.  A request is made at most [`MAX_ATTEMPTS`] times.
+
+use std::error::Error;
+use std::io;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use reqwest::header::RETRY_AFTER;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use simple_error::SimpleError;
+use tokio_dl_stream_to_disk::error::{Error as DownloadError, ErrorKind as DownloadErrorKind};
+
+pub use crate::cli::DEFAULT_RETRY_ON;
+
+pub const MAX_ATTEMPTS: u32 = 3;
+const FIRST_DELAY: Duration = Duration::from_secs(1);
+/// The longest a `Retry-After` header is waited for; sources asking for longer are given up on
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Condition {
+    /// A particular HTTP status, such as 429
+    Status(u16),
+    /// Any status in a class, such as 5 for 5xx
+    StatusClass(u16),
+    Timeout,
+    /// The connection was reset or closed before the response was complete
+    ConnReset,
+    /// No connection could be made at all, such as when it is refused
+    Connect,
+}
+
+static RETRY_ON: OnceLock<Vec<Condition>> = OnceLock::new();
+
+/// Parse the comma-separated conditions of `--retry-on`, such as `429,5xx,timeout,connreset`.
+/// `none` retries nothing.
+pub fn configure(retry_on: &str) -> Result<(), Box<dyn Error>> {
+    let _ = RETRY_ON.set(parse(retry_on)?);
+    Ok(())
+}
+
+fn parse(retry_on: &str) -> Result<Vec<Condition>, Box<dyn Error>> {
+    let mut conditions = vec![];
+    for condition in retry_on.split(',').map(str::trim).filter(|condition| !condition.is_empty()) {
+        let lower = condition.to_lowercase();
+        conditions.push(match lower.as_str() {
+            "none" => continue,
+            "timeout" => Condition::Timeout,
+            "connreset" => Condition::ConnReset,
+            "connect" => Condition::Connect,
+            _ => match (lower.strip_suffix("xx").map(str::parse::<u16>), lower.parse::<u16>()) {
+                (Some(Ok(class @ 1..=5)), _) => Condition::StatusClass(class),
+                (_, Ok(status @ 100..=599)) => Condition::Status(status),
+                _ => return Err(Box::new(SimpleError::new(format!("{} is not an HTTP status, a class of them such as 5xx, timeout, connreset, connect or none", condition)))),
+            },
+        });
+    }
+    Ok(conditions)
+}
+
+fn retry_on() -> &'static [Condition] {
+    RETRY_ON.get_or_init(|| parse(DEFAULT_RETRY_ON).unwrap())
+}
+
+/// Whether a response with this status is retried
+pub fn status(status: StatusCode) -> bool {
+    retry_on().iter().any(|condition| match condition {
+        Condition::Status(code) => status.as_u16() == *code,
+        Condition::StatusClass(class) => status.as_u16() / 100 == *class,
+        _ => false,
+    })
+}
+
+/// Whether a request which failed with this error is retried
+pub fn error(err: &reqwest::Error) -> bool {
+    if err.is_timeout() {
+        return retry_on().contains(&Condition::Timeout);
+    }
+    let mut source = err.source();
+    while let Some(inner) = source {
+        if let Some(io_err) = inner.downcast_ref::<io::Error>() {
+            return io_error(io_err);
+        }
+        source = inner.source();
+    }
+    if err.is_connect() {
+        retry_on().contains(&Condition::Connect)
+    } else {
+        // reqwest reports a body cut short as a body or decode error
+        (err.is_body() || err.is_decode()) && retry_on().contains(&Condition::ConnReset)
+    }
+}
+
+fn io_error(err: &io::Error) -> bool {
+    let condition = match err.kind() {
+        io::ErrorKind::TimedOut => Condition::Timeout,
+        io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted | io::ErrorKind::BrokenPipe | io::ErrorKind::UnexpectedEof => Condition::ConnReset,
+        io::ErrorKind::ConnectionRefused | io::ErrorKind::NotConnected => Condition::Connect,
+        _ => return false,
+    };
+    retry_on().contains(&condition)
+}
+
+/// Whether a download which failed with this error is tried again.  The status of an invalid
+/// response isn't known, so it is retried if any status is.
+pub fn download_error(err: &DownloadError) -> bool {
+    match err.kind() {
+        DownloadErrorKind::InvalidResponse => retry_on().iter().any(|condition| matches!(condition, Condition::Status(_) | Condition::StatusClass(_))),
+        DownloadErrorKind::IO(io_err) => io_error(io_err),
+        DownloadErrorKind::Other(other) => other.downcast_ref::<reqwest::Error>().is_some_and(error),
+        DownloadErrorKind::FileExists | DownloadErrorKind::DirectoryMissing | DownloadErrorKind::PermissionDenied => false,
+    }
+}
+
+/// Send a request, sending it again after a failure `--retry-on` retries, waiting a second and then
+/// twice as long each time, or as long as a `Retry-After` header asks.  A request whose body
+/// can't be sent twice is only sent once.
+pub async fn send(request: RequestBuilder) -> Result<Response, reqwest::Error> {
+    let mut delay = FIRST_DELAY;
+    for _ in 1..MAX_ATTEMPTS {
+        let attempt = match request.try_clone() {
+            Some(attempt) => attempt,
+            None => break,
+        };
+        match super::trace::send(attempt).await {
+            Ok(response) if status(response.status()) => {
+                let retry_after = response.headers().get(RETRY_AFTER)
+                    .and_then(|retry_after| retry_after.to_str().ok())
+                    .and_then(|retry_after| retry_after.trim().parse::<u64>().ok())
+                    .map(Duration::from_secs);
+                match retry_after {
+                    Some(retry_after) if retry_after > MAX_RETRY_AFTER => return Ok(response),
+                    Some(retry_after) => tokio::time::sleep(retry_after).await,
+                    None => tokio::time::sleep(delay).await,
+                }
+            },
+            Err(err) if error(&err) => tokio::time::sleep(delay).await,
+            result => return result,
+        }
+        delay *= 2;
+    }
+    super::trace::send(request).await
+}
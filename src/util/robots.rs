@@ -0,0 +1,168 @@
+//! Honouring the robots.txt of the sites apkeep scrapes, for `--respect-robots`, for those whose
+//! policies require it.  Each site's robots.txt is fetched once per run, and the group for
+//! `apkeep`, or else the one for every crawler, decides which URLs may be requested and how long
+//! to wait between them.  As RFC 9309 has it, a site without a robots.txt may be crawled freely,
+//! and one whose robots.txt can't be fetched because of a server or network error may not be
+//! crawled at all.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use regex::Regex;
+use reqwest::Url;
+use simple_error::SimpleError;
+use tokio::sync::OnceCell;
+
+use super::{http, network_error, trace};
+
+/// The name apkeep looks for its group under
+const USER_AGENT: &str = "apkeep";
+/// The longest crawl delay honoured; a site asking for more is still only visited this often
+const MAX_CRAWL_DELAY: Duration = Duration::from_secs(600);
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+/// The robots.txt of each origin, fetched by whichever request to it comes first
+static ROBOTS: Mutex<BTreeMap<String, Arc<OnceCell<Robots>>>> = Mutex::new(BTreeMap::new());
+/// When the next request to each origin with a crawl delay may be made
+static NEXT_REQUEST: Mutex<BTreeMap<String, Instant>> = Mutex::new(BTreeMap::new());
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+struct Rule {
+    allow: bool,
+    /// The length of the path as written, by which the most specific rule is chosen
+    length: usize,
+    pattern: Regex,
+}
+
+/// The user agents a group of lines names, and the lines which follow them
+struct Group {
+    agents: Vec<String>,
+    lines: Vec<(String, String)>,
+}
+
+enum Robots {
+    Rules { rules: Vec<Rule>, crawl_delay: Option<Duration> },
+    /// The robots.txt couldn't be fetched, for this reason
+    Unreachable(String),
+}
+
+/// Check that robots.txt allows `url` to be requested, waiting out its crawl delay if it has one.
+/// Without `--respect-robots`, every URL is allowed.
+pub async fn allow(url: &str) -> Result<(), Box<dyn Error>> {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+    let url = Url::parse(url)?;
+    let origin = url.origin().ascii_serialization();
+    let cell = Arc::clone(ROBOTS.lock().unwrap().entry(origin.clone()).or_default());
+    let robots = cell.get_or_init(|| fetch(&origin)).await;
+    let (rules, crawl_delay) = match robots {
+        Robots::Rules { rules, crawl_delay } => (rules, crawl_delay),
+        Robots::Unreachable(reason) => return Err(Box::new(SimpleError::new(format!("{} may not be crawled, as its robots.txt could not be fetched: {}", origin, reason)))),
+    };
+    let path = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    };
+    let rule = rules.iter()
+        .filter(|rule| rule.pattern.is_match(&path))
+        .max_by_key(|rule| (rule.length, rule.allow));
+    if rule.is_some_and(|rule| !rule.allow) {
+        return Err(Box::new(SimpleError::new(format!("The robots.txt of {} disallows {}", origin, url))));
+    }
+    if let Some(crawl_delay) = crawl_delay {
+        let slot = {
+            let mut next_request = NEXT_REQUEST.lock().unwrap();
+            let now = Instant::now();
+            let slot = next_request.get(&origin).map_or(now, |next| (*next).max(now));
+            next_request.insert(origin, slot.checked_add(*crawl_delay).unwrap_or(slot));
+            slot
+        };
+        tokio::time::sleep_until(slot.into()).await;
+    }
+    Ok(())
+}
+
+async fn fetch(origin: &str) -> Robots {
+    let response = match trace::send(http::client().get(format!("{}/robots.txt", origin))).await {
+        Ok(response) => response,
+        Err(err) => return Robots::Unreachable(network_error::describe(&err)),
+    };
+    let status = response.status();
+    if status.is_client_error() {
+        return Robots::Rules { rules: vec![], crawl_delay: None };
+    }
+    if !status.is_success() {
+        return Robots::Unreachable(format!("HTTP {}", status));
+    }
+    match response.text().await {
+        Ok(text) => parse(&text),
+        Err(err) => Robots::Unreachable(network_error::describe(&err)),
+    }
+}
+
+/// The rules and crawl delay of the group for apkeep, or of the group for every crawler if there
+/// is none for apkeep
+fn parse(text: &str) -> Robots {
+    let mut groups: Vec<Group> = vec![];
+    let mut naming = false;
+    for line in text.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        let (key, value) = match line.split_once(':') {
+            Some((key, value)) => (key.trim().to_lowercase(), value.trim().to_string()),
+            None => continue,
+        };
+        if key == "user-agent" {
+            if !naming {
+                groups.push(Group { agents: vec![], lines: vec![] });
+                naming = true;
+            }
+            groups.last_mut().unwrap().agents.push(value.to_lowercase());
+        } else if let Some(group) = groups.last_mut() {
+            naming = false;
+            group.lines.push((key, value));
+        }
+    }
+    let group_for = |agent: &str| groups.iter()
+        .filter(|group| group.agents.iter().any(|named| named == agent))
+        .flat_map(|group| &group.lines)
+        .collect::<Vec<_>>();
+    let mut lines = group_for(USER_AGENT);
+    if lines.is_empty() {
+        lines = group_for("*");
+    }
+
+    let mut rules = vec![];
+    let mut crawl_delay = None;
+    for (key, value) in lines {
+        match key.as_str() {
+            // An empty Disallow allows everything, so it is no rule at all
+            "allow" | "disallow" if !value.is_empty() => {
+                if let Some(pattern) = pattern(value) {
+                    rules.push(Rule { allow: key == "allow", length: value.len(), pattern });
+                }
+            },
+            "crawl-delay" => crawl_delay = value.parse::<f64>().ok()
+                .filter(|seconds| seconds.is_finite() && *seconds > 0.0)
+                .map(|seconds| Duration::try_from_secs_f64(seconds).map_or(MAX_CRAWL_DELAY, |delay| delay.min(MAX_CRAWL_DELAY))),
+            _ => {},
+        }
+    }
+    Robots::Rules { rules, crawl_delay }
+}
+
+/// A path pattern, in which `*` matches anything and a final `$` the end of the path
+fn pattern(path: &str) -> Option<Regex> {
+    let (path, anchored) = match path.strip_suffix('$') {
+        Some(path) => (path, true),
+        None => (path, false),
+    };
+    let pattern = path.split('*').map(regex::escape).collect::<Vec<_>>().join(".*");
+    Regex::new(&format!("^{}{}", pattern, if anchored { "$" } else { "" })).ok()
+}
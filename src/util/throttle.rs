@@ -0,0 +1,135 @@
+//! Adapting the parallelism and delay of requests to a source to how well it is coping.  Both back
+//! off when requests fail or are rate-limited, and recover gradually as requests succeed, so big
+//! batches slow down and complete rather than collapsing into a wall of failures.
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use indicatif::MultiProgress;
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tokio::time::sleep;
+
+/// The delay first applied after a failure, if none was asked for.
+const MIN_BACKOFF_DELAY: Duration = Duration::from_millis(500);
+const MAX_DELAY: Duration = Duration::from_secs(60);
+/// Consecutive successes after which one more request is allowed in parallel.
+const RECOVERY_SUCCESSES: usize = 5;
+
+pub enum Outcome {
+    Success,
+    Failure,
+    RateLimited,
+}
+
+impl Outcome {
+    /// Classify a response status: 429 means rate-limited, and server errors mean the source is
+    /// struggling.  Other client errors, such as a missing app, say nothing about the load.
+    pub fn from_status(status: reqwest::StatusCode) -> Outcome {
+        if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            Outcome::RateLimited
+        } else if status.is_server_error() {
+            Outcome::Failure
+        } else {
+            Outcome::Success
+        }
+    }
+}
+
+struct State {
+    max: usize,
+    limit: usize,
+    /// Permits to take out of circulation as they are released, after the limit was lowered
+    /// while they were in use
+    debt: usize,
+    base_delay: Duration,
+    delay: Duration,
+    successes: usize,
+}
+
+pub struct Throttle {
+    source: &'static str,
+    mp: MultiProgress,
+    slots: Semaphore,
+    state: Mutex<State>,
+}
+
+impl Throttle {
+    pub fn new(source: &'static str, parallel: usize, sleep_duration: u64, mp: &MultiProgress) -> Throttle {
+        let parallel = parallel.max(1);
+        let delay = Duration::from_millis(sleep_duration);
+        Throttle {
+            source,
+            mp: mp.clone(),
+            slots: Semaphore::new(parallel),
+            state: Mutex::new(State {
+                max: parallel,
+                limit: parallel,
+                debt: 0,
+                base_delay: delay,
+                delay,
+                successes: 0,
+            }),
+        }
+    }
+
+    /// Wait for a slot within the current parallelism, then for the current delay.  The slot is
+    /// held until the returned permit is dropped.
+    pub async fn acquire(&self) -> SemaphorePermit<'_> {
+        loop {
+            let permit = self.slots.acquire().await.unwrap();
+            let delay = {
+                let mut state = self.state.lock().unwrap();
+                if state.debt > 0 {
+                    state.debt -= 1;
+                    permit.forget();
+                    continue;
+                }
+                state.delay
+            };
+            if !delay.is_zero() {
+                sleep(delay).await;
+            }
+            return permit;
+        }
+    }
+
+    pub fn record(&self, outcome: Outcome) {
+        let mut state = self.state.lock().unwrap();
+        match outcome {
+            Outcome::Success => {
+                state.successes += 1;
+                if state.successes < RECOVERY_SUCCESSES {
+                    return;
+                }
+                state.successes = 0;
+                if state.limit < state.max {
+                    state.limit += 1;
+                    if state.debt > 0 {
+                        state.debt -= 1;
+                    } else {
+                        self.slots.add_permits(1);
+                    }
+                }
+                let delay = state.delay / 2;
+                state.delay = if delay < MIN_BACKOFF_DELAY { state.base_delay } else { delay.max(state.base_delay) };
+            },
+            Outcome::Failure | Outcome::RateLimited => {
+                state.successes = 0;
+                let limit = (state.limit / 2).max(1);
+                let reduction = state.limit - limit;
+                let forgotten = self.slots.forget_permits(reduction);
+                state.debt += reduction - forgotten;
+                state.limit = limit;
+                let delay = (state.delay * 2).clamp(MIN_BACKOFF_DELAY, MAX_DELAY);
+                if reduction > 0 || delay != state.delay {
+                    let reason = match outcome {
+                        Outcome::RateLimited => "is rate-limiting requests",
+                        _ => "is failing requests",
+                    };
+                    self.mp.suspend(|| println!("{} {}. Slowing down to {} at a time, {}ms apart...", self.source, reason, limit, delay.as_millis()));
+                }
+                state.delay = delay;
+            },
+        }
+    }
+}
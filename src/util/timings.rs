@@ -0,0 +1,107 @@
+//! Recording the time spent in each phase of fetching an app, for `--timings`, so that it can be
+//! seen whether a slow run is spent scraping pages or transferring files.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static TIMINGS: Mutex<BTreeMap<String, [Duration; PHASES.len()]>> = Mutex::new(BTreeMap::new());
+/// The key for time which can't be put down to any one app, such as fetching the F-Droid index
+const SHARED: &str = "(shared)";
+
+#[derive(Clone, Copy)]
+pub enum Phase {
+    /// Finding an app's page on the source
+    Search,
+    /// Fetching pages or API responses to learn the download URL
+    Resolve,
+    /// Transferring the files, including writing them as they arrive
+    Download,
+    /// Checking hashes, signatures and manifests
+    Verify,
+    /// Writing anything else to disk: checksums, metadata, links and deduplicated files
+    Write,
+}
+
+const PHASES: [Phase; 5] = [Phase::Search, Phase::Resolve, Phase::Download, Phase::Verify, Phase::Write];
+
+impl Phase {
+    fn name(&self) -> &'static str {
+        match self {
+            Phase::Search => "search",
+            Phase::Resolve => "resolve",
+            Phase::Download => "download",
+            Phase::Verify => "verify",
+            Phase::Write => "write",
+        }
+    }
+}
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Times a phase for an app from its creation until it is dropped.  When `--timings` is not
+/// given this does nothing.
+pub struct Timer {
+    app_id: String,
+    phase: Phase,
+    start: Option<Instant>,
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        if let Some(start) = self.start {
+            let mut timings = TIMINGS.lock().unwrap();
+            timings.entry(std::mem::take(&mut self.app_id)).or_default()[self.phase as usize] += start.elapsed();
+        }
+    }
+}
+
+pub fn start(app_id: &str, phase: Phase) -> Timer {
+    let enabled = ENABLED.load(Ordering::Relaxed);
+    Timer {
+        app_id: if enabled { app_id.to_string() } else { String::new() },
+        phase,
+        start: enabled.then(Instant::now),
+    }
+}
+
+/// Time a phase which serves every app at once.
+pub fn start_shared(phase: Phase) -> Timer {
+    start(SHARED, phase)
+}
+
+fn row(name: &str, durations: &[Duration]) -> String {
+    let mut row = format!("{:<40}", name);
+    for duration in durations.iter().chain(std::iter::once(&durations.iter().sum())) {
+        row.push_str(&format!("{:>10.2}", duration.as_secs_f64()));
+    }
+    row
+}
+
+/// Print the time spent in each phase, in seconds, for each app and in total.  Since apps are
+/// fetched in parallel, the total is the sum of their times rather than how long the run took.
+pub fn report() {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let timings = TIMINGS.lock().unwrap();
+    let mut header = format!("{:<40}", "Timings (s)");
+    for phase in PHASES.iter() {
+        header.push_str(&format!("{:>10}", phase.name()));
+    }
+    header.push_str(&format!("{:>10}", "total"));
+    println!("{}", header);
+
+    let mut totals = [Duration::ZERO; PHASES.len()];
+    for (app_id, durations) in timings.iter() {
+        println!("{}", row(app_id, durations));
+        for (total, duration) in totals.iter_mut().zip(durations) {
+            *total += *duration;
+        }
+    }
+    println!("{}", row("total", &totals));
+}
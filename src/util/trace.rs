@@ -0,0 +1,193 @@
+//! Logging of the HTTP requests made and the responses to them, for `--trace-http`, so that when a
+//! source stops working the log shows what it actually sent back rather than just that the app
+//! wasn't found.  Lines go to stderr, `>` for requests and `<` for responses.  With
+//! `--trace-http-bodies`, the bodies of text responses, such as HTML and JSON, are also saved to
+//! numbered files in a directory; APKs and other binary bodies never are.
+//!
+//! The log is meant to be attached to bug reports, so the values of headers such as
+//! `Authorization` and `Cookie`, the password of URLs and query parameters such as `token` and
+//! `signature` are redacted.
+//!
+//! Whether or not they are logged, the statuses of the responses can be observed, so that
+//! `apkeep doctor` can tell a source which is rate-limiting requests from one which is broken.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+
+use reqwest::header::{HeaderMap, CONTENT_TYPE};
+use reqwest::{Client, Request, RequestBuilder, Response, ResponseBuilderExt, StatusCode, Url};
+
+use super::network_error;
+
+const REDACTED: &str = "REDACTED";
+/// Headers whose values are redacted, besides any whose name mentions one of [`SECRET_WORDS`]
+const SECRET_HEADERS: &[&str] = &["authorization", "proxy-authorization", "cookie", "set-cookie"];
+/// Words which, in the name of a header or query parameter, mark its value as secret
+const SECRET_WORDS: &[&str] = &["auth", "token", "key", "secret", "password", "passwd", "session", "signature", "sig", "credential"];
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static BODIES: OnceLock<PathBuf> = OnceLock::new();
+/// The number of the last request traced, which its lines and body file are labelled with
+static REQUESTS: AtomicUsize = AtomicUsize::new(0);
+/// The status of each response since they were last taken, or `None` for a request which got no
+/// response, while they are being observed for `apkeep doctor`
+static OBSERVED: Mutex<Option<Vec<Option<StatusCode>>>> = Mutex::new(None);
+
+/// Start tracing, saving text response bodies into `bodies` if given.
+pub fn enable(bodies: Option<&Path>) {
+    ENABLED.store(true, Ordering::Relaxed);
+    if let Some(bodies) = bodies {
+        let _ = BODIES.set(bodies.to_path_buf());
+    }
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Send a request, logging it and its response when tracing.
+pub async fn send(request: RequestBuilder) -> Result<Response, reqwest::Error> {
+    let (client, request) = request.build_split();
+    let request = request?;
+    if !enabled() {
+        return observe(execute(client, request).await);
+    }
+    let number = REQUESTS.fetch_add(1, Ordering::Relaxed) + 1;
+    let mut lines = vec![format!("> #{} {} {}", number, request.method(), redact_url(request.url()))];
+    lines.extend(headers(">", request.headers()));
+    eprintln!("{}", lines.join("\n"));
+
+    let start = Instant::now();
+    let response = match observe(execute(client, request).await) {
+        Ok(response) => response,
+        Err(err) => {
+            eprintln!("< #{} failed after {} ms: {}", number, start.elapsed().as_millis(), network_error::describe(&err));
+            return Err(err);
+        },
+    };
+    let mut lines = vec![format!("< #{} {} {} ({} ms)", number, response.status(), redact_url(response.url()), start.elapsed().as_millis())];
+    lines.extend(headers("<", response.headers()));
+    let extension = response.headers().get(CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .and_then(text_extension);
+    let response = match (BODIES.get(), extension) {
+        (Some(bodies), Some(extension)) => {
+            let (response, saved) = save_body(response, &bodies.join(format!("{:04}.{}", number, extension))).await?;
+            lines.push(saved);
+            response
+        },
+        _ => response,
+    };
+    eprintln!("{}", lines.join("\n"));
+    Ok(response)
+}
+
+/// Start noting the status of every response, to be taken with [`take_observed`].
+pub fn start_observing() {
+    *OBSERVED.lock().unwrap() = Some(vec![]);
+}
+
+/// The statuses noted since observing started or they were last taken
+pub fn take_observed() -> Vec<Option<StatusCode>> {
+    OBSERVED.lock().unwrap().as_mut().map(std::mem::take).unwrap_or_default()
+}
+
+fn observe(response: Result<Response, reqwest::Error>) -> Result<Response, reqwest::Error> {
+    if let Some(observed) = OBSERVED.lock().unwrap().as_mut() {
+        observed.push(response.as_ref().ok().map(Response::status));
+    }
+    response
+}
+
+#[cfg(feature = "fixtures")]
+async fn execute(client: Client, request: Request) -> Result<Response, reqwest::Error> {
+    super::fixtures::execute(client, request).await
+}
+
+#[cfg(not(feature = "fixtures"))]
+async fn execute(client: Client, request: Request) -> Result<Response, reqwest::Error> {
+    client.execute(request).await
+}
+
+/// Log a download made by a library which sends its own request, so only its URL is known.
+pub fn download(url: &str) {
+    if !enabled() {
+        return;
+    }
+    let number = REQUESTS.fetch_add(1, Ordering::Relaxed) + 1;
+    match Url::parse(url) {
+        Ok(url) => eprintln!("> #{} GET {} (downloaded to disk, so the response isn't traced)", number, redact_url(&url)),
+        Err(_) => eprintln!("> #{} GET {} (downloaded to disk, so the response isn't traced)", number, REDACTED),
+    }
+}
+
+/// Read a response's body to save it to `path`, returning a response with the same body for the
+/// caller to read, and a line saying where it was saved.
+async fn save_body(response: Response, path: &Path) -> Result<(Response, String), reqwest::Error> {
+    let url = response.url().clone();
+    let mut builder = ::http::Response::builder()
+        .status(response.status())
+        .version(response.version())
+        .url(url);
+    if let Some(headers) = builder.headers_mut() {
+        *headers = response.headers().clone();
+    }
+    let body = response.bytes().await?;
+    let saved = match fs::create_dir_all(path.parent().unwrap_or(path)).and_then(|_| fs::write(path, &body)) {
+        Ok(()) => format!("<   body saved to {}", path.display()),
+        Err(err) => format!("<   body could not be saved to {}: {}", path.display(), err),
+    };
+    Ok((Response::from(builder.body(body).unwrap()), saved))
+}
+
+/// The extension to save a body of this type with, if it is text
+fn text_extension(content_type: &str) -> Option<&'static str> {
+    let mime = content_type.split(';').next().unwrap_or_default().trim().to_lowercase();
+    match mime.as_str() {
+        "text/html" | "application/xhtml+xml" => Some("html"),
+        "application/json" => Some("json"),
+        "text/xml" | "application/xml" | "application/atom+xml" | "application/rss+xml" => Some("xml"),
+        _ if mime.ends_with("+json") => Some("json"),
+        _ if mime.starts_with("text/") => Some("txt"),
+        _ => None,
+    }
+}
+
+fn headers(direction: &str, headers: &HeaderMap) -> Vec<String> {
+    headers.iter().map(|(name, value)| {
+        let value = if secret(name.as_str()) {
+            REDACTED.to_string()
+        } else {
+            String::from_utf8_lossy(value.as_bytes()).to_string()
+        };
+        format!("{}   {}: {}", direction, name, value)
+    }).collect()
+}
+
+/// Whether the value of a header or query parameter with this name may be a credential
+pub fn secret(name: &str) -> bool {
+    let name = name.to_lowercase();
+    SECRET_HEADERS.contains(&name.as_str())
+        || name.split(|c: char| !c.is_ascii_alphanumeric()).any(|word| SECRET_WORDS.iter().any(|secret| word == *secret || word.ends_with(secret)))
+}
+
+/// The URL with its password and the values of secret query parameters redacted
+pub fn redact_url(url: &Url) -> String {
+    let mut url = url.clone();
+    if url.password().is_some() {
+        let _ = url.set_password(Some(REDACTED));
+    }
+    if url.query().is_some() {
+        let pairs = url.query_pairs()
+            .map(|(name, value)| {
+                let value = if secret(&name) { REDACTED.into() } else { value };
+                (name.to_string(), value.to_string())
+            })
+            .collect::<Vec<_>>();
+        url.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+    url.to_string()
+}
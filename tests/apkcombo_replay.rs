@@ -0,0 +1,38 @@
+//! Replays a recorded APKCombo search, app page, download page and download, so that a change to
+//! the `[apkcombo]` patterns which no longer finds the app page, the download button or the file
+//! link fails here rather than against the live site.  Built with the `fixtures` feature:
+//!
+//! ```shell
+//! cargo test --features fixtures
+//! ```
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/apkcombo");
+
+#[test]
+fn apkcombo_download_is_found_in_recorded_pages() {
+    let home = tempfile::tempdir().unwrap();
+    let outpath = tempfile::tempdir().unwrap();
+    // A home of its own keeps the run from using downloaded patterns or cached pages
+    let output = Command::new(env!("CARGO_BIN_EXE_apkeep"))
+        .env("HOME", home.path())
+        .env("XDG_CONFIG_HOME", home.path().join("config"))
+        .env("XDG_CACHE_HOME", home.path().join("cache"))
+        .args(["-a", "org.example.notes", "-d", "apk-combo", "--cache-ttl", "0", "--replay-http", FIXTURES])
+        .arg(outpath.path())
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "apkeep failed:\n{}{}", stdout, String::from_utf8_lossy(&output.stderr));
+
+    // The search result for the app itself is taken, not the one which merely mentions its ID
+    assert!(stdout.contains("Found app page: https://apkcombo.com/en/example-notes/org.example.notes/"), "{}", stdout);
+    assert!(stdout.contains("Found download URL: https://apkcombo.com/en/example-notes/org.example.notes/download/apk"), "{}", stdout);
+
+    let downloaded = fs::read(outpath.path().join("Example Notes_2.4.1_apkcombo.com.apk")).unwrap();
+    let recorded = fs::read(Path::new(FIXTURES).join("download.apkcombo.com-9cc7d2f864670a34.body")).unwrap();
+    assert_eq!(downloaded, recorded);
+}